@@ -0,0 +1,747 @@
+//! `.stem` backup archives: a zip holding a manifest, the folders, one JSON
+//! line per note, the chat history, attachment files and optionally the
+//! embeddings. Both directions stream from a file path instead of passing
+//! the whole backup over IPC, and report progress through `archive-progress`.
+
+use crate::attachments::{AttachmentStore, StoredAttachment};
+use crate::bundle::{decrypt_stream, encrypt_stream, is_bundle, open_text, require_passphrase, ARMOR_PREFIX};
+use crate::commands::{
+    resolve_scope, row_to_chat_message, row_to_folder, row_to_note, ChatMessage, ExportData, ExportScope, Folder, Note,
+    FOLDER_COLUMNS, NOTE_COLUMNS,
+};
+use crate::db::{current_timestamp, Database};
+use crate::encryption::{validate_passphrase, KdfParams};
+use crate::error::StemError;
+use crate::restore::{merge_export_data, parents_first, ConflictStrategy, ImportDiff, Merger};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const ARCHIVE_FORMAT: &str = "stem-archive";
+/// Continues the `ExportData` numbering: JSON backups are versions 0 and 1.
+pub const ARCHIVE_VERSION: u32 = 2;
+pub const PROGRESS_EVENT: &str = "archive-progress";
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const FOLDERS_ENTRY: &str = "folders.json";
+const NOTES_ENTRY: &str = "notes.jsonl";
+const CHAT_ENTRY: &str = "chat.json";
+const ATTACHMENTS_ENTRY: &str = "attachments.json";
+const EMBEDDINGS_ENTRY: &str = "embeddings.jsonl";
+const ATTACHMENT_DIR: &str = "attachments/";
+
+/// Progress is reported every this many notes.
+const PROGRESS_STEP: u32 = 200;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: i64,
+    pub notes: u32,
+    pub folders: u32,
+    pub chat_messages: u32,
+    pub attachments: u32,
+    /// `None` when the archive was made without embeddings.
+    pub embeddings: Option<u32>,
+    /// Set for a scoped export, which leaves out the chat history.
+    #[serde(default)]
+    pub partial: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ArchiveProgress {
+    /// `notes`, `chat`, `attachments` or `embeddings`.
+    pub phase: &'static str,
+    pub done: u32,
+    pub total: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AttachmentRecord {
+    id: String,
+    note_id: Option<String>,
+    file_name: String,
+    original_name: String,
+    mime: Option<String>,
+    size: u64,
+    created_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EmbeddingRecord {
+    note_id: String,
+    model: String,
+    updated_at: i64,
+    /// Base64 of the little-endian `f32` blob stored in `note_embeddings`.
+    embedding: String,
+}
+
+fn json_error(e: serde_json::Error) -> StemError {
+    StemError::Validation(format!("Format de fichier invalide: {}", e))
+}
+
+// ===== Export =====
+
+fn count(conn: &rusqlite::Connection, table: &str) -> Result<u32, StemError> {
+    Ok(conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))?)
+}
+
+fn write_entries<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    db: &Database,
+    store: &AttachmentStore,
+    scope: Option<&ExportScope>,
+    include_embeddings: bool,
+    options: SimpleFileOptions,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ArchiveManifest, StemError> {
+    let conn = db.try_connection()?;
+    let scoped = scope.map(|scope| resolve_scope(&conn, scope)).transpose()?;
+    let in_scope = |note_id: &str| scoped.as_ref().is_none_or(|s| s.note_ids.contains(note_id));
+    let mut manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        created_at: current_timestamp(),
+        notes: 0,
+        folders: 0,
+        chat_messages: 0,
+        attachments: 0,
+        embeddings: None,
+        partial: scoped.is_some(),
+    };
+
+    let mut stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders ORDER BY position ASC"))?;
+    let mut folders = stmt.query_map([], row_to_folder)?.collect::<Result<Vec<_>, _>>()?;
+    if let Some(scoped) = &scoped {
+        folders.retain(|f| scoped.folder_ids.contains(&f.id));
+    }
+    zip.start_file(FOLDERS_ENTRY, options).map_err(io::Error::from)?;
+    serde_json::to_writer(&mut *zip, &folders).map_err(io::Error::from)?;
+    manifest.folders = folders.len() as u32;
+
+    let total = match &scoped {
+        Some(scoped) => scoped.note_ids.len() as u32,
+        None => count(&conn, "notes")?,
+    };
+    zip.start_file(NOTES_ENTRY, options).map_err(io::Error::from)?;
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes ORDER BY created_at ASC"))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let note = row_to_note(row)?;
+        if !in_scope(&note.id) {
+            continue;
+        }
+        serde_json::to_writer(&mut *zip, &note).map_err(io::Error::from)?;
+        zip.write_all(b"\n")?;
+        manifest.notes += 1;
+        if manifest.notes.is_multiple_of(PROGRESS_STEP) {
+            progress(ArchiveProgress { phase: "notes", done: manifest.notes, total });
+        }
+    }
+    progress(ArchiveProgress { phase: "notes", done: manifest.notes, total });
+
+    let mut stmt = conn.prepare(
+        "SELECT id, role, content, command, msg_type, created_at FROM chat_messages ORDER BY created_at ASC",
+    )?;
+    let mut chat = stmt.query_map([], row_to_chat_message)?.collect::<Result<Vec<_>, _>>()?;
+    if scoped.is_some() {
+        chat.clear();
+    }
+    zip.start_file(CHAT_ENTRY, options).map_err(io::Error::from)?;
+    serde_json::to_writer(&mut *zip, &chat).map_err(io::Error::from)?;
+    manifest.chat_messages = chat.len() as u32;
+    progress(ArchiveProgress { phase: "chat", done: manifest.chat_messages, total: manifest.chat_messages });
+
+    // Rows whose file went missing are left out rather than failing the backup
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, file_name, original_name, mime, size, created_at FROM attachments ORDER BY created_at ASC",
+    )?;
+    let attachments: Vec<AttachmentRecord> = stmt
+        .query_map([], |row| {
+            Ok(AttachmentRecord {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                file_name: row.get(2)?,
+                original_name: row.get(3)?,
+                mime: row.get(4)?,
+                size: row.get::<_, i64>(5)? as u64,
+                created_at: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|a| a.note_id.as_deref().map_or(scoped.is_none(), in_scope) && store.path_of(&a.file_name).is_file())
+        .collect();
+    zip.start_file(ATTACHMENTS_ENTRY, options).map_err(io::Error::from)?;
+    serde_json::to_writer(&mut *zip, &attachments).map_err(io::Error::from)?;
+    let total = attachments.len() as u32;
+    for attachment in &attachments {
+        zip.start_file(format!("{}{}", ATTACHMENT_DIR, attachment.file_name), options).map_err(io::Error::from)?;
+        io::copy(&mut File::open(store.path_of(&attachment.file_name))?, zip)?;
+        manifest.attachments += 1;
+        progress(ArchiveProgress { phase: "attachments", done: manifest.attachments, total });
+    }
+
+    if include_embeddings {
+        let total = match &scoped {
+            Some(scoped) => scoped.note_ids.len() as u32,
+            None => count(&conn, "note_embeddings")?,
+        };
+        zip.start_file(EMBEDDINGS_ENTRY, options).map_err(io::Error::from)?;
+        let mut stmt = conn.prepare("SELECT note_id, model, updated_at, embedding FROM note_embeddings")?;
+        let mut rows = stmt.query([])?;
+        let mut done = 0u32;
+        while let Some(row) = rows.next()? {
+            let note_id: String = row.get(0)?;
+            if !in_scope(&note_id) {
+                continue;
+            }
+            let record = EmbeddingRecord {
+                note_id,
+                model: row.get(1)?,
+                updated_at: row.get(2)?,
+                embedding: STANDARD.encode(row.get::<_, Vec<u8>>(3)?),
+            };
+            serde_json::to_writer(&mut *zip, &record).map_err(io::Error::from)?;
+            zip.write_all(b"\n")?;
+            done += 1;
+        }
+        progress(ArchiveProgress { phase: "embeddings", done, total });
+        manifest.embeddings = Some(done);
+    }
+
+    zip.start_file(MANIFEST_ENTRY, options).map_err(io::Error::from)?;
+    serde_json::to_writer_pretty(&mut *zip, &manifest).map_err(io::Error::from)?;
+    Ok(manifest)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Writes the archive next to `path` first and renames it into place once
+/// complete, so an interrupted export never leaves a truncated backup behind.
+/// With a passphrase the zip is sealed as it is written, so its plaintext
+/// never reaches the disk.
+pub(crate) fn export_archive_to(
+    db: &Database,
+    store: &AttachmentStore,
+    path: &Path,
+    scope: Option<&ExportScope>,
+    include_embeddings: bool,
+    passphrase: Option<&str>,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ArchiveManifest, StemError> {
+    if let Some(passphrase) = passphrase {
+        validate_passphrase(passphrase)?;
+    }
+    let partial = with_suffix(path, if passphrase.is_some() { ".enc.part" } else { ".part" });
+
+    let result = (|| {
+        let manifest = match passphrase {
+            Some(passphrase) => {
+                let sealed = BufWriter::new(File::create(&partial)?);
+                let (reader, writer) = io::pipe()?;
+                progress(ArchiveProgress { phase: "encrypt", done: 0, total: 1 });
+                let (manifest, encrypted) = thread::scope(|s| {
+                    let encrypt = s.spawn(|| encrypt_stream(reader, sealed, passphrase, KdfParams::default()));
+                    // Dropping the zip closes the pipe, which ends the encryption
+                    let manifest = (|| {
+                        let mut zip = ZipWriter::new_stream(BufWriter::new(writer));
+                        let manifest = write_entries(&mut zip, db, store, scope, include_embeddings, SimpleFileOptions::default(), progress)?;
+                        zip.finish().map_err(io::Error::from)?.flush()?;
+                        Ok::<_, StemError>(manifest)
+                    })();
+                    let encrypted = encrypt.join().unwrap_or_else(|_| Err(StemError::Validation("Échec du chiffrement de l'archive".to_string())));
+                    (manifest, encrypted)
+                });
+                // A failed encryption closes the pipe too: its error explains the zip's
+                encrypted?;
+                let manifest = manifest?;
+                progress(ArchiveProgress { phase: "encrypt", done: 1, total: 1 });
+                manifest
+            }
+            None => {
+                let mut zip = ZipWriter::new(BufWriter::new(File::create(&partial)?));
+                let manifest = write_entries(&mut zip, db, store, scope, include_embeddings, SimpleFileOptions::default(), progress)?;
+                zip.finish().map_err(io::Error::from)?.flush()?;
+                manifest
+            }
+        };
+        fs::rename(&partial, path)?;
+        Ok(manifest)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Uncompressed archive of the whole database for offsite backups: the
+/// chunker only finds what two backups have in common when entries are stored
+/// as is. Chunks are compressed one by one instead.
+pub(crate) fn write_backup_archive(db: &Database, store: &AttachmentStore, path: &Path) -> Result<ArchiveManifest, StemError> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let manifest = write_entries(&mut zip, db, store, None, false, options, &|_| {})?;
+    zip.finish().map_err(io::Error::from)?.flush()?;
+    Ok(manifest)
+}
+
+// ===== Import =====
+
+fn read_json<T: serde::de::DeserializeOwned, R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<T>, StemError> {
+    match archive.by_name(name) {
+        Ok(entry) => serde_json::from_reader(BufReader::new(entry)).map(Some).map_err(json_error),
+        Err(zip::result::ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(io::Error::from(e).into()),
+    }
+}
+
+fn import_entries<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    db: &Database,
+    store: &AttachmentStore,
+    merger: &mut Merger,
+    restored: &mut Vec<StoredAttachment>,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<(), StemError> {
+    let manifest: ArchiveManifest = read_json(archive, MANIFEST_ENTRY)?
+        .ok_or_else(|| StemError::Validation("Archive Stem invalide : manifeste absent".to_string()))?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(StemError::Validation(format!("Archive Stem invalide : format {}", manifest.format)));
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(StemError::Validation(format!(
+            "Archive créée par une version plus récente de Stem (format {})",
+            manifest.version
+        )));
+    }
+
+    let folders: Vec<Folder> = parents_first(read_json(archive, FOLDERS_ENTRY)?.unwrap_or_default());
+    let chat: Vec<ChatMessage> = read_json(archive, CHAT_ENTRY)?.unwrap_or_default();
+    let attachments: Vec<AttachmentRecord> = read_json(archive, ATTACHMENTS_ENTRY)?.unwrap_or_default();
+
+    let mut conn = db.connection_mut();
+    let tx = conn.transaction()?;
+
+    for folder in &folders {
+        merger.folder(&tx, folder)?;
+    }
+
+    {
+        let entry = archive.by_name(NOTES_ENTRY).map_err(io::Error::from)?;
+        let mut done = 0u32;
+        for line in BufReader::new(entry).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let note: Note = serde_json::from_str(&line).map_err(json_error)?;
+            merger.note(&tx, &note)?;
+            done += 1;
+            if done.is_multiple_of(PROGRESS_STEP) {
+                progress(ArchiveProgress { phase: "notes", done, total: manifest.notes });
+            }
+        }
+        progress(ArchiveProgress { phase: "notes", done, total: manifest.notes });
+    }
+    // The diff only covers notes and folders
+    if merger.dry_run() {
+        return Ok(());
+    }
+
+    for message in &chat {
+        tx.execute(
+            "INSERT OR IGNORE INTO chat_messages (id, role, content, command, msg_type, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![message.id, message.role, message.content, message.command, message.msg_type, message.created_at],
+        )?;
+    }
+    progress(ArchiveProgress { phase: "chat", done: chat.len() as u32, total: chat.len() as u32 });
+
+    let total = attachments.len() as u32;
+    for (done, record) in attachments.iter().enumerate() {
+        let known: bool = tx.query_row(
+            "SELECT COUNT(*) > 0 FROM attachments WHERE file_name = ?1 OR id = ?2",
+            [&record.file_name, &record.id],
+            |row| row.get(0),
+        )?;
+        if !known {
+            let mut entry = archive.by_name(&format!("{}{}", ATTACHMENT_DIR, record.file_name)).map_err(io::Error::from)?;
+            let size = store.restore(&record.file_name, &mut entry).map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => {
+                    StemError::Validation(format!("Un autre fichier porte déjà le nom de la pièce jointe {}", record.file_name))
+                }
+                _ => e.into(),
+            })?;
+            let stored = StoredAttachment {
+                id: record.id.clone(),
+                file_name: record.file_name.clone(),
+                original_name: record.original_name.clone(),
+                mime: record.mime.clone(),
+                size,
+            };
+            restored.push(stored);
+            // The owning note may not be part of this archive
+            tx.execute(
+                "INSERT INTO attachments (id, note_id, file_name, original_name, mime, size, created_at)
+                 VALUES (?1, (SELECT id FROM notes WHERE id = ?2), ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![
+                    record.id, record.note_id.as_deref().map(|id| merger.note_id(id)), record.file_name, record.original_name,
+                    record.mime, size as i64, record.created_at,
+                ],
+            )?;
+        }
+        progress(ArchiveProgress { phase: "attachments", done: done as u32 + 1, total });
+    }
+
+    if manifest.embeddings.is_some() {
+        let entry = archive.by_name(EMBEDDINGS_ENTRY).map_err(io::Error::from)?;
+        let mut done = 0u32;
+        for line in BufReader::new(entry).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: EmbeddingRecord = serde_json::from_str(&line).map_err(json_error)?;
+            let blob = STANDARD
+                .decode(&record.embedding)
+                .map_err(|e| StemError::Validation(format!("Embedding invalide ({}) : {}", record.note_id, e)))?;
+            tx.execute(
+                "INSERT OR IGNORE INTO note_embeddings (note_id, embedding, model, updated_at)
+                 SELECT ?1, ?2, ?3, ?4 WHERE EXISTS (SELECT 1 FROM notes WHERE id = ?1)",
+                rusqlite::params![merger.note_id(&record.note_id), blob, record.model, record.updated_at],
+            )?;
+            done += 1;
+        }
+        progress(ArchiveProgress { phase: "embeddings", done, total: manifest.embeddings.unwrap_or(done) });
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Removes the decrypted copy of an encrypted backup once the import is done.
+pub(crate) struct TempFile(pub PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Private directory in the app data dir for the decrypted copy of an
+/// encrypted backup, which the zip reader needs to seek in.
+#[derive(Clone)]
+pub struct ScratchDir {
+    dir: PathBuf,
+}
+
+impl ScratchDir {
+    /// Copies left behind by an import interrupted by a crash are removed.
+    pub fn new(dir: PathBuf) -> Self {
+        let _ = fs::remove_dir_all(&dir);
+        Self { dir }
+    }
+
+    /// A new empty file only its owner can read, removed with the `TempFile`.
+    fn create_file(&self) -> Result<(TempFile, File), StemError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("stem-import-{}", Uuid::new_v4()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        Ok((TempFile(path), file))
+    }
+}
+
+fn is_armored(prefix: &[u8]) -> bool {
+    prefix.starts_with(ARMOR_PREFIX.as_bytes())
+}
+
+/// Decrypts `path` into `scratch` when it is an encrypted bundle, binary or
+/// armored (an `export_all_data` export saved to a file). The returned file is
+/// the one to import; `None` when `path` is not encrypted.
+pub(crate) fn decrypt_archive(
+    path: &Path,
+    scratch: &ScratchDir,
+    passphrase: Option<&str>,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<Option<TempFile>, StemError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let prefix = file.fill_buf()?;
+    let armored = is_armored(prefix);
+    if !armored && !is_bundle(prefix) {
+        return Ok(None);
+    }
+    let passphrase = require_passphrase(passphrase)?;
+    let (plain, mut out) = scratch.create_file()?;
+    progress(ArchiveProgress { phase: "decrypt", done: 0, total: 1 });
+    if armored {
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        out.write_all(open_text(&text, passphrase)?.as_bytes())?;
+    } else {
+        decrypt_stream(file, BufWriter::new(out), passphrase)?;
+    }
+    progress(ArchiveProgress { phase: "decrypt", done: 1, total: 1 });
+    Ok(Some(plain))
+}
+
+/// Imports a `.stem` archive, or a JSON backup from any earlier `ExportData`
+/// version. Encrypted backups go through `decrypt_archive` first. With
+/// `dry_run` nothing is written and only the diff is returned.
+pub(crate) fn import_archive_from(
+    db: &Database,
+    store: &AttachmentStore,
+    path: &Path,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ImportDiff, StemError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let prefix = file.fill_buf()?;
+    if is_bundle(prefix) || is_armored(prefix) {
+        require_passphrase(None)?;
+    }
+    if !file.fill_buf()?.starts_with(b"PK") {
+        let export: ExportData = serde_json::from_reader(file).map_err(json_error)?;
+        return merge_export_data(db, export, strategy, dry_run);
+    }
+
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| StemError::Validation(format!("Archive Stem invalide : {}", e)))?;
+    let mut merger = Merger::new(strategy, dry_run);
+    let mut restored = Vec::new();
+    if let Err(e) = import_entries(&mut archive, db, store, &mut merger, &mut restored, progress) {
+        store.discard(&restored);
+        return Err(e);
+    }
+    Ok(merger.finish())
+}
+
+// ===== Tauri Commands =====
+
+/// Writes a `.stem` archive of the whole database, or of `scope`, to `path`.
+#[tauri::command]
+pub async fn export_archive(
+    app: AppHandle,
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    path: String,
+    include_embeddings: Option<bool>,
+    scope: Option<ExportScope>,
+    passphrase: Option<String>,
+) -> Result<ArchiveManifest, StemError> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("Le fichier d'archive doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let progress = |p: ArchiveProgress| {
+            let _ = app.emit(PROGRESS_EVENT, p);
+        };
+        export_archive_to(&db, &store, &path, scope.as_ref(), include_embeddings.unwrap_or(false), passphrase.as_deref(), &progress)
+    }).await
+}
+
+/// Imports a `.stem` archive or a JSON backup from `path`. Same summary as `import_all_data`.
+#[tauri::command]
+pub async fn import_archive(
+    app: AppHandle,
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    scratch: State<'_, ScratchDir>,
+    path: String,
+    strategy: Option<ConflictStrategy>,
+    passphrase: Option<String>,
+) -> Result<String, StemError> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("Le fichier d'archive doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    let scratch = scratch.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let progress = |p: ArchiveProgress| {
+            let _ = app.emit(PROGRESS_EVENT, p);
+        };
+        let decrypted = decrypt_archive(&path, &scratch, passphrase.as_deref(), &progress)?;
+        let path = decrypted.as_ref().map_or(path.as_path(), |plain| plain.0.as_path());
+        Ok(import_archive_from(&db, &store, path, strategy.unwrap_or_default(), false, &progress)?.summary)
+    }).await
+}
+
+/// Dry run of `import_archive`: the notes and folders it would insert, change or leave alone.
+#[tauri::command]
+pub async fn preview_import_archive(
+    app: AppHandle,
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    scratch: State<'_, ScratchDir>,
+    path: String,
+    strategy: Option<ConflictStrategy>,
+    passphrase: Option<String>,
+) -> Result<ImportDiff, StemError> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("Le fichier d'archive doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    let scratch = scratch.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let progress = |p: ArchiveProgress| {
+            let _ = app.emit(PROGRESS_EVENT, p);
+        };
+        let decrypted = decrypt_archive(&path, &scratch, passphrase.as_deref(), &progress)?;
+        let path = decrypted.as_ref().map_or(path.as_path(), |plain| plain.0.as_path());
+        import_archive_from(&db, &store, path, strategy.unwrap_or_default(), true, &progress)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::insert_attachment;
+    use std::cell::RefCell;
+
+    fn setup() -> Database {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        db
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("stem-archive-{}", Uuid::new_v4()));
+        let source = setup();
+        let source_store = AttachmentStore::new(dir.join("source"));
+        {
+            let conn = source.connection();
+            conn.execute("INSERT INTO folders (id, name, position, created_at) VALUES ('f1', 'Work', 0, 1)", []).unwrap();
+            for i in 0..3 {
+                conn.execute(
+                    "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES (?1, 'T', 'Body', ?2, ?2, 'f1')",
+                    (format!("n{}", i), i),
+                ).unwrap();
+            }
+            conn.execute("INSERT INTO note_embeddings (note_id, embedding, model, updated_at) VALUES ('n0', x'0000803f', 'm', 1)", []).unwrap();
+            conn.execute("INSERT INTO chat_messages (id, role, content, created_at) VALUES ('c1', 'user', 'Salut', 1)", []).unwrap();
+            let stored = source_store.write_bytes(b"png", "shot.png", None).unwrap();
+            insert_attachment(&conn, &stored, Some("n1"), 1).unwrap();
+        }
+
+        let path = dir.join("backup.stem");
+        let events = RefCell::new(Vec::new());
+        let manifest = export_archive_to(&source, &source_store, &path, None, true, None, &|p| events.borrow_mut().push(p.phase)).unwrap();
+        assert_eq!((manifest.notes, manifest.folders, manifest.chat_messages, manifest.attachments), (3, 1, 1, 1));
+        assert_eq!(manifest.embeddings, Some(1));
+        assert_eq!(*events.borrow(), ["notes", "chat", "attachments", "embeddings"]);
+        assert!(!dir.join("backup.stem.part").exists());
+
+        let target = setup();
+        let target_store = AttachmentStore::new(dir.join("target"));
+        let summary = import_archive_from(&target, &target_store, &path, ConflictStrategy::Skip, false, &|_| {}).unwrap().summary;
+        assert_eq!(summary, "3 notes, 1 dossiers importés");
+
+        let conn = target.connection();
+        let (note_id, file_name): (String, String) = conn
+            .query_row("SELECT note_id, file_name FROM attachments", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(note_id, "n1");
+        assert_eq!(fs::read(target_store.path_of(&file_name)).unwrap(), b"png");
+        let counts: (u32, u32) = conn
+            .query_row("SELECT (SELECT COUNT(*) FROM note_embeddings), (SELECT COUNT(*) FROM chat_messages)", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(counts, (1, 1));
+        drop(conn);
+
+        // Importing again changes nothing
+        let summary = import_archive_from(&target, &target_store, &path, ConflictStrategy::Skip, false, &|_| {}).unwrap().summary;
+        assert_eq!(summary, "0 notes, 0 dossiers importés");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_import_legacy_json_and_newer_archive() {
+        let dir = std::env::temp_dir().join(format!("stem-archive-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let db = setup();
+        let store = AttachmentStore::new(dir.join("store"));
+
+        // Unversioned backups predate folders, pins and archiving
+        let legacy = dir.join("old.json");
+        fs::write(&legacy, r#"{"notes": [{"id": "a", "title": "Old", "content": "x", "created_at": 1, "updated_at": 2}]}"#).unwrap();
+        assert_eq!(import_archive_from(&db, &store, &legacy, ConflictStrategy::Skip, false, &|_| {}).unwrap().summary, "1 notes, 0 dossiers importés");
+
+        let newer = dir.join("newer.stem");
+        let mut zip = ZipWriter::new(File::create(&newer).unwrap());
+        zip.start_file(MANIFEST_ENTRY, SimpleFileOptions::default()).unwrap();
+        zip.write_all(br#"{"format": "stem-archive", "version": 99, "created_at": 0, "notes": 0, "folders": 0, "chat_messages": 0, "attachments": 0, "embeddings": null}"#).unwrap();
+        zip.finish().unwrap();
+        assert!(matches!(import_archive_from(&db, &store, &newer, ConflictStrategy::Skip, false, &|_| {}), Err(StemError::Validation(_))));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_encrypted_archive_requires_passphrase() {
+        let dir = std::env::temp_dir().join(format!("stem-archive-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = setup();
+        source.connection().execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('n1', 'Secret', 'body', 1, 2)", [],
+        ).unwrap();
+        let store = AttachmentStore::new(dir.join("store"));
+        let path = dir.join("backup.stem");
+        export_archive_to(&source, &store, &path, None, false, Some("correct horse"), &|_| {}).unwrap();
+        assert!(is_bundle(&fs::read(&path).unwrap()));
+        assert!(!dir.join("backup.stem.part").exists() && !dir.join("backup.stem.enc.part").exists());
+
+        let target = setup();
+        let scratch = ScratchDir::new(dir.join("tmp"));
+        let import = |passphrase| {
+            let plain = decrypt_archive(&path, &scratch, passphrase, &|_| {})?.expect("encrypted");
+            #[cfg(unix)]
+            assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&plain.0)?.permissions()) & 0o777, 0o600);
+            import_archive_from(&target, &store, &plain.0, ConflictStrategy::Skip, false, &|_| {})
+        };
+        assert!(matches!(import(None), Err(StemError::Validation(m)) if m.contains("phrase de passe requise")));
+        assert!(matches!(
+            import_archive_from(&target, &store, &path, ConflictStrategy::Skip, false, &|_| {}),
+            Err(StemError::Validation(m)) if m.contains("phrase de passe requise")
+        ));
+        assert!(import(Some("wrong horse")).is_err());
+        assert_eq!(import(Some("correct horse")).unwrap().summary, "1 notes, 0 dossiers importés");
+        assert_eq!(fs::read_dir(dir.join("tmp")).unwrap().count(), 0, "decrypted copies removed");
+
+        // A JSON export encrypted by `export_all_data`, saved to a file
+        let armored = dir.join("export.json");
+        let json = r#"{"notes": [{"id": "a", "title": "Old", "content": "x", "created_at": 1, "updated_at": 2}]}"#;
+        fs::write(&armored, crate::bundle::seal_text(json, "correct horse").unwrap()).unwrap();
+        assert!(matches!(
+            import_archive_from(&target, &store, &armored, ConflictStrategy::Skip, false, &|_| {}),
+            Err(StemError::Validation(m)) if m.contains("phrase de passe requise")
+        ));
+        let plain = decrypt_archive(&armored, &scratch, Some("correct horse"), &|_| {}).unwrap().unwrap();
+        assert_eq!(import_archive_from(&target, &store, &plain.0, ConflictStrategy::Skip, false, &|_| {}).unwrap().summary, "1 notes, 0 dossiers importés");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,218 @@
+//! Files embedded in notes (images, PDFs...). They are copied into the app
+//! data directory under a generated name and referenced from Markdown as
+//! `stem-attachment://<file name>`.
+
+use crate::links::protocol_target;
+use rusqlite::Connection;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const ATTACHMENT_URI_PREFIX: &str = "stem-attachment://";
+
+/// Larger files are not copied by importers.
+pub const MAX_ATTACHMENT_SIZE: u64 = 100 * 1024 * 1024;
+
+pub fn attachment_uri(file_name: &str) -> String {
+    format!("{}{}", ATTACHMENT_URI_PREFIX, file_name)
+}
+
+pub fn guess_mime(file_name: &str) -> Option<&'static str> {
+    let ext = Path::new(file_name).extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "txt" => "text/plain",
+        _ => return None,
+    })
+}
+
+/// Stored names are generated (`<uuid>.<ext>`): no separators, no dot files.
+pub fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\', ':', '\0'])
+}
+
+/// Answer to a `stem-attachment://` request, turned into an HTTP response by lib.rs.
+#[derive(Debug, PartialEq)]
+pub struct ProtocolResponse {
+    pub status: u16,
+    pub mime: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl ProtocolResponse {
+    fn status(status: u16) -> Self {
+        ProtocolResponse { status, mime: "text/plain", body: Vec::new() }
+    }
+}
+
+/// Serves the attachment named by `uri` from `store`.
+pub fn serve_attachment(store: &AttachmentStore, uri: &str) -> ProtocolResponse {
+    let Some(file_name) = protocol_target(uri).filter(|name| is_valid_file_name(name)) else {
+        return ProtocolResponse::status(400);
+    };
+    match fs::read(store.path_of(&file_name)) {
+        Ok(body) => ProtocolResponse {
+            status: 200,
+            mime: guess_mime(&file_name).unwrap_or("application/octet-stream"),
+            body,
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => ProtocolResponse::status(404),
+        Err(_) => ProtocolResponse::status(500),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredAttachment {
+    pub id: String,
+    pub file_name: String,
+    pub original_name: String,
+    pub mime: Option<String>,
+    pub size: u64,
+}
+
+/// Directory holding attachment files, managed as Tauri state.
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    dir: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Generated name keeping the original extension, e.g. `<uuid>.png`.
+    fn allocate(original_name: &str) -> String {
+        let id = Uuid::new_v4();
+        match Path::new(original_name).extension().and_then(|e| e.to_str()) {
+            Some(ext) if !ext.is_empty() && ext.len() <= 10 => format!("{}.{}", id, ext.to_ascii_lowercase()),
+            _ => id.to_string(),
+        }
+    }
+
+    pub fn copy_file(&self, source: &Path, original_name: &str) -> io::Result<StoredAttachment> {
+        fs::create_dir_all(&self.dir)?;
+        let file_name = Self::allocate(original_name);
+        let size = fs::copy(source, self.dir.join(&file_name))?;
+        Ok(StoredAttachment {
+            id: Uuid::new_v4().to_string(),
+            mime: guess_mime(original_name).map(str::to_string),
+            file_name,
+            original_name: original_name.to_string(),
+            size,
+        })
+    }
+
+    pub fn write_bytes(&self, bytes: &[u8], original_name: &str, mime: Option<&str>) -> io::Result<StoredAttachment> {
+        self.write_reader(&mut &bytes[..], original_name, mime)
+    }
+
+    /// Streams `reader` into a new attachment file (used for archive entries).
+    pub fn write_reader(&self, reader: &mut impl Read, original_name: &str, mime: Option<&str>) -> io::Result<StoredAttachment> {
+        fs::create_dir_all(&self.dir)?;
+        let file_name = Self::allocate(original_name);
+        let path = self.dir.join(&file_name);
+        let size = match io::copy(reader, &mut fs::File::create(&path)?) {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        Ok(StoredAttachment {
+            id: Uuid::new_v4().to_string(),
+            mime: mime.or_else(|| guess_mime(original_name)).map(str::to_string),
+            file_name,
+            original_name: original_name.to_string(),
+            size,
+        })
+    }
+
+    pub fn path_of(&self, file_name: &str) -> PathBuf {
+        self.dir.join(file_name)
+    }
+
+    /// Writes a file under the exact name it had in a backup, so that
+    /// `stem-attachment://` links in restored notes keep working. A file
+    /// already there is left alone: the error is `AlreadyExists`.
+    pub fn restore(&self, file_name: &str, reader: &mut impl Read) -> io::Result<u64> {
+        if !is_valid_file_name(file_name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Nom de pièce jointe invalide : {}", file_name)));
+        }
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(file_name);
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        match io::copy(reader, &mut file) {
+            Ok(size) => Ok(size),
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Best-effort cleanup of files copied by an import that was rolled back.
+    pub fn discard(&self, stored: &[StoredAttachment]) {
+        for attachment in stored {
+            let _ = fs::remove_file(self.dir.join(&attachment.file_name));
+        }
+    }
+}
+
+pub fn insert_attachment(conn: &Connection, attachment: &StoredAttachment, note_id: Option<&str>, created_at: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO attachments (id, note_id, file_name, original_name, mime, size, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            attachment.id, note_id, attachment.file_name, attachment.original_name,
+            attachment.mime, attachment.size as i64, created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve_attachment() {
+        let dir = std::env::temp_dir().join(format!("stem-attachments-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(dir.join("store"));
+        let stored = store.write_bytes(b"png", "shot.png", None).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let served = serve_attachment(&store, &attachment_uri(&stored.file_name));
+        assert_eq!(served, ProtocolResponse { status: 200, mime: "image/png", body: b"png".to_vec() });
+        let windows = format!("http://stem-attachment.localhost/{}", stored.file_name);
+        assert_eq!(serve_attachment(&store, &windows).status, 200);
+        assert_eq!(serve_attachment(&store, "stem-attachment://missing.png").status, 404);
+        assert_eq!(serve_attachment(&store, "stem-attachment://localhost/..%2Fsecret.txt").status, 400);
+        assert_eq!(serve_attachment(&store, "stem-attachment://localhost/../secret.txt").status, 400);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_restore_never_overwrites() {
+        let dir = std::env::temp_dir().join(format!("stem-attachments-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(dir.clone());
+        assert_eq!(store.restore("a-shot.png", &mut &b"png"[..]).unwrap(), 3);
+        let again = store.restore("a-shot.png", &mut &b"other"[..]).unwrap_err();
+        assert_eq!(again.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(store.path_of("a-shot.png")).unwrap(), b"png");
+        for name in ["C:evil.png", "nul\0.png", "../x.png", ".hidden"] {
+            assert_eq!(store.restore(name, &mut &b"x"[..]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
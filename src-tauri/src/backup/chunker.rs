@@ -0,0 +1,113 @@
+//! Content-defined chunking (gear hash, as in FastCDC): chunk boundaries
+//! depend on the bytes around them rather than on their offset, so an edit
+//! only changes the chunks it touches and two backups share every other one.
+
+use std::io::{self, Read};
+
+pub const MIN_CHUNK: usize = 16 * 1024;
+pub const MAX_CHUNK: usize = 256 * 1024;
+/// 16 bits: 64 KiB chunks on average past the minimum. The high bits depend on
+/// the last 64 bytes, the low ones on only the last few.
+const MASK: u64 = 0xffff << 48;
+
+/// Random values for every byte, from a fixed splitmix64 sequence: changing
+/// them would move every boundary and defeat deduplication with older backups.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Length of the chunk at the start of `data`.
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let mut hash: u64 = 0;
+    for (i, &byte) in data.iter().enumerate().take(MAX_CHUNK).skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        if hash & MASK == 0 {
+            return i + 1;
+        }
+    }
+    data.len().min(MAX_CHUNK)
+}
+
+pub struct Chunker<R> {
+    reader: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, buf: Vec::with_capacity(MAX_CHUNK), eof: false }
+    }
+
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while !self.eof && self.buf.len() < MAX_CHUNK {
+            let start = self.buf.len();
+            self.buf.resize(MAX_CHUNK, 0);
+            match self.reader.read(&mut self.buf[start..]) {
+                Ok(n) => {
+                    self.buf.truncate(start + n);
+                    self.eof = n == 0;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => self.buf.truncate(start),
+                Err(e) => return Err(e),
+            }
+        }
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let rest = self.buf.split_off(cut_point(&self.buf));
+        Ok(Some(std::mem::replace(&mut self.buf, rest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunker = Chunker::new(data);
+        std::iter::from_fn(|| chunker.next_chunk().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_edit_keeps_other_chunks() {
+        // Deterministic pseudo-random bytes
+        let mut state = 1u64;
+        let data: Vec<u8> = (0..2_000_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let before = chunks(&data);
+        assert_eq!(before.concat(), data);
+        assert!(before.len() > 8);
+        assert!(before.iter().all(|c| c.len() <= MAX_CHUNK));
+        assert!(before[..before.len() - 1].iter().all(|c| c.len() > MIN_CHUNK));
+
+        let mut edited = data.clone();
+        edited.splice(1_000_000..1_000_010, b"inserted text".iter().copied());
+        let after = chunks(&edited);
+        assert_eq!(after.concat(), edited);
+        let known: HashSet<&Vec<u8>> = before.iter().collect();
+        let new = after.iter().filter(|c| !known.contains(c)).count();
+        assert!(new <= 2, "{} new chunks", new);
+    }
+}
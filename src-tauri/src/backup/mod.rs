@@ -0,0 +1,950 @@
+//! Scheduled offsite backups to an S3-compatible store (AWS, MinIO, B2...).
+//!
+//! A backup is an uncompressed `.stem` archive cut into content-defined
+//! chunks (chunker.rs). Every chunk is compressed, encrypted with a key
+//! derived from the backup passphrase and stored once as `objects/<id>`, the
+//! id being a keyed hash of its contents: what did not change since the last
+//! backup is never uploaded again, and the store never learns plain hashes. A
+//! snapshot, `snapshots/<id>`, is the encrypted list of the chunks of one
+//! archive. `config.json`, the only plain object, holds the key derivation
+//! salt and parameters.
+//!
+//! The store address lives in the notes database; the access keys and the
+//! passphrase are kept out of it, in a file only the user can read.
+
+mod chunker;
+pub mod s3;
+
+use crate::archive::{import_archive_from, write_backup_archive, ArchiveProgress, TempFile, PROGRESS_EVENT};
+use crate::attachments::AttachmentStore;
+use crate::db::{current_timestamp, Database};
+use crate::encryption::{derive_key, validate_passphrase, KdfParams, SALT_LEN};
+use crate::error::StemError;
+use crate::restore::{ConflictStrategy, ImportDiff};
+use crate::sync::{get_setting, set_setting};
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Datelike, Local, Utc};
+use chunker::Chunker;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use s3::{hex, hmac_sha256, S3Client};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+const SETTINGS_KEY: &str = "backup";
+const LAST_BACKUP_KEY: &str = "backup-last";
+const CONFIG_OBJECT: &str = "config.json";
+const OBJECTS_DIR: &str = "objects/";
+const SNAPSHOTS_DIR: &str = "snapshots/";
+const FORMAT_VERSION: u32 = 1;
+const KEY_CHECK: &[u8] = b"stem-backup";
+const NONCE_LEN: usize = 24;
+/// Unreferenced objects younger than this may belong to a backup still
+/// running on another device, so pruning leaves them alone.
+const PRUNE_GRACE_SECS: i64 = 3600;
+/// How often the scheduler checks whether a backup is due.
+const SCHEDULER_TICK: Duration = Duration::from_secs(10 * 60);
+/// Emitted with a `ScheduledBackup` after every scheduled backup.
+pub const BACKUP_EVENT: &str = "offsite-backup";
+
+fn json_error(e: serde_json::Error) -> StemError {
+    StemError::Backup(format!("Sauvegarde distante invalide : {}", e))
+}
+
+// ===== Settings =====
+
+/// Which snapshots survive a backup; the newest one always does.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Most recent snapshots kept.
+    pub keep_last: u32,
+    /// Days, newest first, whose last snapshot is kept.
+    pub keep_daily: u32,
+    /// Same per ISO week.
+    pub keep_weekly: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self { keep_last: 3, keep_daily: 7, keep_weekly: 4 }
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_interval() -> u32 {
+    24
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// e.g. `https://s3.eu-west-3.amazonaws.com`, or `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Key prefix of the backups inside the bucket.
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    /// Hours between scheduled backups; 0 only backs up on demand.
+    #[serde(default = "default_interval")]
+    pub interval_hours: u32,
+}
+
+impl BackupConfig {
+    fn validate(&self) -> Result<(), StemError> {
+        if !self.endpoint.starts_with("https://") && !self.endpoint.starts_with("http://") {
+            return Err(StemError::Validation("L'adresse S3 doit commencer par https:// ou http://".to_string()));
+        }
+        if self.bucket.trim().is_empty() || self.region.trim().is_empty() {
+            return Err(StemError::Validation("Le bucket et la région sont obligatoires".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Object key of `path` under the prefix.
+    fn key(&self, path: &str) -> String {
+        match self.prefix.trim_matches('/') {
+            "" => path.to_string(),
+            prefix => format!("{}/{}", prefix, path),
+        }
+    }
+}
+
+/// Never stored in the database nor sent back to the app.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BackupCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Encrypts the backups: they cannot be restored without it.
+    pub passphrase: String,
+}
+
+fn load_config(db: &Database) -> Result<Option<BackupConfig>, StemError> {
+    let conn = db.try_connection()?;
+    get_setting(&conn, SETTINGS_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| StemError::Validation(e.to_string())))
+        .transpose()
+}
+
+fn last_backup_at(db: &Database) -> Result<Option<i64>, StemError> {
+    Ok(get_setting(&*db.try_connection()?, LAST_BACKUP_KEY)?.and_then(|value| value.parse().ok()))
+}
+
+/// Writes a secret next to `path` with owner-only permissions, then renames it into place.
+pub(crate) fn write_private(path: &Path, bytes: &[u8]) -> Result<(), StemError> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let _ = fs::remove_file(&partial);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&partial)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Directory holding the credentials file and the archives being uploaded or
+/// restored, managed as Tauri state; the lock keeps backups sequential.
+#[derive(Debug, Clone)]
+pub struct OffsiteBackup {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl OffsiteBackup {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Arc::new(Mutex::new(())) }
+    }
+
+    fn credentials_path(&self) -> PathBuf {
+        self.dir.join("credentials.json")
+    }
+
+    fn load_credentials(&self) -> Result<Option<BackupCredentials>, StemError> {
+        match fs::read(self.credentials_path()) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| StemError::Validation(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_credentials(&self, credentials: &BackupCredentials) -> Result<(), StemError> {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_vec(credentials).map_err(|e| StemError::Validation(e.to_string()))?;
+        write_private(&self.credentials_path(), &json)
+    }
+
+    fn work_file(&self) -> Result<TempFile, StemError> {
+        fs::create_dir_all(&self.dir)?;
+        Ok(TempFile(self.dir.join(format!("{}.stem.part", Uuid::new_v4()))))
+    }
+
+    fn guard(&self) -> Result<std::sync::MutexGuard<'_, ()>, StemError> {
+        self.lock.lock().map_err(|_| StemError::Backup("Sauvegarde interrompue".to_string()))
+    }
+}
+
+// ===== Repository =====
+
+#[derive(Serialize, Deserialize)]
+struct RepositoryConfig {
+    version: u32,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    /// Base64 salt of the passphrase.
+    salt: String,
+    /// `KEY_CHECK` sealed with the derived key, to tell a wrong passphrase apart.
+    check: String,
+}
+
+/// Keys of a repository, derived from the passphrase.
+struct Keys {
+    id: Zeroizing<[u8; 32]>,
+    cipher: XChaCha20Poly1305,
+}
+
+impl Keys {
+    fn derive(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Self, StemError> {
+        let master = derive_key(passphrase, salt, params)?;
+        let id = Zeroizing::new(hmac_sha256(master.as_ref(), b"stem-backup object id"));
+        let encryption = Zeroizing::new(hmac_sha256(master.as_ref(), b"stem-backup encryption"));
+        Ok(Self { id, cipher: XChaCha20Poly1305::new(encryption.as_ref().into()) })
+    }
+
+    fn object_id(&self, plaintext: &[u8]) -> String {
+        hex(&hmac_sha256(self.id.as_ref(), plaintext))
+    }
+
+    /// Compresses and encrypts `plaintext`; `path`, the object's place in the
+    /// repository, is authenticated so objects cannot be swapped.
+    fn seal(&self, path: &str, plaintext: &[u8]) -> Result<Vec<u8>, StemError> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(plaintext)?;
+        let compressed = encoder.finish()?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: &compressed, aad: path.as_bytes() })
+            .map_err(|_| StemError::Backup("Échec du chiffrement".to_string()))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    fn open(&self, path: &str, sealed: &[u8]) -> Result<Vec<u8>, StemError> {
+        let corrupt = || StemError::Backup(format!("{} : objet illisible ou corrompu", path));
+        if sealed.len() < NONCE_LEN {
+            return Err(corrupt());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let compressed = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: path.as_bytes() })
+            .map_err(|_| corrupt())?;
+        let mut plaintext = Vec::new();
+        DeflateDecoder::new(&compressed[..]).read_to_end(&mut plaintext).map_err(|_| corrupt())?;
+        Ok(plaintext)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotManifest {
+    version: u32,
+    created_at: i64,
+    /// Archive size in bytes.
+    size: u64,
+    notes: u32,
+    folders: u32,
+    attachments: u32,
+    /// Object ids, in archive order.
+    chunks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub created_at: i64,
+    pub size: u64,
+    pub notes: u32,
+    pub folders: u32,
+    pub attachments: u32,
+}
+
+impl SnapshotInfo {
+    fn new(id: &str, manifest: &SnapshotManifest) -> Self {
+        Self {
+            id: id.to_string(),
+            created_at: manifest.created_at,
+            size: manifest.size,
+            notes: manifest.notes,
+            folders: manifest.folders,
+            attachments: manifest.attachments,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+    pub snapshot: SnapshotInfo,
+    pub chunks: usize,
+    /// Chunks that were not in the store yet.
+    pub uploaded_chunks: usize,
+    pub uploaded_bytes: u64,
+    /// Snapshots dropped by the retention policy.
+    pub removed_snapshots: Vec<String>,
+    /// Objects no remaining snapshot uses any more.
+    pub removed_objects: usize,
+}
+
+/// Ids of the snapshots to keep; `snapshots` come newest first.
+fn snapshots_to_keep(snapshots: &[SnapshotInfo], policy: &RetentionPolicy) -> HashSet<String> {
+    let mut keep: HashSet<String> = snapshots.iter().take(policy.keep_last.max(1) as usize).map(|s| s.id.clone()).collect();
+    let local = |timestamp: i64| DateTime::from_timestamp(timestamp, 0).unwrap_or_default().with_timezone(&Local);
+    let buckets: [(u32, &dyn Fn(i64) -> String); 2] = [
+        (policy.keep_daily, &|t| local(t).format("%Y-%m-%d").to_string()),
+        (policy.keep_weekly, &|t| {
+            let week = local(t).iso_week();
+            format!("{}-W{}", week.year(), week.week())
+        }),
+    ];
+    for (count, bucket) in buckets {
+        let mut seen = HashSet::new();
+        for snapshot in snapshots {
+            if seen.len() == count as usize {
+                break;
+            }
+            if seen.insert(bucket(snapshot.created_at)) {
+                keep.insert(snapshot.id.clone());
+            }
+        }
+    }
+    keep
+}
+
+struct Repository<'a> {
+    client: S3Client,
+    config: &'a BackupConfig,
+    keys: Keys,
+}
+
+impl<'a> Repository<'a> {
+    /// Opens the repository under the configured prefix, creating it on first use.
+    fn open(config: &'a BackupConfig, credentials: &BackupCredentials) -> Result<Self, StemError> {
+        config.validate()?;
+        let client = S3Client::new(config, credentials)?;
+        let invalid = || StemError::Backup(format!("{} invalide", CONFIG_OBJECT));
+        let keys = match client.get(&config.key(CONFIG_OBJECT))? {
+            Some(bytes) => {
+                let repository: RepositoryConfig = serde_json::from_slice(&bytes).map_err(json_error)?;
+                if repository.version > FORMAT_VERSION {
+                    return Err(StemError::Backup("Sauvegardes créées par une version plus récente de Stem".to_string()));
+                }
+                let params = KdfParams {
+                    memory_kib: repository.memory_kib,
+                    iterations: repository.iterations,
+                    parallelism: repository.parallelism,
+                };
+                params.validate()?;
+                let salt = B64.decode(&repository.salt).map_err(|_| invalid())?;
+                let keys = Keys::derive(&credentials.passphrase, &salt, params)?;
+                let check = B64.decode(&repository.check).map_err(|_| invalid())?;
+                if keys.open(CONFIG_OBJECT, &check).ok().as_deref() != Some(KEY_CHECK) {
+                    return Err(StemError::Validation("Phrase de passe de sauvegarde incorrecte".to_string()));
+                }
+                keys
+            }
+            None => {
+                validate_passphrase(&credentials.passphrase)?;
+                let params = KdfParams::default();
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let keys = Keys::derive(&credentials.passphrase, &salt, params)?;
+                let repository = RepositoryConfig {
+                    version: FORMAT_VERSION,
+                    memory_kib: params.memory_kib,
+                    iterations: params.iterations,
+                    parallelism: params.parallelism,
+                    salt: B64.encode(&salt),
+                    check: B64.encode(keys.seal(CONFIG_OBJECT, KEY_CHECK)?),
+                };
+                let json = serde_json::to_vec_pretty(&repository).map_err(|e| StemError::Validation(e.to_string()))?;
+                client.put(&config.key(CONFIG_OBJECT), &json)?;
+                keys
+            }
+        };
+        Ok(Self { client, config, keys })
+    }
+
+    fn put_sealed(&self, path: &str, plaintext: &[u8]) -> Result<u64, StemError> {
+        let sealed = self.keys.seal(path, plaintext)?;
+        self.client.put(&self.config.key(path), &sealed)?;
+        Ok(sealed.len() as u64)
+    }
+
+    fn get_sealed(&self, path: &str) -> Result<Option<Vec<u8>>, StemError> {
+        self.client.get(&self.config.key(path))?.map(|sealed| self.keys.open(path, &sealed)).transpose()
+    }
+
+    /// Names of the objects under `dir`, with their modification time.
+    fn list(&self, dir: &str) -> Result<Vec<(String, Option<i64>)>, StemError> {
+        let prefix = self.config.key(dir);
+        Ok(self
+            .client
+            .list(&prefix)?
+            .into_iter()
+            .filter_map(|object| Some((object.key.strip_prefix(&prefix)?.to_string(), object.last_modified)))
+            .filter(|(name, _)| !name.is_empty() && !name.contains('/'))
+            .collect())
+    }
+
+    fn manifest(&self, id: &str) -> Result<SnapshotManifest, StemError> {
+        let bytes = self
+            .get_sealed(&format!("{}{}", SNAPSHOTS_DIR, id))?
+            .ok_or_else(|| StemError::NotFound(format!("Sauvegarde {}", id)))?;
+        serde_json::from_slice(&bytes).map_err(json_error)
+    }
+
+    /// Every snapshot, newest first: ids start with their UTC creation time.
+    fn snapshots(&self) -> Result<Vec<(SnapshotInfo, SnapshotManifest)>, StemError> {
+        let mut ids: Vec<String> = self.list(SNAPSHOTS_DIR)?.into_iter().map(|(name, _)| name).collect();
+        ids.sort_by(|a, b| b.cmp(a));
+        ids.iter()
+            .map(|id| {
+                let manifest = self.manifest(id)?;
+                Ok((SnapshotInfo::new(id, &manifest), manifest))
+            })
+            .collect()
+    }
+
+    fn backup(&self, db: &Database, store: &AttachmentStore, work: &OffsiteBackup) -> Result<BackupReport, StemError> {
+        let mut known: HashSet<String> = self.list(OBJECTS_DIR)?.into_iter().map(|(name, _)| name).collect();
+        let archive = work.work_file()?;
+        let written = write_backup_archive(db, store, &archive.0)?;
+        let mut manifest = SnapshotManifest {
+            version: FORMAT_VERSION,
+            created_at: written.created_at,
+            size: 0,
+            notes: written.notes,
+            folders: written.folders,
+            attachments: written.attachments,
+            chunks: Vec::new(),
+        };
+        let (mut uploaded_chunks, mut uploaded_bytes) = (0, 0);
+        let mut chunker = Chunker::new(BufReader::new(File::open(&archive.0)?));
+        while let Some(chunk) = chunker.next_chunk()? {
+            let id = self.keys.object_id(&chunk);
+            manifest.size += chunk.len() as u64;
+            // Chunks left by an interrupted backup are reused like any other
+            if known.insert(id.clone()) {
+                uploaded_bytes += self.put_sealed(&format!("{}{}", OBJECTS_DIR, id), &chunk)?;
+                uploaded_chunks += 1;
+            }
+            manifest.chunks.push(id);
+        }
+        drop(archive);
+
+        let id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"), &Uuid::new_v4().simple().to_string()[..8]);
+        let json = serde_json::to_vec(&manifest).map_err(|e| StemError::Validation(e.to_string()))?;
+        self.put_sealed(&format!("{}{}", SNAPSHOTS_DIR, id), &json)?;
+        let (removed_snapshots, removed_objects) = self.prune()?;
+        Ok(BackupReport {
+            snapshot: SnapshotInfo::new(&id, &manifest),
+            chunks: manifest.chunks.len(),
+            uploaded_chunks,
+            uploaded_bytes,
+            removed_snapshots,
+            removed_objects,
+        })
+    }
+
+    /// Applies the retention policy, then deletes the objects no snapshot uses.
+    fn prune(&self) -> Result<(Vec<String>, usize), StemError> {
+        let snapshots = self.snapshots()?;
+        let infos: Vec<SnapshotInfo> = snapshots.iter().map(|(info, _)| info.clone()).collect();
+        let keep = snapshots_to_keep(&infos, &self.config.retention);
+        let mut removed = Vec::new();
+        let mut used = HashSet::new();
+        for (info, manifest) in &snapshots {
+            if keep.contains(&info.id) {
+                used.extend(manifest.chunks.iter().cloned());
+            } else {
+                self.client.delete(&self.config.key(&format!("{}{}", SNAPSHOTS_DIR, info.id)))?;
+                removed.push(info.id.clone());
+            }
+        }
+
+        let now = current_timestamp();
+        let mut removed_objects = 0;
+        for (name, modified) in self.list(OBJECTS_DIR)? {
+            if !used.contains(&name) && modified.is_none_or(|t| now - t > PRUNE_GRACE_SECS) {
+                self.client.delete(&self.config.key(&format!("{}{}", OBJECTS_DIR, name)))?;
+                removed_objects += 1;
+            }
+        }
+        Ok((removed, removed_objects))
+    }
+
+    /// Reassembles the archive of snapshot `id` at `path`.
+    fn download(&self, id: &str, path: &Path, progress: &dyn Fn(ArchiveProgress)) -> Result<(), StemError> {
+        let manifest = self.manifest(id)?;
+        let mut file = BufWriter::new(File::create(path)?);
+        let total = manifest.chunks.len() as u32;
+        for (done, chunk_id) in manifest.chunks.iter().enumerate() {
+            let chunk = self
+                .get_sealed(&format!("{}{}", OBJECTS_DIR, chunk_id))?
+                .ok_or_else(|| StemError::Backup(format!("Morceau {} absent de la sauvegarde", chunk_id)))?;
+            if self.keys.object_id(&chunk) != *chunk_id {
+                return Err(StemError::Backup(format!("Morceau {} corrompu", chunk_id)));
+            }
+            file.write_all(&chunk)?;
+            progress(ArchiveProgress { phase: "download", done: done as u32 + 1, total });
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+
+fn require_setup(db: &Database, backup: &OffsiteBackup) -> Result<(BackupConfig, BackupCredentials), StemError> {
+    let config = load_config(db)?.ok_or_else(|| StemError::Validation("Aucune sauvegarde distante configurée".to_string()))?;
+    let credentials = backup
+        .load_credentials()?
+        .ok_or_else(|| StemError::Validation("Identifiants de sauvegarde manquants".to_string()))?;
+    Ok((config, credentials))
+}
+
+fn run(db: &Database, store: &AttachmentStore, backup: &OffsiteBackup) -> Result<BackupReport, StemError> {
+    let (config, credentials) = require_setup(db, backup)?;
+    let _guard = backup.guard()?;
+    let report = Repository::open(&config, &credentials)?.backup(db, store, backup)?;
+    set_setting(&*db.try_connection()?, LAST_BACKUP_KEY, &report.snapshot.created_at.to_string())?;
+    Ok(report)
+}
+
+/// Downloads snapshot `id` and imports it like `import_archive`.
+fn restore(
+    db: &Database,
+    store: &AttachmentStore,
+    backup: &OffsiteBackup,
+    id: &str,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    progress: &dyn Fn(ArchiveProgress),
+) -> Result<ImportDiff, StemError> {
+    let (config, credentials) = require_setup(db, backup)?;
+    let _guard = backup.guard()?;
+    let archive = backup.work_file()?;
+    Repository::open(&config, &credentials)?.download(id, &archive.0, progress)?;
+    import_archive_from(db, store, &archive.0, strategy, dry_run, progress)
+}
+
+/// Backs up when scheduled backups are on and the last one is older than the interval.
+fn backup_if_due(db: &Database, store: &AttachmentStore, backup: &OffsiteBackup, now: i64) -> Result<Option<BackupReport>, StemError> {
+    let Some(config) = load_config(db)? else { return Ok(None) };
+    let due = config.interval_hours > 0
+        && last_backup_at(db)?.is_none_or(|last| now - last >= i64::from(config.interval_hours) * 3600);
+    if !due || backup.load_credentials()?.is_none() {
+        return Ok(None);
+    }
+    run(db, store, backup).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledBackup {
+    pub report: Option<BackupReport>,
+    pub error: Option<String>,
+}
+
+/// Runs scheduled backups for as long as the app is open.
+pub fn run_backup_scheduler(app: &AppHandle) {
+    loop {
+        std::thread::sleep(SCHEDULER_TICK);
+        let db = app.state::<Database>().inner().clone();
+        let store = app.state::<AttachmentStore>().inner().clone();
+        let backup = app.state::<OffsiteBackup>().inner().clone();
+        let event = match backup_if_due(&db, &store, &backup, current_timestamp()) {
+            Ok(None) => continue,
+            Ok(Some(report)) => ScheduledBackup { report: Some(report), error: None },
+            Err(e) => ScheduledBackup { report: None, error: Some(e.to_string()) },
+        };
+        let _ = app.emit(BACKUP_EVENT, event);
+    }
+}
+
+// ===== Tauri Commands =====
+
+#[derive(Debug, Serialize)]
+pub struct BackupStatus {
+    pub config: Option<BackupConfig>,
+    pub credentials_saved: bool,
+    pub last_backup_at: Option<i64>,
+}
+
+#[tauri::command]
+pub async fn get_backup_status(db: State<'_, Database>, backup: State<'_, OffsiteBackup>) -> Result<BackupStatus, StemError> {
+    let backup = backup.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        Ok(BackupStatus {
+            config: load_config(&db)?,
+            credentials_saved: backup.load_credentials()?.is_some(),
+            last_backup_at: last_backup_at(&db)?,
+        })
+    }).await
+}
+
+/// Saves the backup settings once the store accepts them. Without
+/// `credentials` the saved ones are kept; with them, they must also open the
+/// existing backups, if any.
+#[tauri::command]
+pub async fn set_backup_config(
+    db: State<'_, Database>,
+    backup: State<'_, OffsiteBackup>,
+    config: BackupConfig,
+    credentials: Option<BackupCredentials>,
+) -> Result<(), StemError> {
+    config.validate()?;
+    let backup = backup.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let credentials = match credentials {
+            Some(credentials) => credentials,
+            None => backup
+                .load_credentials()?
+                .ok_or_else(|| StemError::Validation("Identifiants de sauvegarde manquants".to_string()))?,
+        };
+        Repository::open(&config, &credentials)?;
+        backup.save_credentials(&credentials)?;
+        let json = serde_json::to_string(&config).map_err(|e| StemError::Validation(e.to_string()))?;
+        set_setting(&*db.try_connection()?, SETTINGS_KEY, &json)
+    }).await
+}
+
+/// Uploads a snapshot now, then applies the retention policy.
+#[tauri::command]
+pub async fn run_backup(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    backup: State<'_, OffsiteBackup>,
+) -> Result<BackupReport, StemError> {
+    let store = store.inner().clone();
+    let backup = backup.inner().clone();
+    db.inner().clone().spawn(move |db| run(&db, &store, &backup)).await
+}
+
+/// The snapshots available in the store, newest first.
+#[tauri::command]
+pub async fn list_backup_snapshots(db: State<'_, Database>, backup: State<'_, OffsiteBackup>) -> Result<Vec<SnapshotInfo>, StemError> {
+    let backup = backup.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let (config, credentials) = require_setup(&db, &backup)?;
+        let snapshots = Repository::open(&config, &credentials)?.snapshots()?;
+        Ok(snapshots.into_iter().map(|(info, _)| info).collect())
+    }).await
+}
+
+/// Restores snapshot `id` into the database. Same summary as `import_archive`.
+#[tauri::command]
+pub async fn restore_backup(
+    app: AppHandle,
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    backup: State<'_, OffsiteBackup>,
+    id: String,
+    strategy: Option<ConflictStrategy>,
+) -> Result<String, StemError> {
+    let store = store.inner().clone();
+    let backup = backup.inner().clone();
+    let progress_app = app.clone();
+    let summary = db.inner().clone().spawn(move |db| {
+        let progress = |p: ArchiveProgress| {
+            let _ = progress_app.emit(PROGRESS_EVENT, p);
+        };
+        Ok(restore(&db, &store, &backup, &id, strategy.unwrap_or_default(), false, &progress)?.summary)
+    }).await?;
+    let _ = app.emit("refresh-notes", ());
+    Ok(summary)
+}
+
+/// Dry run of `restore_backup`: the notes and folders it would insert, change or leave alone.
+#[tauri::command]
+pub async fn preview_restore_backup(
+    app: AppHandle,
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    backup: State<'_, OffsiteBackup>,
+    id: String,
+    strategy: Option<ConflictStrategy>,
+) -> Result<ImportDiff, StemError> {
+    let store = store.inner().clone();
+    let backup = backup.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let progress = |p: ArchiveProgress| {
+            let _ = app.emit(PROGRESS_EVENT, p);
+        };
+        restore(&db, &store, &backup, &id, strategy.unwrap_or_default(), true, &progress)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::insert_attachment;
+    use std::collections::BTreeMap;
+    use tiny_http::{Response as HttpResponse, Server};
+
+    /// Key -> contents.
+    type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    fn status(code: u16) -> HttpResponse<std::io::Cursor<Vec<u8>>> {
+        HttpResponse::from_data(Vec::new()).with_status_code(code)
+    }
+
+    fn percent_decode(value: &str) -> String {
+        let bytes = value.as_bytes();
+        let mut decoded = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' && i + 2 < bytes.len() {
+                decoded.push(u8::from_str_radix(&value[i + 1..i + 3], 16).unwrap());
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    /// Minimal S3 server: path-style PUT, GET, DELETE and ListObjectsV2 (two
+    /// keys per page) on bucket `stem`, requiring SigV4 from `test-key`.
+    /// Objects look a day old so pruning can delete them.
+    fn start_server() -> (String, Objects) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let objects: Objects = Arc::default();
+        let store = objects.clone();
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let signed = request.headers().iter().any(|h| {
+                    h.field.equiv("Authorization") && h.value.as_str().starts_with("AWS4-HMAC-SHA256 Credential=test-key/")
+                });
+                if !signed {
+                    let _ = request.respond(status(403));
+                    continue;
+                }
+                let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+                let Some(key) = path.strip_prefix("/stem") else {
+                    let _ = request.respond(status(404));
+                    continue;
+                };
+                let key = percent_decode(key.trim_start_matches('/'));
+                let params: BTreeMap<String, String> = query
+                    .split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(k, v)| (k.to_string(), percent_decode(v)))
+                    .collect();
+                let mut objects = store.lock().unwrap();
+                let response = match request.method().as_str() {
+                    "PUT" => {
+                        let mut body = Vec::new();
+                        request.as_reader().read_to_end(&mut body).unwrap();
+                        objects.insert(key, body);
+                        status(200)
+                    }
+                    "GET" if key.is_empty() => {
+                        let prefix = params.get("prefix").cloned().unwrap_or_default();
+                        let start: usize = params.get("continuation-token").map_or(0, |t| t.parse().unwrap());
+                        let matching: Vec<&String> = objects.keys().filter(|k| k.starts_with(&prefix)).collect();
+                        let page: String = matching
+                            .iter()
+                            .skip(start)
+                            .take(2)
+                            .map(|k| format!("<Contents><Key>{}</Key><LastModified>2020-01-01T00:00:00.000Z</LastModified></Contents>", k))
+                            .collect();
+                        let more = start + 2 < matching.len();
+                        let token = if more { format!("<NextContinuationToken>{}</NextContinuationToken>", start + 2) } else { String::new() };
+                        HttpResponse::from_data(
+                            format!("<ListBucketResult><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>", more, page, token)
+                                .into_bytes(),
+                        )
+                    }
+                    "GET" => match objects.get(&key) {
+                        Some(bytes) => HttpResponse::from_data(bytes.clone()),
+                        None => status(404),
+                    },
+                    "DELETE" => {
+                        objects.remove(&key);
+                        status(204)
+                    }
+                    _ => status(405),
+                };
+                drop(objects);
+                let _ = request.respond(response);
+            }
+        });
+        (url, objects)
+    }
+
+    fn setup() -> Database {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        db
+    }
+
+    fn count(objects: &Objects, prefix: &str) -> usize {
+        objects.lock().unwrap().keys().filter(|k| k.starts_with(prefix)).count()
+    }
+
+    #[test]
+    fn test_backup_dedup_retention_and_restore() {
+        let dir = std::env::temp_dir().join(format!("stem-backup-{}", Uuid::new_v4()));
+        let (url, objects) = start_server();
+        let db = setup();
+        let store = AttachmentStore::new(dir.join("attachments"));
+        let backup = OffsiteBackup::new(dir.join("backup"));
+        let config = BackupConfig {
+            endpoint: url,
+            region: default_region(),
+            bucket: "stem".to_string(),
+            prefix: "/laptop/".to_string(),
+            retention: RetentionPolicy { keep_last: 2, keep_daily: 0, keep_weekly: 0 },
+            interval_hours: 24,
+        };
+        let credentials = BackupCredentials {
+            access_key_id: "test-key".to_string(),
+            secret_access_key: "test-secret".to_string(),
+            passphrase: "correct horse battery".to_string(),
+        };
+        {
+            let conn = db.connection();
+            for i in 0..50 {
+                conn.execute(
+                    "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES (?1, ?2, ?3, 1, 1)",
+                    (format!("n{}", i), format!("Note {}", i), format!("Contenu {} ", i).repeat(200)),
+                ).unwrap();
+            }
+            // Incompressible, so it spans several chunks
+            let mut photo = vec![0u8; 600 * 1024];
+            OsRng.fill_bytes(&mut photo);
+            let stored = store.write_bytes(&photo, "photo.jpg", None).unwrap();
+            insert_attachment(&conn, &stored, Some("n1"), 1).unwrap();
+            set_setting(&conn, SETTINGS_KEY, &serde_json::to_string(&config).unwrap()).unwrap();
+        }
+        assert!(run(&db, &store, &backup).is_err(), "no credentials yet");
+        backup.save_credentials(&credentials).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(backup.credentials_path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let first = run(&db, &store, &backup).unwrap();
+        assert!(first.chunks > 2);
+        assert_eq!(first.uploaded_chunks, first.chunks);
+        assert_eq!(first.snapshot.notes, 50);
+        // Nothing readable reaches the store
+        assert!(objects.lock().unwrap().values().all(|bytes| !bytes.windows(7).any(|w| w == b"Contenu")));
+
+        // Unchanged notes and the photo are not uploaded again
+        let second = run(&db, &store, &backup).unwrap();
+        assert!(second.uploaded_chunks <= 2, "{} of {} uploaded", second.uploaded_chunks, second.chunks);
+        assert!(second.removed_snapshots.is_empty());
+
+        db.connection().execute("UPDATE notes SET content = 'Réécrite' WHERE id = 'n7'", []).unwrap();
+        let third = run(&db, &store, &backup).unwrap();
+        assert_eq!(third.removed_snapshots, vec![first.snapshot.id.clone()]);
+        assert_eq!(count(&objects, "laptop/snapshots/"), 2);
+
+        // Not due again before the interval
+        let now = current_timestamp();
+        assert!(backup_if_due(&db, &store, &backup, now).unwrap().is_none());
+        let scheduled = backup_if_due(&db, &store, &backup, now + 25 * 3600).unwrap().unwrap();
+        let referenced: HashSet<String> = {
+            let repository = Repository::open(&config, &credentials).unwrap();
+            let snapshots = repository.snapshots().unwrap();
+            assert_eq!(snapshots[0].0.id, scheduled.snapshot.id);
+            assert_eq!(snapshots.len(), 2);
+            snapshots.into_iter().flat_map(|(_, manifest)| manifest.chunks).collect()
+        };
+        assert_eq!(count(&objects, "laptop/objects/"), referenced.len());
+
+        // Restore the third snapshot on a new device
+        let target = setup();
+        let target_store = AttachmentStore::new(dir.join("target"));
+        set_setting(&target.connection(), SETTINGS_KEY, &serde_json::to_string(&config).unwrap()).unwrap();
+        let id = &third.snapshot.id;
+        let preview = restore(&target, &target_store, &backup, id, ConflictStrategy::Skip, true, &|_| {}).unwrap();
+        assert_eq!(preview.notes.new.len(), 50);
+        let summary = restore(&target, &target_store, &backup, id, ConflictStrategy::Skip, false, &|_| {}).unwrap().summary;
+        assert_eq!(summary, "50 notes, 0 dossiers importés");
+        let conn = target.connection();
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = 'n7'", [], |r| r.get(0)).unwrap();
+        assert_eq!(content, "Réécrite");
+        let file_name: String = conn.query_row("SELECT file_name FROM attachments", [], |r| r.get(0)).unwrap();
+        assert_eq!(fs::read(target_store.path_of(&file_name)).unwrap().len(), 600 * 1024);
+        drop(conn);
+        assert_eq!(fs::read_dir(dir.join("backup")).unwrap().count(), 1, "work files removed");
+
+        let wrong = BackupCredentials { passphrase: "wrong passphrase".to_string(), ..credentials.clone() };
+        assert!(matches!(Repository::open(&config, &wrong), Err(StemError::Validation(_))));
+
+        // Whoever can write to the bucket must not be able to pick the KDF cost
+        {
+            let mut objects = objects.lock().unwrap();
+            let (_, bytes) = objects.iter_mut().find(|(key, _)| key.ends_with(CONFIG_OBJECT)).unwrap();
+            let mut repository: RepositoryConfig = serde_json::from_slice(bytes).unwrap();
+            repository.memory_kib = u32::MAX;
+            *bytes = serde_json::to_vec(&repository).unwrap();
+        }
+        assert!(matches!(Repository::open(&config, &credentials), Err(StemError::Validation(m)) if m.contains("non pris en charge")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_retention_policy() {
+        let day = 86_400;
+        let snapshots: Vec<SnapshotInfo> = (0..20)
+            .map(|i| SnapshotInfo {
+                id: format!("s{:02}", i),
+                // Two snapshots a day, newest first
+                created_at: 1_700_000_000 - i * day / 2,
+                size: 0,
+                notes: 0,
+                folders: 0,
+                attachments: 0,
+            })
+            .collect();
+        let keep = snapshots_to_keep(&snapshots, &RetentionPolicy { keep_last: 1, keep_daily: 3, keep_weekly: 0 });
+        assert_eq!(keep.len(), 3);
+        assert!(keep.contains("s00"));
+        assert!(keep.iter().all(|id| id.as_str() <= "s05"));
+
+        let keep = snapshots_to_keep(&snapshots, &RetentionPolicy { keep_last: 0, keep_daily: 0, keep_weekly: 0 });
+        assert_eq!(keep, HashSet::from(["s00".to_string()]));
+        let keep = snapshots_to_keep(&snapshots, &RetentionPolicy { keep_last: 0, keep_daily: 0, keep_weekly: 2 });
+        assert_eq!(keep.len(), 2);
+    }
+}
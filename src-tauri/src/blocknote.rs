@@ -0,0 +1,1224 @@
+//! Conversion between BlockNote documents (the JSON block tree the editor
+//! stored before notes moved to Markdown) and Markdown, in both directions.
+//!
+//! Markdown is written so that it parses back to the same blocks: text is
+//! escaped, styles are nested properly, and a piece of inline content whose
+//! delimiters would not parse back falls back to HTML tags (`<strong>`,
+//! `<u>`, ...). What Markdown cannot express is dropped: colors, alignment,
+//! and children of blocks other than list items and quotes, which follow
+//! their parent instead of being nested in it.
+
+use crate::error::StemError;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+// ===== Model =====
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    kind: BlockKind,
+    children: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockKind {
+    Paragraph(Vec<Inline>),
+    Heading(u8, Vec<Inline>),
+    Bullet(Vec<Inline>),
+    Numbered(Vec<Inline>),
+    Check(bool, Vec<Inline>),
+    Quote(Vec<Inline>),
+    Code { language: String, code: String },
+    /// Rows of cells; the first row is the header in Markdown.
+    Table(Vec<Vec<Vec<Inline>>>),
+    Image { url: String, caption: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inline {
+    Text(Run),
+    Link { href: String, content: Vec<Run> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    text: String,
+    styles: Styles,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Styles {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    underline: bool,
+    code: bool,
+}
+
+/// Appends text to `runs`, extending the last run when it has the same styles.
+fn push_run(runs: &mut Vec<Run>, text: &str, styles: Styles) {
+    if text.is_empty() {
+        return;
+    }
+    match runs.last_mut() {
+        Some(last) if last.styles == styles => last.text.push_str(text),
+        _ => runs.push(Run { text: text.to_string(), styles }),
+    }
+}
+
+fn push_text(inlines: &mut Vec<Inline>, text: &str, styles: Styles) {
+    if text.is_empty() {
+        return;
+    }
+    match inlines.last_mut() {
+        Some(Inline::Text(last)) if last.styles == styles => last.text.push_str(text),
+        _ => inlines.push(Inline::Text(Run { text: text.to_string(), styles })),
+    }
+}
+
+// ===== BlockNote JSON =====
+
+fn flag(value: Option<&Value>, key: &str) -> bool {
+    value.and_then(|v| v.get(key)).and_then(Value::as_bool).unwrap_or(false)
+}
+
+fn prop_str(block: &Value, key: &str) -> String {
+    block.get("props").and_then(|p| p.get(key)).and_then(Value::as_str).unwrap_or("").to_string()
+}
+
+fn runs_from_json(content: Option<&Value>, runs: &mut Vec<Run>) {
+    match content {
+        Some(Value::String(text)) => push_run(runs, text, Styles::default()),
+        Some(Value::Array(items)) => {
+            for item in items {
+                let styles = item.get("styles");
+                let styles = Styles {
+                    bold: flag(styles, "bold"),
+                    italic: flag(styles, "italic"),
+                    strike: flag(styles, "strike") || flag(styles, "strikethrough"),
+                    underline: flag(styles, "underline"),
+                    code: flag(styles, "code"),
+                };
+                match item.get("text").and_then(Value::as_str) {
+                    Some(text) => push_run(runs, text, styles),
+                    None => runs_from_json(item.get("content"), runs),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn inlines_from_json(content: Option<&Value>) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    let items = match content {
+        Some(Value::Array(items)) => items.as_slice(),
+        Some(Value::String(text)) => {
+            push_text(&mut inlines, text, Styles::default());
+            return inlines;
+        }
+        _ => return inlines,
+    };
+    for item in items {
+        if item.get("type").and_then(Value::as_str) == Some("link") {
+            let mut content = Vec::new();
+            runs_from_json(item.get("content"), &mut content);
+            let href = item.get("href").and_then(Value::as_str).unwrap_or("").to_string();
+            inlines.push(Inline::Link { href, content });
+        } else {
+            let mut runs = Vec::new();
+            runs_from_json(Some(&Value::Array(vec![item.clone()])), &mut runs);
+            for run in runs {
+                push_text(&mut inlines, &run.text, run.styles);
+            }
+        }
+    }
+    inlines
+}
+
+fn table_from_json(content: Option<&Value>) -> Vec<Vec<Vec<Inline>>> {
+    let rows = content.and_then(|c| c.get("rows")).and_then(Value::as_array);
+    rows.into_iter()
+        .flatten()
+        .map(|row| {
+            let cells = row.get("cells").and_then(Value::as_array);
+            cells
+                .into_iter()
+                .flatten()
+                // Cells are inline arrays, or `tableCell` objects in recent versions
+                .map(|cell| match cell.get("content") {
+                    Some(content) => inlines_from_json(Some(content)),
+                    None => inlines_from_json(Some(cell)),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn block_from_json(block: &Value) -> Block {
+    let content = block.get("content");
+    let kind = match block.get("type").and_then(Value::as_str).unwrap_or("paragraph") {
+        "heading" => {
+            let level = block.get("props").and_then(|p| p.get("level")).and_then(Value::as_u64).unwrap_or(1);
+            BlockKind::Heading(level.clamp(1, 6) as u8, inlines_from_json(content))
+        }
+        "bulletListItem" | "toggleListItem" => BlockKind::Bullet(inlines_from_json(content)),
+        "numberedListItem" => BlockKind::Numbered(inlines_from_json(content)),
+        "checkListItem" => BlockKind::Check(flag(block.get("props"), "checked"), inlines_from_json(content)),
+        "quote" => BlockKind::Quote(inlines_from_json(content)),
+        "codeBlock" => {
+            let mut runs = Vec::new();
+            runs_from_json(content, &mut runs);
+            BlockKind::Code { language: prop_str(block, "language"), code: runs.into_iter().map(|r| r.text).collect() }
+        }
+        "table" => BlockKind::Table(table_from_json(content)),
+        "image" => BlockKind::Image { url: prop_str(block, "url"), caption: prop_str(block, "caption") },
+        "video" | "audio" | "file" => {
+            let (url, name) = (prop_str(block, "url"), prop_str(block, "name"));
+            let label = if name.is_empty() { url.clone() } else { name };
+            BlockKind::Paragraph(vec![Inline::Link { href: url, content: vec![Run { text: label, styles: Styles::default() }] }])
+        }
+        _ => BlockKind::Paragraph(inlines_from_json(content)),
+    };
+    let children = block.get("children").and_then(Value::as_array).map(|c| c.iter().map(block_from_json).collect());
+    Block { kind, children: children.unwrap_or_default() }
+}
+
+fn runs_to_json(runs: &[Run]) -> Vec<Value> {
+    runs.iter()
+        .map(|run| {
+            let mut styles = Map::new();
+            let s = run.styles;
+            for (key, on) in [("bold", s.bold), ("italic", s.italic), ("underline", s.underline), ("strike", s.strike), ("code", s.code)] {
+                if on {
+                    styles.insert(key.to_string(), Value::Bool(true));
+                }
+            }
+            json!({ "type": "text", "text": run.text, "styles": styles })
+        })
+        .collect()
+}
+
+fn inlines_to_json(inlines: &[Inline]) -> Value {
+    let items = inlines.iter().flat_map(|inline| match inline {
+        Inline::Text(run) => runs_to_json(std::slice::from_ref(run)),
+        Inline::Link { href, content } => vec![json!({ "type": "link", "href": href, "content": runs_to_json(content) })],
+    });
+    Value::Array(items.collect())
+}
+
+fn block_to_json(block: &Block) -> Value {
+    let text_props = || json!({ "textColor": "default", "backgroundColor": "default", "textAlignment": "left" });
+    let with = |mut props: Value, key: &str, value: Value| {
+        props[key] = value;
+        props
+    };
+    let (kind, props, content) = match &block.kind {
+        BlockKind::Paragraph(inlines) => ("paragraph", text_props(), inlines_to_json(inlines)),
+        BlockKind::Heading(level, inlines) => ("heading", with(text_props(), "level", json!(level)), inlines_to_json(inlines)),
+        BlockKind::Bullet(inlines) => ("bulletListItem", text_props(), inlines_to_json(inlines)),
+        BlockKind::Numbered(inlines) => ("numberedListItem", text_props(), inlines_to_json(inlines)),
+        BlockKind::Check(checked, inlines) => ("checkListItem", with(text_props(), "checked", json!(checked)), inlines_to_json(inlines)),
+        BlockKind::Quote(inlines) => ("quote", json!({ "textColor": "default", "backgroundColor": "default" }), inlines_to_json(inlines)),
+        BlockKind::Code { language, code } => {
+            let content = if code.is_empty() { json!([]) } else { json!([{ "type": "text", "text": code, "styles": {} }]) };
+            ("codeBlock", json!({ "language": language }), content)
+        }
+        BlockKind::Table(rows) => {
+            let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let rows: Vec<Value> = rows
+                .iter()
+                .map(|row| {
+                    let cells: Vec<Value> = row
+                        .iter()
+                        .map(|cell| {
+                            json!({
+                                "type": "tableCell",
+                                "props": { "backgroundColor": "default", "textColor": "default", "textAlignment": "left", "colspan": 1, "rowspan": 1 },
+                                "content": inlines_to_json(cell),
+                            })
+                        })
+                        .collect();
+                    json!({ "cells": cells })
+                })
+                .collect();
+            let content = json!({ "type": "tableContent", "columnWidths": vec![Value::Null; columns], "rows": rows });
+            ("table", json!({ "textColor": "default" }), content)
+        }
+        BlockKind::Image { url, caption } => {
+            let props = json!({
+                "backgroundColor": "default", "textAlignment": "left", "name": "",
+                "url": url, "caption": caption, "showPreview": true, "previewWidth": 512,
+            });
+            ("image", props, Value::Null)
+        }
+    };
+    let mut value = json!({
+        "id": Uuid::new_v4().to_string(),
+        "type": kind,
+        "props": props,
+        "children": block.children.iter().map(block_to_json).collect::<Vec<_>>(),
+    });
+    if !content.is_null() {
+        value["content"] = content;
+    }
+    value
+}
+
+// ===== Markdown output =====
+
+/// Where inline content goes: a paragraph (line breaks allowed), or a
+/// heading or table cell that must stay on one line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Paragraph,
+    Heading,
+    Cell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Strike,
+    Bold,
+    Italic,
+    Underline,
+}
+
+impl Mark {
+    const ALL: [Mark; 4] = [Mark::Strike, Mark::Bold, Mark::Italic, Mark::Underline];
+
+    fn is_set(self, styles: Styles) -> bool {
+        match self {
+            Mark::Strike => styles.strike,
+            Mark::Bold => styles.bold,
+            Mark::Italic => styles.italic,
+            Mark::Underline => styles.underline,
+        }
+    }
+
+    fn delimiters(self, html: bool) -> (&'static str, &'static str) {
+        match (self, html) {
+            (Mark::Strike, false) => ("~~", "~~"),
+            (Mark::Bold, false) => ("**", "**"),
+            (Mark::Italic, false) => ("*", "*"),
+            (Mark::Strike, true) => ("<del>", "</del>"),
+            (Mark::Bold, true) => ("<strong>", "</strong>"),
+            (Mark::Italic, true) => ("<em>", "</em>"),
+            (Mark::Underline, _) => ("<u>", "</u>"),
+        }
+    }
+}
+
+/// Backslash-escapes what Markdown would otherwise read as syntax. Wiki links
+/// (`[[Note]]`) keep their brackets so that they still work as links.
+fn escape_text(text: &str, keep_wiki_links: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut wiki_end = 0;
+    for (i, &c) in chars.iter().enumerate() {
+        if keep_wiki_links && i >= wiki_end && c == '[' && chars.get(i + 1) == Some(&'[') {
+            let inner = chars[i + 2..].iter().take_while(|&&c| c != ']' && c != '[' && c != '\n').count();
+            if inner > 0 && chars.get(i + 2 + inner) == Some(&']') && chars.get(i + 3 + inner) == Some(&']') {
+                wiki_end = i + 4 + inner;
+            }
+        }
+        let in_wiki = i < wiki_end;
+        let escape = match c {
+            '\\' | '*' | '`' | '<' | '~' => true,
+            '[' | ']' => !in_wiki,
+            // `[[Note]](x)` would be a link
+            '(' => i == wiki_end && i > 0,
+            // Intraword underscores cannot start or end emphasis
+            '_' => !(i > 0 && chars[i - 1].is_alphanumeric() && chars.get(i + 1).is_some_and(|n| n.is_alphanumeric())),
+            '&' => {
+                let rest: String = chars[i + 1..].iter().take(33).collect();
+                let name = rest.strip_prefix('#').unwrap_or(&rest);
+                let len = name.chars().take_while(char::is_ascii_alphanumeric).count();
+                len > 0 && name[len..].starts_with(';')
+            }
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn code_span(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    let padded = text.starts_with('`')
+        || text.ends_with('`')
+        || (text.starts_with(' ') && text.ends_with(' ') && !text.chars().all(|c| c == ' '));
+    let pad = if padded { " " } else { "" };
+    format!("{fence}{pad}{text}{pad}{fence}")
+}
+
+fn link_destination(href: &str) -> String {
+    if href.is_empty() || href.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        let mut out = String::from("<");
+        for c in href.chars() {
+            if matches!(c, '<' | '>' | '\\' | '&') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('>');
+        return out;
+    }
+    let mut out = String::new();
+    for c in href.chars() {
+        if matches!(c, '(' | ')' | '<' | '>' | '\\' | '&') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Renders runs with their styles, moving the whitespace at the edges of a
+/// styled run outside its delimiters, where they are valid.
+struct InlineWriter {
+    out: String,
+    open: Vec<Mark>,
+    pending_space: String,
+    html: bool,
+    keep_wiki_links: bool,
+}
+
+impl InlineWriter {
+    fn close_to(&mut self, keep: usize) {
+        while self.open.len() > keep {
+            let mark = self.open.pop().unwrap_or(Mark::Bold);
+            self.out.push_str(mark.delimiters(self.html).1);
+        }
+    }
+
+    fn flush_space(&mut self) {
+        let space = std::mem::take(&mut self.pending_space);
+        self.out.push_str(&escape_text(&space, false));
+    }
+
+    fn write_runs(&mut self, runs: &[Run]) {
+        for (i, run) in runs.iter().enumerate() {
+            let core = run.text.trim_matches(char::is_whitespace);
+            if core.is_empty() {
+                self.pending_space.push_str(&run.text);
+                continue;
+            }
+            let start = run.text.len() - run.text.trim_start_matches(char::is_whitespace).len();
+            let (lead, trail) = (&run.text[..start], &run.text[start + core.len()..]);
+
+            let keep = self.open.iter().take_while(|m| m.is_set(run.styles)).count();
+            self.close_to(keep);
+            self.pending_space.push_str(lead);
+            self.flush_space();
+            // Marks that last longer open first, so that they close last
+            let lasting = |mark: Mark| runs[i..].iter().take_while(|r| mark.is_set(r.styles) || r.text.trim().is_empty()).count();
+            let mut opening: Vec<Mark> = Mark::ALL.into_iter().filter(|m| m.is_set(run.styles) && !self.open.contains(m)).collect();
+            opening.sort_by_key(|m| std::cmp::Reverse(lasting(*m)));
+            for mark in opening {
+                self.out.push_str(mark.delimiters(self.html).0);
+                self.open.push(mark);
+            }
+            if run.styles.code {
+                let spans: Vec<String> = core.split('\n').map(|line| if line.is_empty() { String::new() } else { code_span(line) }).collect();
+                self.out.push_str(&spans.join("\n"));
+            } else {
+                self.out.push_str(&escape_text(core, self.keep_wiki_links));
+            }
+            self.pending_space.push_str(trail);
+        }
+    }
+
+    /// Expects normalized content: two code spans side by side would read as one.
+    fn write(mut self, inlines: &[Inline]) -> String {
+        let mut i = 0;
+        while i < inlines.len() {
+            match &inlines[i] {
+                Inline::Link { href, content } => {
+                    self.close_to(0);
+                    self.flush_space();
+                    // `!` right before the link would make it an image
+                    if self.out.ends_with('!') {
+                        self.out.pop();
+                        self.out.push_str("\\!");
+                    }
+                    let mut label = InlineWriter { out: String::new(), open: Vec::new(), pending_space: String::new(), html: self.html, keep_wiki_links: false };
+                    label.write_runs(content);
+                    label.close_to(0);
+                    label.flush_space();
+                    self.out.push_str(&format!("[{}]({})", label.out, link_destination(href)));
+                    i += 1;
+                }
+                Inline::Text(_) => {
+                    let mut runs = Vec::new();
+                    while let Some(Inline::Text(run)) = inlines.get(i) {
+                        runs.push(run.clone());
+                        i += 1;
+                    }
+                    self.write_runs(&runs);
+                }
+            }
+        }
+        self.close_to(0);
+        self.flush_space();
+        self.out
+    }
+}
+
+/// Spaces and tabs at the edges of a line would be trimmed: keep them as entities.
+fn protect_edges(line: &str) -> String {
+    let entity = |c: char| if c == '\t' { "&#9;".to_string() } else { "&#32;".to_string() };
+    let is_blank = |c: char| c == ' ' || c == '\t';
+    let body = line.trim_matches(is_blank);
+    if body.is_empty() {
+        return line.chars().map(entity).collect();
+    }
+    let start = line.len() - line.trim_start_matches(is_blank).len();
+    let lead: String = line[..start].chars().map(entity).collect();
+    let trail: String = line[start + body.len()..].chars().map(entity).collect();
+    format!("{lead}{body}{trail}")
+}
+
+/// Escapes what would start another block at the beginning of a line.
+fn protect_line_start(line: &str) -> String {
+    match line.chars().next() {
+        Some('#' | '>' | '-' | '+' | '=' | '|') => format!("\\{}", line),
+        Some(c) if c.is_ascii_digit() => {
+            let digits = line.chars().take_while(char::is_ascii_digit).count();
+            match line[digits..].chars().next() {
+                Some('.' | ')') => format!("{}\\{}", &line[..digits], &line[digits..]),
+                _ => line.to_string(),
+            }
+        }
+        _ => line.to_string(),
+    }
+}
+
+fn layout_inline(raw: &str, context: Context) -> String {
+    let lines: Vec<String> = raw.split('\n').map(protect_edges).collect();
+    let text = if context == Context::Paragraph && lines.iter().all(|l| !l.is_empty()) {
+        lines.iter().map(|l| protect_line_start(l)).collect::<Vec<_>>().join("\\\n")
+    } else {
+        // Breaks a backslash cannot express (on an empty line, or in a heading or cell)
+        let joined = lines.join("<br>");
+        if context == Context::Paragraph { protect_line_start(&joined) } else { joined }
+    };
+    match context {
+        Context::Cell => text.replace('|', "\\|"),
+        Context::Heading if text.ends_with('#') => format!("{}\\#", &text[..text.len() - 1]),
+        _ => text,
+    }
+}
+
+/// Inline Markdown for `inlines`, with HTML tags for the styles when the
+/// Markdown delimiters would not parse back to the same content.
+fn inline_markdown(inlines: &[Inline], context: Context) -> String {
+    let inlines = normalize_inlines(inlines);
+    let render = |html: bool| {
+        let writer = InlineWriter { out: String::new(), open: Vec::new(), pending_space: String::new(), html, keep_wiki_links: true };
+        layout_inline(&writer.write(&inlines), context)
+    };
+    let markdown = render(false);
+    if normalize_inlines(&parse_inline_fragment(&markdown, context)) == inlines {
+        markdown
+    } else {
+        render(true)
+    }
+}
+
+fn indent(text: &str, prefix: &str) -> String {
+    text.split('\n')
+        .map(|line| if line.is_empty() { String::new() } else { format!("{prefix}{line}") })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_list_item(kind: &BlockKind) -> bool {
+    matches!(kind, BlockKind::Bullet(_) | BlockKind::Numbered(_) | BlockKind::Check(..))
+}
+
+/// Markdown of a list item, its children indented under it.
+fn list_item(marker: &str, content: &[Inline], children: &[Block]) -> String {
+    let width = if marker.starts_with('-') { 2 } else { marker.len() };
+    let pad = " ".repeat(width);
+    let text = inline_markdown(content, Context::Paragraph);
+    let mut out = format!("{}{}", marker, indent(&text, &pad).trim_start());
+    if out.ends_with(' ') {
+        out.pop();
+    }
+    if !children.is_empty() {
+        let separator = if is_list_item(&children[0].kind) { "\n" } else { "\n\n" };
+        out.push_str(separator);
+        out.push_str(&indent(&blocks_markdown(children), &pad));
+    }
+    out
+}
+
+fn code_fence(language: &str, code: &str) -> String {
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let language: String = language.chars().filter(|c| !c.is_whitespace() && *c != '`').collect();
+    if code.is_empty() {
+        format!("{fence}{language}\n{fence}")
+    } else {
+        format!("{fence}{language}\n{code}\n{fence}")
+    }
+}
+
+fn table_markdown(rows: &[Vec<Vec<Inline>>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let row_line = |row: &Vec<Vec<Inline>>| {
+        let cells: Vec<String> = (0..columns)
+            .map(|i| row.get(i).map(|cell| inline_markdown(cell, Context::Cell)).unwrap_or_default())
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![row_line(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows[1..].iter().map(row_line));
+    lines.join("\n")
+}
+
+/// Markdown of one block, or `None` when there is nothing to write.
+fn block_markdown(block: &Block, number: usize) -> Option<String> {
+    let flat_children = |text: String| {
+        if block.children.is_empty() {
+            text
+        } else {
+            [text, blocks_markdown(&block.children)].join("\n\n")
+        }
+    };
+    let text = match &block.kind {
+        BlockKind::Paragraph(content) => {
+            let text = inline_markdown(content, Context::Paragraph);
+            if text.is_empty() && block.children.is_empty() {
+                return None;
+            }
+            if text.is_empty() { blocks_markdown(&block.children) } else { flat_children(text) }
+        }
+        BlockKind::Heading(level, content) => {
+            let text = inline_markdown(content, Context::Heading);
+            let hashes = "#".repeat(*level as usize);
+            flat_children(if text.is_empty() { hashes } else { format!("{hashes} {text}") })
+        }
+        BlockKind::Bullet(content) => list_item("- ", content, &block.children),
+        BlockKind::Check(checked, content) => list_item(if *checked { "- [x] " } else { "- [ ] " }, content, &block.children),
+        BlockKind::Numbered(content) => list_item(&format!("{}. ", number), content, &block.children),
+        BlockKind::Quote(content) => {
+            let mut parts = vec![inline_markdown(content, Context::Paragraph)];
+            parts.retain(|p| !p.is_empty());
+            if !block.children.is_empty() {
+                parts.push(blocks_markdown(&block.children));
+            }
+            parts
+                .join("\n\n")
+                .split('\n')
+                .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {line}") })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        BlockKind::Code { language, code } => flat_children(code_fence(language, code)),
+        BlockKind::Table(rows) if rows.is_empty() => return (!block.children.is_empty()).then(|| blocks_markdown(&block.children)),
+        BlockKind::Table(rows) => flat_children(table_markdown(rows)),
+        BlockKind::Image { url, caption } => flat_children(format!("![{}]({})", escape_text(caption, false), link_destination(url))),
+    };
+    Some(text)
+}
+
+fn blocks_markdown(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&BlockKind> = None;
+    let mut number = 0;
+    for block in blocks {
+        number = match (&block.kind, previous) {
+            (BlockKind::Numbered(_), Some(BlockKind::Numbered(_))) => number + 1,
+            _ => 1,
+        };
+        let Some(text) = block_markdown(block, number) else { continue };
+        if let Some(previous) = previous {
+            // Items of the same list stay together; anything else is its own block
+            let same_list = matches!(
+                (previous, &block.kind),
+                (BlockKind::Numbered(_), BlockKind::Numbered(_))
+                    | (BlockKind::Bullet(_) | BlockKind::Check(..), BlockKind::Bullet(_) | BlockKind::Check(..))
+            );
+            out.push_str(if same_list { "\n" } else { "\n\n" });
+        }
+        out.push_str(&text);
+        previous = Some(&block.kind);
+    }
+    out
+}
+
+// ===== Markdown input =====
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+struct Reader<'a> {
+    events: Vec<Event<'a>>,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(markdown: &'a str) -> Self {
+        Self { events: Parser::new_ext(markdown, markdown_options()).collect(), pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&Event<'a>> {
+        self.events.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Event<'a>> {
+        let event = self.events.get(self.pos).cloned();
+        self.pos += 1;
+        event
+    }
+
+    /// Skips the rest of a container whose start was just read.
+    fn skip_container(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Event::Start(_)) => depth += 1,
+                Some(Event::End(_)) => depth -= 1,
+                Some(_) => {}
+                None => break,
+            }
+        }
+    }
+
+    /// Blocks up to the end of the enclosing container, which is consumed.
+    fn blocks(&mut self) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        while let Some(event) = self.next() {
+            let kind = match event {
+                Event::End(_) => break,
+                Event::Start(Tag::Paragraph) => match self.image_paragraph() {
+                    Some(image) => image,
+                    None => {
+                        let content = self.inlines();
+                        self.next();
+                        BlockKind::Paragraph(content)
+                    }
+                },
+                Event::Start(Tag::Heading { level, .. }) => {
+                    let content = self.inlines();
+                    self.next();
+                    BlockKind::Heading(level as u8, content)
+                }
+                Event::Start(Tag::BlockQuote(_)) => {
+                    let mut inner = self.blocks();
+                    match inner.first() {
+                        Some(Block { kind: BlockKind::Paragraph(_), children }) if children.is_empty() => {
+                            let BlockKind::Paragraph(content) = inner.remove(0).kind else { continue };
+                            blocks.push(Block { kind: BlockKind::Quote(content), children: inner });
+                        }
+                        _ => blocks.push(Block { kind: BlockKind::Quote(Vec::new()), children: inner }),
+                    }
+                    continue;
+                }
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    let language = match kind {
+                        CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                        CodeBlockKind::Indented => String::new(),
+                    };
+                    let mut code = String::new();
+                    while let Some(event) = self.next() {
+                        match event {
+                            Event::Text(text) => code.push_str(&text),
+                            Event::End(_) => break,
+                            _ => {}
+                        }
+                    }
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+                    BlockKind::Code { language, code }
+                }
+                Event::Start(Tag::List(start)) => {
+                    while let Some(event) = self.next() {
+                        match event {
+                            Event::Start(Tag::Item) => blocks.push(self.list_item(start.is_some())),
+                            Event::End(_) => break,
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+                Event::Start(Tag::Table(_)) => BlockKind::Table(self.table()),
+                Event::Start(Tag::HtmlBlock) => {
+                    let mut html = String::new();
+                    while let Some(event) = self.next() {
+                        match event {
+                            Event::Html(text) | Event::Text(text) => html.push_str(&text),
+                            Event::End(_) => break,
+                            _ => {}
+                        }
+                    }
+                    let html = html.trim_end_matches('\n');
+                    BlockKind::Paragraph(vec![Inline::Text(Run { text: html.to_string(), styles: Styles::default() })])
+                }
+                Event::Start(_) => {
+                    self.skip_container();
+                    continue;
+                }
+                _ => continue,
+            };
+            blocks.push(Block { kind, children: Vec::new() });
+        }
+        blocks
+    }
+
+    /// A paragraph holding a single image (its start just read) is an image block.
+    fn image_paragraph(&mut self) -> Option<BlockKind> {
+        let Some(Event::Start(Tag::Image { dest_url, .. })) = self.peek() else { return None };
+        let url = dest_url.to_string();
+        let end = (self.pos..self.events.len()).find(|&i| matches!(self.events[i], Event::End(TagEnd::Image)))?;
+        if !matches!(self.events.get(end + 1), Some(Event::End(TagEnd::Paragraph))) {
+            return None;
+        }
+        let caption: String = self.events[self.pos + 1..end]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.to_string()),
+                Event::SoftBreak | Event::HardBreak => Some("\n".to_string()),
+                _ => None,
+            })
+            .collect();
+        self.pos = end + 2;
+        Some(BlockKind::Image { url, caption })
+    }
+
+    fn list_item(&mut self, numbered: bool) -> Block {
+        let mut checked = self.task_marker();
+        let content = if matches!(self.peek(), Some(Event::Start(Tag::Paragraph))) {
+            self.next();
+            checked = checked.or(self.task_marker());
+            let content = self.inlines();
+            self.next();
+            content
+        } else {
+            self.inlines()
+        };
+        let kind = match (checked, numbered) {
+            (Some(checked), _) => BlockKind::Check(checked, content),
+            (None, true) => BlockKind::Numbered(content),
+            (None, false) => BlockKind::Bullet(content),
+        };
+        Block { kind, children: self.blocks() }
+    }
+
+    fn task_marker(&mut self) -> Option<bool> {
+        let Some(Event::TaskListMarker(checked)) = self.peek() else { return None };
+        let checked = *checked;
+        self.next();
+        Some(checked)
+    }
+
+    fn table(&mut self) -> Vec<Vec<Vec<Inline>>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        while let Some(event) = self.next() {
+            match event {
+                Event::Start(Tag::TableCell) => {
+                    row.push(self.inlines());
+                    self.next();
+                }
+                Event::End(TagEnd::TableHead | TagEnd::TableRow) => rows.push(std::mem::take(&mut row)),
+                Event::End(TagEnd::Table) => break,
+                _ => {}
+            }
+        }
+        rows
+    }
+
+    /// Inline content up to the next block-level event, which is not consumed.
+    fn inlines(&mut self) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let (mut bold, mut italic, mut strike, mut underline) = (0i32, 0i32, 0i32, 0i32);
+        let mut link: Option<(String, Vec<Run>)> = None;
+        while let Some(event) = self.peek().cloned() {
+            let styles = Styles { bold: bold > 0, italic: italic > 0, strike: strike > 0, underline: underline > 0, code: false };
+            let mut text = |text: &str, styles: Styles| match &mut link {
+                Some((_, runs)) => push_run(runs, text, styles),
+                None => push_text(&mut inlines, text, styles),
+            };
+            match event {
+                Event::Text(t) => text(&t, styles),
+                Event::Code(t) => text(&t, Styles { code: true, ..styles }),
+                Event::SoftBreak | Event::HardBreak => text("\n", styles),
+                Event::InlineHtml(html) => {
+                    let tag = html.trim().to_ascii_lowercase().replace(' ', "");
+                    let (counter, delta) = match tag.as_str() {
+                        "<strong>" | "<b>" => (&mut bold, 1),
+                        "</strong>" | "</b>" => (&mut bold, -1),
+                        "<em>" | "<i>" => (&mut italic, 1),
+                        "</em>" | "</i>" => (&mut italic, -1),
+                        "<del>" | "<s>" => (&mut strike, 1),
+                        "</del>" | "</s>" => (&mut strike, -1),
+                        "<u>" => (&mut underline, 1),
+                        "</u>" => (&mut underline, -1),
+                        "<br>" | "<br/>" => {
+                            text("\n", styles);
+                            self.pos += 1;
+                            continue;
+                        }
+                        _ => {
+                            text(&html, styles);
+                            self.pos += 1;
+                            continue;
+                        }
+                    };
+                    *counter = (*counter + delta).max(0);
+                }
+                Event::FootnoteReference(label) => text(&format!("[^{}]", label), styles),
+                Event::TaskListMarker(_) => {}
+                Event::Start(Tag::Emphasis) => italic += 1,
+                Event::End(TagEnd::Emphasis) => italic -= 1,
+                Event::Start(Tag::Strong) => bold += 1,
+                Event::End(TagEnd::Strong) => bold -= 1,
+                Event::Start(Tag::Strikethrough) => strike += 1,
+                Event::End(TagEnd::Strikethrough) => strike -= 1,
+                // Images among other content become links
+                Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                    if let Some((href, content)) = link.take() {
+                        inlines.push(Inline::Link { href, content });
+                    }
+                    link = Some((dest_url.to_string(), Vec::new()));
+                }
+                Event::End(TagEnd::Link | TagEnd::Image) => {
+                    if let Some((href, content)) = link.take() {
+                        inlines.push(Inline::Link { href, content });
+                    }
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if let Some((href, content)) = link {
+            inlines.push(Inline::Link { href, content });
+        }
+        inlines
+    }
+}
+
+fn parse_markdown(markdown: &str) -> Vec<Block> {
+    Reader::new(markdown).blocks()
+}
+
+/// Parses inline Markdown written for `context` back, to check it.
+fn parse_inline_fragment(markdown: &str, context: Context) -> Vec<Inline> {
+    let source = match context {
+        Context::Paragraph => markdown.to_string(),
+        Context::Heading => format!("# {}", markdown),
+        Context::Cell => format!("| {} |\n| --- |", markdown),
+    };
+    let mut reader = Reader::new(&source);
+    match reader.blocks().into_iter().next().map(|b| b.kind) {
+        Some(BlockKind::Paragraph(content) | BlockKind::Heading(_, content)) => content,
+        Some(BlockKind::Table(rows)) => rows.into_iter().next().and_then(|r| r.into_iter().next()).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Runs as Markdown can carry them: whitespace takes the style of the text
+/// around it when both sides agree (and no line break is involved), and has
+/// none otherwise; neighbouring runs with the same style are one.
+fn normalize_runs(runs: &[Run]) -> Vec<Run> {
+    // Alternating pieces of text and whitespace; `None` for mixed styles
+    let mut pieces: Vec<(String, Option<Styles>, bool)> = Vec::new();
+    for run in runs {
+        let mut rest = run.text.as_str();
+        while let Some(first) = rest.chars().next() {
+            let blank = first.is_whitespace();
+            let len = rest.find(|c: char| c.is_whitespace() != blank).unwrap_or(rest.len());
+            match pieces.last_mut() {
+                Some((text, styles, last_blank)) if *last_blank == blank && (blank || *styles == Some(run.styles)) => {
+                    text.push_str(&rest[..len]);
+                    if *styles != Some(run.styles) {
+                        *styles = None;
+                    }
+                }
+                _ => pieces.push((rest[..len].to_string(), Some(run.styles), blank)),
+            }
+            rest = &rest[len..];
+        }
+    }
+    let mut out = Vec::new();
+    for (i, (text, styles, blank)) in pieces.iter().enumerate() {
+        let styles = if *blank {
+            let around = (i.checked_sub(1).and_then(|p| pieces.get(p)), pieces.get(i + 1));
+            match around {
+                (Some((_, Some(before), _)), Some((_, Some(after), _))) if before == after && !text.contains('\n') => *before,
+                _ => Styles::default(),
+            }
+        } else {
+            styles.unwrap_or_default()
+        };
+        push_run(&mut out, text, styles);
+    }
+    out
+}
+
+fn normalize_inlines(inlines: &[Inline]) -> Vec<Inline> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < inlines.len() {
+        match &inlines[i] {
+            Inline::Link { href, content } => {
+                out.push(Inline::Link { href: href.clone(), content: normalize_runs(content) });
+                i += 1;
+            }
+            Inline::Text(_) => {
+                let mut runs = Vec::new();
+                while let Some(Inline::Text(run)) = inlines.get(i) {
+                    runs.push(run.clone());
+                    i += 1;
+                }
+                out.extend(normalize_runs(&runs).into_iter().map(Inline::Text));
+            }
+        }
+    }
+    out
+}
+
+// ===== Entry points =====
+
+/// Converts BlockNote JSON content to Markdown.
+/// Returns `Some(markdown)` if the input is valid BlockNote JSON, `None` otherwise.
+pub fn blocknote_to_markdown(content: &str) -> Option<String> {
+    let blocks: Vec<Value> = serde_json::from_str(content).ok()?;
+    // Verify it looks like BlockNote (first block should have a "type" field)
+    blocks.first()?.get("type")?;
+    let blocks: Vec<Block> = blocks.iter().map(block_from_json).collect();
+    let markdown = blocks_markdown(&blocks);
+    let markdown = markdown.trim_matches('\n');
+    if markdown.is_empty() { None } else { Some(markdown.to_string()) }
+}
+
+/// Converts Markdown to BlockNote blocks (a JSON array).
+pub fn markdown_to_blocknote(markdown: &str) -> Value {
+    Value::Array(parse_markdown(markdown).iter().map(block_to_json).collect())
+}
+
+// ===== Tauri Commands =====
+
+#[tauri::command]
+pub async fn convert_blocknote_to_markdown(content: String) -> Result<String, StemError> {
+    blocknote_to_markdown(&content).ok_or_else(|| StemError::Validation("Contenu BlockNote invalide".to_string()))
+}
+
+#[tauri::command]
+pub async fn convert_markdown_to_blocknote(markdown: String) -> Result<Value, StemError> {
+    Ok(markdown_to_blocknote(&markdown))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(Run { text: text.to_string(), styles: Styles::default() })
+    }
+
+    fn styled(text: &str, styles: Styles) -> Inline {
+        Inline::Text(Run { text: text.to_string(), styles })
+    }
+
+    fn block(kind: BlockKind, children: Vec<Block>) -> Block {
+        Block { kind, children }
+    }
+
+    #[test]
+    fn test_nesting_numbering_and_tables() {
+        let json = r#"[
+            {"type":"numberedListItem","content":[{"type":"text","text":"one"}],"children":[
+                {"type":"bulletListItem","content":[{"type":"text","text":"deep"}],"children":[
+                    {"type":"checkListItem","props":{"checked":true},"content":[{"type":"text","text":"deeper"}],"children":[]}
+                ]}
+            ]},
+            {"type":"numberedListItem","content":[{"type":"text","text":"two"}],"children":[]},
+            {"type":"quote","content":[{"type":"text","text":"cited "},{"type":"link","href":"https://ex.com/a b","content":[{"type":"text","text":"source","styles":{"bold":true}}]}],"children":[]},
+            {"type":"table","content":{"type":"tableContent","rows":[{"cells":[[{"type":"text","text":"a|b"}],[]]},{"cells":[[{"type":"text","text":"1"}],[{"type":"text","text":"2"}]]}]}},
+            {"type":"image","props":{"url":"attachment://x.png","caption":"Schéma"},"children":[]}
+        ]"#;
+        let markdown = blocknote_to_markdown(json).unwrap();
+        assert_eq!(
+            markdown,
+            "1. one\n   - deep\n     - [x] deeper\n2. two\n\n> cited [**source**](<https://ex.com/a b>)\n\n| a\\|b |  |\n| --- | --- |\n| 1 | 2 |\n\n![Schéma](attachment://x.png)"
+        );
+    }
+
+    #[test]
+    fn test_escaping_and_style_nesting() {
+        let bold_italic = Styles { bold: true, italic: true, ..Styles::default() };
+        let italic = Styles { italic: true, ..Styles::default() };
+        let blocks = vec![
+            block(BlockKind::Paragraph(vec![text("# not a heading *nor* [[Wiki Link]] 1_000 snake_case")]), vec![]),
+            block(BlockKind::Paragraph(vec![styled("both ", bold_italic), styled("italic", italic), text(" plain")]), vec![]),
+            block(BlockKind::Paragraph(vec![styled("(x)", Styles { bold: true, ..Styles::default() }), text("y")]), vec![]),
+        ];
+        let markdown = blocks_markdown(&blocks);
+        assert_eq!(
+            markdown,
+            "\\# not a heading \\*nor\\* [[Wiki Link]] 1_000 snake_case\n\n***both** italic* plain\n\n<strong>(x)</strong>y"
+        );
+        assert_eq!(normalize_blocks(&parse_markdown(&markdown)), normalize_blocks(&blocks));
+    }
+
+    #[test]
+    fn test_markdown_to_blocknote() {
+        let value = markdown_to_blocknote("## Titre\n\n- [ ] à faire\n  1. sous-étape\n\n```rust\nfn main() {}\n```");
+        let blocks = value.as_array().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0]["type"], "heading");
+        assert_eq!(blocks[0]["props"]["level"], 2);
+        assert_eq!(blocks[1]["type"], "checkListItem");
+        assert_eq!(blocks[1]["props"]["checked"], false);
+        assert_eq!(blocks[1]["children"][0]["type"], "numberedListItem");
+        assert_eq!(blocks[1]["children"][0]["content"][0]["text"], "sous-étape");
+        assert_eq!(blocks[2]["content"][0]["text"], "fn main() {}");
+    }
+
+    // ===== Roundtrip properties =====
+
+    fn styles_strategy() -> impl Strategy<Value = Styles> {
+        (0u8..32).prop_map(|bits| Styles {
+            bold: bits & 1 != 0,
+            italic: bits & 2 != 0,
+            strike: bits & 4 != 0,
+            underline: bits & 8 != 0,
+            code: bits & 16 != 0 && bits & 8 == 0,
+        })
+    }
+
+    fn run_strategy() -> impl Strategy<Value = Run> {
+        ("[a-zA-Zé0-9 *_`#\\[\\]()<>&;!~|.+=\\\\-]{1,8}", styles_strategy()).prop_map(|(text, styles)| Run { text, styles })
+    }
+
+    fn inlines_strategy() -> impl Strategy<Value = Vec<Inline>> {
+        let inline = prop_oneof![
+            4 => run_strategy().prop_map(Inline::Text),
+            1 => ("[a-z:/. ()<>&\\\\]{0,10}", prop::collection::vec(run_strategy(), 0..3))
+                .prop_map(|(href, content)| Inline::Link { href, content }),
+        ];
+        prop::collection::vec(inline, 1..5)
+            .prop_map(|mut inlines| {
+                // A line break between words, now and then
+                if let Some(Inline::Text(run)) = inlines.get_mut(1) {
+                    run.text.insert(0, '\n');
+                }
+                inlines
+            })
+            .prop_filter("visible content", |inlines| {
+                let text: String = normalize_inlines(inlines)
+                    .iter()
+                    .map(|i| match i {
+                        Inline::Text(run) => run.text.clone(),
+                        Inline::Link { .. } => "link".to_string(),
+                    })
+                    .collect();
+                !text.trim().is_empty() && !text.starts_with('\n') && !text.ends_with('\n') && !text.contains("\n\n")
+            })
+    }
+
+    fn leaf_strategy() -> impl Strategy<Value = BlockKind> {
+        prop_oneof![
+            inlines_strategy().prop_map(BlockKind::Paragraph),
+            (1u8..=6, inlines_strategy()).prop_map(|(level, content)| BlockKind::Heading(level, content)),
+            ("[a-z]{0,5}", "[a-z `~\n{}]{0,20}").prop_map(|(language, code)| BlockKind::Code { language, code }),
+            (1usize..4, 1usize..4)
+                .prop_flat_map(|(rows, columns)| {
+                    let cell = prop::collection::vec(run_strategy().prop_map(Inline::Text), 0..3);
+                    prop::collection::vec(prop::collection::vec(cell, columns), rows)
+                })
+                .prop_map(BlockKind::Table),
+            ("[a-z:/. ()]{1,10}", "[a-zA-Z é*_\\[\\]]{0,8}").prop_map(|(url, caption)| BlockKind::Image { url, caption }),
+        ]
+    }
+
+    fn block_strategy() -> impl Strategy<Value = Block> {
+        let leaf = prop_oneof![
+            leaf_strategy(),
+            inlines_strategy().prop_map(BlockKind::Bullet),
+            inlines_strategy().prop_map(BlockKind::Numbered),
+            (any::<bool>(), inlines_strategy()).prop_map(|(checked, content)| BlockKind::Check(checked, content)),
+            inlines_strategy().prop_map(BlockKind::Quote),
+        ]
+        .prop_map(|kind| Block { kind, children: Vec::new() });
+        leaf.prop_recursive(4, 24, 3, |inner| {
+            let container = prop_oneof![
+                inlines_strategy().prop_map(BlockKind::Bullet),
+                inlines_strategy().prop_map(BlockKind::Numbered),
+                (any::<bool>(), inlines_strategy()).prop_map(|(checked, content)| BlockKind::Check(checked, content)),
+                inlines_strategy().prop_map(BlockKind::Quote),
+            ];
+            (container, prop::collection::vec(inner, 1..4)).prop_map(|(kind, children)| Block { kind, children })
+        })
+    }
+
+    fn map_inlines(blocks: &[Block], f: &dyn Fn(&[Inline]) -> Vec<Inline>) -> Vec<Block> {
+        blocks
+            .iter()
+            .map(|b| {
+                let kind = match &b.kind {
+                    BlockKind::Paragraph(c) => BlockKind::Paragraph(f(c)),
+                    BlockKind::Heading(l, c) => BlockKind::Heading(*l, f(c)),
+                    BlockKind::Bullet(c) => BlockKind::Bullet(f(c)),
+                    BlockKind::Numbered(c) => BlockKind::Numbered(f(c)),
+                    BlockKind::Check(checked, c) => BlockKind::Check(*checked, f(c)),
+                    BlockKind::Quote(c) => BlockKind::Quote(f(c)),
+                    BlockKind::Table(rows) => BlockKind::Table(rows.iter().map(|r| r.iter().map(|c| f(c)).collect()).collect()),
+                    kind => kind.clone(),
+                };
+                Block { kind, children: map_inlines(&b.children, f) }
+            })
+            .collect()
+    }
+
+    /// What the Markdown is expected to give back.
+    fn normalize_blocks(blocks: &[Block]) -> Vec<Block> {
+        map_inlines(blocks, &normalize_inlines)
+    }
+
+    /// Neighbouring runs with the same styles, merged as BlockNote content is read.
+    fn merge_runs(inlines: &[Inline]) -> Vec<Inline> {
+        let mut out = Vec::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(run) => push_text(&mut out, &run.text, run.styles),
+                Inline::Link { href, content } => {
+                    let mut runs = Vec::new();
+                    content.iter().for_each(|run| push_run(&mut runs, &run.text, run.styles));
+                    out.push(Inline::Link { href: href.clone(), content: runs });
+                }
+            }
+        }
+        out
+    }
+
+    proptest! {
+        #[test]
+        fn prop_markdown_roundtrip(blocks in prop::collection::vec(block_strategy(), 1..6)) {
+            let markdown = blocks_markdown(&blocks);
+            let parsed = parse_markdown(&markdown);
+            prop_assert_eq!(normalize_blocks(&parsed), normalize_blocks(&blocks), "{}", markdown);
+            // Converting again changes nothing
+            prop_assert_eq!(blocks_markdown(&parsed), markdown);
+        }
+
+        #[test]
+        fn prop_blocknote_json_roundtrip(blocks in prop::collection::vec(block_strategy(), 1..6)) {
+            let json = Value::Array(blocks.iter().map(block_to_json).collect());
+            let back: Vec<Block> = json.as_array().unwrap().iter().map(block_from_json).collect();
+            prop_assert_eq!(back, map_inlines(&blocks, &merge_runs));
+        }
+    }
+}
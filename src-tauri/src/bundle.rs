@@ -0,0 +1,266 @@
+//! Passphrase-encrypted export bundles. Layout:
+//!
+//! ```text
+//! stem-bundle\n
+//! {"version":1,"kdf":"argon2id","memory_kib":..,"iterations":..,"parallelism":..,
+//!  "salt":"..","nonce_prefix":"..","chunk_size":65536}\n
+//! <chunk 0><chunk 1>...<last chunk>
+//! ```
+//!
+//! The payload (JSON export or `.stem` archive) is cut into `chunk_size`
+//! pieces, each sealed with XChaCha20-Poly1305 under an Argon2id key. The
+//! nonce is `nonce_prefix || chunk counter || last-chunk flag`, so chunks
+//! cannot be reordered or dropped, and the header line is authenticated as
+//! associated data of every chunk. Text exports are wrapped in base64 after an
+//! armor line so they can still travel as IPC strings.
+
+use crate::encryption::{derive_key, validate_passphrase, KdfParams, SALT_LEN};
+use crate::error::StemError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Read, Write};
+
+pub const BUNDLE_MAGIC: &[u8] = b"stem-bundle\n";
+/// First line of a bundle sent as text (`export_all_data`).
+pub const ARMOR_PREFIX: &str = "stem-bundle-armor\n";
+pub const BUNDLE_VERSION: u32 = 1;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = 19;
+/// Header lines longer than this are not bundles.
+const MAX_HEADER_LEN: u64 = 4096;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleHeader {
+    version: u32,
+    kdf: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+    nonce_prefix: String,
+    chunk_size: usize,
+}
+
+impl BundleHeader {
+    fn params(&self) -> Result<KdfParams, StemError> {
+        if self.kdf != "argon2id" {
+            return Err(StemError::Validation(format!("Dérivation de clé non prise en charge ({})", self.kdf)));
+        }
+        let params = KdfParams { memory_kib: self.memory_kib, iterations: self.iterations, parallelism: self.parallelism };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+fn invalid() -> StemError {
+    StemError::Validation("Fichier chiffré illisible".to_string())
+}
+
+fn chunk_nonce(prefix: &[u8], counter: u32, last: bool) -> XNonce {
+    let mut nonce = [0u8; 24];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..23].copy_from_slice(&counter.to_be_bytes());
+    nonce[23] = last as u8;
+    XNonce::from(nonce)
+}
+
+/// Reads until `buf` is full or the input ends. Returns the number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+pub fn is_bundle(prefix: &[u8]) -> bool {
+    prefix.starts_with(BUNDLE_MAGIC)
+}
+
+pub fn is_armored(text: &str) -> bool {
+    text.starts_with(ARMOR_PREFIX)
+}
+
+/// Passphrase of an encrypted import, or the error shown when none was given.
+pub fn require_passphrase(passphrase: Option<&str>) -> Result<&str, StemError> {
+    passphrase.ok_or_else(|| StemError::Validation("Ce fichier est chiffré : phrase de passe requise".to_string()))
+}
+
+/// Encrypts everything from `reader` into `writer`.
+pub fn encrypt_stream(mut reader: impl Read, mut writer: impl Write, passphrase: &str, params: KdfParams) -> Result<(), StemError> {
+    validate_passphrase(passphrase)?;
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut nonce_prefix);
+
+    let header = BundleHeader {
+        version: BUNDLE_VERSION,
+        kdf: "argon2id".to_string(),
+        memory_kib: params.memory_kib,
+        iterations: params.iterations,
+        parallelism: params.parallelism,
+        salt: STANDARD.encode(salt),
+        nonce_prefix: STANDARD.encode(nonce_prefix),
+        chunk_size: CHUNK_SIZE,
+    };
+    let header_line = serde_json::to_string(&header).map_err(|e| StemError::Validation(e.to_string()))?;
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+
+    writer.write_all(BUNDLE_MAGIC)?;
+    writer.write_all(header_line.as_bytes())?;
+    writer.write_all(b"\n")?;
+
+    // The last chunk is always shorter than CHUNK_SIZE, possibly empty
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut counter: u32 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        let last = n < CHUNK_SIZE;
+        let sealed = cipher
+            .encrypt(&chunk_nonce(&nonce_prefix, counter, last), Payload { msg: &buf[..n], aad: header_line.as_bytes() })
+            .map_err(|_| StemError::Validation("Échec du chiffrement".to_string()))?;
+        writer.write_all(&sealed)?;
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or_else(|| StemError::Validation("Export trop volumineux".to_string()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Decrypts a bundle from `reader` into `writer`. Fails on a wrong
+/// passphrase, a tampered header or a truncated file.
+pub fn decrypt_stream(mut reader: impl BufRead, mut writer: impl Write, passphrase: &str) -> Result<(), StemError> {
+    let mut magic = [0u8; BUNDLE_MAGIC.len()];
+    if read_full(&mut reader, &mut magic)? != magic.len() || !is_bundle(&magic) {
+        return Err(invalid());
+    }
+    let mut header_line = Vec::new();
+    (&mut reader).take(MAX_HEADER_LEN).read_until(b'\n', &mut header_line)?;
+    if header_line.pop() != Some(b'\n') {
+        return Err(invalid());
+    }
+    let header: BundleHeader = serde_json::from_slice(&header_line).map_err(|_| invalid())?;
+    if header.version > BUNDLE_VERSION {
+        return Err(StemError::Validation(format!(
+            "Fichier chiffré par une version plus récente de Stem (format {})",
+            header.version
+        )));
+    }
+    let params = header.params()?;
+    let salt = STANDARD.decode(&header.salt).map_err(|_| invalid())?;
+    let nonce_prefix = STANDARD.decode(&header.nonce_prefix).map_err(|_| invalid())?;
+    if nonce_prefix.len() != NONCE_PREFIX_LEN || header.chunk_size == 0 || header.chunk_size > 16 * CHUNK_SIZE {
+        return Err(invalid());
+    }
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let mut buf = vec![0u8; header.chunk_size + TAG_LEN];
+    let mut counter: u32 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        let last = n < buf.len();
+        if last && n < TAG_LEN {
+            return Err(StemError::Validation("Fichier chiffré tronqué".to_string()));
+        }
+        let plain = cipher
+            .decrypt(&chunk_nonce(&nonce_prefix, counter, last), Payload { msg: &buf[..n], aad: &header_line })
+            .map_err(|_| {
+                if counter == 0 {
+                    StemError::Validation("Phrase de passe incorrecte".to_string())
+                } else {
+                    StemError::Validation("Fichier chiffré corrompu ou tronqué".to_string())
+                }
+            })?;
+        writer.write_all(&plain)?;
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or_else(invalid)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encrypts a text export into an armored bundle.
+pub fn seal_text(plaintext: &str, passphrase: &str) -> Result<String, StemError> {
+    let mut bundle = Vec::new();
+    encrypt_stream(plaintext.as_bytes(), &mut bundle, passphrase, KdfParams::default())?;
+    Ok(format!("{}{}\n", ARMOR_PREFIX, STANDARD.encode(bundle)))
+}
+
+/// Decrypts an armored bundle produced by `seal_text`.
+pub fn open_text(armored: &str, passphrase: &str) -> Result<String, StemError> {
+    let encoded = armored.strip_prefix(ARMOR_PREFIX).ok_or_else(invalid)?;
+    let bundle = STANDARD.decode(encoded.trim()).map_err(|_| invalid())?;
+    let mut plaintext = Vec::new();
+    decrypt_stream(bundle.as_slice(), &mut plaintext, passphrase)?;
+    String::from_utf8(plaintext).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn encrypt(data: &[u8]) -> Vec<u8> {
+        let mut bundle = Vec::new();
+        encrypt_stream(data, &mut bundle, "correct horse", TEST_PARAMS).unwrap();
+        bundle
+    }
+
+    fn decrypt(bundle: &[u8], passphrase: &str) -> Result<Vec<u8>, StemError> {
+        let mut out = Vec::new();
+        decrypt_stream(bundle, &mut out, passphrase).map(|_| out)
+    }
+
+    #[test]
+    fn test_stream_roundtrip_on_chunk_boundaries() {
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 5] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let bundle = encrypt(&data);
+            assert!(is_bundle(&bundle));
+            assert_eq!(decrypt(&bundle, "correct horse").unwrap(), data, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_rejects_wrong_passphrase_truncation_and_tampering() {
+        let data = vec![7u8; CHUNK_SIZE + 10];
+        let bundle = encrypt(&data);
+        assert!(matches!(decrypt(&bundle, "wrong horse!"), Err(StemError::Validation(m)) if m == "Phrase de passe incorrecte"));
+
+        // Dropping the last chunk leaves a full-size chunk that is not flagged as last
+        let header_end = bundle.iter().skip(BUNDLE_MAGIC.len()).position(|b| *b == b'\n').unwrap() + BUNDLE_MAGIC.len() + 1;
+        assert!(decrypt(&bundle[..header_end + CHUNK_SIZE + TAG_LEN], "correct horse").is_err());
+
+        let text = String::from_utf8_lossy(&bundle[..header_end]).replace("\"iterations\":1", "\"iterations\":2");
+        let mut tampered = text.into_bytes();
+        tampered.extend_from_slice(&bundle[header_end..]);
+        assert!(decrypt(&tampered, "correct horse").is_err());
+    }
+
+    #[test]
+    fn test_text_armor() {
+        let armored = seal_text("{\"version\":1}", "correct horse").unwrap();
+        assert!(is_armored(&armored));
+        assert_eq!(open_text(&armored, "correct horse").unwrap(), "{\"version\":1}");
+        assert!(seal_text("x", "short").is_err());
+    }
+}
@@ -1,22 +1,44 @@
-use crate::db::Database;
+use crate::bundle;
+use crate::db::{current_timestamp, Database};
+use crate::encryption::{self, SessionKeys};
 use crate::error::StemError;
+use crate::restore::{merge_export_data, ConflictStrategy, ImportDiff};
+use crate::sync::crdt;
+use crate::text;
 use rusqlite::{OptionalExtension, Row};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
 use tauri::State;
 use uuid::Uuid;
 
 const DEFAULT_TITLE: &str = "Sans titre";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Column list matching `row_to_note`.
+pub(crate) const NOTE_COLUMNS: &str = "id, title, content, created_at, updated_at, is_pinned, folder_id, is_archived, is_encrypted";
+/// Column list matching `row_to_folder`.
+pub(crate) const FOLDER_COLUMNS: &str = "id, name, parent_id, position, created_at, is_archived";
+
+/// Recursive CTE `subtree(id)` containing folder `?1` and all of its descendants.
+pub(crate) const FOLDER_SUBTREE_CTE: &str = "subtree(id) AS (
+    SELECT ?1
+    UNION SELECT f.id FROM folders f JOIN subtree s ON f.parent_id = s.id
+)";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Note {
     pub id: String,
     pub title: String,
     pub content: Option<String>,
     pub created_at: i64,
     pub updated_at: i64,
+    #[serde(default)]
     pub is_pinned: bool,
     pub folder_id: Option<String>,
+    #[serde(default)]
+    pub is_archived: bool,
+    /// When set, `content` holds an encryption envelope (see encryption.rs).
+    #[serde(default)]
+    pub is_encrypted: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +48,8 @@ pub struct Folder {
     pub parent_id: Option<String>,
     pub position: i32,
     pub created_at: i64,
+    #[serde(default)]
+    pub is_archived: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,14 +77,7 @@ pub struct UpdateNotePayload {
     pub content: Option<String>,
 }
 
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64
-}
-
-fn row_to_note(row: &Row) -> Result<Note, rusqlite::Error> {
+pub(crate) fn row_to_note(row: &Row) -> Result<Note, rusqlite::Error> {
     Ok(Note {
         id: row.get(0)?,
         title: row.get(1)?,
@@ -69,24 +86,36 @@ fn row_to_note(row: &Row) -> Result<Note, rusqlite::Error> {
         updated_at: row.get(4)?,
         is_pinned: row.get::<_, i32>(5).unwrap_or(0) != 0,
         folder_id: row.get(6)?,
+        is_archived: row.get::<_, i32>(7).unwrap_or(0) != 0,
+        is_encrypted: row.get::<_, i32>(8).unwrap_or(0) != 0,
     })
 }
 
-fn row_to_folder(row: &Row) -> Result<Folder, rusqlite::Error> {
+/// Drops the ciphertext of encrypted notes before they leave the backend.
+/// Plaintext is only ever returned by `unlock_note`.
+pub(crate) fn redact(mut note: Note) -> Note {
+    if note.is_encrypted {
+        note.content = None;
+    }
+    note
+}
+
+pub(crate) fn row_to_folder(row: &Row) -> Result<Folder, rusqlite::Error> {
     Ok(Folder {
         id: row.get(0)?,
         name: row.get(1)?,
         parent_id: row.get(2)?,
         position: row.get(3)?,
         created_at: row.get(4)?,
+        is_archived: row.get::<_, i32>(5).unwrap_or(0) != 0,
     })
 }
 
-/// Private sync helper — used internally by update_note & toggle_pin_note.
-fn get_note_sync(db: &Database, id: &str) -> Result<Option<Note>, StemError> {
+/// Sync helper — used internally by update_note & toggle_pin_note. Encrypted content is redacted.
+pub(crate) fn get_note_sync(db: &Database, id: &str) -> Result<Option<Note>, StemError> {
     let conn = db.try_connection()?;
-    let mut stmt = conn.prepare("SELECT id, title, content, created_at, updated_at, is_pinned, folder_id FROM notes WHERE id = ?1")?;
-    Ok(stmt.query_row([id], row_to_note).optional()?)
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"))?;
+    Ok(stmt.query_row([id], row_to_note).optional()?.map(redact))
 }
 
 #[tauri::command]
@@ -106,7 +135,7 @@ pub async fn create_note(db: State<'_, Database>, payload: CreateNotePayload) ->
             "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
             (&id, &title, &content, &now, &now),
         )?;
-        Ok(Note { id, title, content, created_at: now, updated_at: now, is_pinned: false, folder_id: None })
+        Ok(Note { id, title, content, created_at: now, updated_at: now, is_pinned: false, folder_id: None, is_archived: false, is_encrypted: false })
     }).await
 }
 
@@ -115,37 +144,240 @@ pub async fn get_note(db: State<'_, Database>, id: String) -> Result<Option<Note
     db.inner().clone().spawn(move |db| get_note_sync(&db, &id)).await
 }
 
+/// Archived notes are left out unless `include_archived` is set.
 #[tauri::command]
-pub async fn get_all_notes(db: State<'_, Database>) -> Result<Vec<Note>, StemError> {
+pub async fn get_all_notes(db: State<'_, Database>, include_archived: Option<bool>) -> Result<Vec<Note>, StemError> {
+    let include_archived = include_archived.unwrap_or(false);
     db.inner().clone().spawn(move |db| {
         let conn = db.try_connection()?;
-        let mut stmt = conn.prepare("SELECT id, title, content, created_at, updated_at, is_pinned, folder_id FROM notes ORDER BY is_pinned DESC, updated_at DESC")?;
-        let notes = stmt.query_map([], row_to_note)?
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE ?1 OR is_archived = 0 ORDER BY is_pinned DESC, updated_at DESC"
+        ))?;
+        let notes = stmt.query_map([include_archived], row_to_note)?
+            .map(|note| note.map(redact))
             .collect::<Result<Vec<_>, _>>()?;
         Ok(notes)
     }).await
 }
 
-#[tauri::command]
-pub async fn update_note(db: State<'_, Database>, payload: UpdateNotePayload) -> Result<Note, StemError> {
-    db.inner().clone().spawn(move |db| {
-        let now = current_timestamp();
-        let conn = db.try_connection()?;
-        if let Some(title) = &payload.title {
-            conn.execute(
-                "UPDATE notes SET title = ?1, updated_at = ?2 WHERE id = ?3",
-                (title, &now, &payload.id),
-            )?;
+// ===== PAGED LISTING =====
+
+const EXCERPT_CHARS: usize = 160;
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// Lightweight view of a note for the sidebar — full content goes through `get_note`.
+#[derive(Debug, Serialize)]
+pub struct NoteSummary {
+    pub id: String,
+    pub title: String,
+    pub excerpt: String,
+    pub word_count: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub is_pinned: bool,
+    pub folder_id: Option<String>,
+    pub is_archived: bool,
+    pub is_encrypted: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSort {
+    #[default]
+    UpdatedDesc,
+    UpdatedAsc,
+    CreatedDesc,
+    CreatedAsc,
+    TitleAsc,
+    TitleDesc,
+}
+
+impl NoteSort {
+    fn column(self) -> &'static str {
+        match self {
+            NoteSort::UpdatedDesc | NoteSort::UpdatedAsc => "updated_at",
+            NoteSort::CreatedDesc | NoteSort::CreatedAsc => "created_at",
+            NoteSort::TitleAsc | NoteSort::TitleDesc => "title COLLATE NOCASE",
         }
-        if let Some(content) = &payload.content {
-            conn.execute(
-                "UPDATE notes SET content = ?1, updated_at = ?2 WHERE id = ?3",
-                (content, &now, &payload.id),
-            )?;
+    }
+
+    fn is_desc(self) -> bool {
+        matches!(self, NoteSort::UpdatedDesc | NoteSort::CreatedDesc | NoteSort::TitleDesc)
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ListNotesPayload {
+    /// Restricts the listing to notes directly inside this folder.
+    pub folder_id: Option<String>,
+    #[serde(default)]
+    pub sort: NoteSort,
+    /// Opaque cursor returned as `next_cursor` by the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotePage {
+    pub notes: Vec<NoteSummary>,
+    pub next_cursor: Option<String>,
+}
+
+/// Position of the last row of a page. Pinned notes always come first, then
+/// the sort key, with the id as tie-breaker so the ordering is total.
+#[derive(Debug, Serialize, Deserialize)]
+struct NoteCursor {
+    sort: NoteSort,
+    pinned: bool,
+    key: CursorKey,
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum CursorKey {
+    Int(i64),
+    Text(String),
+}
+
+impl From<CursorKey> for rusqlite::types::Value {
+    fn from(key: CursorKey) -> Self {
+        match key {
+            CursorKey::Int(i) => i.into(),
+            CursorKey::Text(t) => t.into(),
         }
-        drop(conn);
-        get_note_sync(&db, &payload.id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", payload.id)))
-    }).await
+    }
+}
+
+pub(crate) fn list_notes_sync(db: &Database, payload: &ListNotesPayload) -> Result<NotePage, StemError> {
+    let limit = payload.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let sort = payload.sort;
+    let column = sort.column();
+    let (dir, cmp) = if sort.is_desc() { ("DESC", "<") } else { ("ASC", ">") };
+
+    let mut clauses: Vec<String> = Vec::new();
+    let mut params: Vec<rusqlite::types::Value> = Vec::new();
+
+    if !payload.include_archived {
+        clauses.push("is_archived = 0".to_string());
+    }
+    if let Some(folder_id) = &payload.folder_id {
+        clauses.push("folder_id = ?".to_string());
+        params.push(folder_id.clone().into());
+    }
+
+    if let Some(raw) = &payload.cursor {
+        let cursor: NoteCursor = serde_json::from_str(raw)
+            .map_err(|_| StemError::Validation("Curseur de pagination invalide".to_string()))?;
+        if cursor.sort != sort {
+            return Err(StemError::Validation("Le curseur ne correspond pas au tri demandé".to_string()));
+        }
+        clauses.push(format!(
+            "(is_pinned < ? OR (is_pinned = ? AND ({column}, id) {cmp} (?, ?)))"
+        ));
+        let pinned = cursor.pinned as i64;
+        params.extend([pinned.into(), pinned.into(), cursor.key.into(), cursor.id.into()]);
+    }
+
+    let where_sql = if clauses.is_empty() { String::new() } else { format!("WHERE {}", clauses.join(" AND ")) };
+    let sql = format!(
+        "SELECT {NOTE_COLUMNS} FROM notes {where_sql} \
+         ORDER BY is_pinned DESC, {column} {dir}, id {dir} LIMIT {}",
+        limit + 1
+    );
+
+    let conn = db.try_connection()?;
+    let mut stmt = conn.prepare(&sql)?;
+    let mut notes = stmt.query_map(rusqlite::params_from_iter(params), row_to_note)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let has_more = notes.len() > limit as usize;
+    notes.truncate(limit as usize);
+
+    let next_cursor = match notes.last() {
+        Some(last) if has_more => {
+            let key = match sort {
+                NoteSort::UpdatedDesc | NoteSort::UpdatedAsc => CursorKey::Int(last.updated_at),
+                NoteSort::CreatedDesc | NoteSort::CreatedAsc => CursorKey::Int(last.created_at),
+                NoteSort::TitleAsc | NoteSort::TitleDesc => CursorKey::Text(last.title.clone()),
+            };
+            let cursor = NoteCursor { sort, pinned: last.is_pinned, key, id: last.id.clone() };
+            Some(serde_json::to_string(&cursor).map_err(|e| StemError::Validation(e.to_string()))?)
+        }
+        _ => None,
+    };
+
+    let notes = notes.into_iter().map(redact).map(|note| {
+        let content = note.content.as_deref().unwrap_or("");
+        NoteSummary {
+            excerpt: text::excerpt(content, EXCERPT_CHARS),
+            word_count: text::word_count(content),
+            id: note.id,
+            title: note.title,
+            created_at: note.created_at,
+            updated_at: note.updated_at,
+            is_pinned: note.is_pinned,
+            folder_id: note.folder_id,
+            is_archived: note.is_archived,
+            is_encrypted: note.is_encrypted,
+        }
+    }).collect();
+
+    Ok(NotePage { notes, next_cursor })
+}
+
+#[tauri::command]
+pub async fn list_notes(db: State<'_, Database>, payload: Option<ListNotesPayload>) -> Result<NotePage, StemError> {
+    db.inner().clone().spawn(move |db| list_notes_sync(&db, &payload.unwrap_or_default())).await
+}
+
+/// Applies a title and/or content edit. Both are written in one transaction,
+/// so a locked encrypted note is left untouched rather than half-updated.
+pub(crate) fn update_note_sync(db: &Database, keys: &SessionKeys, payload: &UpdateNotePayload) -> Result<Note, StemError> {
+    let now = current_timestamp();
+    let mut conn = db.try_connection()?;
+    let tx = conn.transaction()?;
+    if let Some(title) = &payload.title {
+        tx.execute(
+            "UPDATE notes SET title = ?1, updated_at = ?2 WHERE id = ?3",
+            (title, &now, &payload.id),
+        )?;
+    }
+    if let Some(content) = &payload.content {
+        write_content(&tx, keys, &payload.id, content, now)?;
+    }
+    tx.commit()?;
+    drop(conn);
+    get_note_sync(db, &payload.id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", payload.id)))
+}
+
+#[tauri::command]
+pub async fn update_note(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    payload: UpdateNotePayload,
+) -> Result<Note, StemError> {
+    let keys = keys.inner().clone();
+    db.inner().clone().spawn(move |db| update_note_sync(&db, &keys, &payload)).await
+}
+
+/// Stores new content for a note, sealed again if the note is encrypted.
+pub(crate) fn write_content(conn: &rusqlite::Connection, keys: &SessionKeys, id: &str, content: &str, now: i64) -> Result<(), StemError> {
+    let is_encrypted: bool = conn
+        .query_row("SELECT is_encrypted FROM notes WHERE id = ?1", [id], |row| row.get(0))
+        .optional()?
+        .unwrap_or(false);
+    let stored = if is_encrypted {
+        encryption::reseal_content(keys, id, content)?
+    } else {
+        crdt::record_edit(conn, id, content)?;
+        content.to_string()
+    };
+    conn.execute("UPDATE notes SET content = ?1, updated_at = ?2 WHERE id = ?3", (&stored, &now, id))?;
+    Ok(())
 }
 
 #[tauri::command]
@@ -170,89 +402,289 @@ pub async fn toggle_pin_note(db: State<'_, Database>, id: String) -> Result<Note
     }).await
 }
 
+// ===== ARCHIVE =====
+
+/// Recursive CTE `ancestors(id)` containing folder `?1` and all of its parents.
+const FOLDER_ANCESTORS_CTE: &str = "ancestors(id) AS (
+    SELECT ?1
+    UNION SELECT f.parent_id FROM folders f JOIN ancestors a ON f.id = a.id WHERE f.parent_id IS NOT NULL
+)";
+
+/// Unarchives `folder_id` and its parents so a restored item is reachable from the sidebar again.
+fn unarchive_folder_chain(conn: &rusqlite::Connection, folder_id: &str) -> Result<(), StemError> {
+    conn.execute(
+        &format!("WITH RECURSIVE {FOLDER_ANCESTORS_CTE} UPDATE folders SET is_archived = 0 WHERE id IN ancestors"),
+        [folder_id],
+    )?;
+    Ok(())
+}
+
+fn set_note_archived(db: &Database, id: &str, archived: bool) -> Result<Note, StemError> {
+    {
+        let mut conn = db.try_connection()?;
+        let tx = conn.transaction()?;
+        let folder_id: Option<Option<String>> = tx
+            .query_row("SELECT folder_id FROM notes WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        let Some(folder_id) = folder_id else {
+            return Err(StemError::NotFound(format!("Note {}", id)));
+        };
+        tx.execute("UPDATE notes SET is_archived = ?1 WHERE id = ?2", (archived as i32, id))?;
+        if let Some(folder_id) = folder_id.filter(|_| !archived) {
+            unarchive_folder_chain(&tx, &folder_id)?;
+        }
+        tx.commit()?;
+    }
+    get_note_sync(db, id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", id)))
+}
+
+/// Archives or restores a folder, its sub-folders and every note they contain.
+/// Returns the number of notes affected.
+fn set_folder_archived(db: &Database, id: &str, archived: bool) -> Result<usize, StemError> {
+    let mut conn = db.connection_mut();
+    let tx = conn.transaction()?;
+
+    let exists: bool = tx.query_row("SELECT COUNT(*) > 0 FROM folders WHERE id = ?1", [id], |row| row.get(0))?;
+    if !exists {
+        return Err(StemError::NotFound(format!("Folder {}", id)));
+    }
+
+    tx.execute(
+        &format!("WITH RECURSIVE {FOLDER_SUBTREE_CTE} UPDATE folders SET is_archived = ?2 WHERE id IN subtree"),
+        (id, archived as i32),
+    )?;
+    let notes = tx.execute(
+        &format!("WITH RECURSIVE {FOLDER_SUBTREE_CTE} UPDATE notes SET is_archived = ?2 WHERE folder_id IN subtree"),
+        (id, archived as i32),
+    )?;
+    if !archived {
+        unarchive_folder_chain(&tx, id)?;
+    }
+
+    tx.commit()?;
+    Ok(notes)
+}
+
+#[tauri::command]
+pub async fn archive_note(db: State<'_, Database>, id: String) -> Result<Note, StemError> {
+    db.inner().clone().spawn(move |db| set_note_archived(&db, &id, true)).await
+}
+
+#[tauri::command]
+pub async fn unarchive_note(db: State<'_, Database>, id: String) -> Result<Note, StemError> {
+    db.inner().clone().spawn(move |db| set_note_archived(&db, &id, false)).await
+}
+
+#[tauri::command]
+pub async fn archive_folder(db: State<'_, Database>, id: String) -> Result<usize, StemError> {
+    db.inner().clone().spawn(move |db| set_folder_archived(&db, &id, true)).await
+}
+
+#[tauri::command]
+pub async fn unarchive_folder(db: State<'_, Database>, id: String) -> Result<usize, StemError> {
+    db.inner().clone().spawn(move |db| set_folder_archived(&db, &id, false)).await
+}
+
 // ===== EXPORT / IMPORT =====
 
+/// Version written by `export_all_data`. Files without a version predate it.
+pub const EXPORT_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 pub struct ExportData {
+    #[serde(default)]
     pub version: u32,
     pub notes: Vec<Note>,
     #[serde(default)]
     pub folders: Vec<Folder>,
 }
 
+/// Part of the vault to export: whole folders (optionally without their
+/// sub-folders) and/or individual notes.
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportScope {
+    #[serde(default)]
+    pub folder_ids: Vec<String>,
+    /// Defaults to true.
+    pub include_descendants: Option<bool>,
+    #[serde(default)]
+    pub note_ids: Vec<String>,
+}
+
+/// Ids selected by an `ExportScope`. `folder_ids` also holds every ancestor
+/// of a selected folder or note, so the exported tree has no dangling parent.
+#[derive(Debug, Default)]
+pub(crate) struct ScopedIds {
+    pub note_ids: HashSet<String>,
+    pub folder_ids: HashSet<String>,
+}
+
+impl ScopedIds {
+    pub fn retain(&self, notes: &mut Vec<Note>, folders: &mut Vec<Folder>) {
+        notes.retain(|n| self.note_ids.contains(&n.id));
+        folders.retain(|f| self.folder_ids.contains(&f.id));
+    }
+}
+
+pub(crate) fn resolve_scope(conn: &rusqlite::Connection, scope: &ExportScope) -> Result<ScopedIds, StemError> {
+    if scope.folder_ids.is_empty() && scope.note_ids.is_empty() {
+        return Err(StemError::Validation("Aucun dossier ni note sélectionné pour l'export".to_string()));
+    }
+
+    let mut stmt = conn.prepare("SELECT id, parent_id FROM folders")?;
+    let parents: HashMap<String, Option<String>> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let mut stmt = conn.prepare("SELECT id, folder_id FROM notes")?;
+    let note_folders: HashMap<String, Option<String>> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut selected: HashSet<String> = HashSet::new();
+    for id in &scope.folder_ids {
+        if !parents.contains_key(id) {
+            return Err(StemError::NotFound(format!("Folder {}", id)));
+        }
+        if scope.include_descendants.unwrap_or(true) {
+            let mut stmt = conn.prepare(&format!("WITH RECURSIVE {FOLDER_SUBTREE_CTE} SELECT id FROM subtree"))?;
+            let subtree = stmt.query_map([id], |row| row.get::<_, String>(0))?;
+            for folder in subtree {
+                selected.insert(folder?);
+            }
+        } else {
+            selected.insert(id.clone());
+        }
+    }
+
+    let mut scoped = ScopedIds::default();
+    for (id, folder_id) in &note_folders {
+        if folder_id.as_ref().is_some_and(|f| selected.contains(f)) {
+            scoped.note_ids.insert(id.clone());
+        }
+    }
+    for id in &scope.note_ids {
+        if !note_folders.contains_key(id) {
+            return Err(StemError::NotFound(format!("Note {}", id)));
+        }
+        scoped.note_ids.insert(id.clone());
+    }
+
+    let needed = selected
+        .into_iter()
+        .chain(scoped.note_ids.iter().filter_map(|id| note_folders[id].clone()));
+    for mut id in needed {
+        // Walk up to the root; stops on a cycle or a dangling parent
+        while parents.contains_key(&id) && scoped.folder_ids.insert(id.clone()) {
+            match &parents[&id] {
+                Some(parent) => id = parent.clone(),
+                None => break,
+            }
+        }
+    }
+    Ok(scoped)
+}
+
+/// Exports every note and folder, or only those selected by `scope`.
 #[tauri::command]
-pub async fn export_all_data(db: State<'_, Database>) -> Result<String, StemError> {
+pub async fn export_all_data(
+    db: State<'_, Database>,
+    scope: Option<ExportScope>,
+    passphrase: Option<String>,
+) -> Result<String, StemError> {
+    if let Some(passphrase) = &passphrase {
+        encryption::validate_passphrase(passphrase)?;
+    }
     db.inner().clone().spawn(move |db| {
         let conn = db.try_connection()?;
 
-        let mut stmt = conn.prepare("SELECT id, title, content, created_at, updated_at, is_pinned, folder_id FROM notes ORDER BY updated_at DESC")?;
-        let notes = stmt.query_map([], row_to_note)?
+        let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes ORDER BY updated_at DESC"))?;
+        let mut notes = stmt.query_map([], row_to_note)?
             .collect::<Result<Vec<_>, _>>()?;
 
-        let mut folder_stmt = conn.prepare("SELECT id, name, parent_id, position, created_at FROM folders ORDER BY position ASC")?;
-        let folders = folder_stmt.query_map([], row_to_folder)?
+        let mut folder_stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders ORDER BY position ASC"))?;
+        let mut folders = folder_stmt.query_map([], row_to_folder)?
             .collect::<Result<Vec<_>, _>>()?;
 
-        let export = ExportData { version: 1, notes, folders };
-        serde_json::to_string_pretty(&export).map_err(|e| StemError::Validation(e.to_string()))
+        if let Some(scope) = &scope {
+            resolve_scope(&conn, scope)?.retain(&mut notes, &mut folders);
+        }
+
+        let export = ExportData { version: EXPORT_VERSION, notes, folders };
+        let json = serde_json::to_string_pretty(&export).map_err(|e| StemError::Validation(e.to_string()))?;
+        match passphrase {
+            Some(passphrase) => bundle::seal_text(&json, &passphrase),
+            None => Ok(json),
+        }
     }).await
 }
 
 const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024; // 10 MB
 
+/// Parses an `export_all_data` payload, decrypting it first when it was
+/// exported with a passphrase.
+fn parse_export(data: &str, passphrase: Option<&str>) -> Result<ExportData, StemError> {
+    let decrypted;
+    let json = if bundle::is_armored(data) {
+        decrypted = bundle::open_text(data, bundle::require_passphrase(passphrase)?)?;
+        decrypted.as_str()
+    } else {
+        data
+    };
+    serde_json::from_str(json).map_err(|e| StemError::Validation(format!("Format de fichier invalide: {}", e)))
+}
+
+/// Message shown after an import; shared by every importer.
+pub(crate) fn import_summary(notes: u32, folders: u32) -> String {
+    format!("{} notes, {} dossiers importés", notes, folders)
+}
+
 #[tauri::command]
-pub async fn import_all_data(db: State<'_, Database>, data: String) -> Result<String, StemError> {
+pub async fn import_all_data(
+    db: State<'_, Database>,
+    data: String,
+    strategy: Option<ConflictStrategy>,
+    passphrase: Option<String>,
+) -> Result<String, StemError> {
     if data.len() > MAX_IMPORT_SIZE {
         return Err(StemError::Validation(format!("Fichier trop volumineux ({:.1} MB, max {} MB)", data.len() as f64 / 1_048_576.0, MAX_IMPORT_SIZE / 1_048_576)));
     }
 
     db.inner().clone().spawn(move |db| {
-        let export: ExportData = serde_json::from_str(&data)
-            .map_err(|e| StemError::Validation(format!("Format de fichier invalide: {}", e)))?;
-
-        let mut conn = db.connection_mut();
-        let tx = conn.transaction()?;
-
-        let mut notes_imported = 0u32;
-        let mut folders_imported = 0u32;
-
-        for folder in &export.folders {
-            let exists: bool = tx
-                .query_row("SELECT COUNT(*) > 0 FROM folders WHERE id = ?1", [&folder.id], |row| row.get(0))?;
-            if !exists {
-                tx.execute(
-                    "INSERT INTO folders (id, name, parent_id, position, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-                    (&folder.id, &folder.name, &folder.parent_id, &folder.position, &folder.created_at),
-                )?;
-                folders_imported += 1;
-            }
-        }
+        let export = parse_export(&data, passphrase.as_deref())?;
+        Ok(merge_export_data(&db, export, strategy.unwrap_or_default(), false)?.summary)
+    }).await
+}
 
-        for note in &export.notes {
-            let exists: bool = tx
-                .query_row("SELECT COUNT(*) > 0 FROM notes WHERE id = ?1", [&note.id], |row| row.get(0))?;
-            if !exists {
-                tx.execute(
-                    "INSERT INTO notes (id, title, content, created_at, updated_at, is_pinned, folder_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    (&note.id, &note.title, &note.content, &note.created_at, &note.updated_at, &(note.is_pinned as i32), &note.folder_id),
-                )?;
-                notes_imported += 1;
-            }
-        }
+/// Dry run of `import_all_data`: what would be inserted, changed or left alone.
+#[tauri::command]
+pub async fn preview_import_data(
+    db: State<'_, Database>,
+    data: String,
+    strategy: Option<ConflictStrategy>,
+    passphrase: Option<String>,
+) -> Result<ImportDiff, StemError> {
+    if data.len() > MAX_IMPORT_SIZE {
+        return Err(StemError::Validation(format!("Fichier trop volumineux ({:.1} MB, max {} MB)", data.len() as f64 / 1_048_576.0, MAX_IMPORT_SIZE / 1_048_576)));
+    }
 
-        tx.commit()?;
-        Ok(format!("{} notes, {} dossiers importés", notes_imported, folders_imported))
+    db.inner().clone().spawn(move |db| {
+        let export = parse_export(&data, passphrase.as_deref())?;
+        merge_export_data(&db, export, strategy.unwrap_or_default(), true)
     }).await
 }
 
 // ===== FOLDERS =====
 
+/// Archived folders are left out unless `include_archived` is set.
 #[tauri::command]
-pub async fn get_all_folders(db: State<'_, Database>) -> Result<Vec<Folder>, StemError> {
+pub async fn get_all_folders(db: State<'_, Database>, include_archived: Option<bool>) -> Result<Vec<Folder>, StemError> {
+    let include_archived = include_archived.unwrap_or(false);
     db.inner().clone().spawn(move |db| {
         let conn = db.try_connection()?;
-        let mut stmt = conn.prepare("SELECT id, name, parent_id, position, created_at FROM folders ORDER BY position ASC, created_at ASC")?;
-        let folders = stmt.query_map([], row_to_folder)?
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {FOLDER_COLUMNS} FROM folders WHERE ?1 OR is_archived = 0 ORDER BY position ASC, created_at ASC"
+        ))?;
+        let folders = stmt.query_map([include_archived], row_to_folder)?
             .collect::<Result<Vec<_>, _>>()?;
         Ok(folders)
     }).await
@@ -278,7 +710,7 @@ pub async fn create_folder(db: State<'_, Database>, payload: CreateFolderPayload
             (&id, &payload.name, &payload.parent_id, &position, &now),
         )?;
 
-        Ok(Folder { id, name: payload.name, parent_id: payload.parent_id, position, created_at: now })
+        Ok(Folder { id, name: payload.name, parent_id: payload.parent_id, position, created_at: now, is_archived: false })
     }).await
 }
 
@@ -293,7 +725,7 @@ pub async fn rename_folder(db: State<'_, Database>, payload: RenameFolderPayload
         drop(conn);
 
         let conn = db.try_connection()?;
-        let mut stmt = conn.prepare("SELECT id, name, parent_id, position, created_at FROM folders WHERE id = ?1")?;
+        let mut stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders WHERE id = ?1"))?;
         stmt.query_row([&payload.id], row_to_folder)
             .map_err(|_| StemError::NotFound(format!("Folder {}", payload.id)))
     }).await
@@ -343,7 +775,7 @@ pub async fn move_folder(db: State<'_, Database>, id: String, parent_id: Option<
         drop(conn);
 
         let conn = db.try_connection()?;
-        let mut stmt = conn.prepare("SELECT id, name, parent_id, position, created_at FROM folders WHERE id = ?1")?;
+        let mut stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders WHERE id = ?1"))?;
         stmt.query_row([&id], row_to_folder)
             .map_err(|_| StemError::NotFound(format!("Folder {}", id)))
     }).await
@@ -361,7 +793,7 @@ pub struct ChatMessage {
     pub created_at: i64,
 }
 
-fn row_to_chat_message(row: &rusqlite::Row) -> Result<ChatMessage, rusqlite::Error> {
+pub(crate) fn row_to_chat_message(row: &rusqlite::Row) -> Result<ChatMessage, rusqlite::Error> {
     Ok(ChatMessage {
         id: row.get(0)?,
         role: row.get(1)?,
@@ -527,4 +959,196 @@ mod tests {
         assert_eq!(notes[0].id, "n2"); // Pinned first
         assert_eq!(notes[1].id, "n1");
     }
+
+    fn insert_full_note(db: &Database, id: &str, title: &str, content: &str, updated_at: i64, folder_id: Option<&str>) {
+        let conn = db.connection();
+        conn.execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES (?1, ?2, ?3, ?4, ?4, ?5)",
+            rusqlite::params![id, title, content, updated_at, folder_id],
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_list_notes_returns_summaries() {
+        let db = setup_db();
+        insert_full_note(&db, "n1", "Hello", "# Heading\n\nSome **bold** words", 1000, None);
+
+        let page = list_notes_sync(&db, &ListNotesPayload::default()).unwrap();
+        assert_eq!(page.notes.len(), 1);
+        assert_eq!(page.notes[0].excerpt, "Heading Some bold words");
+        assert_eq!(page.notes[0].word_count, 4);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_list_notes_pages_with_cursor() {
+        let db = setup_db();
+        for i in 0..5 {
+            insert_full_note(&db, &format!("n{}", i), &format!("Note {}", i), "", 1000 + i, None);
+        }
+        db.connection().execute("UPDATE notes SET is_pinned = 1 WHERE id = 'n1'", []).unwrap();
+
+        let mut payload = ListNotesPayload { limit: Some(2), ..Default::default() };
+        let mut seen = Vec::new();
+        loop {
+            let page = list_notes_sync(&db, &payload).unwrap();
+            seen.extend(page.notes.into_iter().map(|n| n.id));
+            match page.next_cursor {
+                Some(cursor) => payload.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["n1", "n4", "n3", "n2", "n0"]);
+    }
+
+    #[test]
+    fn test_list_notes_pages_by_title_with_equal_titles() {
+        let db = setup_db();
+        // Titles equal ignoring case straddle each page boundary
+        for (id, title) in [("e", "gamma"), ("b", "Alpha"), ("d", "beta"), ("a", "alpha"), ("c", "Beta")] {
+            insert_full_note(&db, id, title, "", 1000, None);
+        }
+
+        for (sort, expected) in [
+            (NoteSort::TitleAsc, vec!["a", "b", "c", "d", "e"]),
+            (NoteSort::TitleDesc, vec!["e", "d", "c", "b", "a"]),
+        ] {
+            let mut payload = ListNotesPayload { sort, limit: Some(1), ..Default::default() };
+            let mut seen = Vec::new();
+            loop {
+                let page = list_notes_sync(&db, &payload).unwrap();
+                seen.extend(page.notes.into_iter().map(|n| n.id));
+                match page.next_cursor {
+                    Some(cursor) => payload.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+            assert_eq!(seen, expected);
+        }
+    }
+
+    #[test]
+    fn test_list_notes_filters_folder_and_sorts_by_title() {
+        let db = setup_db();
+        insert_full_note(&db, "a", "beta", "", 1000, Some("f1"));
+        insert_full_note(&db, "b", "Alpha", "", 1001, Some("f1"));
+        insert_full_note(&db, "c", "gamma", "", 1002, None);
+
+        let payload = ListNotesPayload {
+            folder_id: Some("f1".to_string()),
+            sort: NoteSort::TitleAsc,
+            ..Default::default()
+        };
+        let page = list_notes_sync(&db, &payload).unwrap();
+        let ids: Vec<_> = page.notes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_list_notes_rejects_cursor_from_other_sort() {
+        let db = setup_db();
+        for i in 0..3 {
+            insert_full_note(&db, &format!("n{}", i), "t", "", 1000 + i, None);
+        }
+        let page = list_notes_sync(&db, &ListNotesPayload { limit: Some(1), ..Default::default() }).unwrap();
+        let payload = ListNotesPayload { sort: NoteSort::TitleAsc, cursor: page.next_cursor, ..Default::default() };
+        assert!(list_notes_sync(&db, &payload).is_err());
+    }
+
+    #[test]
+    fn test_archived_notes_hidden_by_default() {
+        let db = setup_db();
+        insert_full_note(&db, "n1", "Kept", "", 1000, None);
+        insert_full_note(&db, "n2", "Archived", "", 1001, None);
+
+        let note = set_note_archived(&db, "n2", true).unwrap();
+        assert!(note.is_archived);
+
+        let page = list_notes_sync(&db, &ListNotesPayload::default()).unwrap();
+        let ids: Vec<_> = page.notes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["n1"]);
+
+        let page = list_notes_sync(&db, &ListNotesPayload { include_archived: true, ..Default::default() }).unwrap();
+        assert_eq!(page.notes.len(), 2);
+
+        assert!(set_note_archived(&db, "missing", true).is_err());
+    }
+
+    #[test]
+    fn test_archive_folder_cascades_and_unarchive_restores_parents() {
+        let db = setup_db();
+        {
+            let conn = db.connection();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('f1', 'Projects', NULL, 0)", []).unwrap();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('f2', 'Done', 'f1', 0)", []).unwrap();
+        }
+        insert_full_note(&db, "n1", "In parent", "", 1000, Some("f1"));
+        insert_full_note(&db, "n2", "In child", "", 1000, Some("f2"));
+        insert_full_note(&db, "n3", "Elsewhere", "", 1000, None);
+
+        assert_eq!(set_folder_archived(&db, "f1", true).unwrap(), 2);
+        let archived: i32 = db.connection()
+            .query_row("SELECT COUNT(*) FROM folders WHERE is_archived = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(archived, 2);
+
+        // Restoring a single note brings its folder chain back too
+        set_note_archived(&db, "n2", false).unwrap();
+        let conn = db.connection();
+        let archived: Vec<String> = conn
+            .prepare("SELECT id FROM folders WHERE is_archived = 1").unwrap()
+            .query_map([], |r| r.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert!(archived.is_empty());
+        let n1_archived: bool = conn.query_row("SELECT is_archived FROM notes WHERE id = 'n1'", [], |r| r.get(0)).unwrap();
+        assert!(n1_archived);
+    }
+
+    #[test]
+    fn test_resolve_scope_keeps_ancestors() {
+        let db = setup_db();
+        {
+            let conn = db.connection();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('root', 'Root', NULL, 0)", []).unwrap();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('p', 'Project', 'root', 0)", []).unwrap();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('sub', 'Sub', 'p', 0)", []).unwrap();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('other', 'Other', NULL, 0)", []).unwrap();
+        }
+        insert_full_note(&db, "n1", "In project", "", 1000, Some("p"));
+        insert_full_note(&db, "n2", "In sub", "", 1000, Some("sub"));
+        insert_full_note(&db, "n3", "Elsewhere", "", 1000, Some("other"));
+        insert_full_note(&db, "n4", "Loose", "", 1000, None);
+
+        let sorted = |ids: &HashSet<String>| {
+            let mut ids: Vec<String> = ids.iter().cloned().collect();
+            ids.sort();
+            ids
+        };
+        let conn = db.connection();
+        let scope = ExportScope { folder_ids: vec!["p".into()], include_descendants: None, note_ids: vec!["n4".into()] };
+        let scoped = resolve_scope(&conn, &scope).unwrap();
+        assert_eq!(sorted(&scoped.note_ids), ["n1", "n2", "n4"]);
+        assert_eq!(sorted(&scoped.folder_ids), ["p", "root", "sub"]);
+
+        let scope = ExportScope { folder_ids: vec!["p".into()], include_descendants: Some(false), note_ids: vec![] };
+        let scoped = resolve_scope(&conn, &scope).unwrap();
+        assert_eq!(sorted(&scoped.note_ids), ["n1"]);
+        assert_eq!(sorted(&scoped.folder_ids), ["p", "root"]);
+
+        let scope = ExportScope { folder_ids: vec![], include_descendants: None, note_ids: vec!["n2".into()] };
+        assert_eq!(sorted(&resolve_scope(&conn, &scope).unwrap().folder_ids), ["p", "root", "sub"]);
+
+        let scope = ExportScope { folder_ids: vec!["missing".into()], ..Default::default() };
+        assert!(matches!(resolve_scope(&conn, &scope), Err(StemError::NotFound(_))));
+        assert!(matches!(resolve_scope(&conn, &ExportScope::default()), Err(StemError::Validation(_))));
+    }
+
+    #[test]
+    fn test_parse_export_decrypts_armored_data() {
+        let json = r#"{"version": 1, "notes": [], "folders": []}"#;
+        let armored = bundle::seal_text(json, "correct horse").unwrap();
+        assert_eq!(parse_export(&armored, Some("correct horse")).unwrap().version, 1);
+        assert!(matches!(parse_export(&armored, None), Err(StemError::Validation(_))));
+        assert!(parse_export(&armored, Some("wrong horse")).is_err());
+        assert_eq!(parse_export(json, Some("ignored")).unwrap().version, 1);
+    }
 }
@@ -1,8 +1,17 @@
+use crate::blocknote::blocknote_to_markdown;
 use crate::error::StemError;
 use rusqlite::{Connection, Result};
-use serde_json::Value;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch, the unit of every `*_at` column.
+pub(crate) fn current_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
 
 #[derive(Clone)]
 pub struct Database {
@@ -50,10 +59,26 @@ impl Database {
             "ALTER TABLE notes ADD COLUMN folder_id TEXT DEFAULT NULL",
             [],
         );
+        // Migration: add is_archived column if missing
+        let _ = conn.execute(
+            "ALTER TABLE notes ADD COLUMN is_archived INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        // Migration: add is_encrypted column if missing
+        let _ = conn.execute(
+            "ALTER TABLE notes ADD COLUMN is_encrypted INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
         // Migration: drop legacy tag tables (ignore errors if they don't exist)
         let _ = conn.execute("DROP TABLE IF EXISTS note_tags", []);
         let _ = conn.execute("DROP TABLE IF EXISTS tags", []);
 
+        // Index for folder-scoped listing (list_notes)
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_notes_folder ON notes(folder_id)",
+            [],
+        )?;
+
         // Folders table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS folders (
@@ -65,6 +90,11 @@ impl Database {
             )",
             [],
         )?;
+        // Migration: add is_archived column to folders if missing
+        let _ = conn.execute(
+            "ALTER TABLE folders ADD COLUMN is_archived INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
 
         // Embeddings table for semantic search (RAG)
         conn.execute(
@@ -90,6 +120,196 @@ impl Database {
             [],
         )?;
 
+        // Statistics cache: per-note counts, invalidated by trigger when content changes
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS note_stats (
+                note_id TEXT PRIMARY KEY REFERENCES notes(id) ON DELETE CASCADE,
+                words INTEGER NOT NULL,
+                chars INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Days on which each note was edited (one row per note and day)
+        let activity_existed = table_exists(&conn, "note_activity")?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS note_activity (
+                note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+                day INTEGER NOT NULL,
+                PRIMARY KEY (note_id, day)
+            ) WITHOUT ROWID",
+            [],
+        )?;
+        if !activity_existed {
+            // Seed with the last known edit so existing vaults don't start empty
+            conn.execute(
+                "INSERT OR IGNORE INTO note_activity (note_id, day)
+                 SELECT id, updated_at / 86400 FROM notes WHERE updated_at / 86400 > created_at / 86400",
+                [],
+            )?;
+        }
+        // Earlier versions had one trigger for both, firing on title edits too
+        conn.execute_batch(
+            "DROP TRIGGER IF EXISTS notes_stats_on_edit;
+             CREATE TRIGGER notes_stats_on_edit
+             AFTER UPDATE OF content ON notes
+             WHEN NEW.content IS NOT OLD.content
+             BEGIN
+                 DELETE FROM note_stats WHERE note_id = NEW.id;
+             END;
+             CREATE TRIGGER IF NOT EXISTS notes_activity_on_edit
+             AFTER UPDATE OF title, content ON notes
+             WHEN NEW.content IS NOT OLD.content OR NEW.title IS NOT OLD.title
+             BEGIN
+                 INSERT OR IGNORE INTO note_activity (note_id, day) VALUES (NEW.id, NEW.updated_at / 86400);
+             END;",
+        )?;
+
+        // Note views, aggregated per note and day (see usage.rs)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS note_views (
+                note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+                day INTEGER NOT NULL,
+                opens INTEGER NOT NULL DEFAULT 1,
+                last_opened_at INTEGER NOT NULL,
+                PRIMARY KEY (note_id, day)
+            ) WITHOUT ROWID",
+            [],
+        )?;
+
+        // Attachment files live in the app data dir; rows outlive deleted notes (see attachments.rs)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                note_id TEXT REFERENCES notes(id) ON DELETE SET NULL,
+                file_name TEXT NOT NULL UNIQUE,
+                original_name TEXT NOT NULL,
+                mime TEXT,
+                size INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Sync backends settings (see sync/), one JSON value per backend
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Notes a sync could not merge automatically (see sync/conflicts.rs)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_conflicts (
+                id TEXT PRIMARY KEY,
+                note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+                title TEXT NOT NULL,
+                base TEXT,
+                local TEXT,
+                remote TEXT,
+                copy_note_id TEXT,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Automerge documents of the notes edited as CRDTs (see sync/crdt.rs)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS note_crdt (
+                note_id TEXT PRIMARY KEY REFERENCES notes(id) ON DELETE CASCADE,
+                doc BLOB NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Sequence of the last change to every note and folder, for LAN sync
+        // (see sync/lan.rs). Logged by triggers so that every write path counts;
+        // they delete then insert because an `OR IGNORE` on the statement that
+        // fires them would override an `OR REPLACE` of their own.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS change_log (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                UNIQUE (kind, item_id)
+            )",
+            [],
+        )?;
+        conn.execute_batch(
+            "CREATE TRIGGER IF NOT EXISTS change_log_note_insert AFTER INSERT ON notes
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'note' AND item_id = NEW.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('note', NEW.id);
+             END;
+             CREATE TRIGGER IF NOT EXISTS change_log_note_update
+             AFTER UPDATE OF title, content, created_at, updated_at, is_pinned, folder_id, is_archived, is_encrypted ON notes
+             WHEN NEW.title IS NOT OLD.title OR NEW.content IS NOT OLD.content OR NEW.created_at IS NOT OLD.created_at
+                 OR NEW.updated_at IS NOT OLD.updated_at OR NEW.is_pinned IS NOT OLD.is_pinned
+                 OR NEW.folder_id IS NOT OLD.folder_id OR NEW.is_archived IS NOT OLD.is_archived
+                 OR NEW.is_encrypted IS NOT OLD.is_encrypted
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'note' AND item_id = NEW.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('note', NEW.id);
+             END;
+             CREATE TRIGGER IF NOT EXISTS change_log_note_delete AFTER DELETE ON notes
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'note' AND item_id = OLD.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('note', OLD.id);
+             END;
+             CREATE TRIGGER IF NOT EXISTS change_log_folder_insert AFTER INSERT ON folders
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'folder' AND item_id = NEW.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('folder', NEW.id);
+             END;
+             CREATE TRIGGER IF NOT EXISTS change_log_folder_update
+             AFTER UPDATE OF name, parent_id, position, created_at, is_archived ON folders
+             WHEN NEW.name IS NOT OLD.name OR NEW.parent_id IS NOT OLD.parent_id OR NEW.position IS NOT OLD.position
+                 OR NEW.created_at IS NOT OLD.created_at OR NEW.is_archived IS NOT OLD.is_archived
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'folder' AND item_id = NEW.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('folder', NEW.id);
+             END;
+             CREATE TRIGGER IF NOT EXISTS change_log_folder_delete AFTER DELETE ON folders
+             BEGIN
+                 DELETE FROM change_log WHERE kind = 'folder' AND item_id = OLD.id;
+                 INSERT INTO change_log (kind, item_id) VALUES ('folder', OLD.id);
+             END;",
+        )?;
+        // Items written before the log existed
+        conn.execute_batch(
+            "INSERT OR IGNORE INTO change_log (kind, item_id) SELECT 'folder', id FROM folders;
+             INSERT OR IGNORE INTO change_log (kind, item_id) SELECT 'note', id FROM notes;",
+        )?;
+
+        // Paired devices and, per device, the version of every item both had
+        // at the end of the last sync (the merge base)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS lan_peers (
+                device_id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                address TEXT,
+                secret BLOB NOT NULL,
+                local_seq INTEGER NOT NULL DEFAULT 0,
+                remote_seq INTEGER NOT NULL DEFAULT 0,
+                paired_at INTEGER NOT NULL,
+                last_sync_at INTEGER
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS lan_base (
+                peer_id TEXT NOT NULL REFERENCES lan_peers(device_id) ON DELETE CASCADE,
+                kind TEXT NOT NULL,
+                item_id TEXT NOT NULL,
+                text TEXT NOT NULL,
+                PRIMARY KEY (peer_id, kind, item_id)
+            ) WITHOUT ROWID",
+            [],
+        )?;
+
         // Migration v1: BlockNote JSON → Markdown
         let version: i32 = conn
             .query_row("PRAGMA user_version", [], |row| row.get(0))
@@ -152,124 +372,12 @@ impl Database {
     }
 }
 
-/// Converts BlockNote JSON content to Markdown.
-/// Returns `Some(markdown)` if the input is valid BlockNote JSON, `None` otherwise.
-fn blocknote_to_markdown(content: &str) -> Option<String> {
-    let blocks: Vec<Value> = serde_json::from_str(content).ok()?;
-    if blocks.is_empty() {
-        return None;
-    }
-    // Verify it looks like BlockNote (first block should have a "type" field)
-    blocks.first()?.get("type")?;
-
-    let mut lines: Vec<String> = Vec::new();
-
-    for block in &blocks {
-        let block_type = block.get("type")?.as_str().unwrap_or("paragraph");
-        let text = extract_inline_content(block.get("content"));
-
-        match block_type {
-            "heading" => {
-                let level = block
-                    .get("props")
-                    .and_then(|p| p.get("level"))
-                    .and_then(|l| l.as_u64())
-                    .unwrap_or(1);
-                let prefix = "#".repeat(level as usize);
-                lines.push(format!("{} {}", prefix, text));
-            }
-            "bulletListItem" => {
-                lines.push(format!("- {}", text));
-            }
-            "numberedListItem" => {
-                lines.push(format!("1. {}", text));
-            }
-            "checkListItem" => {
-                let checked = block
-                    .get("props")
-                    .and_then(|p| p.get("checked"))
-                    .and_then(|c| c.as_bool())
-                    .unwrap_or(false);
-                let marker = if checked { "[x]" } else { "[ ]" };
-                lines.push(format!("- {} {}", marker, text));
-            }
-            "codeBlock" => {
-                let lang = block
-                    .get("props")
-                    .and_then(|p| p.get("language"))
-                    .and_then(|l| l.as_str())
-                    .unwrap_or("");
-                lines.push(format!("```{}", lang));
-                lines.push(text);
-                lines.push("```".to_string());
-            }
-            _ => {
-                // paragraph, image, etc. — just output the text
-                lines.push(text);
-            }
-        }
-
-        // Process children (nested blocks)
-        if let Some(children) = block.get("children") {
-            if let Some(arr) = children.as_array() {
-                for child in arr {
-                    let child_type = child.get("type").and_then(|t| t.as_str()).unwrap_or("paragraph");
-                    let child_text = extract_inline_content(child.get("content"));
-                    match child_type {
-                        "bulletListItem" => lines.push(format!("  - {}", child_text)),
-                        "numberedListItem" => lines.push(format!("  1. {}", child_text)),
-                        _ => {
-                            if !child_text.is_empty() {
-                                lines.push(format!("  {}", child_text));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    let result = lines.join("\n").trim().to_string();
-    if result.is_empty() { None } else { Some(result) }
-}
-
-/// Extracts inline text from a BlockNote "content" array, applying styles.
-fn extract_inline_content(content: Option<&Value>) -> String {
-    let arr = match content.and_then(|c| c.as_array()) {
-        Some(a) => a,
-        None => return String::new(),
-    };
-
-    let mut result = String::new();
-    for item in arr {
-        let text = item.get("text").and_then(|t| t.as_str()).unwrap_or("");
-        if text.is_empty() {
-            continue;
-        }
-
-        let styles = item.get("styles");
-        let bold = styles.and_then(|s| s.get("bold")).and_then(|b| b.as_bool()).unwrap_or(false);
-        let italic = styles.and_then(|s| s.get("italic")).and_then(|b| b.as_bool()).unwrap_or(false);
-        let code = styles.and_then(|s| s.get("code")).and_then(|b| b.as_bool()).unwrap_or(false);
-        let strike = styles.and_then(|s| s.get("strikethrough")).and_then(|b| b.as_bool()).unwrap_or(false);
-
-        let mut formatted = text.to_string();
-        if code {
-            formatted = format!("`{}`", formatted);
-        } else {
-            if bold {
-                formatted = format!("**{}**", formatted);
-            }
-            if italic {
-                formatted = format!("*{}*", formatted);
-            }
-            if strike {
-                formatted = format!("~~{}~~", formatted);
-            }
-        }
-        result.push_str(&formatted);
-    }
-    result
+fn table_exists(conn: &Connection, name: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |row| row.get(0),
+    )
 }
 
 #[cfg(test)]
@@ -1,7 +1,7 @@
-use crate::db::Database;
+use crate::db::{current_timestamp, Database};
 use crate::error::StemError;
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
 use tauri::State;
 
 // ===== Ollama Embedding API types =====
@@ -28,13 +28,6 @@ pub struct SemanticResult {
 
 // ===== Helpers =====
 
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() as i64
-}
-
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() {
         return 0.0;
@@ -59,13 +52,10 @@ fn bytes_to_embedding(bytes: &[u8]) -> Vec<f32> {
         .collect()
 }
 
-// ===== Tauri Commands =====
-
-/// Generate an embedding vector from text via Ollama and store it for the given note.
-#[tauri::command]
-pub async fn generate_embedding(
-    client: State<'_, reqwest::Client>,
-    db: State<'_, Database>,
+/// Embeds `text` via Ollama and stores the vector for `note_id`.
+pub(crate) async fn embed_note(
+    client: &reqwest::Client,
+    db: &Database,
     note_id: String,
     text: String,
     model: Option<String>,
@@ -75,6 +65,19 @@ pub async fn generate_embedding(
         return Ok(());
     }
 
+    // Encrypted notes never leave the machine, not even to a local model
+    let encrypted_note_id = note_id.clone();
+    let is_encrypted = db.clone().spawn(move |db| {
+        let conn = db.try_connection()?;
+        let encrypted: Option<bool> = conn
+            .query_row("SELECT is_encrypted FROM notes WHERE id = ?1", [&encrypted_note_id], |row| row.get(0))
+            .optional()?;
+        Ok(encrypted.unwrap_or(false))
+    }).await?;
+    if is_encrypted {
+        return Ok(());
+    }
+
     let model = model.unwrap_or_else(|| "nomic-embed-text".to_string());
     let base_url = ollama_url.unwrap_or_else(|| "http://localhost:11434".to_string());
 
@@ -109,7 +112,7 @@ pub async fn generate_embedding(
     let now = current_timestamp();
 
     // Store in DB
-    db.inner().clone().spawn(move |db| {
+    db.clone().spawn(move |db| {
         let conn = db.try_connection()?;
         conn.execute(
             "INSERT OR REPLACE INTO note_embeddings (note_id, embedding, model, updated_at) VALUES (?1, ?2, ?3, ?4)",
@@ -120,8 +123,24 @@ pub async fn generate_embedding(
     .await
 }
 
+// ===== Tauri Commands =====
+
+/// Generate an embedding vector from text via Ollama and store it for the given note.
+#[tauri::command]
+pub async fn generate_embedding(
+    client: State<'_, reqwest::Client>,
+    db: State<'_, Database>,
+    note_id: String,
+    text: String,
+    model: Option<String>,
+    ollama_url: Option<String>,
+) -> Result<(), StemError> {
+    embed_note(client.inner(), db.inner(), note_id, text, model, ollama_url).await
+}
+
 /// Search for notes semantically similar to the given query text.
 /// Returns top `limit` results sorted by cosine similarity.
+/// Archived notes are skipped unless `include_archived` is set.
 #[tauri::command]
 pub async fn search_similar_notes(
     client: State<'_, reqwest::Client>,
@@ -130,6 +149,7 @@ pub async fn search_similar_notes(
     model: Option<String>,
     ollama_url: Option<String>,
     limit: Option<usize>,
+    include_archived: Option<bool>,
 ) -> Result<Vec<SemanticResult>, StemError> {
     if query.trim().is_empty() {
         return Ok(vec![]);
@@ -138,6 +158,7 @@ pub async fn search_similar_notes(
     let model = model.unwrap_or_else(|| "nomic-embed-text".to_string());
     let base_url = ollama_url.unwrap_or_else(|| "http://localhost:11434".to_string());
     let limit = limit.unwrap_or(5);
+    let include_archived = include_archived.unwrap_or(false);
 
     let request = OllamaEmbedRequest {
         model,
@@ -174,11 +195,12 @@ pub async fn search_similar_notes(
                 "SELECT ne.note_id, ne.embedding, n.title
                  FROM note_embeddings ne
                  JOIN notes n ON n.id = ne.note_id
+                 WHERE (?1 OR n.is_archived = 0) AND n.is_encrypted = 0
                  ORDER BY ne.updated_at DESC",
             )?;
 
         let mut results: Vec<SemanticResult> = stmt
-            .query_map([], |row| {
+            .query_map([include_archived], |row| {
                 let note_id: String = row.get(0)?;
                 let embedding_bytes: Vec<u8> = row.get(1)?;
                 let title: String = row.get(2)?;
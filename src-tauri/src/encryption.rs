@@ -0,0 +1,427 @@
+use crate::commands::{get_note_sync, Note, NOTE_COLUMNS};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD_NO_PAD as B64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+use zeroize::Zeroizing;
+
+/// Every encrypted `content` starts with this marker.
+const ENVELOPE_PREFIX: &str = "stem-enc:v1:";
+
+const MIN_PASSPHRASE_LEN: usize = 8;
+pub(crate) const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// Upper bounds for KDF parameters read from a file, a remote or a peer, so
+/// that a crafted header cannot make the app allocate gigabytes or spin for
+/// minutes.
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+
+// ===== KDF parameters =====
+
+/// Argon2id cost parameters, stored in every envelope so they can be raised
+/// later without breaking notes encrypted with older settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// OWASP-recommended Argon2id baseline.
+    fn default() -> Self {
+        Self { memory_kib: 19_456, iterations: 2, parallelism: 1 }
+    }
+}
+
+impl KdfParams {
+    fn header(&self) -> String {
+        format!("argon2id$m={},t={},p={}", self.memory_kib, self.iterations, self.parallelism)
+    }
+
+    /// Rejects parameters above the bounds, which the app never writes itself.
+    pub(crate) fn validate(&self) -> Result<(), StemError> {
+        if self.memory_kib > MAX_MEMORY_KIB || self.iterations > MAX_ITERATIONS || self.parallelism > MAX_PARALLELISM {
+            return Err(StemError::Validation("Paramètres de chiffrement non pris en charge".to_string()));
+        }
+        Ok(())
+    }
+
+    fn parse(header: &str) -> Result<Self, StemError> {
+        let parsed = (|| {
+            let params = header.strip_prefix("argon2id$")?;
+            let mut values = HashMap::new();
+            for pair in params.split(',') {
+                let (k, v) = pair.split_once('=')?;
+                values.insert(k, v.parse::<u32>().ok()?);
+            }
+            Some(Self {
+                memory_kib: *values.get("m")?,
+                iterations: *values.get("t")?,
+                parallelism: *values.get("p")?,
+            })
+        })();
+        let params = parsed.ok_or_else(|| StemError::Validation("Contenu chiffré illisible".to_string()))?;
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+/// Argon2id key for `passphrase`; also used for encrypted export bundles.
+pub(crate) fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; KEY_LEN]>, StemError> {
+    let argon_params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LEN))
+        .map_err(|e| StemError::Validation(format!("Paramètres de chiffrement invalides: {}", e)))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| StemError::Validation(format!("Dérivation de clé impossible: {}", e)))?;
+    Ok(key)
+}
+
+// ===== Envelope =====
+
+/// Key material kept in memory while a note is unlocked.
+#[derive(Clone)]
+pub struct NoteKey {
+    key: Zeroizing<[u8; KEY_LEN]>,
+    salt: Vec<u8>,
+    params: KdfParams,
+}
+
+impl NoteKey {
+    pub fn derive(passphrase: &str, salt: Vec<u8>, params: KdfParams) -> Result<Self, StemError> {
+        let key = derive_key(passphrase, &salt, params)?;
+        Ok(Self { key, salt, params })
+    }
+
+    /// Derives a key for a fresh random salt.
+    pub fn generate(passphrase: &str, params: KdfParams) -> Result<Self, StemError> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt, params)
+    }
+
+    fn header(&self) -> String {
+        format!("{}{}${}", ENVELOPE_PREFIX, self.params.header(), B64.encode(&self.salt))
+    }
+
+    /// Encrypts `plaintext` with a random nonce. The header (KDF parameters and
+    /// salt) is authenticated as associated data.
+    pub fn seal(&self, plaintext: &str) -> Result<String, StemError> {
+        let header = self.header();
+        let cipher = XChaCha20Poly1305::new(self.key.as_ref().into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext.as_bytes(), aad: header.as_bytes() })
+            .map_err(|_| StemError::Validation("Échec du chiffrement".to_string()))?;
+        Ok(format!("{}${}${}", header, B64.encode(nonce), B64.encode(ciphertext)))
+    }
+
+    /// Decrypts an envelope produced by `seal` with the same key.
+    pub fn open(&self, envelope: &str) -> Result<String, StemError> {
+        let parsed = Envelope::parse(envelope)?;
+        let cipher = XChaCha20Poly1305::new(self.key.as_ref().into());
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&parsed.nonce), Payload { msg: &parsed.ciphertext, aad: parsed.header.as_bytes() })
+            .map_err(|_| StemError::Validation("Phrase de passe incorrecte".to_string()))?;
+        String::from_utf8(plaintext).map_err(|_| StemError::Validation("Contenu déchiffré invalide".to_string()))
+    }
+
+    /// Derives the key from `passphrase` using the envelope's own salt and
+    /// parameters, then decrypts it.
+    pub fn unlock(passphrase: &str, envelope: &str) -> Result<(Self, String), StemError> {
+        let parsed = Envelope::parse(envelope)?;
+        let key = Self::derive(passphrase, parsed.salt, parsed.params)?;
+        let plaintext = key.open(envelope)?;
+        Ok((key, plaintext))
+    }
+}
+
+struct Envelope {
+    header: String,
+    params: KdfParams,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Layout: `stem-enc:v1:argon2id$m=..,t=..,p=..$<salt>$<nonce>$<ciphertext>` (base64).
+    fn parse(envelope: &str) -> Result<Self, StemError> {
+        let invalid = || StemError::Validation("Contenu chiffré illisible".to_string());
+        let body = envelope.strip_prefix(ENVELOPE_PREFIX).ok_or_else(invalid)?;
+        let parts: Vec<&str> = body.split('$').collect();
+        let [kdf, raw_params, salt, nonce, ciphertext] = parts[..] else {
+            return Err(invalid());
+        };
+        let params = KdfParams::parse(&format!("{}${}", kdf, raw_params))?;
+        let nonce = B64.decode(nonce).map_err(|_| invalid())?;
+        if nonce.len() != 24 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            header: format!("{}{}${}${}", ENVELOPE_PREFIX, kdf, raw_params, salt),
+            params,
+            salt: B64.decode(salt).map_err(|_| invalid())?,
+            nonce,
+            ciphertext: B64.decode(ciphertext).map_err(|_| invalid())?,
+        })
+    }
+}
+
+// ===== Session state =====
+
+/// Keys of the notes unlocked during this session. Never persisted.
+#[derive(Clone, Default)]
+pub struct SessionKeys {
+    keys: Arc<Mutex<HashMap<String, NoteKey>>>,
+}
+
+impl SessionKeys {
+    pub fn get(&self, note_id: &str) -> Option<NoteKey> {
+        self.keys.lock().ok()?.get(note_id).cloned()
+    }
+
+    fn insert(&self, note_id: String, key: NoteKey) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.insert(note_id, key);
+        }
+    }
+
+    fn remove(&self, note_id: &str) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.remove(note_id);
+        }
+    }
+}
+
+// ===== Helpers =====
+
+/// Loads the raw (possibly encrypted) content of a note.
+fn raw_note(db: &Database, id: &str) -> Result<Note, StemError> {
+    let conn = db.try_connection()?;
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"))?;
+    stmt.query_row([id], crate::commands::row_to_note)
+        .optional()?
+        .ok_or_else(|| StemError::NotFound(format!("Note {}", id)))
+}
+
+pub(crate) fn validate_passphrase(passphrase: &str) -> Result<(), StemError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(StemError::Validation(format!(
+            "La phrase de passe doit contenir au moins {} caractères",
+            MIN_PASSPHRASE_LEN
+        )));
+    }
+    Ok(())
+}
+
+fn found(db: &Database, id: &str) -> Result<Note, StemError> {
+    get_note_sync(db, id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", id)))
+}
+
+pub(crate) fn encrypt_note_sync(db: &Database, id: &str, passphrase: &str, params: KdfParams) -> Result<Note, StemError> {
+    validate_passphrase(passphrase)?;
+    let note = raw_note(db, id)?;
+    if note.is_encrypted {
+        return Err(StemError::Validation("Cette note est déjà chiffrée".to_string()));
+    }
+
+    let key = NoteKey::generate(passphrase, params)?;
+    let envelope = key.seal(note.content.as_deref().unwrap_or(""))?;
+
+    let conn = db.try_connection()?;
+    conn.execute(
+        "UPDATE notes SET content = ?1, is_encrypted = 1, updated_at = ?2 WHERE id = ?3",
+        rusqlite::params![envelope, current_timestamp(), id],
+    )?;
+    // The stored vector would leak what the note is about
+    conn.execute("DELETE FROM note_embeddings WHERE note_id = ?1", [id])?;
+    // So would the editing history
+    conn.execute("DELETE FROM note_crdt WHERE note_id = ?1", [id])?;
+    drop(conn);
+    found(db, id)
+}
+
+/// Returns the note with its plaintext content and keeps the key for the session.
+pub(crate) fn unlock_note_sync(db: &Database, keys: &SessionKeys, id: &str, passphrase: &str) -> Result<Note, StemError> {
+    let mut note = raw_note(db, id)?;
+    let envelope = match (&note.content, note.is_encrypted) {
+        (Some(content), true) => content.clone(),
+        _ => return Err(StemError::Validation("Cette note n'est pas chiffrée".to_string())),
+    };
+    let (key, plaintext) = NoteKey::unlock(passphrase, &envelope)?;
+    keys.insert(id.to_string(), key);
+    note.content = Some(plaintext);
+    Ok(note)
+}
+
+/// Removes the encryption for good, storing the plaintext back in `content`.
+pub(crate) fn decrypt_note_sync(db: &Database, keys: &SessionKeys, id: &str, passphrase: &str) -> Result<Note, StemError> {
+    let plaintext = unlock_note_sync(db, keys, id, passphrase)?.content.unwrap_or_default();
+    keys.remove(id);
+
+    let conn = db.try_connection()?;
+    conn.execute(
+        "UPDATE notes SET content = ?1, is_encrypted = 0, updated_at = ?2 WHERE id = ?3",
+        rusqlite::params![plaintext, current_timestamp(), id],
+    )?;
+    drop(conn);
+    found(db, id)
+}
+
+/// Decrypts an encrypted note's content with the key unlocked this session.
+pub(crate) fn open_content(keys: &SessionKeys, id: &str, envelope: &str) -> Result<String, StemError> {
+    let key = keys
+        .get(id)
+        .ok_or_else(|| StemError::Validation("Note verrouillée : déverrouillez-la avant de la lire".to_string()))?;
+    key.open(envelope)
+}
+
+/// Encrypts new content for an encrypted note with the key unlocked this session.
+pub(crate) fn reseal_content(keys: &SessionKeys, id: &str, plaintext: &str) -> Result<String, StemError> {
+    let key = keys
+        .get(id)
+        .ok_or_else(|| StemError::Validation("Note verrouillée : déverrouillez-la avant de la modifier".to_string()))?;
+    key.seal(plaintext)
+}
+
+// ===== Tauri Commands =====
+
+/// Encrypts a note's content with a key derived from `passphrase`.
+#[tauri::command]
+pub async fn encrypt_note(db: State<'_, Database>, id: String, passphrase: String) -> Result<Note, StemError> {
+    let passphrase = Zeroizing::new(passphrase);
+    db.inner().clone().spawn(move |db| encrypt_note_sync(&db, &id, &passphrase, KdfParams::default())).await
+}
+
+/// Decrypts a note for this session; edits are re-encrypted until it is locked again.
+#[tauri::command]
+pub async fn unlock_note(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    id: String,
+    passphrase: String,
+) -> Result<Note, StemError> {
+    let passphrase = Zeroizing::new(passphrase);
+    let keys = keys.inner().clone();
+    db.inner().clone().spawn(move |db| unlock_note_sync(&db, &keys, &id, &passphrase)).await
+}
+
+/// Forgets the session key of a note.
+#[tauri::command]
+pub async fn lock_note(db: State<'_, Database>, keys: State<'_, SessionKeys>, id: String) -> Result<Note, StemError> {
+    keys.remove(&id);
+    db.inner().clone().spawn(move |db| found(&db, &id)).await
+}
+
+/// Permanently removes the encryption of a note.
+#[tauri::command]
+pub async fn decrypt_note(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    id: String,
+    passphrase: String,
+) -> Result<Note, StemError> {
+    let passphrase = Zeroizing::new(passphrase);
+    let keys = keys.inner().clone();
+    db.inner().clone().spawn(move |db| decrypt_note_sync(&db, &keys, &id, &passphrase)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{update_note_sync, UpdateNotePayload};
+
+    // Cheap parameters keep the tests fast; production uses KdfParams::default().
+    const TEST_PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn setup_db() -> Database {
+        let db = Database::in_memory().expect("Failed to create in-memory DB");
+        db.init().expect("Failed to init DB");
+        db.connection().execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('n1', 'Secret', 'my password is hunter2', 0, 0)",
+            [],
+        ).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let key = NoteKey::generate("correct horse", TEST_PARAMS).unwrap();
+        let envelope = key.seal("hello").unwrap();
+        assert!(envelope.starts_with(ENVELOPE_PREFIX));
+        assert!(envelope.contains("argon2id$m=64,t=1,p=1$"));
+
+        let (_, plaintext) = NoteKey::unlock("correct horse", &envelope).unwrap();
+        assert_eq!(plaintext, "hello");
+        assert!(NoteKey::unlock("wrong horse", &envelope).is_err());
+    }
+
+    #[test]
+    fn test_tampered_header_is_rejected() {
+        let key = NoteKey::generate("correct horse", TEST_PARAMS).unwrap();
+        let envelope = key.seal("hello").unwrap().replace("t=1", "t=2");
+        assert!(key.open(&envelope).is_err());
+    }
+
+    #[test]
+    fn test_oversized_kdf_params_are_rejected() {
+        let key = NoteKey::generate("correct horse", TEST_PARAMS).unwrap();
+        let envelope = key.seal("hello").unwrap().replace("m=64", "m=4294967295");
+        let result = NoteKey::unlock("correct horse", &envelope);
+        assert!(matches!(result, Err(StemError::Validation(m)) if m.contains("non pris en charge")));
+    }
+
+    #[test]
+    fn test_encrypt_unlock_decrypt_lifecycle() {
+        let db = setup_db();
+        let keys = SessionKeys::default();
+
+        assert!(encrypt_note_sync(&db, "n1", "short", TEST_PARAMS).is_err());
+        let note = encrypt_note_sync(&db, "n1", "correct horse", TEST_PARAMS).unwrap();
+        assert!(note.is_encrypted);
+        assert!(note.content.is_none(), "ciphertext must not reach the frontend");
+
+        let stored: String = db.connection()
+            .query_row("SELECT content FROM notes WHERE id = 'n1'", [], |r| r.get(0)).unwrap();
+        assert!(stored.starts_with(ENVELOPE_PREFIX));
+        assert!(!stored.contains("hunter2"));
+
+        assert!(reseal_content(&keys, "n1", "edit").is_err());
+        let unlocked = unlock_note_sync(&db, &keys, "n1", "correct horse").unwrap();
+        assert_eq!(unlocked.content.as_deref(), Some("my password is hunter2"));
+        assert!(reseal_content(&keys, "n1", "edit").is_ok());
+
+        let plain = decrypt_note_sync(&db, &keys, "n1", "correct horse").unwrap();
+        assert!(!plain.is_encrypted);
+        assert_eq!(plain.content.as_deref(), Some("my password is hunter2"));
+        assert!(keys.get("n1").is_none());
+    }
+
+    #[test]
+    fn test_update_of_locked_note_changes_nothing() {
+        let db = setup_db();
+        let keys = SessionKeys::default();
+        encrypt_note_sync(&db, "n1", "correct horse", TEST_PARAMS).unwrap();
+
+        let payload = UpdateNotePayload { id: "n1".into(), title: Some("Renamed".into()), content: Some("edit".into()) };
+        assert!(update_note_sync(&db, &keys, &payload).is_err());
+        let title: String = db.connection().query_row("SELECT title FROM notes WHERE id = 'n1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(title, "Secret");
+
+        unlock_note_sync(&db, &keys, "n1", "correct horse").unwrap();
+        assert_eq!(update_note_sync(&db, &keys, &payload).unwrap().title, "Renamed");
+        assert_eq!(unlock_note_sync(&db, &keys, "n1", "correct horse").unwrap().content.as_deref(), Some("edit"));
+    }
+}
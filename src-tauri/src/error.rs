@@ -16,6 +16,18 @@ pub enum StemError {
 
     #[error("{0}")]
     Validation(String),
+
+    #[error("Git error: {0}")]
+    Git(String),
+
+    #[error("Sync error: {0}")]
+    Sync(String),
+
+    #[error("Backup error: {0}")]
+    Backup(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl Serialize for StemError {
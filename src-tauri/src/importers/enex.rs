@@ -0,0 +1,353 @@
+//! Evernote ENEX import. Files are read as an XML stream, one note at a time,
+//! so exports of several GB never have to fit in memory. Each `.enex` file is
+//! one notebook and becomes a folder.
+
+use super::enml::{enml_to_markdown, MediaRef};
+use super::{append_tags, commit_batch, ImportBatch, ImportReport, NewNote};
+use crate::attachments::{attachment_uri, AttachmentStore, MAX_ATTACHMENT_SIZE};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::NaiveDateTime;
+use md5::{Digest, Md5};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tauri::State;
+use uuid::Uuid;
+
+const DEFAULT_TITLE: &str = "Sans titre";
+const DEFAULT_NOTEBOOK: &str = "Evernote";
+
+// ===== MD5 =====
+
+/// ENML references resources by the MD5 of their data (lowercase hex).
+fn md5_hex(data: &[u8]) -> String {
+    Md5::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// ===== Parsing =====
+
+/// ENEX dates look like `20240102T030405Z`.
+fn parse_enex_date(value: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y%m%dT%H%M%SZ").ok().map(|dt| dt.and_utc().timestamp())
+}
+
+#[derive(Default)]
+struct Resource {
+    /// Base64 without whitespace.
+    data: String,
+    /// Set, and `data` dropped, once the data decodes to more than `MAX_ATTACHMENT_SIZE`.
+    too_large: bool,
+    mime: Option<String>,
+    file_name: Option<String>,
+}
+
+impl Resource {
+    fn push_data(&mut self, chunk: &str) {
+        if self.too_large {
+            return;
+        }
+        self.data.extend(chunk.chars().filter(|c| !c.is_whitespace()));
+        if self.data.len() as u64 * 3 / 4 > MAX_ATTACHMENT_SIZE {
+            self.data = String::new();
+            self.too_large = true;
+        }
+    }
+}
+
+#[derive(Default)]
+struct NoteBuilder {
+    title: Option<String>,
+    content: String,
+    created: Option<i64>,
+    updated: Option<i64>,
+    tags: Vec<String>,
+    resources: Vec<Resource>,
+}
+
+struct EnexReader<'a> {
+    source: String,
+    store: &'a AttachmentStore,
+    batch: &'a mut ImportBatch,
+    folder_id: String,
+}
+
+impl EnexReader<'_> {
+    fn finish_note(&mut self, note: NoteBuilder) {
+        let title = note.title.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        let label = format!("{} › {}", self.source, title.as_deref().unwrap_or(DEFAULT_TITLE));
+        let id = Uuid::new_v4().to_string();
+
+        let mut media = HashMap::new();
+        for (i, resource) in note.resources.into_iter().enumerate() {
+            if resource.too_large {
+                self.batch.skip(
+                    format!("{} (pièce jointe {})", label, i + 1),
+                    format!("Pièce jointe trop volumineuse (max {} MB)", MAX_ATTACHMENT_SIZE / 1_048_576),
+                );
+                continue;
+            }
+            let bytes = match STANDARD.decode(resource.data.as_bytes()) {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    self.batch.skip(format!("{} (pièce jointe {})", label, i + 1), "Données base64 invalides");
+                    continue;
+                }
+            };
+            let name = resource.file_name.unwrap_or_else(|| format!("piece-jointe-{}", i + 1));
+            match self.store.write_bytes(&bytes, &name, resource.mime.as_deref()) {
+                Ok(stored) => {
+                    let is_image = stored.mime.as_deref().is_some_and(|m| m.starts_with("image/"));
+                    media.insert(md5_hex(&bytes), MediaRef { uri: attachment_uri(&stored.file_name), name, is_image });
+                    self.batch.attachments.push((stored, Some(id.clone())));
+                }
+                Err(e) => self.batch.skip(format!("{} ({})", label, name), format!("Copie impossible : {}", e)),
+            }
+        }
+
+        let mut content = enml_to_markdown(&note.content, &media);
+        append_tags(&mut content, &note.tags);
+        let created_at = note.created.or(note.updated).unwrap_or_else(current_timestamp);
+        self.batch.notes.push(NewNote {
+            id,
+            title: title.unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            content,
+            created_at,
+            updated_at: note.updated.unwrap_or(created_at).max(created_at),
+            is_pinned: false,
+            is_archived: false,
+            folder_id: Some(self.folder_id.clone()),
+        });
+    }
+
+    /// Streams `<note>` elements out of an ENEX document. A malformed document
+    /// stops the import of this file but keeps the notes read so far.
+    fn read<R: BufRead>(&mut self, input: R) {
+        let mut reader = Reader::from_reader(input);
+        reader.config_mut().check_end_names = false;
+        let mut buf = Vec::new();
+        let mut path: Vec<Vec<u8>> = Vec::new();
+        let mut text = String::new();
+        let mut note: Option<NoteBuilder> = None;
+        let mut resource: Option<Resource> = None;
+
+        loop {
+            let event = match reader.read_event_into(&mut buf) {
+                Ok(event) => event,
+                Err(e) => {
+                    let position = reader.buffer_position();
+                    self.batch.skip(self.source.clone(), format!("XML invalide (octet {}) : {}", position, e));
+                    break;
+                }
+            };
+            match event {
+                Event::Start(e) => {
+                    let name = e.local_name().as_ref().to_vec();
+                    match name.as_slice() {
+                        b"note" => note = Some(NoteBuilder::default()),
+                        b"resource" if note.is_some() => resource = Some(Resource::default()),
+                        _ => {}
+                    }
+                    path.push(name);
+                    text.clear();
+                }
+                Event::Text(e) => {
+                    let chunk = e.decode().unwrap_or_default();
+                    // Resource data goes straight to its resource, which keeps it in check
+                    match resource.as_mut().filter(|_| path.last().map(Vec::as_slice) == Some(b"data")) {
+                        Some(res) => res.push_data(&chunk),
+                        None => text.push_str(&chunk),
+                    }
+                }
+                Event::CData(e) => text.push_str(&e.decode().unwrap_or_default()),
+                Event::GeneralRef(e) => {
+                    if let Ok(Some(c)) = e.resolve_char_ref() {
+                        text.push(c);
+                    } else if let Some(s) = e.decode().ok().and_then(|n| resolve_predefined_entity(&n)) {
+                        text.push_str(s);
+                    }
+                }
+                Event::End(_) => {
+                    let Some(name) = path.pop() else { continue };
+                    let value = std::mem::take(&mut text);
+                    if let Some(res) = resource.as_mut() {
+                        match name.as_slice() {
+                            b"mime" => res.mime = Some(value.trim().to_string()),
+                            b"file-name" => res.file_name = Some(value.trim().to_string()).filter(|n| !n.is_empty()),
+                            b"resource" => {
+                                if let (Some(n), Some(res)) = (note.as_mut(), resource.take()) {
+                                    n.resources.push(res);
+                                }
+                            }
+                            _ => {}
+                        }
+                    } else if name == b"note" {
+                        if let Some(finished) = note.take() {
+                            self.finish_note(finished);
+                        }
+                    } else if let Some(n) = note.as_mut() {
+                        // Only direct children of <note>, not <note-attributes>
+                        if path.last().map(Vec::as_slice) == Some(b"note") {
+                            match name.as_slice() {
+                                b"title" => n.title = Some(value),
+                                b"content" => n.content = value,
+                                b"created" => n.created = parse_enex_date(&value),
+                                b"updated" => n.updated = parse_enex_date(&value),
+                                b"tag" => n.tags.push(value.trim().to_string()),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                Event::Eof => {
+                    if note.is_some() {
+                        self.batch.skip(self.source.clone(), "Fichier ENEX tronqué : dernière note incomplète ignorée");
+                    }
+                    break;
+                }
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+}
+
+/// Reads `.enex` files into a batch: one folder per file, named after it.
+pub(crate) fn read_enex_files(paths: &[PathBuf], store: &AttachmentStore, parent_folder_id: Option<String>) -> ImportBatch {
+    let mut batch = ImportBatch::default();
+    let now = current_timestamp();
+    for path in paths {
+        let source = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                batch.skip(source, format!("Lecture impossible : {}", e));
+                continue;
+            }
+        };
+        let notebook = path.file_stem().and_then(|s| s.to_str()).filter(|s| !s.is_empty()).unwrap_or(DEFAULT_NOTEBOOK);
+        let folder_id = batch.add_folder(notebook, parent_folder_id.clone(), now);
+        EnexReader { source, store, batch: &mut batch, folder_id }.read(BufReader::new(file));
+    }
+    batch
+}
+
+fn validate_paths(paths: &[String]) -> Result<Vec<PathBuf>, StemError> {
+    paths
+        .iter()
+        .map(|p| {
+            let path = Path::new(p);
+            if !path.is_absolute() {
+                return Err(StemError::Validation("Les fichiers ENEX doivent être des chemins absolus".to_string()));
+            }
+            Ok(path.to_path_buf())
+        })
+        .collect()
+}
+
+// ===== Tauri Commands =====
+
+/// Imports one or more Evernote `.enex` exports; each file becomes a folder.
+#[tauri::command]
+pub async fn import_enex(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    paths: Vec<String>,
+    parent_folder_id: Option<String>,
+) -> Result<ImportReport, StemError> {
+    let paths = validate_paths(&paths)?;
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let batch = read_enex_files(&paths, &store, parent_folder_id);
+        commit_batch(&db, &store, batch)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-export SYSTEM "http://xml.evernote.com/pub/evernote-export3.dtd">
+<en-export export-date="20240101T000000Z" application="Evernote" version="10">
+  <note>
+    <title>Courses &amp; co</title>
+    <content><![CDATA[<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd"><en-note><div>Lait</div><div><en-media hash="900150983cd24fb0d6963f7d28e17f72" type="image/png"/></div></en-note>]]></content>
+    <created>20230102T030405Z</created>
+    <updated>20230103T000000Z</updated>
+    <tag>maison</tag>
+    <tag>to do</tag>
+    <note-attributes><author>me</author><created>19990101T000000Z</created></note-attributes>
+    <resource>
+      <data encoding="base64">YWJj</data>
+      <mime>image/png</mime>
+      <resource-attributes><file-name>photo.png</file-name></resource-attributes>
+    </resource>
+  </note>
+  <note>
+    <title></title>
+    <content><![CDATA[<en-note>Second</en-note>]]></content>
+  </note>
+  <note>
+    <title>Broken
+"#;
+
+    #[test]
+    fn test_md5_hex() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(md5_hex(&[b'a'; 100]), "36a92cc94a9e0fa21f625f8bfb007adf");
+    }
+
+    #[test]
+    fn test_oversized_resource_is_dropped_while_read() {
+        let chunk = "QUFB\n".repeat(1 << 20);
+        let mut resource = Resource::default();
+        resource.push_data(&chunk);
+        assert_eq!(resource.data.len(), 4 << 20, "whitespace is not kept");
+        while !resource.too_large {
+            resource.push_data(&chunk);
+            assert!(resource.data.len() as u64 <= MAX_ATTACHMENT_SIZE * 4 / 3 + 4);
+        }
+        assert!(resource.data.is_empty());
+        resource.push_data(&chunk);
+        assert!(resource.data.is_empty());
+    }
+
+    #[test]
+    fn test_read_enex_stream() {
+        let store_dir = std::env::temp_dir().join(format!("stem-enex-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(store_dir.clone());
+        let mut batch = ImportBatch::default();
+        let folder_id = batch.add_folder("Carnet", None, 0);
+        EnexReader { source: "Carnet.enex".to_string(), store: &store, batch: &mut batch, folder_id: folder_id.clone() }
+            .read(SAMPLE.as_bytes());
+
+        assert_eq!(batch.notes.len(), 2);
+        let first = &batch.notes[0];
+        assert_eq!(first.title, "Courses & co");
+        assert_eq!(first.created_at, 1_672_628_645);
+        assert_eq!(first.updated_at, 1_672_704_000);
+        assert_eq!(first.folder_id.as_deref(), Some(folder_id.as_str()));
+        let (stored, note_id) = &batch.attachments[0];
+        assert_eq!(note_id.as_deref(), Some(first.id.as_str()));
+        assert_eq!(
+            first.content,
+            format!("Lait\n\n![photo.png](stem-attachment://{})\n\n#maison #to-do", stored.file_name)
+        );
+        assert_eq!(std::fs::read(store_dir.join(&stored.file_name)).unwrap(), b"abc");
+
+        assert_eq!(batch.notes[1].title, DEFAULT_TITLE);
+        assert_eq!(batch.notes[1].content, "Second");
+        assert_eq!(batch.skipped.len(), 1);
+        assert!(batch.skipped[0].reason.starts_with("Fichier ENEX tronqué"));
+
+        let _ = std::fs::remove_dir_all(&store_dir);
+    }
+}
@@ -0,0 +1,469 @@
+//! ENML (Evernote's XHTML subset) to Markdown.
+
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// A resource referenced from ENML by `<en-media hash="...">`.
+pub struct MediaRef {
+    pub uri: String,
+    pub name: String,
+    pub is_image: bool,
+}
+
+const ENCRYPTED_PLACEHOLDER: &str = "*[Contenu chiffré Evernote non importé]*";
+
+struct Converter<'a> {
+    out: String,
+    media: &'a HashMap<String, MediaRef>,
+    /// Byte offset where the current line starts, and where its content starts (after prefixes).
+    line_begin: usize,
+    content_start: usize,
+    last_line_blank: bool,
+    pending_space: bool,
+    quote_depth: usize,
+    /// Open lists: next number for ordered ones.
+    lists: Vec<Option<u32>>,
+    /// Open inline markers (`**`, `*`, `~~`, `` ` ``) with their output offset.
+    inline: Vec<(usize, &'static str)>,
+    links: Vec<(usize, Option<String>)>,
+    /// Open tables: (rows written, cells in the current row).
+    tables: Vec<(usize, usize)>,
+    pre_depth: usize,
+    skip_depth: usize,
+}
+
+fn list_indent(lists: &[Option<u32>]) -> usize {
+    lists.iter().map(|l| if l.is_some() { 3 } else { 2 }).sum()
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .filter_map(|a| a.ok())
+        .find(|a| a.key.as_ref() == name)
+        .and_then(|a| a.unescape_value_with(resolve_predefined_entity).ok().map(|v| v.into_owned()))
+}
+
+impl<'a> Converter<'a> {
+    fn new(media: &'a HashMap<String, MediaRef>) -> Self {
+        Self {
+            out: String::new(),
+            media,
+            line_begin: 0,
+            content_start: 0,
+            last_line_blank: true,
+            pending_space: false,
+            quote_depth: 0,
+            lists: Vec::new(),
+            inline: Vec::new(),
+            links: Vec::new(),
+            tables: Vec::new(),
+            pre_depth: 0,
+            skip_depth: 0,
+        }
+    }
+
+    fn line_has_content(&self) -> bool {
+        self.out[self.content_start..].trim().chars().next().is_some()
+    }
+
+    fn in_cell(&self) -> bool {
+        self.tables.last().is_some_and(|(_, cells)| *cells > 0 || self.out[self.content_start..].starts_with('|'))
+    }
+
+    fn write_prefix(&mut self, indent: usize) {
+        self.out.push_str(&"> ".repeat(self.quote_depth));
+        self.out.push_str(&" ".repeat(indent));
+        self.content_start = self.out.len();
+    }
+
+    fn break_line(&mut self) {
+        self.last_line_blank = !self.line_has_content();
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        self.line_begin = self.out.len();
+        self.write_prefix(list_indent(&self.lists));
+        self.pending_space = false;
+    }
+
+    /// Rewrites the (empty) current line's prefix after the quote or list depth changed.
+    fn restart_line(&mut self, indent: usize) {
+        self.out.truncate(self.line_begin);
+        self.write_prefix(indent);
+    }
+
+    /// Drops trailing empty (or `>`-only) lines so a closing quote does not leave them behind.
+    fn trim_blank_lines(&mut self) {
+        loop {
+            let last_nl = self.out.rfind('\n');
+            let tail = &self.out[last_nl.map_or(0, |i| i + 1)..];
+            if !tail.trim_start_matches(['>', ' ']).is_empty() {
+                break;
+            }
+            match last_nl {
+                Some(i) => self.out.truncate(i),
+                None => {
+                    self.out.clear();
+                    break;
+                }
+            }
+        }
+        self.line_begin = self.out.rfind('\n').map_or(0, |i| i + 1);
+        self.content_start = self.line_begin;
+        self.last_line_blank = false;
+    }
+
+    fn start_line(&mut self) {
+        if self.line_has_content() {
+            self.break_line();
+        }
+    }
+
+    fn paragraph(&mut self) {
+        if !self.lists.is_empty() {
+            return self.start_line();
+        }
+        if self.line_has_content() {
+            self.break_line();
+        }
+        if !self.last_line_blank {
+            self.break_line();
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        if self.pre_depth > 0 {
+            self.out.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.pending_space = true;
+                continue;
+            }
+            if self.pending_space && self.out.len() > self.content_start && !self.out.ends_with(char::is_whitespace) {
+                self.out.push(' ');
+            }
+            self.pending_space = false;
+            if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']') {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+
+    /// Writes a deferred space before markup that is not plain text.
+    fn flush_space(&mut self) {
+        if self.pending_space && self.line_has_content() && !self.out.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+        self.pending_space = false;
+    }
+
+    fn open_inline(&mut self, marker: &'static str) {
+        self.flush_space();
+        self.inline.push((self.out.len(), marker));
+        self.out.push_str(marker);
+    }
+
+    fn close_inline(&mut self) {
+        if let Some((start, marker)) = self.inline.pop() {
+            if self.out.len() == start + marker.len() {
+                self.out.truncate(start);
+            } else {
+                self.out.push_str(marker);
+            }
+        }
+    }
+
+    fn start(&mut self, e: &BytesStart, empty: bool) {
+        let name = e.local_name().as_ref().to_ascii_lowercase();
+        if self.skip_depth > 0 {
+            if !empty {
+                self.skip_depth += 1;
+            }
+            return;
+        }
+        match name.as_slice() {
+            b"p" | b"div" => {
+                if self.in_cell() {
+                    self.pending_space = true;
+                } else {
+                    self.paragraph();
+                }
+            }
+            b"br" => {
+                if self.in_cell() {
+                    self.pending_space = true;
+                } else {
+                    self.break_line();
+                }
+            }
+            b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                self.paragraph();
+                let level = (name[1] - b'0') as usize;
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+                self.content_start = self.out.len();
+            }
+            b"hr" => {
+                self.paragraph();
+                self.out.push_str("---");
+                self.paragraph();
+            }
+            b"blockquote" => {
+                self.paragraph();
+                self.quote_depth += 1;
+                self.restart_line(list_indent(&self.lists));
+            }
+            b"ul" | b"ol" => {
+                if self.lists.is_empty() {
+                    self.paragraph();
+                } else {
+                    self.start_line();
+                }
+                self.lists.push((name == b"ol").then_some(1));
+            }
+            b"li" => {
+                self.start_line();
+                let depth = self.lists.len().saturating_sub(1);
+                self.restart_line(list_indent(&self.lists[..depth]));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.out.push_str(&marker);
+                self.content_start = self.out.len();
+            }
+            b"en-todo" => {
+                let checked = attribute(e, b"checked").is_some_and(|v| v == "true");
+                let box_ = if checked { "[x] " } else { "[ ] " };
+                if self.lists.is_empty() && !self.line_has_content() {
+                    self.out.push_str("- ");
+                }
+                self.out.push_str(box_);
+                self.content_start = self.out.len();
+            }
+            b"b" | b"strong" => self.open_inline("**"),
+            b"i" | b"em" => self.open_inline("*"),
+            b"s" | b"strike" | b"del" => self.open_inline("~~"),
+            b"code" if self.pre_depth == 0 => self.open_inline("`"),
+            b"pre" => {
+                self.paragraph();
+                self.out.push_str("```");
+                self.break_line();
+                self.pre_depth += 1;
+            }
+            b"a" => {
+                self.flush_space();
+                self.links.push((self.out.len(), attribute(e, b"href")));
+            }
+            b"en-media" => {
+                let media = attribute(e, b"hash").and_then(|h| self.media.get(&h.to_ascii_lowercase()));
+                if let Some(media) = media {
+                    let bang = if media.is_image { "!" } else { "" };
+                    let link = format!("{}[{}]({})", bang, media.name.replace(['[', ']'], ""), media.uri);
+                    self.flush_space();
+                    self.out.push_str(&link);
+                }
+            }
+            b"img" => {
+                if let Some(src) = attribute(e, b"src") {
+                    let alt = attribute(e, b"alt").unwrap_or_default();
+                    self.flush_space();
+                    self.out.push_str(&format!("![{}]({})", alt.replace(['[', ']'], ""), src));
+                }
+            }
+            b"en-crypt" => {
+                self.out.push_str(ENCRYPTED_PLACEHOLDER);
+                if !empty {
+                    self.skip_depth = 1;
+                }
+            }
+            b"table" => {
+                self.paragraph();
+                self.tables.push((0, 0));
+            }
+            b"tr" => {
+                self.start_line();
+                self.out.push('|');
+                if let Some(table) = self.tables.last_mut() {
+                    table.1 = 0;
+                }
+            }
+            b"td" | b"th" => {
+                self.out.push(' ');
+                self.pending_space = false;
+            }
+            _ => {}
+        }
+        if empty {
+            self.end(&name);
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        if self.skip_depth > 0 {
+            self.skip_depth -= 1;
+            return;
+        }
+        match name {
+            b"p" | b"div" if !self.in_cell() => self.paragraph(),
+            b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => self.paragraph(),
+            b"blockquote" => {
+                self.trim_blank_lines();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.paragraph();
+            }
+            b"ul" | b"ol" => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.paragraph();
+                } else {
+                    self.start_line();
+                }
+            }
+            b"li" => self.start_line(),
+            b"b" | b"strong" | b"i" | b"em" | b"s" | b"strike" | b"del" => self.close_inline(),
+            b"code" if self.pre_depth == 0 => self.close_inline(),
+            b"pre" => {
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.line_begin = self.out.len();
+                self.write_prefix(list_indent(&self.lists));
+                self.out.push_str("```");
+                self.paragraph();
+            }
+            b"a" => {
+                if let Some((start, href)) = self.links.pop() {
+                    let label = self.out[start..].trim().to_string();
+                    if let Some(href) = href.filter(|h| !h.is_empty()) {
+                        let target = if href.contains(' ') { format!("<{}>", href) } else { href.clone() };
+                        let label = if label.is_empty() { href } else { label };
+                        self.out.truncate(start);
+                        self.out.push_str(&format!("[{}]({})", label, target));
+                    }
+                }
+            }
+            b"td" | b"th" => {
+                let trimmed = self.out.trim_end_matches(' ').len();
+                self.out.truncate(trimmed);
+                self.out.push_str(" |");
+                self.pending_space = false;
+                if let Some(table) = self.tables.last_mut() {
+                    table.1 += 1;
+                }
+            }
+            b"tr" => {
+                if let Some((rows, cells)) = self.tables.last_mut() {
+                    *rows += 1;
+                    if *rows == 1 {
+                        let separator = format!("|{}", " --- |".repeat((*cells).max(1)));
+                        self.break_line();
+                        self.out.push_str(&separator);
+                    }
+                }
+                self.break_line();
+            }
+            b"table" => {
+                self.tables.pop();
+                self.paragraph();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Converts an ENML document to Markdown. Resources are looked up by their
+/// lowercase hex MD5 hash. Malformed markup ends the conversion early, keeping
+/// what was converted so far.
+pub fn enml_to_markdown(enml: &str, media: &HashMap<String, MediaRef>) -> String {
+    let mut reader = Reader::from_str(enml);
+    reader.config_mut().check_end_names = false;
+    let mut conv = Converter::new(media);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => conv.start(&e, false),
+            Ok(Event::Empty(e)) => conv.start(&e, true),
+            Ok(Event::End(e)) => conv.end(&e.local_name().as_ref().to_ascii_lowercase()),
+            Ok(Event::Text(e)) => conv.text(&e.decode().unwrap_or_default()),
+            Ok(Event::CData(e)) => conv.text(&e.decode().unwrap_or_default()),
+            Ok(Event::GeneralRef(e)) => {
+                let resolved = match e.resolve_char_ref() {
+                    Ok(Some(c)) => Some(c.to_string()),
+                    _ => e.decode().ok().and_then(|name| resolve_predefined_entity(&name)).map(str::to_string),
+                };
+                conv.text(resolved.as_deref().unwrap_or(""));
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    let lines: Vec<&str> = conv.out.lines().map(str::trim_end).collect();
+    let mut markdown = String::new();
+    let mut blank = 0;
+    for line in lines {
+        let is_blank = line.trim_start_matches(['>', ' ']).is_empty();
+        blank = if is_blank { blank + 1 } else { 0 };
+        if blank < 2 {
+            markdown.push_str(line);
+            markdown.push('\n');
+        }
+    }
+    markdown.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(body: &str) -> String {
+        let enml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><!DOCTYPE en-note SYSTEM \"http://xml.evernote.com/pub/enml2.dtd\"><en-note>{}</en-note>",
+            body
+        );
+        let mut media = HashMap::new();
+        media.insert("abc123".to_string(), MediaRef { uri: "stem-attachment://x.png".to_string(), name: "pic.png".to_string(), is_image: true });
+        enml_to_markdown(&enml, &media)
+    }
+
+    #[test]
+    fn test_blocks_and_inline() {
+        assert_eq!(
+            convert("<h2>Title</h2><div>Some <b>bold</b> and <i>it</i>&nbsp;text_1</div><div><br/></div><div>Next <a href=\"https://x.org\">link</a></div>"),
+            "## Title\n\nSome **bold** and *it* text\\_1\n\nNext [link](https://x.org)"
+        );
+        assert_eq!(convert("<pre>let a = 1;\nlet b = 2;</pre>"), "```\nlet a = 1;\nlet b = 2;\n```");
+        assert_eq!(convert("<blockquote><div>quoted</div></blockquote><div>after</div>"), "> quoted\n\nafter");
+    }
+
+    #[test]
+    fn test_lists_todos_media_and_tables() {
+        assert_eq!(
+            convert("<ul><li><div>one</div></li><li>two<ol><li>a</li><li>b</li></ol></li></ul>"),
+            "- one\n- two\n  1. a\n  2. b"
+        );
+        assert_eq!(
+            convert("<div><en-todo checked=\"true\"/>done</div><div><en-todo/>todo</div>"),
+            "- [x] done\n\n- [ ] todo"
+        );
+        assert_eq!(convert("<div>see <en-media hash=\"ABC123\" type=\"image/png\"/></div>"), "see ![pic.png](stem-attachment://x.png)");
+        assert_eq!(
+            convert("<table><tr><td>a</td><td>b</td></tr><tr><td><div>c</div></td><td>d</td></tr></table>"),
+            "| a | b |\n| --- | --- |\n| c | d |"
+        );
+        assert_eq!(convert("<div>x<en-crypt hint=\"h\">AAAA</en-crypt></div>"), format!("x{}", ENCRYPTED_PLACEHOLDER));
+    }
+}
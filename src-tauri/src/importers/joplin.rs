@@ -0,0 +1,338 @@
+//! Joplin JEX import. A JEX file is a tar of `<id>.md` items (notes,
+//! notebooks, tags, resources...) each ending with a `key: value` metadata
+//! block, plus the resource files under `resources/`.
+
+use super::{append_tags, commit_batch, file_name, file_stem, ImportBatch, ImportReport, NewNote, MAX_NOTE_FILE_SIZE};
+use crate::attachments::{attachment_uri, guess_mime, AttachmentStore, MAX_ATTACHMENT_SIZE, StoredAttachment};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use crate::links::{find_markdown_links, note_link, replace_ranges};
+use crate::vault::{parse_timestamp, to_portable};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use tauri::State;
+use uuid::Uuid;
+
+const ROOT_FOLDER: &str = "Joplin";
+const DEFAULT_TITLE: &str = "Sans titre";
+
+// Joplin item types (`type_` metadata)
+const TYPE_NOTE: u32 = 1;
+const TYPE_FOLDER: u32 = 2;
+const TYPE_RESOURCE: u32 = 4;
+const TYPE_TAG: u32 = 5;
+const TYPE_NOTE_TAG: u32 = 6;
+
+struct Item {
+    title: String,
+    body: String,
+    meta: HashMap<String, String>,
+}
+
+impl Item {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).map(String::as_str).filter(|v| !v.is_empty())
+    }
+
+    fn item_type(&self) -> Option<u32> {
+        self.get("type_")?.parse().ok()
+    }
+
+    fn timestamp(&self, keys: &[&str]) -> Option<i64> {
+        keys.iter().find_map(|k| self.get(k).and_then(parse_timestamp))
+    }
+}
+
+fn is_meta_line(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((key, _)) => !key.is_empty() && key.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'),
+        None => false,
+    }
+}
+
+/// Splits an item into title (first line), body and the trailing metadata block.
+fn parse_item(text: &str) -> Option<Item> {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let meta_start = lines.iter().rposition(|l| !is_meta_line(l)).map_or(0, |i| i + 1);
+    let meta: HashMap<String, String> = lines[meta_start..]
+        .iter()
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.to_string(), v.trim().to_string()))
+        .collect();
+    if !meta.contains_key("type_") {
+        return None;
+    }
+    let content = &lines[..meta_start];
+    let title = content.first().map(|l| l.trim().to_string()).unwrap_or_default();
+    let body = content.iter().skip(1).copied().collect::<Vec<_>>().join("\n").trim().to_string();
+    Some(Item { title, body, meta })
+}
+
+/// Number of known ancestors; stops on cycles and missing parents.
+fn folder_depth<'a>(folders: &'a HashMap<String, Item>, mut id: &'a str) -> usize {
+    let mut seen = HashSet::new();
+    let mut depth = 0;
+    while let Some(parent) = folders.get(id).and_then(|f| f.get("parent_id")) {
+        if !seen.insert(parent) || !folders.contains_key(parent) {
+            break;
+        }
+        depth += 1;
+        id = parent;
+    }
+    depth
+}
+
+/// Folders sorted so that every parent comes before its children.
+fn folder_order(folders: &HashMap<String, Item>) -> Vec<(&String, &Item)> {
+    let mut ordered: Vec<_> = folders.iter().collect();
+    ordered.sort_by_key(|(id, item)| (folder_depth(folders, id), item.title.clone(), (*id).clone()));
+    ordered
+}
+
+pub(crate) fn read_jex<R: Read>(input: R, store: &AttachmentStore, parent_folder_id: Option<String>) -> Result<ImportBatch, StemError> {
+    let mut batch = ImportBatch::default();
+    let mut archive = tar::Archive::new(input);
+    let (mut notes, mut folders, mut resources, mut tags) = (Vec::new(), HashMap::new(), HashMap::new(), HashMap::new());
+    let mut note_tags: Vec<(String, String)> = Vec::new();
+    // Resource id -> copied file
+    let mut files: HashMap<String, StoredAttachment> = HashMap::new();
+
+    let entries = archive.entries().map_err(|e| StemError::Validation(format!("Archive JEX invalide : {}", e)))?;
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                batch.skip("?", format!("Archive JEX tronquée : {}", e));
+                break;
+            }
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map(|p| to_portable(&p)).unwrap_or_default();
+        if path.starts_with("resources/") {
+            if entry.size() > MAX_ATTACHMENT_SIZE {
+                batch.skip(path, format!("Pièce jointe trop volumineuse (max {} MB)", MAX_ATTACHMENT_SIZE / 1_048_576));
+                continue;
+            }
+            match store.write_reader(&mut entry, file_name(&path), None) {
+                Ok(stored) => {
+                    files.insert(file_stem(&path).to_string(), stored);
+                }
+                Err(e) => batch.skip(path, format!("Copie impossible : {}", e)),
+            }
+            continue;
+        }
+        if !path.ends_with(".md") {
+            batch.skip(path, "Type de fichier non pris en charge");
+            continue;
+        }
+        if entry.size() > MAX_NOTE_FILE_SIZE {
+            batch.skip(path, format!("Fichier trop volumineux (max {} MB)", MAX_NOTE_FILE_SIZE / 1_048_576));
+            continue;
+        }
+        let mut text = String::new();
+        if let Err(e) = entry.read_to_string(&mut text) {
+            batch.skip(path, format!("Lecture impossible : {}", e));
+            continue;
+        }
+        let Some(item) = parse_item(&text) else {
+            batch.skip(path, "Métadonnées Joplin absentes");
+            continue;
+        };
+        if item.get("encryption_applied") == Some("1") {
+            batch.skip(path, "Élément chiffré par Joplin");
+            continue;
+        }
+        let id = item.get("id").unwrap_or(file_stem(&path)).to_string();
+        match item.item_type() {
+            Some(TYPE_NOTE) => notes.push((id, item)),
+            Some(TYPE_FOLDER) => {
+                folders.insert(id, item);
+            }
+            Some(TYPE_RESOURCE) => {
+                resources.insert(id, item);
+            }
+            Some(TYPE_TAG) => {
+                tags.insert(id, item.title);
+            }
+            Some(TYPE_NOTE_TAG) => {
+                if let (Some(note), Some(tag)) = (item.get("note_id"), item.get("tag_id")) {
+                    note_tags.push((note.to_string(), tag.to_string()));
+                }
+            }
+            // Revisions, master keys, settings...
+            _ => {}
+        }
+    }
+
+    let now = current_timestamp();
+    let root_id = batch.add_folder(ROOT_FOLDER, parent_folder_id, now);
+    let mut folder_ids: HashMap<String, String> = HashMap::new();
+    for (id, item) in folder_order(&folders) {
+        let parent = item.get("parent_id").and_then(|p| folder_ids.get(p)).cloned().unwrap_or_else(|| root_id.clone());
+        let created = item.timestamp(&["user_created_time", "created_time"]).unwrap_or(now);
+        let name = if item.title.is_empty() { DEFAULT_TITLE } else { item.title.as_str() };
+        folder_ids.insert(id.clone(), batch.add_folder(name, Some(parent), created));
+    }
+
+    // Attachments keep the name and type Joplin recorded
+    for (id, stored) in files.iter_mut() {
+        if let Some(resource) = resources.get(id) {
+            if let Some(name) = resource.get("filename").or(Some(resource.title.as_str()).filter(|t| !t.is_empty())) {
+                stored.original_name = name.to_string();
+            }
+            stored.mime = resource.get("mime").map(str::to_string).or_else(|| guess_mime(&stored.original_name).map(str::to_string));
+        }
+    }
+
+    let new_ids: HashMap<&str, String> = notes.iter().map(|(id, _)| (id.as_str(), Uuid::new_v4().to_string())).collect();
+    let mut tags_by_note: HashMap<&str, Vec<String>> = HashMap::new();
+    for (note, tag) in &note_tags {
+        if let Some(name) = tags.get(tag) {
+            tags_by_note.entry(note.as_str()).or_default().push(name.clone());
+        }
+    }
+
+    let mut owners: HashMap<String, String> = HashMap::new();
+    for (old_id, item) in &notes {
+        let id = new_ids[old_id.as_str()].clone();
+        // Joplin links items as `:/<32 hex id>`
+        let mut edits = Vec::new();
+        for link in find_markdown_links(&item.body) {
+            let Some(target) = link.target.strip_prefix(":/") else { continue };
+            let (target_id, anchor) = target.split_once('#').unwrap_or((target, ""));
+            let anchor = Some(anchor).filter(|a| !a.is_empty());
+            if let Some(note) = new_ids.get(target_id) {
+                edits.push((link.range, format!("[{}]({})", link.label, note_link(note, anchor))));
+            } else if let Some(stored) = files.get(target_id) {
+                owners.entry(target_id.to_string()).or_insert_with(|| id.clone());
+                let bang = if link.is_image { "!" } else { "" };
+                edits.push((link.range, format!("{}[{}]({})", bang, link.label, attachment_uri(&stored.file_name))));
+            }
+        }
+        let mut content = replace_ranges(&item.body, edits);
+        append_tags(&mut content, tags_by_note.get(old_id.as_str()).map(Vec::as_slice).unwrap_or_default());
+
+        let created_at = item.timestamp(&["user_created_time", "created_time"]).unwrap_or(now);
+        let updated_at = item.timestamp(&["user_updated_time", "updated_time"]).unwrap_or(created_at);
+        batch.notes.push(NewNote {
+            id,
+            title: if item.title.is_empty() { DEFAULT_TITLE.to_string() } else { item.title.clone() },
+            content,
+            created_at,
+            updated_at: updated_at.max(created_at),
+            is_pinned: false,
+            is_archived: false,
+            folder_id: Some(item.get("parent_id").and_then(|p| folder_ids.get(p)).cloned().unwrap_or_else(|| root_id.clone())),
+        });
+    }
+
+    // Files no note links to are dropped rather than left orphaned in the store
+    let mut unused = Vec::new();
+    for (id, stored) in files {
+        match owners.remove(&id) {
+            Some(note) => batch.attachments.push((stored, Some(note))),
+            None => {
+                batch.skip(format!("resources/{}", stored.original_name), "Ressource non référencée par une note");
+                unused.push(stored);
+            }
+        }
+    }
+    store.discard(&unused);
+    batch.attachments.sort_by(|a, b| a.0.original_name.cmp(&b.0.original_name));
+    Ok(batch)
+}
+
+// ===== Tauri Commands =====
+
+/// Imports a Joplin `.jex` export under a new "Joplin" folder.
+#[tauri::command]
+pub async fn import_joplin_jex(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    path: String,
+    parent_folder_id: Option<String>,
+) -> Result<ImportReport, StemError> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("Le fichier JEX doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let batch = read_jex(BufReader::new(File::open(&path)?), &store, parent_folder_id)?;
+        commit_batch(&db, &store, batch)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, content: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, content).unwrap();
+    }
+
+    #[test]
+    fn test_parse_item() {
+        let item = parse_item("Title\n\nBody line\nnot: meta because body\n\nid: abc\nparent_id: \ntype_: 1").unwrap();
+        assert_eq!(item.title, "Title");
+        assert_eq!(item.body, "Body line\nnot: meta because body");
+        assert_eq!(item.get("id"), Some("abc"));
+        assert_eq!(item.get("parent_id"), None);
+        assert!(parse_item("Just text").is_none());
+    }
+
+    #[test]
+    fn test_read_jex() {
+        let (n1, n2, f1, f2, r1, t1) = ("a".repeat(32), "b".repeat(32), "c".repeat(32), "d".repeat(32), "e".repeat(32), "f".repeat(32));
+        let mut builder = tar::Builder::new(Vec::new());
+        append(&mut builder, &format!("{f1}.md"), format!("Work\n\nid: {f1}\nparent_id: {f2}\ncreated_time: 2020-01-01T00:00:00.000Z\ntype_: 2").as_bytes());
+        append(&mut builder, &format!("{f2}.md"), format!("Root book\n\nid: {f2}\nparent_id: \ntype_: 2").as_bytes());
+        append(&mut builder, &format!("{n1}.md"), format!(
+            "Plan\n\nSee [other](:/{n2}) and ![shot](:/{r1})\n\nid: {n1}\nparent_id: {f1}\ncreated_time: 2021-01-01T00:00:00.000Z\nupdated_time: 2021-01-02T00:00:00.000Z\nuser_created_time: 2020-06-01T00:00:00.000Z\nuser_updated_time: 2020-06-02T00:00:00.000Z\ntype_: 1"
+        ).as_bytes());
+        append(&mut builder, &format!("{n2}.md"), format!("Other\n\nid: {n2}\nparent_id: missing\ntype_: 1").as_bytes());
+        append(&mut builder, &format!("{r1}.md"), format!("shot.png\n\nid: {r1}\nmime: image/png\nfilename: \ntype_: 4").as_bytes());
+        append(&mut builder, &format!("resources/{r1}.png"), b"png");
+        append(&mut builder, &format!("resources/{}.txt", "9".repeat(32)), b"orphan");
+        append(&mut builder, &format!("{t1}.md"), format!("projet perso\n\nid: {t1}\ntype_: 5").as_bytes());
+        append(&mut builder, &format!("{}.md", "7".repeat(32)), format!("\n\nid: x\nnote_id: {n1}\ntag_id: {t1}\ntype_: 6").as_bytes());
+        let data = builder.into_inner().unwrap();
+
+        let store_dir = std::env::temp_dir().join(format!("stem-jex-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(store_dir.clone());
+        let batch = read_jex(data.as_slice(), &store, None).unwrap();
+
+        let folders: Vec<(&str, Option<&str>)> = batch.folders.iter().map(|f| (f.name.as_str(), f.parent_id.as_deref())).collect();
+        assert_eq!(folders[0], ("Joplin", None));
+        assert_eq!(folders[1], ("Root book", Some(batch.folders[0].id.as_str())));
+        assert_eq!(folders[2], ("Work", Some(batch.folders[1].id.as_str())));
+
+        let plan = batch.notes.iter().find(|n| n.title == "Plan").unwrap();
+        let other = batch.notes.iter().find(|n| n.title == "Other").unwrap();
+        assert_eq!(plan.created_at, 1_590_969_600);
+        assert_eq!(plan.updated_at, 1_591_056_000);
+        assert_eq!(plan.folder_id.as_deref(), Some(batch.folders[2].id.as_str()));
+        assert_eq!(other.folder_id.as_deref(), Some(batch.folders[0].id.as_str()));
+
+        let (stored, owner) = &batch.attachments[0];
+        assert_eq!(batch.attachments.len(), 1);
+        assert_eq!(owner.as_deref(), Some(plan.id.as_str()));
+        assert_eq!((stored.original_name.as_str(), stored.mime.as_deref()), ("shot.png", Some("image/png")));
+        assert_eq!(
+            plan.content,
+            format!("See [other](stem-note://{}) and ![shot](stem-attachment://{})\n\n#projet-perso", other.id, stored.file_name)
+        );
+        assert_eq!(batch.skipped.len(), 1);
+        assert_eq!(std::fs::read_dir(&store_dir).unwrap().count(), 1);
+
+        let _ = std::fs::remove_dir_all(&store_dir);
+    }
+}
@@ -0,0 +1,321 @@
+//! Google Keep import from Takeout: one `.json` file per note in the `Keep`
+//! directory, next to its attached images and recordings. The Takeout can be
+//! given as the extracted directory or as the downloaded zip.
+
+use super::{child_path, commit_batch, file_name, parent_dir, append_tags, ImportBatch, ImportReport, NewNote, MAX_NOTE_FILE_SIZE};
+use crate::attachments::{attachment_uri, AttachmentStore, MAX_ATTACHMENT_SIZE, StoredAttachment};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use crate::text::excerpt;
+use crate::vault::to_portable;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use tauri::State;
+use zip::ZipArchive;
+
+const ROOT_FOLDER: &str = "Google Keep";
+const DEFAULT_TITLE: &str = "Sans titre";
+const TITLE_EXCERPT_CHARS: usize = 60;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct KeepNote {
+    title: String,
+    text_content: String,
+    list_content: Vec<KeepListItem>,
+    attachments: Vec<KeepAttachment>,
+    annotations: Vec<KeepAnnotation>,
+    labels: Vec<KeepLabel>,
+    is_trashed: bool,
+    is_archived: bool,
+    is_pinned: bool,
+    created_timestamp_usec: Option<i64>,
+    user_edited_timestamp_usec: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct KeepListItem {
+    text: String,
+    is_checked: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct KeepAttachment {
+    file_path: String,
+    mimetype: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct KeepAnnotation {
+    url: String,
+    title: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct KeepLabel {
+    name: String,
+}
+
+/// Keep checklists become Markdown task lists.
+fn checklist_markdown(items: &[KeepListItem]) -> String {
+    items
+        .iter()
+        .map(|item| format!("- [{}] {}", if item.is_checked { "x" } else { " " }, item.text.replace('\n', " ").trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// ===== Source =====
+
+/// The `Keep` directory, extracted or inside the Takeout zip.
+pub(crate) enum KeepSource<R: Read + Seek> {
+    Dir(PathBuf),
+    Zip(ZipArchive<R>),
+}
+
+impl<R: Read + Seek> KeepSource<R> {
+    /// Relative paths of all files, `/`-separated.
+    fn files(&mut self) -> Vec<(String, u64)> {
+        match self {
+            KeepSource::Dir(root) => {
+                let mut files = Vec::new();
+                let mut pending = vec![String::new()];
+                while let Some(rel) = pending.pop() {
+                    let Ok(entries) = fs::read_dir(root.join(&rel)) else { continue };
+                    for entry in entries.flatten() {
+                        let Ok(meta) = entry.metadata() else { continue };
+                        let path = child_path(&rel, &entry.file_name().to_string_lossy());
+                        if meta.is_dir() {
+                            pending.push(path);
+                        } else if meta.is_file() {
+                            files.push((path, meta.len()));
+                        }
+                    }
+                }
+                files.sort();
+                files
+            }
+            KeepSource::Zip(archive) => (0..archive.len())
+                .filter_map(|i| archive.by_index(i).ok().filter(|f| f.is_file()).map(|f| (f.name().to_string(), f.size())))
+                .collect(),
+        }
+    }
+
+    fn with_reader<T>(&mut self, path: &str, f: impl FnOnce(&mut dyn Read) -> io::Result<T>) -> io::Result<T> {
+        match self {
+            KeepSource::Dir(root) => f(&mut File::open(root.join(path))?),
+            KeepSource::Zip(archive) => f(&mut archive.by_name(path).map_err(io::Error::from)?),
+        }
+    }
+}
+
+pub(crate) fn read_keep<R: Read + Seek>(
+    source: &mut KeepSource<R>,
+    store: &AttachmentStore,
+    parent_folder_id: Option<String>,
+) -> ImportBatch {
+    let mut batch = ImportBatch::default();
+    let files = source.files();
+    let sizes: HashMap<String, u64> = files.iter().cloned().collect();
+    // A full Takeout holds other products: only JSON files inside a `Keep` directory count
+    let notes: Vec<&String> = files
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|p| p.ends_with(".json") && (p.split('/').any(|part| part == "Keep") || !p.contains('/')))
+        .collect();
+
+    let now = current_timestamp();
+    let root_id = batch.add_folder(ROOT_FOLDER, parent_folder_id, now);
+    let mut label_folders: HashMap<String, String> = HashMap::new();
+
+    for path in notes {
+        if sizes[path] > MAX_NOTE_FILE_SIZE {
+            batch.skip(path.as_str(), format!("Fichier trop volumineux (max {} MB)", MAX_NOTE_FILE_SIZE / 1_048_576));
+            continue;
+        }
+        let parsed = source.with_reader(path, |r| serde_json::from_reader::<_, KeepNote>(r).map_err(io::Error::from));
+        let note = match parsed {
+            Ok(note) => note,
+            Err(e) => {
+                batch.skip(path.as_str(), format!("Note Keep illisible : {}", e));
+                continue;
+            }
+        };
+        if note.is_trashed {
+            batch.skip(path.as_str(), "Note dans la corbeille");
+            continue;
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut blocks = Vec::new();
+        if !note.text_content.trim().is_empty() {
+            blocks.push(note.text_content.trim_end().to_string());
+        }
+        if !note.list_content.is_empty() {
+            blocks.push(checklist_markdown(&note.list_content));
+        }
+
+        let mut embeds = Vec::new();
+        for attachment in &note.attachments {
+            let rel = child_path(parent_dir(path), &attachment.file_path);
+            let Some(&size) = sizes.get(&rel) else {
+                batch.skip(rel, "Pièce jointe introuvable");
+                continue;
+            };
+            if size > MAX_ATTACHMENT_SIZE {
+                batch.skip(rel, format!("Pièce jointe trop volumineuse (max {} MB)", MAX_ATTACHMENT_SIZE / 1_048_576));
+                continue;
+            }
+            let name = file_name(&rel).to_string();
+            let copied: io::Result<StoredAttachment> =
+                source.with_reader(&rel, |mut r| store.write_reader(&mut r, &name, attachment.mimetype.as_deref()));
+            match copied {
+                Ok(stored) => {
+                    let is_image = stored.mime.as_deref().is_some_and(|m| m.starts_with("image/"));
+                    embeds.push(format!("{}[{}]({})", if is_image { "!" } else { "" }, name, attachment_uri(&stored.file_name)));
+                    batch.attachments.push((stored, Some(id.clone())));
+                }
+                Err(e) => batch.skip(rel, format!("Copie impossible : {}", e)),
+            }
+        }
+        if !embeds.is_empty() {
+            blocks.push(embeds.join("\n"));
+        }
+
+        let links: Vec<String> = note
+            .annotations
+            .iter()
+            .filter(|a| !a.url.is_empty())
+            .map(|a| format!("- [{}]({})", if a.title.is_empty() { a.url.as_str() } else { a.title.as_str() }, a.url))
+            .collect();
+        if !links.is_empty() {
+            blocks.push(links.join("\n"));
+        }
+
+        let mut content = blocks.join("\n\n");
+        // The first label becomes the folder, the others stay as hashtags
+        let labels: Vec<String> = note.labels.iter().map(|l| l.name.trim().to_string()).filter(|l| !l.is_empty()).collect();
+        let folder_id = match labels.first() {
+            Some(label) => label_folders
+                .entry(label.clone())
+                .or_insert_with(|| batch.add_folder(label, Some(root_id.clone()), now))
+                .clone(),
+            None => root_id.clone(),
+        };
+        append_tags(&mut content, labels.get(1..).unwrap_or_default());
+
+        let title = match note.title.trim() {
+            "" => Some(excerpt(&note.text_content, TITLE_EXCERPT_CHARS))
+                .filter(|t| !t.is_empty())
+                .or_else(|| note.list_content.first().map(|i| excerpt(&i.text, TITLE_EXCERPT_CHARS)).filter(|t| !t.is_empty()))
+                .unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            title => title.to_string(),
+        };
+        let created_at = note.created_timestamp_usec.map(|us| us / 1_000_000).unwrap_or(now);
+        let updated_at = note.user_edited_timestamp_usec.map(|us| us / 1_000_000).unwrap_or(created_at);
+        batch.notes.push(NewNote {
+            id,
+            title,
+            content,
+            created_at,
+            updated_at: updated_at.max(created_at),
+            is_pinned: note.is_pinned,
+            is_archived: note.is_archived,
+            folder_id: Some(folder_id),
+        });
+    }
+
+    batch
+}
+
+// ===== Tauri Commands =====
+
+/// Imports Google Keep notes from a Takeout directory or zip under a new "Google Keep" folder.
+#[tauri::command]
+pub async fn import_google_keep(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    path: String,
+    parent_folder_id: Option<String>,
+) -> Result<ImportReport, StemError> {
+    let path = PathBuf::from(path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("L'export Google Keep doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let mut source = open_source(&path)?;
+        let batch = read_keep(&mut source, &store, parent_folder_id);
+        commit_batch(&db, &store, batch)
+    }).await
+}
+
+fn open_source(path: &Path) -> Result<KeepSource<BufReader<File>>, StemError> {
+    if path.is_dir() {
+        return Ok(KeepSource::Dir(path.to_path_buf()));
+    }
+    let file = File::open(path)?;
+    ZipArchive::new(BufReader::new(file))
+        .map(KeepSource::Zip)
+        .map_err(|e| StemError::Validation(format!("Archive Takeout invalide ({}) : {}", to_portable(path), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_read_keep_zip() {
+        let files: [(&str, &[u8]); 5] = [
+            ("Takeout/Keep/Courses.json", br#"{
+                "title": "", "isTrashed": false, "isArchived": true, "isPinned": true,
+                "listContent": [{"text": "Pain", "isChecked": true}, {"text": "Lait", "isChecked": false}],
+                "attachments": [{"filePath": "photo.jpg", "mimetype": "image/jpeg"}],
+                "labels": [{"name": "Maison"}, {"name": "urgent"}],
+                "createdTimestampUsec": 1600000000000000, "userEditedTimestampUsec": 1600000100000000
+            }"#),
+            ("Takeout/Keep/photo.jpg", b"jpg"),
+            ("Takeout/Keep/Idee.json", br#"{"title": "Id\u00e9e", "textContent": "Lire", "annotations": [{"url": "https://example.com", "title": "Ex"}]}"#),
+            ("Takeout/Keep/Old.json", br#"{"title": "Old", "isTrashed": true}"#),
+            ("Takeout/Drive/other.json", b"{}"),
+        ];
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        let mut source = KeepSource::Zip(ZipArchive::new(writer.finish().unwrap()).unwrap());
+
+        let store_dir = std::env::temp_dir().join(format!("stem-keep-{}", uuid::Uuid::new_v4()));
+        let store = AttachmentStore::new(store_dir.clone());
+        let batch = read_keep(&mut source, &store, None);
+
+        let folders: Vec<&str> = batch.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(folders, ["Google Keep", "Maison"]);
+        assert_eq!(batch.notes.len(), 2);
+
+        let courses = &batch.notes[0];
+        let stored = &batch.attachments[0].0;
+        assert_eq!(courses.title, "Pain");
+        assert_eq!(courses.content, format!("- [x] Pain\n- [ ] Lait\n\n![photo.jpg](stem-attachment://{})\n\n#urgent", stored.file_name));
+        assert_eq!((courses.created_at, courses.updated_at), (1_600_000_000, 1_600_000_100));
+        assert!(courses.is_pinned && courses.is_archived);
+        assert_eq!(courses.folder_id.as_deref(), Some(batch.folders[1].id.as_str()));
+
+        let idea = &batch.notes[1];
+        assert_eq!(idea.content, "Lire\n\n- [Ex](https://example.com)");
+        assert_eq!(idea.folder_id.as_deref(), Some(batch.folders[0].id.as_str()));
+        assert_eq!(batch.skipped, vec![super::super::SkippedFile { path: "Takeout/Keep/Old.json".into(), reason: "Note dans la corbeille".into() }]);
+
+        let _ = std::fs::remove_dir_all(&store_dir);
+    }
+}
@@ -0,0 +1,198 @@
+//! Importers for other note apps. Each one reads its source into an
+//! `ImportBatch` (fresh ids, links already rewritten, attachments copied)
+//! which `commit_batch` then inserts in a single transaction.
+
+pub mod enex;
+mod enml;
+pub mod joplin;
+pub mod keep;
+pub mod notion;
+pub mod obsidian;
+
+use crate::attachments::{self, AttachmentStore, StoredAttachment};
+use crate::commands::import_summary;
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub struct NewFolder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: i64,
+}
+
+pub struct NewNote {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub is_pinned: bool,
+    pub is_archived: bool,
+    pub folder_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SkippedFile {
+    /// Path relative to the imported source.
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    /// Same wording as `import_all_data`.
+    pub summary: String,
+    pub notes: u32,
+    pub folders: u32,
+    pub attachments: u32,
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Everything an importer produced, in insertion order (parents before children).
+#[derive(Default)]
+pub struct ImportBatch {
+    pub folders: Vec<NewFolder>,
+    pub notes: Vec<NewNote>,
+    /// Files already copied into the store, with the note that references them.
+    pub attachments: Vec<(StoredAttachment, Option<String>)>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+impl ImportBatch {
+    pub fn add_folder(&mut self, name: &str, parent_id: Option<String>, created_at: i64) -> String {
+        let id = Uuid::new_v4().to_string();
+        self.folders.push(NewFolder { id: id.clone(), name: name.to_string(), parent_id, created_at });
+        id
+    }
+
+    pub fn skip(&mut self, path: impl Into<String>, reason: impl Into<String>) {
+        self.skipped.push(SkippedFile { path: path.into(), reason: reason.into() });
+    }
+}
+
+/// Markdown files larger than this are skipped.
+pub const MAX_NOTE_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+// ===== Paths =====
+// Importers work on `/`-separated paths relative to the imported source.
+
+pub fn child_path(rel: &str, name: &str) -> String {
+    if rel.is_empty() { name.to_string() } else { format!("{}/{}", rel, name) }
+}
+
+pub fn parent_dir(rel: &str) -> &str {
+    rel.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+pub fn file_name(rel: &str) -> &str {
+    rel.rsplit('/').next().unwrap_or(rel)
+}
+
+pub fn file_stem(rel: &str) -> &str {
+    let name = file_name(rel);
+    name.rsplit_once('.').map(|(stem, _)| stem).filter(|s| !s.is_empty()).unwrap_or(name)
+}
+
+pub fn is_markdown(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".md")
+}
+
+/// Joins `target` onto `base_dir`, resolving `.` and `..`. Lowercased for lookups.
+pub fn normalize(base_dir: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        vec![]
+    } else {
+        base_dir.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            p => parts.push(p),
+        }
+    }
+    Some(parts.join("/").to_lowercase())
+}
+
+/// External links (`https://`, `mailto:`...) are never rewritten.
+pub fn has_scheme(target: &str) -> bool {
+    target.contains("://") || target.starts_with("mailto:") || target.starts_with("data:")
+}
+
+// ===== Content =====
+
+/// Stem has no tag model: source tags are kept as `#hashtags` on a last line.
+pub fn append_tags(content: &mut String, tags: &[String]) {
+    let tags: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().trim_start_matches('#').split_whitespace().collect::<Vec<_>>().join("-"))
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("#{}", t))
+        .collect();
+    if tags.is_empty() {
+        return;
+    }
+    if !content.is_empty() {
+        content.push_str("\n\n");
+    }
+    content.push_str(&tags.join(" "));
+}
+
+/// Inserts the batch atomically. On failure the copied attachment files are removed.
+pub(crate) fn commit_batch(db: &Database, store: &AttachmentStore, batch: ImportBatch) -> Result<ImportReport, StemError> {
+    if let Err(e) = insert_batch(db, &batch) {
+        let copied: Vec<StoredAttachment> = batch.attachments.into_iter().map(|(a, _)| a).collect();
+        store.discard(&copied);
+        return Err(e);
+    }
+
+    let notes = batch.notes.len() as u32;
+    let folders = batch.folders.len() as u32;
+    Ok(ImportReport {
+        summary: import_summary(notes, folders),
+        notes,
+        folders,
+        attachments: batch.attachments.len() as u32,
+        skipped: batch.skipped,
+    })
+}
+
+fn insert_batch(db: &Database, batch: &ImportBatch) -> Result<(), StemError> {
+    let mut conn = db.try_connection()?;
+    let tx = conn.transaction()?;
+
+    for folder in &batch.folders {
+        let position: i32 = tx.query_row(
+            "SELECT COALESCE(MAX(position), -1) + 1 FROM folders WHERE parent_id IS ?1",
+            [&folder.parent_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT INTO folders (id, name, parent_id, position, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (&folder.id, &folder.name, &folder.parent_id, &position, &folder.created_at),
+        )?;
+    }
+
+    for note in &batch.notes {
+        tx.execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at, is_pinned, folder_id, is_archived) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                note.id, note.title, note.content, note.created_at, note.updated_at,
+                note.is_pinned, note.folder_id, note.is_archived,
+            ],
+        )?;
+    }
+
+    let now = current_timestamp();
+    for (attachment, note_id) in &batch.attachments {
+        attachments::insert_attachment(&tx, attachment, note_id.as_deref(), now)?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,451 @@
+//! Notion export import. The zip holds one `.md` file per page, named
+//! `<Title> <32 hex id>.md`, whose sub-pages live in a directory of the same
+//! name, plus one `.csv` file per database.
+
+use super::{
+    child_path, commit_batch, current_timestamp, file_name, file_stem, has_scheme, is_markdown, normalize, parent_dir,
+    ImportBatch, ImportReport, NewNote, MAX_NOTE_FILE_SIZE,
+};
+use crate::attachments::{attachment_uri, AttachmentStore, MAX_ATTACHMENT_SIZE};
+use crate::db::Database;
+use crate::error::StemError;
+use crate::links::{find_markdown_links, note_link, percent_decode, replace_ranges};
+use crate::vault::to_portable;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use tauri::State;
+use uuid::Uuid;
+use zip::ZipArchive;
+
+const ROOT_FOLDER: &str = "Notion";
+const DEFAULT_TITLE: &str = "Sans titre";
+
+// ===== Names =====
+
+/// `Page 0123…cdef` -> `Page`. Notion appends the page id to every file and directory name.
+fn strip_notion_id(name: &str) -> &str {
+    let Some(cut) = name.len().checked_sub(32).filter(|c| *c > 1 && name.is_char_boundary(*c)) else {
+        return name;
+    };
+    let (head, id) = name.split_at(cut);
+    if head.ends_with(' ') && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        head.trim_end()
+    } else {
+        name
+    }
+}
+
+fn zip_time(dt: zip::DateTime) -> Option<i64> {
+    NaiveDate::from_ymd_opt(dt.year() as i32, dt.month() as u32, dt.day() as u32)?
+        .and_hms_opt(dt.hour() as u32, dt.minute() as u32, dt.second() as u32)
+        .map(|t| t.and_utc().timestamp())
+}
+
+/// RFC 4180 records: quoted fields may contain commas, line breaks and `""`.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| r.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+// ===== Reading =====
+
+struct Entry {
+    index: usize,
+    path: String,
+    size: u64,
+    modified: Option<i64>,
+}
+
+struct Page {
+    rel: String,
+    id: String,
+    title: String,
+    body: String,
+    modified: Option<i64>,
+}
+
+fn read_text<R: Read + Seek>(archive: &mut ZipArchive<R>, entry: &Entry, batch: &mut ImportBatch) -> Option<String> {
+    if entry.size > MAX_NOTE_FILE_SIZE {
+        batch.skip(entry.path.as_str(), format!("Fichier trop volumineux (max {} MB)", MAX_NOTE_FILE_SIZE / 1_048_576));
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(entry.size as usize);
+    if let Err(e) = archive.by_index(entry.index).map_err(std::io::Error::from).and_then(|mut f| f.read_to_end(&mut bytes)) {
+        batch.skip(entry.path.as_str(), format!("Lecture impossible : {}", e));
+        return None;
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Some(text),
+        Err(_) => {
+            batch.skip(entry.path.as_str(), "Encodage non UTF-8");
+            None
+        }
+    }
+}
+
+/// Notion starts every page with `# Title`; it becomes the note title.
+fn split_title(text: &str) -> (Option<String>, String) {
+    let text = text.trim_start_matches('\u{feff}');
+    match text.split_once('\n') {
+        Some((first, rest)) if first.starts_with("# ") => {
+            (Some(first[2..].trim().to_string()), rest.trim_start_matches(['\r', '\n']).to_string())
+        }
+        None if text.starts_with("# ") => (Some(text[2..].trim().to_string()), String::new()),
+        _ => (None, text.to_string()),
+    }
+}
+
+/// Copies archive entries into the attachment store on first reference.
+struct ZipCopier<'a, R: Read + Seek> {
+    archive: &'a mut ZipArchive<R>,
+    store: &'a AttachmentStore,
+    copied: HashMap<usize, Option<String>>,
+}
+
+impl<R: Read + Seek> ZipCopier<'_, R> {
+    fn uri(&mut self, entry: &Entry, note_id: &str, batch: &mut ImportBatch) -> Option<String> {
+        if let Some(stored) = self.copied.get(&entry.index) {
+            return stored.as_ref().map(|name| attachment_uri(name));
+        }
+        let stored = if entry.size > MAX_ATTACHMENT_SIZE {
+            batch.skip(entry.path.as_str(), format!("Pièce jointe trop volumineuse (max {} MB)", MAX_ATTACHMENT_SIZE / 1_048_576));
+            None
+        } else {
+            let result = self
+                .archive
+                .by_index(entry.index)
+                .map_err(std::io::Error::from)
+                .and_then(|mut f| self.store.write_reader(&mut f, file_name(&entry.path), None));
+            match result {
+                Ok(stored) => {
+                    let name = stored.file_name.clone();
+                    batch.attachments.push((stored, Some(note_id.to_string())));
+                    Some(name)
+                }
+                Err(e) => {
+                    batch.skip(entry.path.as_str(), format!("Copie impossible : {}", e));
+                    None
+                }
+            }
+        };
+        let uri = stored.as_ref().map(|n| attachment_uri(n));
+        self.copied.insert(entry.index, stored);
+        uri
+    }
+}
+
+pub(crate) fn read_notion_export<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    store: &AttachmentStore,
+    parent_folder_id: Option<String>,
+) -> ImportBatch {
+    let mut batch = ImportBatch::default();
+    let mut dirs: BTreeSet<String> = BTreeSet::new();
+    let (mut page_entries, mut csv_entries, mut files) = (Vec::new(), Vec::new(), Vec::new());
+
+    for index in 0..archive.len() {
+        let entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                batch.skip(format!("#{}", index), format!("Entrée illisible : {}", e));
+                continue;
+            }
+        };
+        let Some(path) = entry.enclosed_name().map(|p| to_portable(&p)).filter(|p| !p.is_empty()) else {
+            batch.skip(entry.name(), "Chemin invalide");
+            continue;
+        };
+        if entry.is_dir() {
+            dirs.insert(path);
+            continue;
+        }
+        let mut dir = parent_dir(&path);
+        while !dir.is_empty() {
+            dirs.insert(dir.to_string());
+            dir = parent_dir(dir);
+        }
+        let lower = path.to_lowercase();
+        let info = Entry { index, size: entry.size(), modified: entry.last_modified().and_then(zip_time), path };
+        if is_markdown(&lower) {
+            page_entries.push(info);
+        } else if lower.ends_with(".csv") {
+            csv_entries.push(info);
+        } else if lower.ends_with(".zip") {
+            batch.skip(info.path, "Archive imbriquée : importez-la séparément");
+        } else {
+            files.push(info);
+        }
+    }
+
+    let now = current_timestamp();
+    let mut folder_ids: HashMap<String, String> = HashMap::new();
+    folder_ids.insert(String::new(), batch.add_folder(ROOT_FOLDER, parent_folder_id, now));
+    // BTreeSet order puts every directory after its parent
+    for dir in &dirs {
+        let parent = folder_ids.get(parent_dir(dir)).cloned();
+        let id = batch.add_folder(strip_notion_id(file_name(dir)), parent, now);
+        folder_ids.insert(dir.clone(), id);
+    }
+    // A page with sub-pages goes inside the folder built from its directory
+    let folder_for = |rel: &str, folder_ids: &HashMap<String, String>| {
+        let own_dir = &rel[..rel.len() - file_name(rel).len() + file_stem(rel).len()];
+        folder_ids.get(own_dir).or_else(|| folder_ids.get(parent_dir(rel))).cloned()
+    };
+
+    let mut pages = Vec::new();
+    let mut notes_by_path: HashMap<String, usize> = HashMap::new();
+    for entry in &page_entries {
+        let Some(text) = read_text(archive, entry, &mut batch) else { continue };
+        let (title, body) = split_title(&text);
+        notes_by_path.insert(entry.path.to_lowercase(), pages.len());
+        pages.push(Page {
+            rel: entry.path.clone(),
+            id: Uuid::new_v4().to_string(),
+            title: title
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| strip_notion_id(file_stem(&entry.path)).to_string()),
+            body,
+            modified: entry.modified,
+        });
+    }
+    let files_by_path: HashMap<String, &Entry> = files.iter().map(|e| (e.path.to_lowercase(), e)).collect();
+
+    let mut copier = ZipCopier { archive, store, copied: HashMap::new() };
+    for page in &pages {
+        let dir = parent_dir(&page.rel);
+        let mut edits = Vec::new();
+        for link in find_markdown_links(&page.body) {
+            if link.target.is_empty() || link.target.starts_with('#') || has_scheme(&link.target) {
+                continue;
+            }
+            let (path, fragment) = match link.target.split_once('#') {
+                Some((p, f)) => (p, Some(percent_decode(f))),
+                None => (link.target.as_str(), None),
+            };
+            let Some(target) = normalize(dir, &percent_decode(path)) else { continue };
+            if let Some(&note) = notes_by_path.get(&target) {
+                edits.push((link.range, format!("[{}]({})", link.label, note_link(&pages[note].id, fragment.as_deref()))));
+            } else if let Some(entry) = files_by_path.get(&target) {
+                let Some(uri) = copier.uri(entry, &page.id, &mut batch) else { continue };
+                let bang = if link.is_image { "!" } else { "" };
+                edits.push((link.range, format!("{}[{}]({})", bang, link.label, uri)));
+            }
+        }
+        let timestamp = page.modified.unwrap_or(now);
+        batch.notes.push(NewNote {
+            id: page.id.clone(),
+            title: page.title.clone(),
+            content: replace_ranges(&page.body, edits),
+            created_at: timestamp,
+            updated_at: timestamp,
+            is_pinned: false,
+            is_archived: false,
+            folder_id: folder_for(&page.rel, &folder_ids),
+        });
+    }
+    for entry in &files {
+        if !copier.copied.contains_key(&entry.index) {
+            batch.skip(entry.path.as_str(), "Fichier non référencé par une page");
+        }
+    }
+
+    // Databases: Notion may write both `DB <id>.csv` and `DB <id>_all.csv`; the latter has every row.
+    let mut tables: BTreeMap<String, &Entry> = BTreeMap::new();
+    for entry in &csv_entries {
+        let stem = file_stem(&entry.path);
+        let base = stem.strip_suffix("_all").unwrap_or(stem);
+        let key = child_path(parent_dir(&entry.path), base);
+        match tables.get(&key) {
+            Some(existing) if file_stem(&existing.path).ends_with("_all") => {
+                batch.skip(entry.path.as_str(), format!("Doublon de {}", existing.path));
+            }
+            Some(existing) => {
+                batch.skip(existing.path.as_str(), format!("Doublon de {}", entry.path));
+                tables.insert(key, entry);
+            }
+            None => {
+                tables.insert(key, entry);
+            }
+        }
+    }
+
+    for (db_dir, entry) in tables {
+        let Some(text) = read_text(copier.archive, entry, &mut batch) else { continue };
+        let mut rows = parse_csv(&text).into_iter();
+        let Some(header) = rows.next() else { continue };
+        let folder_id = match folder_ids.get(&db_dir) {
+            Some(id) => id.clone(),
+            None => {
+                let parent = folder_ids.get(parent_dir(&db_dir)).cloned();
+                let id = batch.add_folder(strip_notion_id(file_name(&db_dir)), parent, now);
+                folder_ids.insert(db_dir.clone(), id.clone());
+                id
+            }
+        };
+        // Rows that have their own page were already imported from the `.md` file
+        let lower_dir = db_dir.to_lowercase();
+        let page_titles: HashSet<&str> = pages
+            .iter()
+            .filter(|p| parent_dir(&p.rel).to_lowercase() == lower_dir)
+            .map(|p| p.title.as_str())
+            .collect();
+
+        for row in rows {
+            let title = row.first().map(|t| t.trim()).filter(|t| !t.is_empty()).unwrap_or(DEFAULT_TITLE);
+            if page_titles.contains(title) {
+                continue;
+            }
+            let content = header
+                .iter()
+                .zip(row.iter())
+                .skip(1)
+                .filter(|(_, value)| !value.trim().is_empty())
+                .map(|(name, value)| format!("{}: {}", name.trim(), value.trim()))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let timestamp = entry.modified.unwrap_or(now);
+            batch.notes.push(NewNote {
+                id: Uuid::new_v4().to_string(),
+                title: title.to_string(),
+                content,
+                created_at: timestamp,
+                updated_at: timestamp,
+                is_pinned: false,
+                is_archived: false,
+                folder_id: Some(folder_id.clone()),
+            });
+        }
+    }
+
+    batch
+}
+
+// ===== Tauri Commands =====
+
+/// Imports a Notion "Markdown & CSV" export zip under a new "Notion" folder.
+#[tauri::command]
+pub async fn import_notion_export(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    zip_path: String,
+    parent_folder_id: Option<String>,
+) -> Result<ImportReport, StemError> {
+    let path = PathBuf::from(zip_path);
+    if !path.is_absolute() {
+        return Err(StemError::Validation("L'archive Notion doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let mut archive = open_archive(&path)?;
+        let batch = read_notion_export(&mut archive, &store, parent_folder_id);
+        commit_batch(&db, &store, batch)
+    }).await
+}
+
+fn open_archive(path: &Path) -> Result<ZipArchive<BufReader<File>>, StemError> {
+    let file = File::open(path)?;
+    ZipArchive::new(BufReader::new(file)).map_err(|e| StemError::Validation(format!("Archive Notion invalide : {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    const P: &str = "0123456789abcdef0123456789abcdef";
+    const C: &str = "fedcba9876543210fedcba9876543210";
+    const T: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+    fn build_zip(files: &[(String, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(name.as_str(), SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_strip_notion_id_and_csv() {
+        assert_eq!(strip_notion_id(&format!("My Page {}", P)), "My Page");
+        assert_eq!(strip_notion_id("Plain name"), "Plain name");
+        assert_eq!(strip_notion_id(P), P);
+        assert_eq!(
+            parse_csv("\u{feff}Name,Notes\r\n\"a, \"\"b\"\"\",\"multi\nline\"\n\n"),
+            vec![vec!["Name", "Notes"], vec!["a, \"b\"", "multi\nline"]]
+        );
+    }
+
+    #[test]
+    fn test_read_notion_export() {
+        let mut archive = build_zip(&[
+            (format!("Projects {P}.md"), &format!("# Projects\n\nSee [Plan](Projects%20{P}/Plan%20{C}.md) ![img](Projects%20{P}/img.png)")),
+            (format!("Projects {P}/Plan {C}.md"), &format!("# Plan\n\nBack to [parent](../Projects%20{P}.md#goals)")),
+            (format!("Projects {P}/img.png"), "png"),
+            (format!("Tasks {T}.csv"), "Name,Status\nBuy milk,Done\n"),
+            (format!("Tasks {T}_all.csv"), "\u{feff}Name,Status,Notes\n\"Buy milk\",Done,\"a, b\"\nRow page,Todo,\n"),
+            (format!("Tasks {T}/Row page {C}.md"), "# Row page\n\nStatus: Todo\n"),
+            ("stray.bin".to_string(), "x"),
+        ]);
+        let store_dir = std::env::temp_dir().join(format!("stem-notion-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(store_dir.clone());
+        let batch = read_notion_export(&mut archive, &store, None);
+
+        let folders: Vec<&str> = batch.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(folders, vec!["Notion", "Projects", "Tasks"]);
+        let titles: Vec<&str> = batch.notes.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(titles, vec!["Projects", "Plan", "Row page", "Buy milk"]);
+
+        let (projects, plan) = (&batch.notes[0], &batch.notes[1]);
+        assert_eq!(projects.folder_id, plan.folder_id);
+        assert_eq!(projects.folder_id.as_deref(), Some(batch.folders[1].id.as_str()));
+        assert!(projects.content.contains(&format!("[Plan](stem-note://{})", plan.id)));
+        assert!(projects.content.contains("![img](stem-attachment://"));
+        assert_eq!(plan.content, format!("Back to [parent](stem-note://{}#goals)", projects.id));
+        assert_eq!(batch.notes[3].content, "Status: Done\nNotes: a, b");
+        assert_eq!(batch.notes[3].folder_id.as_deref(), Some(batch.folders[2].id.as_str()));
+
+        let reasons: Vec<&str> = batch.skipped.iter().map(|s| s.reason.as_str()).collect();
+        assert_eq!(reasons, vec!["Fichier non référencé par une page", &format!("Doublon de Tasks {T}_all.csv")]);
+        assert_eq!(batch.attachments.len(), 1);
+
+        let _ = std::fs::remove_dir_all(&store_dir);
+    }
+}
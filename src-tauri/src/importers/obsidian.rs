@@ -0,0 +1,435 @@
+//! Obsidian vault import: every `.md` file becomes a note, sub-directories
+//! become folders, embedded files are copied as attachments and wiki links are
+//! rewritten to point at the new note ids.
+
+use super::{
+    child_path, commit_batch, file_name, file_stem, has_scheme, is_markdown, normalize, parent_dir, ImportBatch,
+    ImportReport, NewNote, MAX_NOTE_FILE_SIZE,
+};
+use crate::attachments::{attachment_uri, AttachmentStore, MAX_ATTACHMENT_SIZE};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use crate::links::{find_markdown_links, find_wiki_links, note_link, percent_decode, replace_ranges};
+use crate::vault::{parse_front_matter, parse_timestamp, FrontValue};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::State;
+use uuid::Uuid;
+
+const CREATED_KEYS: [&str; 3] = ["created", "created_at", "date"];
+const UPDATED_KEYS: [&str; 4] = ["updated", "updated_at", "modified", "last_modified"];
+
+// ===== Walking =====
+
+/// Paths relative to the vault root, `/`-separated, in depth-first order.
+#[derive(Default)]
+struct VaultFiles {
+    dirs: Vec<String>,
+    markdown: Vec<String>,
+    others: Vec<String>,
+}
+
+/// Hidden entries (`.obsidian`, `.trash`, `.git`...) are app data, not notes.
+fn walk(root: &Path, rel: &str, files: &mut VaultFiles, batch: &mut ImportBatch) {
+    let entries = match fs::read_dir(root.join(rel)) {
+        Ok(entries) => entries,
+        Err(e) => return batch.skip(rel, format!("Dossier illisible : {}", e)),
+    };
+    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            batch.skip(entry.file_name().to_string_lossy(), "Nom de fichier non UTF-8");
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = child_path(rel, &name);
+        match entry.file_type() {
+            Ok(t) if t.is_symlink() => batch.skip(path, "Lien symbolique ignoré"),
+            Ok(t) if t.is_dir() => {
+                files.dirs.push(path.clone());
+                walk(root, &path, files, batch);
+            }
+            Ok(_) if is_markdown(&name) => files.markdown.push(path),
+            Ok(_) => files.others.push(path),
+            Err(e) => batch.skip(path, format!("Lecture impossible : {}", e)),
+        }
+    }
+}
+
+// ===== Resolution =====
+
+struct Draft {
+    rel: String,
+    id: String,
+    title: String,
+    body: String,
+    created_at: i64,
+    updated_at: i64,
+    is_pinned: bool,
+    is_archived: bool,
+}
+
+#[derive(Default)]
+struct VaultIndex {
+    /// Lowercased path without `.md` -> draft index.
+    notes_by_path: HashMap<String, usize>,
+    /// Lowercased file stem or front-matter alias -> draft index (first one wins).
+    notes_by_name: HashMap<String, usize>,
+    files_by_path: HashMap<String, usize>,
+    files_by_name: HashMap<String, usize>,
+}
+
+impl VaultIndex {
+    fn resolve_note(&self, target: &str, note_dir: &str) -> Option<usize> {
+        let target = target.replace('\\', "/");
+        let target = match target.len().checked_sub(3) {
+            Some(cut) if is_markdown(&target) => &target[..cut],
+            _ => target.as_str(),
+        };
+        let relative = normalize(note_dir, target).and_then(|p| self.notes_by_path.get(&p));
+        let absolute = normalize("", target).and_then(|p| self.notes_by_path.get(&p));
+        let by_name = || self.notes_by_name.get(&target.to_lowercase());
+        relative.or(absolute).or_else(|| if target.contains('/') { None } else { by_name() }).copied()
+    }
+
+    fn resolve_file(&self, target: &str, note_dir: &str) -> Option<usize> {
+        let target = target.replace('\\', "/");
+        let relative = normalize(note_dir, &target).and_then(|p| self.files_by_path.get(&p));
+        let absolute = normalize("", &target).and_then(|p| self.files_by_path.get(&p));
+        let by_name = || self.files_by_name.get(&file_name(&target).to_lowercase());
+        relative.or(absolute).or_else(by_name).copied()
+    }
+}
+
+/// Copies referenced files on first use; `None` once a copy has failed.
+struct AttachmentCopier<'a> {
+    root: &'a Path,
+    store: &'a AttachmentStore,
+    others: &'a [String],
+    copied: HashMap<usize, Option<String>>,
+}
+
+impl AttachmentCopier<'_> {
+    fn uri(&mut self, file: usize, note_id: &str, batch: &mut ImportBatch) -> Option<String> {
+        if let Some(stored) = self.copied.get(&file) {
+            return stored.as_ref().map(|name| attachment_uri(name));
+        }
+        let rel = &self.others[file];
+        let source = self.root.join(rel);
+        let name = file_name(rel);
+
+        let stored = match fs::metadata(&source) {
+            Ok(meta) if meta.len() > MAX_ATTACHMENT_SIZE => {
+                batch.skip(rel.as_str(), format!("Pièce jointe trop volumineuse (max {} MB)", MAX_ATTACHMENT_SIZE / 1_048_576));
+                None
+            }
+            Ok(_) => match self.store.copy_file(&source, name) {
+                Ok(stored) => {
+                    let file_name = stored.file_name.clone();
+                    batch.attachments.push((stored, Some(note_id.to_string())));
+                    Some(file_name)
+                }
+                Err(e) => {
+                    batch.skip(rel.as_str(), format!("Copie impossible : {}", e));
+                    None
+                }
+            },
+            Err(e) => {
+                batch.skip(rel.as_str(), format!("Lecture impossible : {}", e));
+                None
+            }
+        };
+        let uri = stored.as_ref().map(|n| attachment_uri(n));
+        self.copied.insert(file, stored);
+        uri
+    }
+}
+
+/// Obsidian uses the alias slot of image embeds for sizes (`![[img.png|300]]`).
+fn is_size_hint(alias: &str) -> bool {
+    !alias.is_empty() && alias.chars().all(|c| c.is_ascii_digit() || c == 'x')
+}
+
+fn rewrite_links(
+    draft_idx: usize,
+    drafts: &[Draft],
+    index: &VaultIndex,
+    copier: &mut AttachmentCopier,
+    batch: &mut ImportBatch,
+) -> String {
+    let draft = &drafts[draft_idx];
+    let note_dir = parent_dir(&draft.rel);
+    let body = &draft.body;
+    let mut edits = Vec::new();
+
+    for link in find_wiki_links(body) {
+        let heading = link.heading.as_deref().filter(|h| !h.is_empty());
+        let note = if link.target.is_empty() { Some(draft_idx) } else { index.resolve_note(&link.target, note_dir) };
+        if let Some(target) = note {
+            let label = link.alias.clone().unwrap_or_else(|| match heading {
+                Some(h) if link.target.is_empty() => h.to_string(),
+                Some(h) => format!("{} > {}", link.target, h),
+                None => link.target.clone(),
+            });
+            edits.push((link.range, format!("[{}]({})", label, note_link(&drafts[target].id, heading))));
+        } else if let Some(file) = index.resolve_file(&link.target, note_dir) {
+            let Some(uri) = copier.uri(file, &draft.id, batch) else { continue };
+            let label = link
+                .alias
+                .clone()
+                .filter(|a| !is_size_hint(a))
+                .unwrap_or_else(|| file_name(&link.target).to_string());
+            let bang = if link.embed { "!" } else { "" };
+            edits.push((link.range, format!("{}[{}]({})", bang, label, uri)));
+        }
+    }
+
+    for link in find_markdown_links(body) {
+        if link.target.is_empty() || link.target.starts_with('#') || has_scheme(&link.target) {
+            continue;
+        }
+        let (path, fragment) = match link.target.split_once('#') {
+            Some((p, f)) => (p, Some(percent_decode(f))),
+            None => (link.target.as_str(), None),
+        };
+        let path = percent_decode(path);
+        if is_markdown(&path) {
+            if let Some(target) = index.resolve_note(&path, note_dir) {
+                let uri = note_link(&drafts[target].id, fragment.as_deref());
+                edits.push((link.range, format!("[{}]({})", link.label, uri)));
+            }
+        } else if let Some(file) = index.resolve_file(&path, note_dir) {
+            let Some(uri) = copier.uri(file, &draft.id, batch) else { continue };
+            let bang = if link.is_image { "!" } else { "" };
+            edits.push((link.range, format!("{}[{}]({})", bang, link.label, uri)));
+        }
+    }
+
+    replace_ranges(body, edits)
+}
+
+// ===== Import =====
+
+fn timestamp_from(fields: &BTreeMap<String, FrontValue>, keys: &[&str]) -> Option<i64> {
+    keys.iter()
+        .filter_map(|k| fields.get(*k).and_then(FrontValue::as_text))
+        .find_map(parse_timestamp)
+}
+
+fn system_time(time: std::io::Result<SystemTime>) -> Option<i64> {
+    time.ok()?.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() as i64)
+}
+
+fn read_note(root: &Path, rel: &str, batch: &mut ImportBatch) -> Option<(Draft, Vec<String>)> {
+    let path = root.join(rel);
+    let meta = match fs::metadata(&path) {
+        Ok(meta) => meta,
+        Err(e) => {
+            batch.skip(rel, format!("Lecture impossible : {}", e));
+            return None;
+        }
+    };
+    if meta.len() > MAX_NOTE_FILE_SIZE {
+        batch.skip(rel, format!("Fichier trop volumineux (max {} MB)", MAX_NOTE_FILE_SIZE / 1_048_576));
+        return None;
+    }
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            batch.skip(rel, format!("Lecture impossible : {}", e));
+            return None;
+        }
+    };
+    let Ok(text) = String::from_utf8(bytes) else {
+        batch.skip(rel, "Encodage non UTF-8");
+        return None;
+    };
+
+    let (fields, body) = parse_front_matter(&text);
+    let modified = system_time(meta.modified()).unwrap_or_else(current_timestamp);
+    let created_at = timestamp_from(&fields, &CREATED_KEYS)
+        .or_else(|| system_time(meta.created()))
+        .unwrap_or(modified);
+    let updated_at = timestamp_from(&fields, &UPDATED_KEYS).unwrap_or(modified).max(created_at);
+    let title = fields
+        .get("title")
+        .and_then(FrontValue::as_text)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| file_stem(rel))
+        .to_string();
+    let aliases = fields.get("aliases").map(FrontValue::to_list).unwrap_or_default();
+
+    let draft = Draft {
+        rel: rel.to_string(),
+        id: Uuid::new_v4().to_string(),
+        title,
+        body: body.to_string(),
+        created_at,
+        updated_at,
+        is_pinned: fields.get("pinned").and_then(FrontValue::as_bool).unwrap_or(false),
+        is_archived: fields.get("archived").and_then(FrontValue::as_bool).unwrap_or(false),
+    };
+    Some((draft, aliases))
+}
+
+/// Reads a whole vault into a batch rooted at a new folder named after the vault.
+pub(crate) fn read_vault(root: &Path, store: &AttachmentStore, parent_folder_id: Option<String>) -> Result<ImportBatch, StemError> {
+    if !root.is_dir() {
+        return Err(StemError::Validation(format!("Coffre introuvable : {}", root.display())));
+    }
+    let mut batch = ImportBatch::default();
+    let mut files = VaultFiles::default();
+    walk(root, "", &mut files, &mut batch);
+
+    let now = current_timestamp();
+    let vault_name = root.file_name().and_then(|n| n.to_str()).unwrap_or("Obsidian");
+    let mut folder_ids: HashMap<String, String> = HashMap::new();
+    folder_ids.insert(String::new(), batch.add_folder(vault_name, parent_folder_id, now));
+    for dir in &files.dirs {
+        let parent = folder_ids.get(parent_dir(dir)).cloned();
+        let id = batch.add_folder(file_name(dir), parent, now);
+        folder_ids.insert(dir.clone(), id);
+    }
+
+    let mut index = VaultIndex::default();
+    let mut drafts = Vec::new();
+    for rel in &files.markdown {
+        let Some((draft, aliases)) = read_note(root, rel, &mut batch) else { continue };
+        let i = drafts.len();
+        index.notes_by_path.insert(rel[..rel.len() - 3].to_lowercase(), i);
+        for name in std::iter::once(file_stem(rel).to_string()).chain(aliases) {
+            index.notes_by_name.entry(name.to_lowercase()).or_insert(i);
+        }
+        drafts.push(draft);
+    }
+    for (i, rel) in files.others.iter().enumerate() {
+        index.files_by_path.insert(rel.to_lowercase(), i);
+        index.files_by_name.entry(file_name(rel).to_lowercase()).or_insert(i);
+    }
+
+    let mut copier = AttachmentCopier { root, store, others: &files.others, copied: HashMap::new() };
+    for i in 0..drafts.len() {
+        let content = rewrite_links(i, &drafts, &index, &mut copier, &mut batch);
+        let draft = &drafts[i];
+        batch.notes.push(NewNote {
+            id: draft.id.clone(),
+            title: draft.title.clone(),
+            content,
+            created_at: draft.created_at,
+            updated_at: draft.updated_at,
+            is_pinned: draft.is_pinned,
+            is_archived: draft.is_archived,
+            folder_id: folder_ids.get(parent_dir(&draft.rel)).cloned(),
+        });
+    }
+
+    for (i, rel) in files.others.iter().enumerate() {
+        if !copier.copied.contains_key(&i) {
+            batch.skip(rel.as_str(), "Fichier non référencé par une note");
+        }
+    }
+    Ok(batch)
+}
+
+// ===== Tauri Commands =====
+
+/// Imports an Obsidian vault under a new folder named after it.
+#[tauri::command]
+pub async fn import_obsidian_vault(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    source_dir: String,
+    parent_folder_id: Option<String>,
+) -> Result<ImportReport, StemError> {
+    let root = PathBuf::from(source_dir);
+    if !root.is_absolute() {
+        return Err(StemError::Validation("Le dossier du coffre doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let batch = read_vault(&root, &store, parent_folder_id)?;
+        commit_batch(&db, &store, batch)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(label: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("stem-{}-{}", label, Uuid::new_v4()))
+    }
+
+    fn write(root: &Path, rel: &str, bytes: &[u8]) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_and_resolution() {
+        assert_eq!(normalize("a/b", "../C.png"), Some("a/c.png".to_string()));
+        assert_eq!(normalize("a", "../../x"), None);
+        assert_eq!(normalize("a", "/x/y"), Some("x/y".to_string()));
+        assert!(is_size_hint("300") && is_size_hint("100x200") && !is_size_hint("Diagram"));
+    }
+
+    #[test]
+    fn test_import_vault() {
+        let root = temp_dir("obsidian");
+        write(&root, ".obsidian/app.json", b"{}");
+        write(&root, "Projects/Plan.md", b"---\ntitle: The Plan\ncreated: 2024-01-02\npinned: true\naliases: [Roadmap]\n---\n# Goals\nBody");
+        write(&root, "Index.md", b"See [[Plan|the plan]], [[Roadmap#Goals]], [[Missing]] and [rel](Projects/Plan.md).\n![[pic.png|300]] ![x](assets/a%20b.png)\n`[[Plan]]`");
+        write(&root, "pic.png", b"png");
+        write(&root, "assets/a b.png", b"png2");
+        write(&root, "notes.bin", b"bin");
+        write(&root, "Bad.md", &[0xff, 0xfe, 0x00]);
+        fs::create_dir_all(root.join("Empty")).unwrap();
+
+        let store_dir = temp_dir("attachments");
+        let store = AttachmentStore::new(store_dir.clone());
+        let batch = read_vault(&root, &store, None).unwrap();
+
+        let folder_names: Vec<&str> = batch.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(folder_names.len(), 4);
+        assert!(folder_names.contains(&"Empty") && folder_names.contains(&"Projects") && folder_names.contains(&"assets"));
+
+        let plan = batch.notes.iter().find(|n| n.title == "The Plan").unwrap();
+        assert!(plan.is_pinned);
+        assert_eq!(plan.created_at, 1_704_153_600);
+        assert_eq!(plan.folder_id.as_deref(), Some(batch.folders.iter().find(|f| f.name == "Projects").unwrap().id.as_str()));
+
+        let index = batch.notes.iter().find(|n| n.title == "Index").unwrap();
+        assert!(index.content.contains(&format!("[the plan](stem-note://{})", plan.id)));
+        assert!(index.content.contains(&format!("[Roadmap > Goals](stem-note://{}#goals)", plan.id)));
+        assert!(index.content.contains(&format!("[rel](stem-note://{})", plan.id)));
+        assert!(index.content.contains("[[Missing]]"));
+        assert!(index.content.contains("`[[Plan]]`"));
+        assert!(index.content.contains("![pic.png](stem-attachment://"));
+        assert!(index.content.contains("![x](stem-attachment://"));
+
+        assert_eq!(batch.attachments.len(), 2);
+        assert!(batch.attachments.iter().all(|(a, note)| store_dir.join(&a.file_name).exists() && note.as_deref() == Some(index.id.as_str())));
+
+        let skipped: Vec<(&str, &str)> = batch.skipped.iter().map(|s| (s.path.as_str(), s.reason.as_str())).collect();
+        assert_eq!(skipped, vec![("Bad.md", "Encodage non UTF-8"), ("notes.bin", "Fichier non référencé par une note")]);
+
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        let report = commit_batch(&db, &store, batch).unwrap();
+        assert_eq!(report.summary, "2 notes, 4 dossiers importés");
+        assert_eq!(report.attachments, 2);
+        let count: i64 = db.connection().query_row("SELECT COUNT(*) FROM attachments", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+}
@@ -1,18 +1,64 @@
+mod archive;
+mod attachments;
+mod backup;
+mod blocknote;
+mod bundle;
 mod commands;
 mod db;
 mod embeddings;
+mod encryption;
 mod error;
+mod importers;
+mod linkcheck;
+mod links;
+mod markdown;
 mod ollama;
+mod restore;
+mod site;
+mod stats;
+mod sync;
+mod text;
+mod usage;
+mod vault;
 
 use commands::{
     create_note, delete_note, get_all_notes, get_note, init_database, update_note, toggle_pin_note,
-    export_all_data, import_all_data,
+    list_notes, archive_note, unarchive_note, archive_folder, unarchive_folder,
+    export_all_data, import_all_data, preview_import_data,
     get_all_folders, create_folder, rename_folder, delete_folder, move_note_to_folder, move_folder,
     get_chat_messages, save_chat_message, clear_chat_messages,
 };
+use archive::{export_archive, import_archive, preview_import_archive, ScratchDir};
+use attachments::{serve_attachment, AttachmentStore};
+use blocknote::{convert_blocknote_to_markdown, convert_markdown_to_blocknote};
+use backup::{
+    get_backup_status, list_backup_snapshots, preview_restore_backup, restore_backup, run_backup, run_backup_scheduler,
+    set_backup_config, OffsiteBackup,
+};
 use db::Database;
 use embeddings::{generate_embedding, search_similar_notes, delete_embedding};
+use encryption::{decrypt_note, encrypt_note, lock_note, unlock_note, SessionKeys};
+use importers::enex::import_enex;
+use importers::joplin::import_joplin_jex;
+use importers::keep::import_google_keep;
+use importers::notion::import_notion_export;
+use importers::obsidian::import_obsidian_vault;
+use linkcheck::{check_links, fix_broken_link};
+use markdown::{get_note_outline, get_note_section, replace_note_section};
 use ollama::{check_ollama_connection, get_ollama_models, ollama_chat};
+use site::export_site;
+use stats::get_statistics;
+use sync::conflicts::{list_sync_conflicts, resolve_sync_conflict};
+use sync::crdt::{apply_note_updates, disable_note_crdt, enable_note_crdt, export_note_updates, get_note_heads};
+use sync::git::{get_git_sync_config, git_sync, run_git_scheduler, set_git_sync_config, GitSync};
+use sync::lan::{
+    create_lan_pairing_code, lan_sync, list_lan_peers, pair_lan_device, remove_lan_peer, start_lan_server, stop_lan_server,
+    LanSync,
+};
+use sync::mirror::{get_mirror_config, resume_mirror, start_mirror, stop_mirror, FsMirror};
+use sync::webdav::{get_webdav_config, set_webdav_config, webdav_sync, WebDavSync};
+use usage::{get_frequent_notes, get_recent_notes, note_opened};
+use vault::export_markdown;
 use tauri::{Manager, Emitter};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut};
 
@@ -26,6 +72,25 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_window_state::Builder::new().build())
+        // Images and files embedded in notes as `stem-attachment://<file>`
+        .register_uri_scheme_protocol("stem-attachment", |ctx, request| {
+            let store = ctx.app_handle().state::<AttachmentStore>();
+            let served = serve_attachment(&store, &request.uri().to_string());
+            tauri::http::Response::builder()
+                .status(served.status)
+                .header("Content-Type", served.mime)
+                .header("Content-Security-Policy", "default-src 'none'; img-src 'self'; style-src 'unsafe-inline'; sandbox")
+                .header("X-Content-Type-Options", "nosniff")
+                .body(served.body)
+                .unwrap_or_default()
+        })
+        // Internal links `stem-note://<id>`: the webview opens the note instead of navigating
+        .register_uri_scheme_protocol("stem-note", |ctx, request| {
+            if let Some(id) = links::protocol_target(&request.uri().to_string()) {
+                let _ = ctx.app_handle().emit("open-note", id);
+            }
+            tauri::http::Response::builder().status(204).body(Vec::new()).unwrap_or_default()
+        })
         .setup(|app| {
             let app_data_dir = app.path().app_data_dir().expect("Failed to get app data dir");
             std::fs::create_dir_all(&app_data_dir).expect("Failed to create app data dir");
@@ -52,6 +117,14 @@ pub fn run() {
             database.init().expect("Failed to initialize database");
             
             app.manage(database);
+            app.manage(SessionKeys::default());
+            app.manage(AttachmentStore::new(app_data_dir.join("attachments")));
+            app.manage(ScratchDir::new(app_data_dir.join("tmp")));
+            app.manage(GitSync::new(app_data_dir.join("git-sync")));
+            app.manage(FsMirror::default());
+            app.manage(LanSync::default());
+            app.manage(WebDavSync::new(app_data_dir.join("webdav")));
+            app.manage(OffsiteBackup::new(app_data_dir.join("backup")));
 
             // A4: Singleton reqwest::Client shared across all Ollama commands
             let http_client = reqwest::Client::builder()
@@ -60,6 +133,20 @@ pub fn run() {
                 .expect("Failed to create HTTP client");
             app.manage(http_client);
 
+            // Resume the file mirror in the background: the first pass reads the whole directory
+            let mirror_handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let _ = resume_mirror(&mirror_handle);
+            });
+
+            // Git sync: pull on launch, then every five minutes
+            let git_handle = app.handle().clone();
+            std::thread::spawn(move || run_git_scheduler(&git_handle));
+
+            // Scheduled offsite backups
+            let backup_handle = app.handle().clone();
+            std::thread::spawn(move || run_backup_scheduler(&backup_handle));
+
             // Register global shortcut Ctrl+Shift+N for quick capture
             let shortcut = Shortcut::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::KeyN);
             let app_handle = app.handle().clone();
@@ -110,14 +197,23 @@ pub fn run() {
             create_note,
             get_note,
             get_all_notes,
+            list_notes,
             update_note,
             delete_note,
             toggle_pin_note,
+            archive_note,
+            unarchive_note,
+            archive_folder,
+            unarchive_folder,
             check_ollama_connection,
             get_ollama_models,
             ollama_chat,
             export_all_data,
             import_all_data,
+            preview_import_data,
+            export_archive,
+            import_archive,
+            preview_import_archive,
             generate_embedding,
             search_similar_notes,
             delete_embedding,
@@ -129,7 +225,58 @@ pub fn run() {
             move_folder,
             get_chat_messages,
             save_chat_message,
-            clear_chat_messages
+            clear_chat_messages,
+            get_statistics,
+            note_opened,
+            get_recent_notes,
+            get_frequent_notes,
+            encrypt_note,
+            unlock_note,
+            lock_note,
+            decrypt_note,
+            export_markdown,
+            convert_blocknote_to_markdown,
+            convert_markdown_to_blocknote,
+            get_note_outline,
+            get_note_section,
+            replace_note_section,
+            check_links,
+            fix_broken_link,
+            export_site,
+            get_git_sync_config,
+            set_git_sync_config,
+            git_sync,
+            list_sync_conflicts,
+            resolve_sync_conflict,
+            enable_note_crdt,
+            disable_note_crdt,
+            get_note_heads,
+            export_note_updates,
+            apply_note_updates,
+            start_lan_server,
+            stop_lan_server,
+            create_lan_pairing_code,
+            pair_lan_device,
+            list_lan_peers,
+            remove_lan_peer,
+            lan_sync,
+            get_mirror_config,
+            start_mirror,
+            stop_mirror,
+            get_webdav_config,
+            set_webdav_config,
+            webdav_sync,
+            get_backup_status,
+            set_backup_config,
+            run_backup,
+            list_backup_snapshots,
+            restore_backup,
+            preview_restore_backup,
+            import_obsidian_vault,
+            import_enex,
+            import_notion_export,
+            import_joplin_jex,
+            import_google_keep
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
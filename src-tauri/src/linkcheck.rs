@@ -0,0 +1,429 @@
+//! Broken references between notes: wiki links to missing titles, internal
+//! links to deleted notes, attachment URIs whose file is gone, and relative
+//! links left over from imports. Each broken link can be relinked to the
+//! closest match or removed, keeping its text.
+
+use crate::attachments::{attachment_uri, AttachmentStore, ATTACHMENT_URI_PREFIX};
+use crate::commands::{get_note_sync, write_content, Note};
+use crate::db::{current_timestamp, Database};
+use crate::encryption::{open_content, SessionKeys};
+use crate::error::StemError;
+use crate::importers::{file_name, file_stem, has_scheme, is_markdown};
+use crate::links::{find_markdown_links, find_wiki_links, note_link, percent_decode, NOTE_LINK_PREFIX};
+use crate::markdown::note_content;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use tauri::State;
+
+/// Below this similarity a title is not offered as a replacement.
+const MIN_SIMILARITY: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[Title]]`, `![[file.png]]`
+    Wiki,
+    /// `[label](stem-note://<id>)`
+    Note,
+    /// `[label](stem-attachment://<file>)`
+    Attachment,
+    /// `[label](Other%20Note.md)`: paths of the original vault never resolve
+    /// inside Stem, so these are always reported.
+    Relative,
+}
+
+/// Something a broken link can point to instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LinkTarget {
+    Note { id: String, title: String },
+    Attachment { file_name: String, original_name: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrokenLink {
+    pub kind: LinkKind,
+    /// Byte offset and text of the link, passed back to `fix_broken_link`.
+    pub start: usize,
+    pub raw: String,
+    pub label: String,
+    pub target: String,
+    /// Closest title or attachment name, if any is close enough.
+    pub suggestion: Option<LinkTarget>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteLinks {
+    pub note_id: String,
+    pub title: String,
+    pub links: Vec<BrokenLink>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkReport {
+    /// Only notes with at least one broken link.
+    pub notes: Vec<NoteLinks>,
+    pub checked: usize,
+    /// Encrypted notes that are still locked this session.
+    pub skipped_locked: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum LinkFix {
+    /// Point the link at `target`, keeping its text and heading.
+    Relink { target: LinkTarget },
+    /// Replace the link with its text.
+    Remove,
+}
+
+// ===== Scanning =====
+
+/// A link in a note, with what a fix needs to rewrite it.
+struct Link {
+    kind: LinkKind,
+    range: Range<usize>,
+    /// Image or embed (`![...]`).
+    embed: bool,
+    label: String,
+    /// Title, note id, file name or decoded path, depending on `kind`.
+    target: String,
+    /// Heading of a wiki link, or the anchor of a Markdown link.
+    heading: Option<String>,
+    /// Whether a file rather than a note is meant.
+    wants_file: bool,
+}
+
+fn find_links(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    for link in find_wiki_links(content) {
+        // `[[#Heading]]` points into the note itself
+        if link.target.is_empty() {
+            continue;
+        }
+        links.push(Link {
+            kind: LinkKind::Wiki,
+            embed: link.embed,
+            label: link.alias.clone().unwrap_or_else(|| link.target.clone()),
+            wants_file: link.embed,
+            target: link.target,
+            heading: link.heading,
+            range: link.range,
+        });
+    }
+    for link in find_markdown_links(content) {
+        let (kind, target, heading) = if let Some(rest) = link.target.strip_prefix(NOTE_LINK_PREFIX) {
+            let (id, anchor) = rest.split_once('#').unwrap_or((rest, ""));
+            (LinkKind::Note, id.to_string(), Some(anchor.to_string()).filter(|a| !a.is_empty()))
+        } else if let Some(file) = link.target.strip_prefix(ATTACHMENT_URI_PREFIX) {
+            (LinkKind::Attachment, file.to_string(), None)
+        } else if link.target.is_empty() || link.target.starts_with('#') || has_scheme(&link.target) {
+            continue;
+        } else {
+            let (path, fragment) = link.target.split_once('#').unwrap_or((&link.target, ""));
+            let fragment = Some(percent_decode(fragment)).filter(|f| !f.is_empty());
+            (LinkKind::Relative, percent_decode(path), fragment)
+        };
+        let wants_file = match kind {
+            LinkKind::Attachment => true,
+            LinkKind::Relative => link.is_image || (file_name(&target).contains('.') && !is_markdown(&target)),
+            _ => false,
+        };
+        links.push(Link { kind, range: link.range, embed: link.is_image, label: link.label, target, heading, wants_file });
+    }
+    links.sort_by_key(|link| link.range.start);
+    links
+}
+
+/// Notes and attachment files that links can point to.
+#[derive(Default)]
+struct Targets {
+    /// Note id -> title.
+    notes: BTreeMap<String, String>,
+    /// Attachment file name -> original name, for files still on disk.
+    attachments: BTreeMap<String, String>,
+}
+
+impl Targets {
+    fn load(db: &Database, store: &AttachmentStore) -> Result<Self, StemError> {
+        let conn = db.try_connection()?;
+        let mut targets = Targets::default();
+        let mut stmt = conn.prepare("SELECT id, title FROM notes")?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (id, title) = row?;
+            targets.notes.insert(id, title);
+        }
+        let mut stmt = conn.prepare("SELECT file_name, original_name FROM attachments")?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (file, original) = row?;
+            if store.path_of(&file).is_file() {
+                targets.attachments.insert(file, original);
+            }
+        }
+        Ok(targets)
+    }
+
+    fn is_broken(&self, link: &Link) -> bool {
+        match link.kind {
+            LinkKind::Wiki => !self.notes.values().any(|title| title.to_lowercase() == link.target.to_lowercase()),
+            LinkKind::Note => !self.notes.contains_key(&link.target),
+            LinkKind::Attachment => !self.attachments.contains_key(&link.target),
+            LinkKind::Relative => true,
+        }
+    }
+
+    /// Closest note title, or attachment name for files, to what the link names.
+    fn suggest(&self, link: &Link) -> Option<LinkTarget> {
+        // Ids and generated file names say nothing, the label might
+        let wanted = match link.kind {
+            LinkKind::Note | LinkKind::Attachment => link.label.as_str(),
+            LinkKind::Wiki => link.target.as_str(),
+            LinkKind::Relative if link.wants_file => file_name(&link.target),
+            LinkKind::Relative => file_stem(&link.target),
+        };
+        if link.wants_file {
+            closest(wanted, &self.attachments).map(|(file, original)| LinkTarget::Attachment {
+                file_name: file.clone(),
+                original_name: original.clone(),
+            })
+        } else {
+            closest(wanted, &self.notes).map(|(id, title)| LinkTarget::Note { id: id.clone(), title: title.clone() })
+        }
+    }
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Entry whose value is most similar to `wanted`, ignoring case.
+fn closest<'a>(wanted: &str, candidates: &'a BTreeMap<String, String>) -> Option<(&'a String, &'a String)> {
+    let wanted: Vec<char> = wanted.trim().to_lowercase().chars().collect();
+    if wanted.is_empty() {
+        return None;
+    }
+    let mut best: Option<(f64, (&String, &String))> = None;
+    for (key, value) in candidates {
+        let name: Vec<char> = value.trim().to_lowercase().chars().collect();
+        let longest = wanted.len().max(name.len());
+        let score = 1.0 - edit_distance(&wanted, &name) as f64 / longest as f64;
+        if score >= MIN_SIMILARITY && best.is_none_or(|(top, _)| score > top) {
+            best = Some((score, (key, value)));
+        }
+    }
+    best.map(|(_, entry)| entry)
+}
+
+fn broken_links(content: &str, targets: &Targets) -> Vec<BrokenLink> {
+    find_links(content)
+        .into_iter()
+        .filter(|link| targets.is_broken(link))
+        .map(|link| BrokenLink {
+            kind: link.kind,
+            start: link.range.start,
+            raw: content[link.range.clone()].to_string(),
+            suggestion: targets.suggest(&link),
+            label: link.label,
+            target: link.target,
+        })
+        .collect()
+}
+
+pub(crate) fn check_links_sync(db: &Database, keys: &SessionKeys, store: &AttachmentStore) -> Result<LinkReport, StemError> {
+    let targets = Targets::load(db, store)?;
+    let rows: Vec<(String, String, Option<String>, bool)> = {
+        let conn = db.try_connection()?;
+        let mut stmt = conn.prepare("SELECT id, title, content, is_encrypted FROM notes ORDER BY title COLLATE NOCASE")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, i32>(3)? != 0)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut report = LinkReport { notes: Vec::new(), checked: 0, skipped_locked: 0 };
+    for (note_id, title, content, is_encrypted) in rows {
+        let content = content.unwrap_or_default();
+        let content = if is_encrypted {
+            match open_content(keys, &note_id, &content) {
+                Ok(plaintext) => plaintext,
+                Err(_) => {
+                    report.skipped_locked += 1;
+                    continue;
+                }
+            }
+        } else {
+            content
+        };
+        report.checked += 1;
+        let links = broken_links(&content, &targets);
+        if !links.is_empty() {
+            report.notes.push(NoteLinks { note_id, title, links });
+        }
+    }
+    Ok(report)
+}
+
+// ===== Fixing =====
+
+fn relinked(link: &Link, target: &LinkTarget) -> String {
+    let bang = if link.embed { "!" } else { "" };
+    match target {
+        LinkTarget::Note { title, .. } if link.kind == LinkKind::Wiki => {
+            let heading = link.heading.as_ref().map(|h| format!("#{}", h)).unwrap_or_default();
+            let alias = if link.label != link.target { format!("|{}", link.label) } else { String::new() };
+            format!("[[{}{}{}]]", title, heading, alias)
+        }
+        LinkTarget::Note { id, .. } => format!("[{}]({})", link.label, note_link(id, link.heading.as_deref())),
+        LinkTarget::Attachment { file_name, .. } => format!("{}[{}]({})", bang, link.label, attachment_uri(file_name)),
+    }
+}
+
+fn fix_link_sync(db: &Database, keys: &SessionKeys, note_id: &str, start: usize, raw: &str, fix: &LinkFix) -> Result<Note, StemError> {
+    let content = note_content(db, keys, note_id)?;
+    let link = find_links(&content)
+        .into_iter()
+        .find(|link| link.range.start == start && content[link.range.clone()] == *raw)
+        .ok_or_else(|| StemError::Validation("Le lien a changé depuis la vérification, relancez-la".to_string()))?;
+
+    let replacement = match fix {
+        LinkFix::Remove => link.label.clone(),
+        LinkFix::Relink { target } => {
+            let conn = db.try_connection()?;
+            let exists = match target {
+                LinkTarget::Note { id, .. } => conn.query_row("SELECT COUNT(*) FROM notes WHERE id = ?1", [id], |row| row.get::<_, i64>(0))?,
+                LinkTarget::Attachment { file_name, .. } => {
+                    conn.query_row("SELECT COUNT(*) FROM attachments WHERE file_name = ?1", [file_name], |row| row.get::<_, i64>(0))?
+                }
+            };
+            if exists == 0 {
+                return Err(StemError::NotFound("Cible du lien".to_string()));
+            }
+            relinked(&link, target)
+        }
+    };
+    let updated = format!("{}{}{}", &content[..link.range.start], replacement, &content[link.range.end..]);
+    write_content(&*db.try_connection()?, keys, note_id, &updated, current_timestamp())?;
+    get_note_sync(db, note_id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", note_id)))
+}
+
+// ===== Tauri Commands =====
+
+/// Lists the notes containing broken links, with a suggested target for each.
+#[tauri::command]
+pub async fn check_links(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    store: State<'_, AttachmentStore>,
+) -> Result<LinkReport, StemError> {
+    let keys = keys.inner().clone();
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| check_links_sync(&db, &keys, &store)).await
+}
+
+/// Relinks or removes the link reported at `start` with text `raw`.
+#[tauri::command]
+pub async fn fix_broken_link(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    note_id: String,
+    start: usize,
+    raw: String,
+    fix: LinkFix,
+) -> Result<Note, StemError> {
+    let keys = keys.inner().clone();
+    db.inner().clone().spawn(move |db| fix_link_sync(&db, &keys, &note_id, start, &raw, &fix)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::insert_attachment;
+    use uuid::Uuid;
+
+    fn insert_note(db: &Database, id: &str, title: &str, content: &str) {
+        db.connection()
+            .execute(
+                "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES (?1, ?2, ?3, 1, 1)",
+                [id, title, content],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_edit_distance_and_closest() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("réunion"), &chars("reunions")), 2);
+        let titles = BTreeMap::from([("a".to_string(), "Réunion équipe".to_string()), ("b".to_string(), "Recettes".to_string())]);
+        assert_eq!(closest("reunion equipe", &titles).map(|(id, _)| id.as_str()), Some("a"));
+        assert_eq!(closest("Budget 2024", &titles), None);
+    }
+
+    #[test]
+    fn test_check_and_fix_links() {
+        let dir = std::env::temp_dir().join(format!("stem-links-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(dir.clone());
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        let keys = SessionKeys::default();
+
+        let image = store.write_bytes(b"png", "schema.png", None).unwrap();
+        insert_attachment(&db.connection(), &image, None, 1).unwrap();
+        insert_note(&db, "plan", "Plan de projet", "");
+        let content = format!(
+            "[[Plan de projet]] [[Plan du projet#Étapes|le plan]] [ancien](stem-note://disparu)\n\
+             ![schéma](stem-attachment://{}) ![schema](stem-attachment://perdu.png) [notes](Plan%20projet.md)\n\
+             `[[Code]]` [site](https://example.com) [[Inconnu total]]\n",
+            image.file_name
+        );
+        insert_note(&db, "n", "Notes", &content);
+
+        let report = check_links_sync(&db, &keys, &store).unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.notes.len(), 1);
+        let links = &report.notes[0].links;
+        let summary: Vec<(LinkKind, &str)> = links.iter().map(|l| (l.kind, l.target.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (LinkKind::Wiki, "Plan du projet"),
+                (LinkKind::Note, "disparu"),
+                (LinkKind::Attachment, "perdu.png"),
+                (LinkKind::Relative, "Plan projet.md"),
+                (LinkKind::Wiki, "Inconnu total"),
+            ]
+        );
+        let plan = LinkTarget::Note { id: "plan".into(), title: "Plan de projet".into() };
+        assert_eq!(links[0].suggestion, Some(plan.clone()));
+        assert_eq!(links[1].suggestion, None);
+        assert!(matches!(&links[2].suggestion, Some(LinkTarget::Attachment { original_name, .. }) if original_name == "schema.png"));
+        assert_eq!(links[3].suggestion, Some(plan.clone()));
+        assert_eq!(links[4].suggestion, None);
+
+        // Later fixes use offsets from the same report, so go from the end
+        let fix = |link: &BrokenLink, fix: LinkFix| fix_link_sync(&db, &keys, "n", link.start, &link.raw, &fix).unwrap();
+        fix(&links[4], LinkFix::Remove);
+        fix(&links[3], LinkFix::Relink { target: plan.clone() });
+        fix(&links[2], LinkFix::Relink { target: links[2].suggestion.clone().unwrap() });
+        fix(&links[1], LinkFix::Remove);
+        let note = fix(&links[0], LinkFix::Relink { target: plan });
+        let fixed = note.content.unwrap();
+        assert!(fixed.starts_with("[[Plan de projet]] [[Plan de projet#Étapes|le plan]] ancien\n"));
+        assert!(fixed.contains(&format!("![schema](stem-attachment://{}) [notes](stem-note://plan)", image.file_name)));
+        assert!(fixed.ends_with("[site](https://example.com) Inconnu total\n"));
+        assert!(check_links_sync(&db, &keys, &store).unwrap().notes.is_empty());
+
+        let stale = fix_link_sync(&db, &keys, "n", 0, "[[Autre]]", &LinkFix::Remove);
+        assert!(matches!(stale, Err(StemError::Validation(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,274 @@
+//! Link syntax found in note Markdown: Stem's internal note links, wiki links
+//! (`[[Target|Alias]]`) and regular Markdown links/images. Links inside code
+//! spans and fenced code blocks are ignored.
+
+use std::ops::Range;
+
+/// Internal link to another note: `[label](stem-note://<note id>)`.
+pub const NOTE_LINK_PREFIX: &str = "stem-note://";
+
+pub fn note_link(note_id: &str, heading: Option<&str>) -> String {
+    match heading.map(slugify).filter(|s| !s.is_empty()) {
+        Some(anchor) => format!("{}{}#{}", NOTE_LINK_PREFIX, note_id, anchor),
+        None => format!("{}{}", NOTE_LINK_PREFIX, note_id),
+    }
+}
+
+/// GitHub-style heading anchor: lowercase, spaces to `-`, punctuation dropped.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c);
+        } else if c.is_whitespace() {
+            slug.push('-');
+        }
+    }
+    slug
+}
+
+/// Decodes `%XX` escapes (e.g. `My%20Note.md`); invalid sequences are kept as-is.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Target of a request made through one of Stem's URI schemes: the `<target>`
+/// of `<scheme>://<target>` as written in notes, or of
+/// `<scheme>://localhost/<target>` and `http://<scheme>.localhost/<target>`
+/// as the webviews send it. Query and fragment are dropped.
+pub fn protocol_target(uri: &str) -> Option<String> {
+    let (_, rest) = uri.split_once("://")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or("");
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let is_local = authority == "localhost" || authority.ends_with(".localhost");
+    let target = if is_local { path } else { authority };
+    let target = percent_decode(target.trim_end_matches('/'));
+    (!target.is_empty()).then_some(target)
+}
+
+// ===== Scanning =====
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    /// Byte range of the whole `[[...]]` (including a leading `!` for embeds).
+    pub range: Range<usize>,
+    pub embed: bool,
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownLink {
+    /// Byte range of the whole `[label](target)` (including `!` for images).
+    pub range: Range<usize>,
+    pub is_image: bool,
+    pub label: String,
+    pub target: String,
+}
+
+/// Byte ranges of fenced code blocks and inline code spans.
+fn code_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    let mut fence_start: Option<usize> = None;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            match fence_start.take() {
+                Some(start) => ranges.push(start..offset + line.len()),
+                None => fence_start = Some(offset),
+            }
+        } else if fence_start.is_none() {
+            let mut open: Option<usize> = None;
+            for (i, c) in line.char_indices() {
+                if c == '`' {
+                    match open.take() {
+                        Some(start) => ranges.push(offset + start..offset + i + 1),
+                        None => open = Some(i),
+                    }
+                }
+            }
+        }
+        offset += line.len();
+    }
+    if let Some(start) = fence_start {
+        ranges.push(start..text.len());
+    }
+    ranges
+}
+
+fn in_code(ranges: &[Range<usize>], pos: usize) -> bool {
+    ranges.iter().any(|r| r.contains(&pos))
+}
+
+pub fn find_wiki_links(text: &str) -> Vec<WikiLink> {
+    let code = code_ranges(text);
+    let mut links = Vec::new();
+    let mut search = 0;
+
+    while let Some(rel) = text[search..].find("[[") {
+        let open = search + rel;
+        let Some(close_rel) = text[open + 2..].find("]]") else { break };
+        let close = open + 2 + close_rel;
+        let inner = &text[open + 2..close];
+        search = close + 2;
+
+        if inner.is_empty() || inner.contains('\n') || inner.contains("[[") || in_code(&code, open) {
+            search = open + 2;
+            continue;
+        }
+        let embed = open > 0 && text.as_bytes()[open - 1] == b'!';
+        let (reference, alias) = match inner.split_once('|') {
+            Some((r, a)) => (r, Some(a.trim().to_string())),
+            None => (inner, None),
+        };
+        let (target, heading) = match reference.split_once('#') {
+            Some((t, h)) => (t, Some(h.trim().to_string())),
+            None => (reference, None),
+        };
+        links.push(WikiLink {
+            range: if embed { open - 1..close + 2 } else { open..close + 2 },
+            embed,
+            target: target.trim().to_string(),
+            heading,
+            alias,
+        });
+    }
+    links
+}
+
+pub fn find_markdown_links(text: &str) -> Vec<MarkdownLink> {
+    let code = code_ranges(text);
+    let bytes = text.as_bytes();
+    let mut links = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        // Wiki links are handled by find_wiki_links
+        if bytes[i] != b'[' || bytes.get(i + 1) == Some(&b'[') || (i > 0 && bytes[i - 1] == b'[') || in_code(&code, i) {
+            i += 1;
+            continue;
+        }
+        let Some(label_len) = text[i + 1..].find(']') else { break };
+        let label_end = i + 1 + label_len;
+        if bytes.get(label_end + 1) != Some(&b'(') || text[i + 1..label_end].contains('\n') {
+            i += 1;
+            continue;
+        }
+
+        // Balanced parentheses so targets like `Page_(draft).md` survive
+        let mut depth = 0;
+        let mut target_end = None;
+        for (j, c) in text[label_end + 1..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        target_end = Some(label_end + 1 + j);
+                        break;
+                    }
+                }
+                '\n' => break,
+                _ => {}
+            }
+        }
+        let Some(target_end) = target_end else {
+            i += 1;
+            continue;
+        };
+
+        let raw_target = text[label_end + 2..target_end].trim();
+        // Drop an optional "title" and unwrap <angle brackets>
+        let target = match raw_target.strip_prefix('<').and_then(|t| t.split_once('>')) {
+            Some((inner, _)) => inner,
+            None => raw_target.split_once(" \"").map(|(t, _)| t).unwrap_or(raw_target),
+        };
+        let is_image = i > 0 && bytes[i - 1] == b'!';
+        links.push(MarkdownLink {
+            range: if is_image { i - 1..target_end + 1 } else { i..target_end + 1 },
+            is_image,
+            label: text[i + 1..label_end].to_string(),
+            target: target.to_string(),
+        });
+        i = target_end + 1;
+    }
+    links
+}
+
+/// Applies non-overlapping replacements given as `(byte range, new text)`.
+pub fn replace_ranges(text: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(r, _)| r.start);
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (range, replacement) in edits {
+        if range.start < cursor {
+            continue;
+        }
+        out.push_str(&text[cursor..range.start]);
+        out.push_str(&replacement);
+        cursor = range.end;
+    }
+    out.push_str(&text[cursor..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_wiki_links() {
+        let text = "See [[Other Note|that]] and ![[diagram.png]] or [[Page#Intro]]. `[[not a link]]`";
+        let links = find_wiki_links(text);
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].target, "Other Note");
+        assert_eq!(links[0].alias.as_deref(), Some("that"));
+        assert!(links[1].embed);
+        assert_eq!(&text[links[1].range.clone()], "![[diagram.png]]");
+        assert_eq!(links[2].heading.as_deref(), Some("Intro"));
+    }
+
+    #[test]
+    fn test_find_markdown_links_skips_code() {
+        let text = "![alt](img/a%20b.png) [x](<My Page.md>) [y](Page_(draft).md \"t\")\n```\n[z](in-code.md)\n```";
+        let links = find_markdown_links(text);
+        let targets: Vec<_> = links.iter().map(|l| l.target.as_str()).collect();
+        assert_eq!(targets, vec!["img/a%20b.png", "My Page.md", "Page_(draft).md"]);
+        assert!(links[0].is_image);
+    }
+
+    #[test]
+    fn test_note_links_and_helpers() {
+        assert_eq!(note_link("abc", Some("Mon Titre !")), "stem-note://abc#mon-titre-");
+        assert_eq!(percent_decode("a%20b%zz"), "a b%zz");
+        assert_eq!(replace_ranges("hello world", vec![(6..11, "there".to_string())]), "hello there");
+    }
+
+    #[test]
+    fn test_protocol_target() {
+        assert_eq!(protocol_target("stem-note://abc").as_deref(), Some("abc"));
+        assert_eq!(protocol_target("stem-note://abc/#intro").as_deref(), Some("abc"));
+        assert_eq!(protocol_target("stem-attachment://localhost/a%20b.png").as_deref(), Some("a b.png"));
+        assert_eq!(protocol_target("http://stem-attachment.localhost/x.png?v=1").as_deref(), Some("x.png"));
+        assert_eq!(protocol_target("stem-note://localhost/"), None);
+        assert_eq!(protocol_target("not a uri"), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! Structure of a note's Markdown: its outline (headings with anchors and
+//! byte offsets) and the section under a heading, which the AI tools read or
+//! replace on their own instead of the whole note.
+
+use crate::commands::{get_note_sync, write_content, Note, NOTE_COLUMNS, row_to_note};
+use crate::db::{current_timestamp, Database};
+use crate::encryption::{open_content, SessionKeys};
+use crate::error::StemError;
+use crate::links::slugify;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::State;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutlineHeading {
+    pub level: u8,
+    pub text: String,
+    /// Anchor as in the exported site: `slugify` of the text, `-1`, `-2`...
+    /// appended to repeated ones. Empty for a heading without text.
+    pub slug: String,
+    /// Byte range of the heading itself.
+    pub start: usize,
+    pub end: usize,
+    /// End of its section: the next heading of the same or a higher level, or
+    /// the end of the note.
+    pub section_end: usize,
+}
+
+/// Headings of `content` in order. `#` lines in code blocks are not headings.
+pub fn outline(content: &str) -> Vec<OutlineHeading> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut headings: Vec<OutlineHeading> = Vec::new();
+    let mut current: Option<OutlineHeading> = None;
+    for (event, range) in Parser::new_ext(content, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                let level = level as u8;
+                // The range runs through the line break after the heading
+                let end = range.start + content[range.clone()].trim_end().len();
+                current = Some(OutlineHeading { level, text: String::new(), slug: String::new(), start: range.start, end, section_end: content.len() });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    let mut used: HashMap<String, usize> = HashMap::new();
+    for i in 0..headings.len() {
+        let base = slugify(&headings[i].text);
+        if !base.is_empty() {
+            let count = used.entry(base.clone()).or_insert(0);
+            headings[i].slug = if *count == 0 { base } else { format!("{}-{}", base, count) };
+            *count += 1;
+        }
+        let level = headings[i].level;
+        if let Some(next) = headings[i + 1..].iter().find(|h| h.level <= level) {
+            headings[i].section_end = next.start;
+        }
+    }
+    headings
+}
+
+/// The heading named `heading`: its anchor (`intro`, `#intro-1`), or its text
+/// ignoring case and leading `#`.
+pub fn find_heading(content: &str, heading: &str) -> Option<OutlineHeading> {
+    let wanted = heading.trim().trim_start_matches('#').trim();
+    let headings = outline(content);
+    let slug = slugify(wanted);
+    headings
+        .iter()
+        .find(|h| h.slug == wanted)
+        .or_else(|| headings.iter().find(|h| h.text.trim().to_lowercase() == wanted.to_lowercase()))
+        .or_else(|| headings.iter().find(|h| !slug.is_empty() && h.slug == slug))
+        .cloned()
+}
+
+/// `content` with the section of `heading` (heading line included) replaced by `replacement`.
+fn replace_section(content: &str, heading: &OutlineHeading, replacement: &str) -> String {
+    let mut section = replacement.trim_end().to_string();
+    if heading.section_end < content.len() {
+        section.push_str("\n\n");
+    } else if content.ends_with('\n') {
+        section.push('\n');
+    }
+    format!("{}{}{}", &content[..heading.start], section, &content[heading.section_end..])
+}
+
+fn section_not_found(heading: &str) -> StemError {
+    StemError::NotFound(format!("Section « {} »", heading))
+}
+
+fn load_note(conn: &Connection, id: &str) -> Result<Note, StemError> {
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"))?;
+    stmt.query_row([id], row_to_note)
+        .optional()?
+        .ok_or_else(|| StemError::NotFound(format!("Note {}", id)))
+}
+
+/// Plaintext content of a note; encrypted notes must be unlocked.
+pub(crate) fn note_content(db: &Database, keys: &SessionKeys, id: &str) -> Result<String, StemError> {
+    let note = load_note(&*db.try_connection()?, id)?;
+    let content = note.content.unwrap_or_default();
+    if note.is_encrypted {
+        open_content(keys, id, &content)
+    } else {
+        Ok(content)
+    }
+}
+
+/// Content for the section commands, which the AI tools call: like `get_note`,
+/// they never hand out an encrypted note's plaintext, even once unlocked.
+fn section_source(conn: &Connection, id: &str) -> Result<String, StemError> {
+    let note = load_note(conn, id)?;
+    if note.is_encrypted {
+        return Err(StemError::Validation("Cette note est chiffrée : ses sections ne sont pas accessibles".to_string()));
+    }
+    Ok(note.content.unwrap_or_default())
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteSection {
+    pub heading: OutlineHeading,
+    /// From the heading line to the end of the section, subsections included.
+    pub content: String,
+}
+
+fn get_section_sync(db: &Database, id: &str, heading: &str) -> Result<NoteSection, StemError> {
+    let content = section_source(&*db.try_connection()?, id)?;
+    let found = find_heading(&content, heading).ok_or_else(|| section_not_found(heading))?;
+    let section = content[found.start..found.section_end].trim_end().to_string();
+    Ok(NoteSection { heading: found, content: section })
+}
+
+fn replace_section_sync(db: &Database, keys: &SessionKeys, id: &str, heading: &str, replacement: &str) -> Result<Note, StemError> {
+    {
+        // Read and write in one transaction, so that a concurrent edit is not lost
+        let mut conn = db.try_connection()?;
+        let tx = conn.transaction()?;
+        let content = section_source(&tx, id)?;
+        let found = find_heading(&content, heading).ok_or_else(|| section_not_found(heading))?;
+        let updated = replace_section(&content, &found, replacement);
+        write_content(&tx, keys, id, &updated, current_timestamp())?;
+        tx.commit()?;
+    }
+    get_note_sync(db, id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", id)))
+}
+
+// ===== Tauri Commands =====
+
+#[tauri::command]
+pub async fn get_note_outline(db: State<'_, Database>, note_id: String) -> Result<Vec<OutlineHeading>, StemError> {
+    db.inner().clone().spawn(move |db| Ok(outline(&section_source(&*db.try_connection()?, &note_id)?))).await
+}
+
+/// Reads the section under `heading` (anchor or text, see `find_heading`).
+#[tauri::command]
+pub async fn get_note_section(db: State<'_, Database>, note_id: String, heading: String) -> Result<NoteSection, StemError> {
+    db.inner().clone().spawn(move |db| get_section_sync(&db, &note_id, &heading)).await
+}
+
+/// Replaces the section under `heading`, heading line included, with `content`.
+#[tauri::command]
+pub async fn replace_note_section(
+    db: State<'_, Database>,
+    keys: State<'_, SessionKeys>,
+    note_id: String,
+    heading: String,
+    content: String,
+) -> Result<Note, StemError> {
+    let keys = keys.inner().clone();
+    db.inner().clone().spawn(move |db| replace_section_sync(&db, &keys, &note_id, &heading, &content)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "# Projet\n\nIntro.\n\n## Tâches\n\n- une\n\n```sh\n# pas un titre\n```\n\n### Détail\n\ntexte\n\n## Tâches\n\nencore\n\nNotes\n-----\n\nfin\n";
+
+    #[test]
+    fn test_outline() {
+        let headings = outline(NOTE);
+        let summary: Vec<(u8, &str)> = headings.iter().map(|h| (h.level, h.slug.as_str())).collect();
+        assert_eq!(summary, vec![(1, "projet"), (2, "tâches"), (3, "détail"), (2, "tâches-1"), (2, "notes")]);
+        assert_eq!(&NOTE[headings[1].start..headings[1].end], "## Tâches");
+        assert_eq!(headings[1].section_end, headings[3].start);
+        assert_eq!(headings[0].section_end, NOTE.len());
+        assert!(NOTE[headings[4].start..headings[4].end].starts_with("Notes\n-----"));
+    }
+
+    #[test]
+    fn test_read_and_replace_section() {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        db.connection()
+            .execute("INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('n', 'Projet', ?1, 1, 1)", [NOTE])
+            .unwrap();
+        let keys = SessionKeys::default();
+
+        let section = get_section_sync(&db, "n", "tâches").unwrap();
+        assert_eq!(section.content, "## Tâches\n\n- une\n\n```sh\n# pas un titre\n```\n\n### Détail\n\ntexte");
+        assert_eq!(get_section_sync(&db, "n", "#tâches-1").unwrap().content, "## Tâches\n\nencore");
+        assert!(matches!(get_section_sync(&db, "n", "Absent"), Err(StemError::NotFound(_))));
+
+        let note = replace_section_sync(&db, &keys, "n", "Détail", "### Détail\n\nréécrit\n").unwrap();
+        let content = note.content.unwrap();
+        assert!(content.contains("### Détail\n\nréécrit\n\n## Tâches\n\nencore"));
+        let note = replace_section_sync(&db, &keys, "n", "notes", "## Notes\n\nnouvelle fin").unwrap();
+        assert!(note.content.unwrap().ends_with("encore\n\n## Notes\n\nnouvelle fin\n"));
+
+        // Encrypted notes stay out of reach of the AI tools, unlocked or not
+        db.connection()
+            .execute("INSERT INTO notes (id, title, content, created_at, updated_at, is_encrypted) VALUES ('e', 'Secret', 'stem-enc:v1:x', 1, 1, 1)", [])
+            .unwrap();
+        assert!(matches!(get_section_sync(&db, "e", "Projet"), Err(StemError::Validation(_))));
+        assert!(matches!(replace_section_sync(&db, &keys, "e", "Projet", "# Projet"), Err(StemError::Validation(_))));
+    }
+}
@@ -0,0 +1,422 @@
+//! Merging a backup (`ExportData` JSON or `.stem` archive) into the database.
+//! Items whose id already exists are compared with the stored row and
+//! resolved with a `ConflictStrategy`; a dry run only reports the diff.
+
+use crate::commands::{
+    import_summary, row_to_folder, row_to_note, ExportData, Folder, Note, EXPORT_VERSION, FOLDER_COLUMNS, NOTE_COLUMNS,
+};
+use crate::db::Database;
+use crate::error::StemError;
+use rusqlite::{OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// What to do with an incoming item whose id exists with different content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Keep the stored item (the historical behavior).
+    #[default]
+    Skip,
+    /// Replace the stored item with the incoming one.
+    Overwrite,
+    /// Keep whichever has the later `updated_at`. Folders have no
+    /// modification date, so existing folders are kept.
+    Newer,
+    /// Insert the incoming item under a new id next to the stored one.
+    Duplicate,
+}
+
+/// What the merge does (or would do, in a dry run) with an incoming item.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeAction {
+    Insert,
+    Overwrite,
+    Keep,
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffItem {
+    pub id: String,
+    /// Note title or folder name, as found in the backup.
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedItem {
+    pub id: String,
+    pub name: String,
+    /// Fields that differ from the stored row, e.g. `["title", "content"]`.
+    pub fields: Vec<&'static str>,
+    /// Depends on the strategy; never `insert`.
+    pub action: MergeAction,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct EntityDiff {
+    pub new: Vec<DiffItem>,
+    pub changed: Vec<ChangedItem>,
+    pub identical: Vec<DiffItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportDiff {
+    pub strategy: ConflictStrategy,
+    pub dry_run: bool,
+    pub notes: EntityDiff,
+    pub folders: EntityDiff,
+    /// Same wording as `import_all_data`, counting written items.
+    pub summary: String,
+}
+
+fn note_changes(stored: &Note, incoming: &Note) -> Vec<&'static str> {
+    [
+        (stored.title != incoming.title, "title"),
+        (stored.content != incoming.content, "content"),
+        (stored.folder_id != incoming.folder_id, "folder_id"),
+        (stored.is_pinned != incoming.is_pinned, "is_pinned"),
+        (stored.is_archived != incoming.is_archived, "is_archived"),
+        (stored.is_encrypted != incoming.is_encrypted, "is_encrypted"),
+        (stored.created_at != incoming.created_at, "created_at"),
+        (stored.updated_at != incoming.updated_at, "updated_at"),
+    ]
+    .into_iter()
+    .filter_map(|(differs, field)| differs.then_some(field))
+    .collect()
+}
+
+fn folder_changes(stored: &Folder, incoming: &Folder) -> Vec<&'static str> {
+    [
+        (stored.name != incoming.name, "name"),
+        (stored.parent_id != incoming.parent_id, "parent_id"),
+        (stored.position != incoming.position, "position"),
+        (stored.is_archived != incoming.is_archived, "is_archived"),
+        (stored.created_at != incoming.created_at, "created_at"),
+    ]
+    .into_iter()
+    .filter_map(|(differs, field)| differs.then_some(field))
+    .collect()
+}
+
+/// Folders reordered so that a parent always comes before its children,
+/// which lets duplicated parents be remapped before their children are seen.
+pub(crate) fn parents_first(folders: Vec<Folder>) -> Vec<Folder> {
+    let ids: HashSet<&str> = folders.iter().map(|f| f.id.as_str()).collect();
+    let parents: HashMap<&str, &str> = folders
+        .iter()
+        .filter_map(|f| f.parent_id.as_deref().filter(|p| ids.contains(p)).map(|p| (f.id.as_str(), p)))
+        .collect();
+    let depth = |id: &str| {
+        let (mut id, mut depth, mut seen) = (id, 0, HashSet::new());
+        while let Some(parent) = parents.get(id) {
+            if !seen.insert(*parent) {
+                break;
+            }
+            depth += 1;
+            id = parent;
+        }
+        depth
+    };
+    let mut keyed: Vec<(usize, usize)> = folders.iter().enumerate().map(|(i, f)| (depth(&f.id), i)).collect();
+    keyed.sort();
+    let mut slots: Vec<Option<Folder>> = folders.into_iter().map(Some).collect();
+    keyed.into_iter().filter_map(|(_, i)| slots[i].take()).collect()
+}
+
+/// Applies incoming items one by one inside the caller's transaction.
+pub(crate) struct Merger {
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    folder_ids: HashMap<String, String>,
+    note_ids: HashMap<String, String>,
+    notes: EntityDiff,
+    folders: EntityDiff,
+    notes_written: u32,
+    folders_written: u32,
+}
+
+impl Merger {
+    pub fn new(strategy: ConflictStrategy, dry_run: bool) -> Self {
+        Self {
+            strategy,
+            dry_run,
+            folder_ids: HashMap::new(),
+            note_ids: HashMap::new(),
+            notes: EntityDiff::default(),
+            folders: EntityDiff::default(),
+            notes_written: 0,
+            folders_written: 0,
+        }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Id under which an incoming note ended up (differs when duplicated).
+    pub fn note_id<'a>(&'a self, id: &'a str) -> &'a str {
+        self.note_ids.get(id).map(String::as_str).unwrap_or(id)
+    }
+
+    fn folder_id(&self, id: Option<&String>) -> Option<String> {
+        id.map(|id| self.folder_ids.get(id).unwrap_or(id).clone())
+    }
+
+    fn action(&self, incoming_newer: bool) -> MergeAction {
+        match self.strategy {
+            ConflictStrategy::Skip => MergeAction::Keep,
+            ConflictStrategy::Overwrite => MergeAction::Overwrite,
+            ConflictStrategy::Newer if incoming_newer => MergeAction::Overwrite,
+            ConflictStrategy::Newer => MergeAction::Keep,
+            ConflictStrategy::Duplicate => MergeAction::Duplicate,
+        }
+    }
+
+    pub fn folder(&mut self, tx: &Transaction, folder: &Folder) -> Result<(), StemError> {
+        let stored = tx
+            .query_row(&format!("SELECT {FOLDER_COLUMNS} FROM folders WHERE id = ?1"), [&folder.id], row_to_folder)
+            .optional()?;
+        let item = DiffItem { id: folder.id.clone(), name: folder.name.clone() };
+        let parent_id = self.folder_id(folder.parent_id.as_ref());
+
+        let action = match &stored {
+            None => {
+                self.folders.new.push(item);
+                MergeAction::Insert
+            }
+            Some(stored) => {
+                let fields = folder_changes(stored, folder);
+                if fields.is_empty() {
+                    self.folders.identical.push(item);
+                    return Ok(());
+                }
+                let action = self.action(false);
+                self.folders.changed.push(ChangedItem { id: item.id, name: item.name, fields, action });
+                action
+            }
+        };
+        if self.dry_run || action == MergeAction::Keep {
+            return Ok(());
+        }
+
+        match action {
+            MergeAction::Overwrite => {
+                tx.execute(
+                    "UPDATE folders SET name = ?2, parent_id = ?3, position = ?4, created_at = ?5, is_archived = ?6 WHERE id = ?1",
+                    (&folder.id, &folder.name, &parent_id, &folder.position, &folder.created_at, &(folder.is_archived as i32)),
+                )?;
+            }
+            _ => {
+                let id = if action == MergeAction::Duplicate { Uuid::new_v4().to_string() } else { folder.id.clone() };
+                tx.execute(
+                    "INSERT INTO folders (id, name, parent_id, position, created_at, is_archived) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    (&id, &folder.name, &parent_id, &folder.position, &folder.created_at, &(folder.is_archived as i32)),
+                )?;
+                if id != folder.id {
+                    self.folder_ids.insert(folder.id.clone(), id);
+                }
+            }
+        }
+        self.folders_written += 1;
+        Ok(())
+    }
+
+    pub fn note(&mut self, tx: &Transaction, note: &Note) -> Result<(), StemError> {
+        let stored = tx
+            .query_row(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"), [&note.id], row_to_note)
+            .optional()?;
+        let item = DiffItem { id: note.id.clone(), name: note.title.clone() };
+        let folder_id = self.folder_id(note.folder_id.as_ref());
+
+        let action = match &stored {
+            None => {
+                self.notes.new.push(item);
+                MergeAction::Insert
+            }
+            Some(stored) => {
+                let fields = note_changes(stored, note);
+                if fields.is_empty() {
+                    self.notes.identical.push(item);
+                    return Ok(());
+                }
+                let action = self.action(note.updated_at > stored.updated_at);
+                self.notes.changed.push(ChangedItem { id: item.id, name: item.name, fields, action });
+                action
+            }
+        };
+        if self.dry_run || action == MergeAction::Keep {
+            return Ok(());
+        }
+
+        match action {
+            MergeAction::Overwrite => {
+                tx.execute(
+                    "UPDATE notes SET title = ?2, content = ?3, created_at = ?4, updated_at = ?5, is_pinned = ?6, folder_id = ?7, is_archived = ?8, is_encrypted = ?9 WHERE id = ?1",
+                    rusqlite::params![
+                        note.id, note.title, note.content, note.created_at, note.updated_at,
+                        note.is_pinned, folder_id, note.is_archived, note.is_encrypted,
+                    ],
+                )?;
+            }
+            _ => {
+                let id = if action == MergeAction::Duplicate { Uuid::new_v4().to_string() } else { note.id.clone() };
+                tx.execute(
+                    "INSERT INTO notes (id, title, content, created_at, updated_at, is_pinned, folder_id, is_archived, is_encrypted) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    rusqlite::params![
+                        id, note.title, note.content, note.created_at, note.updated_at,
+                        note.is_pinned, folder_id, note.is_archived, note.is_encrypted,
+                    ],
+                )?;
+                if id != note.id {
+                    self.note_ids.insert(note.id.clone(), id);
+                }
+            }
+        }
+        self.notes_written += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> ImportDiff {
+        ImportDiff {
+            strategy: self.strategy,
+            dry_run: self.dry_run,
+            summary: import_summary(self.notes_written, self.folders_written),
+            notes: self.notes,
+            folders: self.folders,
+        }
+    }
+}
+
+/// Merges a JSON backup. Nothing is written when `dry_run` is set.
+pub(crate) fn merge_export_data(
+    db: &Database,
+    export: ExportData,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+) -> Result<ImportDiff, StemError> {
+    if export.version > EXPORT_VERSION {
+        return Err(StemError::Validation(format!("Version de fichier non prise en charge ({})", export.version)));
+    }
+
+    let mut conn = db.connection_mut();
+    let tx = conn.transaction()?;
+    let mut merger = Merger::new(strategy, dry_run);
+
+    for folder in &parents_first(export.folders) {
+        merger.folder(&tx, folder)?;
+    }
+    for note in &export.notes {
+        merger.note(&tx, note)?;
+    }
+
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(merger.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Database {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        let conn = db.connection();
+        conn.execute("INSERT INTO folders (id, name, position, created_at) VALUES ('f1', 'Work', 0, 1)", []).unwrap();
+        conn.execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES ('a', 'A', 'old', 1, 10, 'f1'), ('b', 'B', 'same', 1, 10, NULL)",
+            [],
+        ).unwrap();
+        drop(conn);
+        db
+    }
+
+    fn note(id: &str, title: &str, content: &str, updated_at: i64, folder_id: Option<&str>) -> Note {
+        Note {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: Some(content.to_string()),
+            created_at: 1,
+            updated_at,
+            is_pinned: false,
+            folder_id: folder_id.map(str::to_string),
+            is_archived: false,
+            is_encrypted: false,
+        }
+    }
+
+    fn backup(updated_at: i64) -> ExportData {
+        ExportData {
+            version: EXPORT_VERSION,
+            notes: vec![
+                note("a", "A", "edited", updated_at, Some("f1")),
+                note("b", "B", "same", 10, None),
+                note("c", "C", "new", 5, Some("f2")),
+            ],
+            folders: vec![
+                Folder { id: "f2".into(), name: "Child".into(), parent_id: Some("f1".into()), position: 0, created_at: 2, is_archived: false },
+                Folder { id: "f1".into(), name: "Renamed".into(), parent_id: None, position: 0, created_at: 1, is_archived: false },
+            ],
+        }
+    }
+
+    fn content(db: &Database, id: &str) -> String {
+        db.connection().query_row("SELECT content FROM notes WHERE id = ?1", [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_dry_run_reports_diff_without_writing() {
+        let db = setup();
+        let diff = merge_export_data(&db, backup(20), ConflictStrategy::Overwrite, true).unwrap();
+
+        let ids = |items: &[DiffItem]| items.iter().map(|i| i.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&diff.notes.new), ["c"]);
+        assert_eq!(ids(&diff.notes.identical), ["b"]);
+        assert_eq!(diff.notes.changed[0].fields, ["content", "updated_at"]);
+        assert_eq!(diff.notes.changed[0].action, MergeAction::Overwrite);
+        assert_eq!(ids(&diff.folders.new), ["f2"]);
+        assert_eq!(diff.folders.changed[0].fields, ["name"]);
+        assert_eq!(diff.summary, "0 notes, 0 dossiers importés");
+        assert_eq!(content(&db, "a"), "old");
+        assert_eq!(db.connection().query_row("SELECT COUNT(*) FROM notes", [], |row| row.get::<_, i64>(0)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_strategies() {
+        let db = setup();
+        let diff = merge_export_data(&db, backup(20), ConflictStrategy::Skip, false).unwrap();
+        assert_eq!(diff.summary, "1 notes, 1 dossiers importés");
+        assert_eq!(content(&db, "a"), "old");
+
+        let db = setup();
+        merge_export_data(&db, backup(5), ConflictStrategy::Newer, false).unwrap();
+        assert_eq!(content(&db, "a"), "old");
+        merge_export_data(&db, backup(20), ConflictStrategy::Newer, false).unwrap();
+        assert_eq!(content(&db, "a"), "edited");
+
+        let db = setup();
+        let diff = merge_export_data(&db, backup(20), ConflictStrategy::Overwrite, false).unwrap();
+        assert_eq!(diff.summary, "2 notes, 2 dossiers importés");
+        let name: String = db.connection().query_row("SELECT name FROM folders WHERE id = 'f1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(name, "Renamed");
+
+        // Duplicated folders get a new id and their children follow them
+        let db = setup();
+        let diff = merge_export_data(&db, backup(20), ConflictStrategy::Duplicate, false).unwrap();
+        assert_eq!(diff.summary, "2 notes, 2 dossiers importés");
+        assert_eq!(content(&db, "a"), "old");
+        let conn = db.connection();
+        let (copy_id, copy_folder): (String, String) = conn
+            .query_row("SELECT id, folder_id FROM notes WHERE content = 'edited'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_ne!(copy_id, "a");
+        let copy_name: String = conn.query_row("SELECT name FROM folders WHERE id = ?1", [&copy_folder], |row| row.get(0)).unwrap();
+        assert_eq!(copy_name, "Renamed");
+        let child_parent: String = conn.query_row("SELECT parent_id FROM folders WHERE id = 'f2'", [], |row| row.get(0)).unwrap();
+        assert_eq!(child_parent, copy_folder);
+    }
+}
@@ -0,0 +1,489 @@
+//! Static HTML export: one page per note under `notes/`, laid out like the
+//! Markdown export, with a navigation tree, an `index.html`, referenced
+//! attachments copied to `attachments/` and a search index loaded as a script
+//! so that the site works straight from disk (`file://` forbids `fetch`).
+
+use crate::attachments::{AttachmentStore, ATTACHMENT_URI_PREFIX};
+use crate::commands::{resolve_scope, ExportScope, Folder, Note};
+use crate::db::Database;
+use crate::error::StemError;
+use crate::links::{find_wiki_links, note_link, replace_ranges, slugify, NOTE_LINK_PREFIX};
+use crate::text::plain_text;
+use crate::vault::{load_vault, path_under, plan_layout, prune_empty_dirs, to_portable, write_if_changed};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Lists what the previous run wrote, so stale pages can be removed.
+const MANIFEST_FILE: &str = ".stem-site.json";
+const NOTES_DIR: &str = "notes";
+const ATTACHMENTS_DIR: &str = "attachments";
+const SEARCH_INDEX_FILE: &str = "search-index.js";
+/// Plain text kept per note in the search index.
+const SEARCH_TEXT_CHARS: usize = 5000;
+
+const STYLE: &str = "
+body { margin: 0; display: flex; font: 16px/1.6 system-ui, sans-serif; color: #1f2328; }
+nav { width: 280px; flex-shrink: 0; height: 100vh; overflow-y: auto; position: sticky; top: 0; padding: 16px; box-sizing: border-box; background: #f6f8fa; border-right: 1px solid #d0d7de; font-size: 14px; }
+nav ul { list-style: none; padding-left: 14px; margin: 0; }
+nav > ul { padding-left: 0; }
+nav a { color: inherit; text-decoration: none; }
+nav a.current { font-weight: 600; color: #0969da; }
+nav summary { cursor: pointer; font-weight: 500; }
+main { flex: 1; max-width: 860px; padding: 32px 48px; }
+main img { max-width: 100%; }
+pre { background: #f6f8fa; padding: 12px; overflow-x: auto; border-radius: 6px; }
+code { font-family: ui-monospace, monospace; font-size: 90%; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d0d7de; padding: 4px 10px; }
+blockquote { margin: 0; padding-left: 16px; border-left: 4px solid #d0d7de; color: #59636e; }
+#search { width: 100%; box-sizing: border-box; padding: 6px 8px; margin-bottom: 12px; }
+#results a { display: block; padding: 2px 0; }
+.meta { color: #59636e; font-size: 13px; }
+";
+
+const SEARCH_SCRIPT: &str = "
+(function () {
+  var input = document.getElementById('search');
+  var results = document.getElementById('results');
+  var root = document.body.dataset.root;
+  input.addEventListener('input', function () {
+    var words = input.value.toLowerCase().split(/\\s+/).filter(Boolean);
+    results.innerHTML = '';
+    if (!words.length) return;
+    (window.STEM_SEARCH_INDEX || []).filter(function (page) {
+      var haystack = (page.title + ' ' + page.text).toLowerCase();
+      return words.every(function (w) { return haystack.indexOf(w) !== -1; });
+    }).slice(0, 30).forEach(function (page) {
+      var a = document.createElement('a');
+      a.href = root + page.path;
+      a.textContent = page.title;
+      results.appendChild(a);
+    });
+  });
+})();
+";
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct SiteExportReport {
+    pub pages: usize,
+    pub attachments: usize,
+    /// Pages and attachments left over from a previous export.
+    pub removed: usize,
+    /// Encrypted notes are never written in plaintext.
+    pub skipped_encrypted: usize,
+    /// Attachment links whose file is no longer in the store.
+    pub missing_attachments: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SiteManifest {
+    version: u32,
+    files: BTreeSet<String>,
+}
+
+#[derive(Serialize)]
+struct SearchEntry<'a> {
+    title: &'a str,
+    path: String,
+    text: String,
+}
+
+/// Percent-encodes a `/`-separated path for use in `href`.
+fn encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Everything a page needs to resolve links.
+struct Site<'a> {
+    /// Note id -> page path relative to the site root, `/`-separated.
+    pages: HashMap<&'a str, String>,
+    /// Lowercased title -> note id, for wiki links.
+    titles: HashMap<String, &'a str>,
+    folders: &'a [Folder],
+    notes: Vec<(&'a Note, String)>,
+}
+
+impl Site<'_> {
+    /// `[[Title#Heading|Alias]]` -> `[Alias](stem-note://id#heading)`; unknown titles become plain text.
+    fn resolve_wiki_links(&self, content: &str) -> String {
+        let edits = find_wiki_links(content)
+            .into_iter()
+            .map(|link| {
+                let label = link.alias.clone().unwrap_or_else(|| link.target.clone());
+                let replacement = match self.titles.get(&link.target.to_lowercase()) {
+                    Some(id) => format!("[{}]({})", label, note_link(id, link.heading.as_deref())),
+                    None => label,
+                };
+                (link.range, replacement)
+            })
+            .collect();
+        replace_ranges(content, edits)
+    }
+
+    /// Resolves a link destination for a page `root` (the `../` prefix back to the site root).
+    /// `None` drops the link and keeps its text.
+    fn destination(&self, dest: &str, root: &str, attachments: &mut BTreeSet<String>) -> Option<String> {
+        if let Some(target) = dest.strip_prefix(NOTE_LINK_PREFIX) {
+            let (id, anchor) = target.split_once('#').unwrap_or((target, ""));
+            let page = self.pages.get(id)?;
+            let anchor = if anchor.is_empty() { String::new() } else { format!("#{}", anchor) };
+            return Some(format!("{}{}{}", root, encode_path(page), anchor));
+        }
+        if let Some(file_name) = dest.strip_prefix(ATTACHMENT_URI_PREFIX) {
+            if file_name.is_empty() || file_name.contains(['/', '\\']) {
+                return None;
+            }
+            attachments.insert(file_name.to_string());
+            return Some(format!("{}{}/{}", root, ATTACHMENTS_DIR, encode_path(file_name)));
+        }
+        if dest.trim_start().to_ascii_lowercase().starts_with("javascript:") {
+            return None;
+        }
+        Some(dest.to_string())
+    }
+
+    fn render_markdown(&self, content: &str, root: &str, attachments: &mut BTreeSet<String>) -> String {
+        let content = self.resolve_wiki_links(content);
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES;
+        let mut events: Vec<Event> = Vec::new();
+        // Whether each open link was kept, to drop the matching end tag
+        let mut open_links: Vec<bool> = Vec::new();
+        for event in Parser::new_ext(&content, options) {
+            let event = match event {
+                Event::Start(Tag::Link { link_type, dest_url, title, id }) => {
+                    match self.destination(&dest_url, root, attachments) {
+                        Some(dest) => {
+                            open_links.push(true);
+                            Event::Start(Tag::Link { link_type, dest_url: dest.into(), title, id })
+                        }
+                        None => {
+                            open_links.push(false);
+                            continue;
+                        }
+                    }
+                }
+                Event::End(TagEnd::Link) => {
+                    if !open_links.pop().unwrap_or(true) {
+                        continue;
+                    }
+                    Event::End(TagEnd::Link)
+                }
+                Event::Start(Tag::Image { link_type, dest_url, title, id }) => {
+                    let dest = self.destination(&dest_url, root, attachments).unwrap_or_default();
+                    Event::Start(Tag::Image { link_type, dest_url: dest.into(), title, id })
+                }
+                // Notes are not trusted to inject markup or scripts into the site
+                Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+                event => event,
+            };
+            events.push(event);
+        }
+        add_heading_ids(&mut events);
+
+        let mut out = String::new();
+        html::push_html(&mut out, events.into_iter());
+        out
+    }
+
+    fn render_nav(&self, root: &str, current: Option<&str>) -> String {
+        let mut child_folders: HashMap<Option<&str>, Vec<&Folder>> = HashMap::new();
+        let known: HashSet<&str> = self.folders.iter().map(|f| f.id.as_str()).collect();
+        for folder in self.folders {
+            let parent = folder.parent_id.as_deref().filter(|p| known.contains(p));
+            child_folders.entry(parent).or_default().push(folder);
+        }
+        for folders in child_folders.values_mut() {
+            folders.sort_by(|a, b| (a.position, &a.name).cmp(&(b.position, &b.name)));
+        }
+        let mut child_notes: HashMap<Option<&str>, Vec<&(&Note, String)>> = HashMap::new();
+        for entry in &self.notes {
+            let folder = entry.0.folder_id.as_deref().filter(|f| known.contains(f));
+            child_notes.entry(folder).or_default().push(entry);
+        }
+        for notes in child_notes.values_mut() {
+            notes.sort_by_key(|(note, _)| note.title.to_lowercase());
+        }
+
+        let mut out = String::new();
+        let mut visited = HashSet::new();
+        self.render_level(&mut out, None, &child_folders, &child_notes, root, current, &mut visited);
+        out
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_level<'b>(
+        &self,
+        out: &mut String,
+        parent: Option<&'b str>,
+        folders: &HashMap<Option<&'b str>, Vec<&'b Folder>>,
+        notes: &HashMap<Option<&str>, Vec<&(&Note, String)>>,
+        root: &str,
+        current: Option<&str>,
+        visited: &mut HashSet<&'b str>,
+    ) {
+        out.push_str("<ul>");
+        for folder in folders.get(&parent).into_iter().flatten() {
+            if !visited.insert(folder.id.as_str()) {
+                continue;
+            }
+            out.push_str(&format!("<li><details open><summary>{}</summary>", escape(folder.name.as_str())));
+            self.render_level(out, Some(folder.id.as_str()), folders, notes, root, current, visited);
+            out.push_str("</details></li>");
+        }
+        for (note, path) in notes.get(&parent).into_iter().flatten() {
+            let class = if current == Some(note.id.as_str()) { " class=\"current\"" } else { "" };
+            out.push_str(&format!(
+                "<li><a href=\"{}{}\"{}>{}</a></li>",
+                root,
+                encode_path(path),
+                class,
+                escape(note.title.as_str())
+            ));
+        }
+        out.push_str("</ul>");
+    }
+}
+
+/// Gives every heading a GitHub-style `id`, so `stem-note://<id>#<slug>` anchors work.
+fn add_heading_ids(events: &mut [Event]) {
+    let mut used: HashMap<String, usize> = HashMap::new();
+    for i in 0..events.len() {
+        let Event::Start(Tag::Heading { id: None, .. }) = &events[i] else { continue };
+        let mut text = String::new();
+        for event in &events[i + 1..] {
+            match event {
+                Event::End(TagEnd::Heading(_)) => break,
+                Event::Text(t) | Event::Code(t) => text.push_str(t),
+                _ => {}
+            }
+        }
+        let base = slugify(&text);
+        if base.is_empty() {
+            continue;
+        }
+        let count = used.entry(base.clone()).or_insert(0);
+        let slug = if *count == 0 { base } else { format!("{}-{}", base, count) };
+        *count += 1;
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+            *id = Some(CowStr::from(slug));
+        }
+    }
+}
+
+fn page(title: &str, root: &str, nav: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"fr\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n\
+         <body data-root=\"{root}\">\n<nav>\n<a href=\"{root}index.html\"><strong>Index</strong></a>\n\
+         <input id=\"search\" type=\"search\" placeholder=\"Rechercher…\">\n<div id=\"results\"></div>\n{nav}\n</nav>\n\
+         <main>\n{body}\n</main>\n<script src=\"{root}{SEARCH_INDEX_FILE}\"></script>\n<script>{SEARCH_SCRIPT}</script>\n\
+         </body>\n</html>\n",
+        title = escape(title),
+    )
+}
+
+pub(crate) fn export_site_to(
+    notes: Vec<Note>,
+    folders: &[Folder],
+    store: &AttachmentStore,
+    root: &Path,
+) -> Result<SiteExportReport, StemError> {
+    fs::create_dir_all(root)?;
+    let manifest_path = root.join(MANIFEST_FILE);
+    let previous: SiteManifest = fs::read(&manifest_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    let mut report = SiteExportReport::default();
+    let (plain, encrypted): (Vec<Note>, Vec<Note>) = notes.into_iter().partition(|n| !n.is_encrypted);
+    report.skipped_encrypted = encrypted.len();
+
+    let layout = plan_layout(plain, folders);
+    let mut site = Site { pages: HashMap::new(), titles: HashMap::new(), folders, notes: Vec::new() };
+    for (note, rel) in &layout.notes {
+        let path = format!("{}/{}", NOTES_DIR, to_portable(&rel.with_extension("html")));
+        site.pages.insert(note.id.as_str(), path.clone());
+        site.titles.entry(note.title.to_lowercase()).or_insert(note.id.as_str());
+        site.notes.push((note, path));
+    }
+
+    let mut manifest = SiteManifest { version: 1, ..Default::default() };
+    let mut attachments = BTreeSet::new();
+    for (note, path) in &site.notes {
+        let root_prefix = "../".repeat(path.matches('/').count());
+        let body = site.render_markdown(note.content.as_deref().unwrap_or(""), &root_prefix, &mut attachments);
+        let body = format!(
+            "<h1>{}</h1>\n<p class=\"meta\">Modifiée le {}</p>\n{}",
+            escape(note.title.as_str()),
+            chrono::DateTime::from_timestamp(note.updated_at, 0).unwrap_or_default().format("%d/%m/%Y"),
+            body
+        );
+        let nav = site.render_nav(&root_prefix, Some(note.id.as_str()));
+        write_if_changed(&root.join(path), page(&note.title, &root_prefix, &nav, &body).as_bytes())?;
+        manifest.files.insert(path.clone());
+        report.pages += 1;
+    }
+
+    let index_body = format!("<h1>Index</h1>\n{}", site.render_nav("", None));
+    write_if_changed(&root.join("index.html"), page("Index", "", &site.render_nav("", None), &index_body).as_bytes())?;
+    manifest.files.insert("index.html".to_string());
+
+    let entries: Vec<SearchEntry> = site
+        .notes
+        .iter()
+        .map(|(note, path)| SearchEntry {
+            title: &note.title,
+            path: encode_path(path),
+            text: plain_text(note.content.as_deref().unwrap_or("")).chars().take(SEARCH_TEXT_CHARS).collect(),
+        })
+        .collect();
+    let json = serde_json::to_string(&entries).map_err(|e| StemError::Validation(e.to_string()))?;
+    // `</script>` inside a note must not end the script element
+    let script = format!("window.STEM_SEARCH_INDEX = {};\n", json.replace("</", "<\\/"));
+    write_if_changed(&root.join(SEARCH_INDEX_FILE), script.as_bytes())?;
+    manifest.files.insert(SEARCH_INDEX_FILE.to_string());
+
+    for file_name in &attachments {
+        let source = store.path_of(file_name);
+        if !source.is_file() {
+            report.missing_attachments += 1;
+            continue;
+        }
+        let rel = format!("{}/{}", ATTACHMENTS_DIR, file_name);
+        let target = root.join(&rel);
+        let unchanged = fs::metadata(&target).is_ok_and(|t| fs::metadata(&source).is_ok_and(|s| s.len() == t.len()));
+        if !unchanged {
+            fs::create_dir_all(root.join(ATTACHMENTS_DIR))?;
+            fs::copy(&source, &target)?;
+        }
+        manifest.files.insert(rel);
+        report.attachments += 1;
+    }
+
+    // The manifest is read back from disk: only pages and attachments this
+    // export writes, inside `root`, are ever removed
+    for old in previous.files.difference(&manifest.files) {
+        let ours = [NOTES_DIR, ATTACHMENTS_DIR].iter().any(|dir| old.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/')));
+        let Some(path) = path_under(root, old).filter(|_| ours) else { continue };
+        if fs::remove_file(&path).is_ok() {
+            report.removed += 1;
+        }
+        prune_empty_dirs(root, path.parent());
+    }
+
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| StemError::Validation(e.to_string()))?;
+    write_if_changed(&manifest_path, &json)?;
+    Ok(report)
+}
+
+// ===== Tauri Commands =====
+
+/// Renders every note (or only `scope`) to a static HTML site in `target_dir`.
+#[tauri::command]
+pub async fn export_site(
+    db: State<'_, Database>,
+    store: State<'_, AttachmentStore>,
+    target_dir: String,
+    scope: Option<ExportScope>,
+) -> Result<SiteExportReport, StemError> {
+    let root = PathBuf::from(target_dir);
+    if !root.is_absolute() {
+        return Err(StemError::Validation("Le dossier d'export doit être un chemin absolu".to_string()));
+    }
+    let store = store.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let (mut notes, mut folders) = load_vault(&db)?;
+        if let Some(scope) = &scope {
+            resolve_scope(&*db.try_connection()?, scope)?.retain(&mut notes, &mut folders);
+        }
+        export_site_to(notes, &folders, &store, &root)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support;
+    use uuid::Uuid;
+
+    fn note(id: &str, title: &str, content: &str, folder_id: Option<&str>) -> Note {
+        Note { folder_id: folder_id.map(str::to_string), ..test_support::note(id, title, content, 1) }
+    }
+
+    #[test]
+    fn test_export_site() {
+        let dir = std::env::temp_dir().join(format!("stem-site-{}", Uuid::new_v4()));
+        let store = AttachmentStore::new(dir.join("store"));
+        let image = store.write_bytes(b"png", "shot.png", None).unwrap();
+        let root = dir.join("site");
+        let folders = vec![Folder {
+            id: "f1".into(),
+            name: "Docs & co".into(),
+            parent_id: None,
+            position: 0,
+            created_at: 1,
+            is_archived: false,
+        }];
+        let notes = vec![
+            note("a", "Guide", &format!(
+                "## Mise en route\n\nVoir [[Autre#Détails|l'autre]] et [absente](stem-note://zzz) [x](javascript:alert(1)).\n\n![img]({})\n\n<script>alert(1)</script>",
+                crate::attachments::attachment_uri(&image.file_name)
+            ), Some("f1")),
+            note("b", "Autre", "# Détails\n\nRetour au [guide](stem-note://a#mise-en-route).", None),
+        ];
+
+        let report = export_site_to(notes, &folders, &store, &root).unwrap();
+        assert_eq!((report.pages, report.attachments, report.missing_attachments), (2, 1, 0));
+
+        let guide = fs::read_to_string(root.join("notes/Docs & co/Guide.html")).unwrap();
+        assert!(guide.contains("<h2 id=\"mise-en-route\">Mise en route</h2>"));
+        assert!(guide.contains("<a href=\"../../notes/Autre.html#d%C3%A9tails\">l'autre</a>"));
+        assert!(guide.contains("et absente x."));
+        assert!(guide.contains(&format!("<img src=\"../../attachments/{}\"", image.file_name)));
+        assert!(guide.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(guide.contains("<a href=\"../../notes/Docs%20%26%20co/Guide.html\" class=\"current\">Guide</a>"));
+        assert!(root.join("attachments").join(&image.file_name).is_file());
+
+        let other = fs::read_to_string(root.join("notes/Autre.html")).unwrap();
+        assert!(other.contains("<a href=\"../notes/Docs%20%26%20co/Guide.html#mise-en-route\">guide</a>"));
+        let index = fs::read_to_string(root.join("index.html")).unwrap();
+        assert!(index.contains("<summary>Docs &amp; co</summary>"));
+        let search = fs::read_to_string(root.join(SEARCH_INDEX_FILE)).unwrap();
+        assert!(search.starts_with("window.STEM_SEARCH_INDEX = [") && search.contains("\"title\":\"Autre\""));
+
+        // A second run without the first note removes its page and attachment
+        let report = export_site_to(vec![note("b", "Autre", "Seule", None)], &[], &store, &root).unwrap();
+        assert_eq!(report.removed, 2);
+        assert!(!root.join("notes/Docs & co").exists());
+
+        // An edited manifest cannot make the export delete anything else
+        let outside = dir.join("precious.txt");
+        fs::write(&outside, "keep me").unwrap();
+        let absolute = outside.to_string_lossy().to_string();
+        let files = ["../precious.txt", "notes/../../precious.txt", absolute.as_str(), "index.htm"];
+        let manifest = serde_json::json!({ "version": 1, "files": files });
+        fs::write(root.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+        let report = export_site_to(vec![note("b", "Autre", "Seule", None)], &[], &store, &root).unwrap();
+        assert_eq!(report.removed, 0);
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "keep me");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::commands::FOLDER_SUBTREE_CTE;
+use crate::db::Database;
+use crate::error::StemError;
+use crate::text;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tauri::State;
+
+const WORDS_PER_MINUTE: u64 = 200;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// ===== Types =====
+
+/// What to compute statistics over. Both fields empty means the whole vault.
+#[derive(Debug, Deserialize, Default)]
+pub struct StatisticsScope {
+    /// A folder and all of its sub-folders.
+    pub folder_id: Option<String>,
+    /// A single note (takes precedence over `folder_id`).
+    pub note_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DayActivity {
+    /// UTC date, `YYYY-MM-DD`.
+    pub date: String,
+    pub created: u32,
+    pub edited: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageStats {
+    pub database_bytes: u64,
+    pub embeddings_bytes: u64,
+    pub chat_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Statistics {
+    pub note_count: u64,
+    pub word_count: u64,
+    pub char_count: u64,
+    pub reading_time_minutes: u64,
+    pub activity: Vec<DayActivity>,
+    pub longest_streak_days: u32,
+    pub storage: StorageStats,
+}
+
+// ===== Helpers =====
+
+/// Fills `note_stats` for every note that has no cached row yet. Rows are
+/// dropped by the `notes_stats_on_edit` trigger, so only notes whose content
+/// changed, or new ones, are re-parsed here. Encrypted notes count as empty.
+/// Runs in one transaction so readers never see a half-filled cache.
+fn refresh_cache(conn: &mut Connection) -> Result<usize, StemError> {
+    let tx = conn.transaction()?;
+    let stale: Vec<(String, String)> = {
+        let mut stmt = tx.prepare(
+            "SELECT n.id, CASE WHEN n.is_encrypted = 1 THEN '' ELSE COALESCE(n.content, '') END FROM notes n
+             LEFT JOIN note_stats s ON s.note_id = n.id
+             WHERE s.note_id IS NULL",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    {
+        let mut insert = tx.prepare("INSERT OR REPLACE INTO note_stats (note_id, words, chars) VALUES (?1, ?2, ?3)")?;
+        for (id, content) in &stale {
+            let plain = text::plain_text(content);
+            let words = plain.split_whitespace().count() as i64;
+            let chars = plain.chars().count() as i64;
+            insert.execute(rusqlite::params![id, words, chars])?;
+        }
+    }
+    tx.commit()?;
+    Ok(stale.len())
+}
+
+/// Builds a `scoped(id)` CTE listing the ids of the notes covered by `scope`.
+fn scope_cte(scope: &StatisticsScope) -> (String, Vec<Value>) {
+    if let Some(note_id) = &scope.note_id {
+        ("scoped(id) AS (SELECT id FROM notes WHERE id = ?1)".to_string(), vec![note_id.clone().into()])
+    } else if let Some(folder_id) = &scope.folder_id {
+        let cte = format!("{FOLDER_SUBTREE_CTE}, scoped(id) AS (SELECT id FROM notes WHERE folder_id IN subtree)");
+        (cte, vec![folder_id.clone().into()])
+    } else {
+        ("scoped(id) AS (SELECT id FROM notes)".to_string(), vec![])
+    }
+}
+
+fn longest_streak(days: impl IntoIterator<Item = i64>) -> u32 {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous: Option<i64> = None;
+    for day in days {
+        current = match previous {
+            Some(prev) if day == prev + 1 => current + 1,
+            _ => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(day);
+    }
+    longest
+}
+
+fn storage_stats(conn: &Connection) -> Result<StorageStats, StemError> {
+    let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    let embeddings_bytes: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(LENGTH(embedding) + LENGTH(note_id) + LENGTH(model)), 0) FROM note_embeddings",
+        [],
+        |row| row.get(0),
+    )?;
+    let chat_bytes: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(LENGTH(CAST(content AS BLOB)) + LENGTH(id) + COALESCE(LENGTH(command), 0)), 0) FROM chat_messages",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(StorageStats {
+        database_bytes: (page_count * page_size).max(0) as u64,
+        embeddings_bytes: embeddings_bytes.max(0) as u64,
+        chat_bytes: chat_bytes.max(0) as u64,
+    })
+}
+
+pub(crate) fn compute_statistics(db: &Database, scope: &StatisticsScope) -> Result<Statistics, StemError> {
+    let mut conn = db.try_connection()?;
+    refresh_cache(&mut conn)?;
+
+    let (cte, params) = scope_cte(scope);
+
+    let (note_count, word_count, char_count): (i64, i64, i64) = conn.query_row(
+        &format!(
+            "WITH RECURSIVE {cte}
+             SELECT COUNT(*), COALESCE(SUM(words), 0), COALESCE(SUM(chars), 0)
+             FROM note_stats WHERE note_id IN scoped"
+        ),
+        params_from_iter(params.iter()),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    // day -> (date, created, edited)
+    let mut days: BTreeMap<i64, (String, u32, u32)> = BTreeMap::new();
+
+    let mut created_stmt = conn.prepare(&format!(
+        "WITH RECURSIVE {cte}
+         SELECT created_at / {SECONDS_PER_DAY} AS day, date(created_at, 'unixepoch'), COUNT(*)
+         FROM notes WHERE id IN scoped GROUP BY day"
+    ))?;
+    let created = created_stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?))
+    })?;
+    for row in created {
+        let (day, date, count) = row?;
+        days.entry(day).or_insert((date, 0, 0)).1 += count;
+    }
+
+    let mut edited_stmt = conn.prepare(&format!(
+        "WITH RECURSIVE {cte}
+         SELECT day, date(day * {SECONDS_PER_DAY}, 'unixepoch'), COUNT(*)
+         FROM note_activity WHERE note_id IN scoped GROUP BY day"
+    ))?;
+    let edited = edited_stmt.query_map(params_from_iter(params.iter()), |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?))
+    })?;
+    for row in edited {
+        let (day, date, count) = row?;
+        days.entry(day).or_insert((date, 0, 0)).2 += count;
+    }
+
+    let longest_streak_days = longest_streak(days.keys().copied());
+    let activity = days
+        .into_values()
+        .map(|(date, created, edited)| DayActivity { date, created, edited })
+        .collect();
+
+    let word_count = word_count.max(0) as u64;
+    Ok(Statistics {
+        note_count: note_count.max(0) as u64,
+        word_count,
+        char_count: char_count.max(0) as u64,
+        reading_time_minutes: word_count.div_ceil(WORDS_PER_MINUTE),
+        activity,
+        longest_streak_days,
+        storage: storage_stats(&conn)?,
+    })
+}
+
+// ===== Tauri Commands =====
+
+/// Word, character and activity statistics for the vault, a folder subtree or one note.
+#[tauri::command]
+pub async fn get_statistics(db: State<'_, Database>, scope: Option<StatisticsScope>) -> Result<Statistics, StemError> {
+    db.inner().clone().spawn(move |db| compute_statistics(&db, &scope.unwrap_or_default())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_db() -> Database {
+        let db = Database::in_memory().expect("Failed to create in-memory DB");
+        db.init().expect("Failed to init DB");
+        db
+    }
+
+    fn insert_note(db: &Database, id: &str, content: &str, created_at: i64, folder_id: Option<&str>) {
+        db.connection().execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES (?1, 't', ?2, ?3, ?3, ?4)",
+            rusqlite::params![id, content, created_at, folder_id],
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_longest_streak() {
+        assert_eq!(longest_streak([]), 0);
+        assert_eq!(longest_streak([1, 2, 3, 5, 6]), 3);
+        assert_eq!(longest_streak([10, 12, 14]), 1);
+    }
+
+    #[test]
+    fn test_vault_statistics_and_cache_invalidation() {
+        let db = setup_db();
+        insert_note(&db, "a", "one two three", 0, None);
+        insert_note(&db, "b", "**four**", SECONDS_PER_DAY, None);
+
+        let stats = compute_statistics(&db, &StatisticsScope::default()).unwrap();
+        assert_eq!(stats.note_count, 2);
+        assert_eq!(stats.word_count, 4);
+        assert_eq!(stats.char_count, 17);
+        assert_eq!(stats.reading_time_minutes, 1);
+        assert_eq!(stats.longest_streak_days, 2);
+        assert_eq!(stats.activity[0], DayActivity { date: "1970-01-01".to_string(), created: 1, edited: 0 });
+
+        db.connection().execute(
+            "UPDATE notes SET content = 'five six', updated_at = ?1 WHERE id = 'b'",
+            [2 * SECONDS_PER_DAY],
+        ).unwrap();
+
+        let stats = compute_statistics(&db, &StatisticsScope::default()).unwrap();
+        assert_eq!(stats.word_count, 5);
+        assert_eq!(stats.longest_streak_days, 3);
+        assert_eq!(stats.activity[2], DayActivity { date: "1970-01-03".to_string(), created: 0, edited: 1 });
+    }
+
+    #[test]
+    fn test_refresh_only_touches_stale_notes() {
+        let db = setup_db();
+        insert_note(&db, "a", "hello", 0, None);
+        insert_note(&db, "b", "world", 0, None);
+        let mut conn = db.connection();
+        assert_eq!(refresh_cache(&mut conn).unwrap(), 2);
+        assert_eq!(refresh_cache(&mut conn).unwrap(), 0);
+        conn.execute("UPDATE notes SET content = 'changed' WHERE id = 'a'", []).unwrap();
+        assert_eq!(refresh_cache(&mut conn).unwrap(), 1);
+        // The counts only depend on the content
+        conn.execute("UPDATE notes SET title = 'renamed' WHERE id = 'b'", []).unwrap();
+        assert_eq!(refresh_cache(&mut conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_folder_subtree_scope() {
+        let db = setup_db();
+        {
+            let conn = db.connection();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('f1', 'Root', NULL, 0)", []).unwrap();
+            conn.execute("INSERT INTO folders (id, name, parent_id, created_at) VALUES ('f2', 'Child', 'f1', 0)", []).unwrap();
+        }
+        insert_note(&db, "a", "in root folder", 0, Some("f1"));
+        insert_note(&db, "b", "in child", 0, Some("f2"));
+        insert_note(&db, "c", "outside", 0, None);
+
+        let scope = StatisticsScope { folder_id: Some("f1".to_string()), note_id: None };
+        let stats = compute_statistics(&db, &scope).unwrap();
+        assert_eq!(stats.note_count, 2);
+        assert_eq!(stats.word_count, 5);
+
+        let scope = StatisticsScope { folder_id: None, note_id: Some("c".to_string()) };
+        assert_eq!(compute_statistics(&db, &scope).unwrap().word_count, 1);
+    }
+}
@@ -0,0 +1,199 @@
+//! Notes that a sync could not merge automatically. The merged note keeps
+//! both versions (inline markers, or a conflict copy for encrypted notes)
+//! until the user picks a resolution.
+
+use super::merge::NoteConflict;
+use crate::commands::{redact, row_to_note, Note, NOTE_COLUMNS};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct SyncConflict {
+    pub id: String,
+    pub note_id: String,
+    pub title: String,
+    /// Versions of the content. Not kept for encrypted notes, whose remote
+    /// version lives in the `copy_note_id` note instead.
+    pub base: Option<String>,
+    pub local: Option<String>,
+    pub remote: Option<String>,
+    pub copy_note_id: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Replace the note with this device's version.
+    KeepLocal,
+    /// Replace the note with the other device's version.
+    KeepRemote,
+    /// Keep this device's version and save the other one as a separate note.
+    KeepBoth,
+    /// Keep the note as it is now, e.g. after editing the markers by hand.
+    Dismiss,
+}
+
+/// Stores the conflicts of a merge, replacing older ones for the same notes.
+pub(crate) fn record_conflicts(db: &Database, conflicts: &[NoteConflict]) -> Result<(), StemError> {
+    let mut conn = db.try_connection()?;
+    let tx = conn.transaction()?;
+    let now = current_timestamp();
+    for conflict in conflicts {
+        let inline = conflict.copy_id.is_none();
+        tx.execute("DELETE FROM sync_conflicts WHERE note_id = ?1", [&conflict.note_id])?;
+        tx.execute(
+            "INSERT INTO sync_conflicts (id, note_id, title, base, local, remote, copy_note_id, created_at)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 WHERE EXISTS (SELECT 1 FROM notes WHERE id = ?2)",
+            params![
+                Uuid::new_v4().to_string(),
+                conflict.note_id,
+                conflict.title,
+                conflict.base.as_ref().filter(|_| inline),
+                Some(&conflict.local).filter(|_| inline),
+                Some(&conflict.remote).filter(|_| inline),
+                conflict.copy_id,
+                now,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub(crate) fn list_conflicts(db: &Database) -> Result<Vec<SyncConflict>, StemError> {
+    let conn = db.try_connection()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, title, base, local, remote, copy_note_id, created_at FROM sync_conflicts ORDER BY created_at DESC",
+    )?;
+    let conflicts = stmt
+        .query_map([], |row| {
+            Ok(SyncConflict {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                title: row.get(2)?,
+                base: row.get(3)?,
+                local: row.get(4)?,
+                remote: row.get(5)?,
+                copy_note_id: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(conflicts)
+}
+
+pub(crate) fn resolve_conflict(db: &Database, id: &str, resolution: ConflictResolution) -> Result<Note, StemError> {
+    let conflict = list_conflicts(db)?
+        .into_iter()
+        .find(|c| c.id == id)
+        .ok_or_else(|| StemError::NotFound(format!("Conflit {}", id)))?;
+    let mut conn = db.try_connection()?;
+    let tx = conn.transaction()?;
+    let now = current_timestamp();
+    let set_content = |content: &Option<String>, is_encrypted: bool| {
+        tx.execute(
+            "UPDATE notes SET content = ?2, is_encrypted = ?3, updated_at = ?4 WHERE id = ?1",
+            params![conflict.note_id, content, is_encrypted, now],
+        )
+    };
+
+    match (resolution, &conflict.copy_note_id) {
+        (ConflictResolution::Dismiss, _) => {}
+        (ConflictResolution::KeepLocal, None) => {
+            set_content(&conflict.local, false)?;
+        }
+        (ConflictResolution::KeepRemote, None) => {
+            set_content(&conflict.remote, false)?;
+        }
+        (ConflictResolution::KeepBoth, None) => {
+            set_content(&conflict.local, false)?;
+            tx.execute(
+                "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id)
+                 SELECT ?2, title || ' (conflit)', ?3, ?4, ?4, folder_id FROM notes WHERE id = ?1",
+                params![conflict.note_id, Uuid::new_v4().to_string(), conflict.remote, now],
+            )?;
+        }
+        (ConflictResolution::KeepLocal, Some(copy_id)) => {
+            tx.execute("DELETE FROM notes WHERE id = ?1", [copy_id])?;
+        }
+        (ConflictResolution::KeepRemote, Some(copy_id)) => {
+            let copy: Option<(Option<String>, bool)> = tx
+                .query_row("SELECT content, is_encrypted FROM notes WHERE id = ?1", [copy_id], |row| {
+                    Ok((row.get(0)?, row.get::<_, i32>(1)? != 0))
+                })
+                .optional()?;
+            let (content, is_encrypted) = copy.ok_or_else(|| StemError::NotFound(format!("Note {}", copy_id)))?;
+            set_content(&content, is_encrypted)?;
+            tx.execute("DELETE FROM notes WHERE id = ?1", [copy_id])?;
+        }
+        (ConflictResolution::KeepBoth, Some(_)) => {}
+    }
+
+    tx.execute("DELETE FROM sync_conflicts WHERE id = ?1", [id])?;
+    let note = tx
+        .query_row(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"), [&conflict.note_id], row_to_note)
+        .optional()?
+        .ok_or_else(|| StemError::NotFound(format!("Note {}", conflict.note_id)))?;
+    tx.commit()?;
+    Ok(redact(note))
+}
+
+// ===== Tauri Commands =====
+
+/// Notes left with unresolved sync conflicts, most recent first.
+#[tauri::command]
+pub async fn list_sync_conflicts(db: State<'_, Database>) -> Result<Vec<SyncConflict>, StemError> {
+    db.inner().clone().spawn(move |db| list_conflicts(&db)).await
+}
+
+/// Applies `resolution` to a conflict and returns the resulting note.
+#[tauri::command]
+pub async fn resolve_sync_conflict(
+    db: State<'_, Database>,
+    id: String,
+    resolution: ConflictResolution,
+) -> Result<Note, StemError> {
+    db.inner().clone().spawn(move |db| resolve_conflict(&db, &id, resolution)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_keep_both_creates_copy() {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        db.connection().execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('n', 'Plan', '<<<<<<< markers', 1, 1)",
+            [],
+        ).unwrap();
+        let conflict = NoteConflict {
+            note_id: "n".into(),
+            title: "Plan".into(),
+            base: Some("a\n".into()),
+            local: "local\n".into(),
+            remote: "remote\n".into(),
+            copy_id: None,
+        };
+        record_conflicts(&db, std::slice::from_ref(&conflict)).unwrap();
+        record_conflicts(&db, &[conflict]).unwrap();
+        let listed = list_conflicts(&db).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].remote.as_deref(), Some("remote\n"));
+
+        let note = resolve_conflict(&db, &listed[0].id, ConflictResolution::KeepBoth).unwrap();
+        assert_eq!(note.content.as_deref(), Some("local\n"));
+        let copy: String = db.connection()
+            .query_row("SELECT content FROM notes WHERE title = 'Plan (conflit)'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(copy, "remote\n");
+        assert!(list_conflicts(&db).unwrap().is_empty());
+        assert!(matches!(resolve_conflict(&db, &listed[0].id, ConflictResolution::Dismiss), Err(StemError::NotFound(_))));
+    }
+}
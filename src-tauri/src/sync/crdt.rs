@@ -0,0 +1,279 @@
+//! Optional CRDT backing for note content, with Automerge. A note with a row
+//! in `note_crdt` keeps its editing history next to the Markdown snapshot in
+//! `notes.content`: `update_note` records each save as text splices instead
+//! of replacing the string, and two instances converge by exchanging binary
+//! updates over any transport (`export_note_updates`, `apply_note_updates`).
+//!
+//! Devices must start from the same text: the first change is made with an
+//! actor and a time derived from the note alone, so enabling the CRDT on
+//! identical notes yields identical documents. Content written by other means
+//! (imports, other sync backends) is folded in as a local edit the next time
+//! the document is used.
+
+use super::device_id;
+use crate::commands::{get_note_sync, Note};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use automerge::transaction::{CommitOptions, Transactable};
+use automerge::{ActorId, AutoCommit, ChangeHash, ObjType, ReadDoc, Value, ROOT};
+use rusqlite::{Connection, OptionalExtension};
+use std::str::FromStr;
+use tauri::State;
+use uuid::Uuid;
+
+fn crdt_error(e: automerge::AutomergeError) -> StemError {
+    StemError::Sync(format!("Document CRDT invalide : {}", e))
+}
+
+fn not_enabled(note_id: &str) -> StemError {
+    StemError::Validation(format!("La note {} n'est pas éditée en CRDT", note_id))
+}
+
+/// A loaded document and its `content` text object.
+struct NoteDoc {
+    doc: AutoCommit,
+    text: automerge::ObjId,
+}
+
+impl NoteDoc {
+    /// The same first change on every device for the same `content`.
+    fn create(note_id: &str, content: &str) -> Result<Self, StemError> {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from(note_id.as_bytes()));
+        let text = doc.put_object(ROOT, "content", ObjType::Text).map_err(crdt_error)?;
+        doc.update_text(&text, content).map_err(crdt_error)?;
+        doc.commit_with(CommitOptions::default().with_time(0));
+        Ok(Self { doc, text })
+    }
+
+    fn load(bytes: &[u8], actor: ActorId) -> Result<Self, StemError> {
+        let doc = AutoCommit::load(bytes).map_err(crdt_error)?.with_actor(actor);
+        match doc.get(ROOT, "content").map_err(crdt_error)? {
+            Some((Value::Object(ObjType::Text), text)) => Ok(Self { doc, text }),
+            _ => Err(StemError::Sync("Document CRDT sans texte".to_string())),
+        }
+    }
+
+    fn content(&self) -> Result<String, StemError> {
+        self.doc.text(&self.text).map_err(crdt_error)
+    }
+
+    /// Records the change from the current text to `content` as splices.
+    fn edit(&mut self, content: &str) -> Result<(), StemError> {
+        if self.content()? != content {
+            self.doc.update_text(&self.text, content).map_err(crdt_error)?;
+            self.doc.commit();
+        }
+        Ok(())
+    }
+}
+
+fn actor(conn: &Connection) -> Result<ActorId, StemError> {
+    let id = Uuid::parse_str(&device_id(conn)?).map_err(|e| StemError::Validation(e.to_string()))?;
+    Ok(ActorId::from(id.as_bytes().as_slice()))
+}
+
+/// Plain content of a note; encrypted notes cannot be edited as CRDTs.
+fn plain_content(conn: &Connection, note_id: &str) -> Result<String, StemError> {
+    let (content, is_encrypted): (Option<String>, bool) = conn
+        .query_row("SELECT content, is_encrypted FROM notes WHERE id = ?1", [note_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()?
+        .ok_or_else(|| StemError::NotFound(format!("Note {}", note_id)))?;
+    if is_encrypted {
+        return Err(StemError::Validation("Une note chiffrée ne peut pas être éditée en CRDT".to_string()));
+    }
+    Ok(content.unwrap_or_default())
+}
+
+/// The document of `note_id` in step with its Markdown snapshot, if it has one.
+fn load(conn: &Connection, note_id: &str) -> Result<Option<NoteDoc>, StemError> {
+    let bytes: Option<Vec<u8>> = conn
+        .query_row("SELECT doc FROM note_crdt WHERE note_id = ?1", [note_id], |row| row.get(0))
+        .optional()?;
+    let Some(bytes) = bytes else { return Ok(None) };
+    let mut doc = NoteDoc::load(&bytes, actor(conn)?)?;
+    doc.edit(&plain_content(conn, note_id)?)?;
+    Ok(Some(doc))
+}
+
+fn store(conn: &Connection, note_id: &str, doc: &mut NoteDoc) -> Result<(), StemError> {
+    conn.execute(
+        "INSERT INTO note_crdt (note_id, doc, updated_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(note_id) DO UPDATE SET doc = excluded.doc, updated_at = excluded.updated_at",
+        (note_id, doc.doc.save(), current_timestamp()),
+    )?;
+    Ok(())
+}
+
+fn enable(conn: &Connection, note_id: &str) -> Result<NoteDoc, StemError> {
+    if let Some(doc) = load(conn, note_id)? {
+        return Ok(doc);
+    }
+    let mut doc = NoteDoc::create(note_id, &plain_content(conn, note_id)?)?;
+    doc.doc.set_actor(actor(conn)?);
+    store(conn, note_id, &mut doc)?;
+    Ok(doc)
+}
+
+/// Called by `update_note` before it stores new content: a no-op for notes
+/// without a document.
+pub(crate) fn record_edit(conn: &Connection, note_id: &str, content: &str) -> Result<(), StemError> {
+    if let Some(mut doc) = load(conn, note_id)? {
+        doc.edit(content)?;
+        store(conn, note_id, &mut doc)?;
+    }
+    Ok(())
+}
+
+/// Changes the peer lacks, given its heads, or the whole document without them.
+fn export_updates(conn: &Connection, note_id: &str, since: Option<&[String]>) -> Result<Vec<u8>, StemError> {
+    let mut doc = load(conn, note_id)?.ok_or_else(|| not_enabled(note_id))?;
+    store(conn, note_id, &mut doc)?;
+    match since {
+        Some(heads) => {
+            let heads = heads
+                .iter()
+                .map(|h| ChangeHash::from_str(h).map_err(|_| StemError::Validation(format!("Tête CRDT invalide : {}", h))))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(doc.doc.save_after(&heads))
+        }
+        None => Ok(doc.doc.save()),
+    }
+}
+
+/// Merges updates from another device and rewrites the Markdown snapshot.
+fn apply_updates(db: &Database, note_id: &str, updates: &[u8]) -> Result<Note, StemError> {
+    let conn = db.try_connection()?;
+    let mut doc = enable(&conn, note_id)?;
+    let before = doc.content()?;
+    doc.doc.load_incremental(updates).map_err(crdt_error)?;
+    store(&conn, note_id, &mut doc)?;
+    let after = doc.content()?;
+    if after != before {
+        conn.execute(
+            "UPDATE notes SET content = ?1, updated_at = ?2 WHERE id = ?3",
+            (&after, current_timestamp(), note_id),
+        )?;
+    }
+    drop(conn);
+    get_note_sync(db, note_id)?.ok_or_else(|| StemError::NotFound(format!("Note {}", note_id)))
+}
+
+/// Stored first, like `export_updates`: an out-of-band edit folded in by
+/// `load` must be kept for the heads to mean anything to a peer.
+fn heads(conn: &Connection, note_id: &str) -> Result<Vec<String>, StemError> {
+    let mut doc = load(conn, note_id)?.ok_or_else(|| not_enabled(note_id))?;
+    store(conn, note_id, &mut doc)?;
+    Ok(doc.doc.get_heads().iter().map(ToString::to_string).collect())
+}
+
+// ===== Tauri Commands =====
+
+/// Starts keeping the editing history of a note as a CRDT document.
+#[tauri::command]
+pub async fn enable_note_crdt(db: State<'_, Database>, note_id: String) -> Result<(), StemError> {
+    db.inner().clone().spawn(move |db| {
+        enable(&*db.try_connection()?, &note_id)?;
+        Ok(())
+    }).await
+}
+
+/// Drops the history; the Markdown snapshot stays as it is.
+#[tauri::command]
+pub async fn disable_note_crdt(db: State<'_, Database>, note_id: String) -> Result<(), StemError> {
+    db.inner().clone().spawn(move |db| {
+        db.try_connection()?.execute("DELETE FROM note_crdt WHERE note_id = ?1", [&note_id])?;
+        Ok(())
+    }).await
+}
+
+/// Hashes of the latest changes, for a peer to ask only for what it lacks.
+#[tauri::command]
+pub async fn get_note_heads(db: State<'_, Database>, note_id: String) -> Result<Vec<String>, StemError> {
+    db.inner().clone().spawn(move |db| heads(&*db.try_connection()?, &note_id)).await
+}
+
+/// Binary Automerge updates of a note: those a peer at `since` lacks, or the whole document.
+#[tauri::command]
+pub async fn export_note_updates(
+    db: State<'_, Database>,
+    note_id: String,
+    since: Option<Vec<String>>,
+) -> Result<Vec<u8>, StemError> {
+    db.inner().clone().spawn(move |db| export_updates(&*db.try_connection()?, &note_id, since.as_deref())).await
+}
+
+/// Applies updates exported by another device; enables the CRDT on the note
+/// if needed. Returns the merged note.
+#[tauri::command]
+pub async fn apply_note_updates(db: State<'_, Database>, note_id: String, updates: Vec<u8>) -> Result<Note, StemError> {
+    db.inner().clone().spawn(move |db| apply_updates(&db, &note_id, &updates)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support;
+
+    fn device(content: &str) -> Database {
+        let db = test_support::device();
+        db.connection()
+            .execute("INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('n1', 'Plan', ?1, 1, 1)", [content])
+            .unwrap();
+        db
+    }
+
+    fn content(db: &Database) -> String {
+        test_support::content(db, "n1").unwrap()
+    }
+
+    /// What `update_note` does with new content.
+    fn save(db: &Database, content: &str) {
+        let conn = db.connection();
+        record_edit(&conn, "n1", content).unwrap();
+        conn.execute("UPDATE notes SET content = ?1 WHERE id = 'n1'", [content]).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_edits_converge() {
+        let base = "# Plan\n\nLundi : courses\nMardi : sport\n";
+        let (laptop, desktop) = (device(base), device(base));
+        enable(&laptop.connection(), "n1").unwrap();
+        enable(&desktop.connection(), "n1").unwrap();
+
+        save(&laptop, "# Plan\n\nLundi : courses et pharmacie\nMardi : sport\n");
+        save(&desktop, "# Plan\n\nLundi : courses\nMardi : sport\nMercredi : cinéma\n");
+        // An edit that bypassed update_note, e.g. an import
+        desktop.connection().execute("UPDATE notes SET content = replace(content, 'sport', 'piscine')", []).unwrap();
+
+        let desktop_heads = heads(&desktop.connection(), "n1").unwrap();
+        assert_eq!(heads(&desktop.connection(), "n1").unwrap(), desktop_heads, "the folded edit is kept");
+        let to_desktop = export_updates(&laptop.connection(), "n1", Some(&desktop_heads)).unwrap();
+        let to_laptop = export_updates(&desktop.connection(), "n1", None).unwrap();
+        assert!(to_desktop.len() < to_laptop.len());
+
+        apply_updates(&laptop, "n1", &to_laptop).unwrap();
+        let merged = apply_updates(&desktop, "n1", &to_desktop).unwrap();
+        let expected = "# Plan\n\nLundi : courses et pharmacie\nMardi : piscine\nMercredi : cinéma\n";
+        assert_eq!(merged.content.as_deref(), Some(expected));
+        assert_eq!(content(&laptop), expected);
+
+        // Applying the same updates again changes nothing
+        apply_updates(&laptop, "n1", &to_laptop).unwrap();
+        assert_eq!(content(&laptop), expected);
+        assert_eq!(heads(&laptop.connection(), "n1").unwrap(), heads(&desktop.connection(), "n1").unwrap());
+    }
+
+    #[test]
+    fn test_apply_enables_and_encrypted_notes_refused() {
+        let (laptop, desktop) = (device("Texte"), device("Texte"));
+        enable(&laptop.connection(), "n1").unwrap();
+        save(&laptop, "Texte modifié");
+        let updates = export_updates(&laptop.connection(), "n1", None).unwrap();
+        assert!(export_updates(&desktop.connection(), "n1", None).is_err());
+        // Same starting text, so the first changes match and nothing is duplicated
+        assert_eq!(apply_updates(&desktop, "n1", &updates).unwrap().content.as_deref(), Some("Texte modifié"));
+
+        desktop.connection().execute("UPDATE notes SET is_encrypted = 1 WHERE id = 'n1'", []).unwrap();
+        assert!(matches!(apply_updates(&desktop, "n1", &updates), Err(StemError::Validation(_))));
+    }
+}
@@ -0,0 +1,482 @@
+//! Git sync backend, implemented with gix (pure Rust, no `git` binary).
+//!
+//! The vault snapshot is committed to a repository in the app data dir whose
+//! working tree holds the `.md` files. A sync commits local changes, fetches
+//! the configured remote, fast-forwards or merges (see `merge_states`),
+//! applies the result to the database and pushes it back.
+//!
+//! gix can fetch over the network but not push, so remotes are repositories
+//! reachable through the file system (a path or `file://` URL, e.g. a bare
+//! repository on a NAS or a synced drive); objects are copied between the
+//! two object databases directly.
+
+use super::conflicts::record_conflicts;
+use super::merge::merge_states;
+use super::{apply_state, describe_changes, get_setting, set_setting, ApplyReport, SnapshotFiles, VaultState};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use crate::vault::{prune_empty_dirs, write_if_changed};
+use gix::bstr::ByteSlice;
+use gix::objs::tree::EntryKind;
+use gix::refs::transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog};
+use gix::refs::Target;
+use gix::{ObjectId, Repository};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+const SETTINGS_KEY: &str = "git";
+const REMOTE_NAME: &str = "origin";
+/// Time between two scheduled syncs.
+const SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Emitted after each scheduled sync, with a `ScheduledGitSync`.
+pub const GIT_SYNC_EVENT: &str = "git-sync";
+
+fn git_error(e: impl std::fmt::Display) -> StemError {
+    StemError::Git(e.to_string())
+}
+
+fn default_branch() -> String {
+    "main".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitSyncConfig {
+    /// Path or `file://` URL of the remote repository.
+    pub remote: String,
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    #[serde(default)]
+    pub author_name: Option<String>,
+    #[serde(default)]
+    pub author_email: Option<String>,
+}
+
+impl GitSyncConfig {
+    fn remote_path(&self) -> Result<PathBuf, StemError> {
+        let path = PathBuf::from(self.remote.strip_prefix("file://").unwrap_or(&self.remote));
+        if !path.is_absolute() {
+            return Err(StemError::Validation(
+                "Le dépôt distant doit être un chemin absolu ou une URL file:// (les remotes réseau ne sont pas pris en charge)".to_string(),
+            ));
+        }
+        Ok(path)
+    }
+
+    fn branch_ref(&self) -> String {
+        format!("refs/heads/{}", self.branch)
+    }
+
+    fn signature(&self) -> gix::actor::Signature {
+        gix::actor::Signature {
+            name: self.author_name.as_deref().unwrap_or("Stem").into(),
+            email: self.author_email.as_deref().unwrap_or("stem@localhost").into(),
+            time: gix::date::Time::new(current_timestamp(), 0),
+        }
+    }
+
+    fn validate(&self) -> Result<(), StemError> {
+        self.remote_path()?;
+        gix::refs::FullName::try_from(self.branch_ref())
+            .map_err(|_| StemError::Validation(format!("Nom de branche invalide : {}", self.branch)))?;
+        Ok(())
+    }
+}
+
+pub(crate) fn load_config(db: &Database) -> Result<Option<GitSyncConfig>, StemError> {
+    let conn = db.try_connection()?;
+    get_setting(&conn, SETTINGS_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| StemError::Validation(e.to_string())))
+        .transpose()
+}
+
+/// Local repository managed as Tauri state; the lock keeps syncs sequential.
+#[derive(Debug, Clone)]
+pub struct GitSync {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl GitSync {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Arc::new(Mutex::new(())) }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GitSyncReport {
+    /// Commit the branch points to after syncing.
+    pub head: Option<String>,
+    /// Local changes were committed.
+    pub committed: bool,
+    /// Diverging histories were merged.
+    pub merged: bool,
+    pub pushed: bool,
+    /// Titles of the notes left with conflicts (see `list_sync_conflicts`).
+    pub conflicts: Vec<String>,
+    /// Remote changes written to the database.
+    pub applied: ApplyReport,
+}
+
+// ===== Repository helpers =====
+
+fn open_or_init(dir: &Path, config: &GitSyncConfig) -> Result<Repository, StemError> {
+    if dir.join(".git").exists() {
+        return gix::open(dir).map_err(git_error);
+    }
+    fs::create_dir_all(dir)?;
+    let repo = gix::init(dir).map_err(git_error)?;
+    let head = RefEdit {
+        change: Change::Update {
+            log: LogChange { mode: RefLog::Only, force_create_reflog: false, message: "init".into() },
+            expected: PreviousValue::Any,
+            new: Target::Symbolic(config.branch_ref().try_into().map_err(git_error)?),
+        },
+        name: "HEAD".try_into().map_err(git_error)?,
+        deref: false,
+    };
+    repo.edit_references_as([head], None).map_err(git_error)?;
+    Ok(repo)
+}
+
+fn open_remote(config: &GitSyncConfig) -> Result<Repository, StemError> {
+    let path = config.remote_path()?;
+    gix::open(&path).map_err(|_| StemError::NotFound(format!("Dépôt Git distant {}", path.display())))
+}
+
+fn find_tip(repo: &Repository, name: &str) -> Result<Option<ObjectId>, StemError> {
+    let reference = repo.try_find_reference(name).map_err(git_error)?;
+    Ok(reference.and_then(|r| r.target().try_id().map(ToOwned::to_owned)))
+}
+
+fn set_ref(repo: &Repository, name: &str, id: ObjectId, expected: PreviousValue, config: &GitSyncConfig, message: &str) -> Result<(), StemError> {
+    let edit = RefEdit {
+        change: Change::Update {
+            log: LogChange { mode: RefLog::AndReference, force_create_reflog: false, message: message.into() },
+            expected,
+            new: Target::Object(id),
+        },
+        name: name.try_into().map_err(git_error)?,
+        deref: false,
+    };
+    let signature = config.signature();
+    let mut time = gix::date::parse::TimeBuf::default();
+    repo.edit_references_as([edit], Some(signature.to_ref(&mut time))).map_err(git_error)?;
+    Ok(())
+}
+
+fn expect(tip: Option<ObjectId>) -> PreviousValue {
+    match tip {
+        Some(id) => PreviousValue::MustExistAndMatch(Target::Object(id)),
+        None => PreviousValue::MustNotExist,
+    }
+}
+
+/// Paths from another repository end up on disk: refuse anything that could escape the working tree.
+fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.starts_with('/')
+        && path.split('/').all(|c| !c.is_empty() && c != "." && c != ".." && !c.eq_ignore_ascii_case(".git") && !c.contains('\\'))
+}
+
+fn read_files(repo: &Repository, commit: ObjectId) -> Result<SnapshotFiles, StemError> {
+    let tree = repo.find_commit(commit).map_err(git_error)?.tree().map_err(git_error)?;
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    tree.traverse().breadthfirst(&mut recorder).map_err(git_error)?;
+    let mut files = SnapshotFiles::new();
+    for entry in recorder.records {
+        if !entry.mode.is_blob() {
+            continue;
+        }
+        let Ok(path) = entry.filepath.to_str() else { continue };
+        if is_safe_path(path) {
+            let blob = repo.find_object(entry.oid).map_err(git_error)?;
+            files.insert(path.to_string(), blob.data.to_vec());
+        }
+    }
+    Ok(files)
+}
+
+fn write_commit(repo: &Repository, files: &SnapshotFiles, parents: &[ObjectId], message: &str, config: &GitSyncConfig) -> Result<ObjectId, StemError> {
+    let mut editor = repo.edit_tree(ObjectId::empty_tree(repo.object_hash())).map_err(git_error)?;
+    for (path, bytes) in files {
+        let blob = repo.write_blob(bytes).map_err(git_error)?;
+        editor.upsert(path.as_str(), EntryKind::Blob, blob).map_err(git_error)?;
+    }
+    let tree = editor.write().map_err(git_error)?.detach();
+    let signature = config.signature();
+    let commit = gix::objs::Commit {
+        tree,
+        parents: parents.iter().copied().collect(),
+        author: signature.clone(),
+        committer: signature,
+        encoding: None,
+        message: message.into(),
+        extra_headers: Vec::new(),
+    };
+    Ok(repo.write_object(commit).map_err(git_error)?.detach())
+}
+
+fn copy_object(src: &Repository, dst: &Repository, id: ObjectId) -> Result<gix::ObjectDetached, StemError> {
+    let object = src.find_object(id).map_err(git_error)?.detach();
+    gix::objs::Write::write_buf(&dst.objects, object.kind, &object.data).map_err(git_error)?;
+    Ok(object)
+}
+
+fn copy_tree(src: &Repository, dst: &Repository, id: ObjectId) -> Result<(), StemError> {
+    // A tree already present is complete, along with everything below it
+    if dst.has_object(id) {
+        return Ok(());
+    }
+    let tree = copy_object(src, dst, id)?;
+    for entry in gix::objs::TreeRefIter::from_bytes(&tree.data) {
+        let entry = entry.map_err(git_error)?;
+        if entry.mode.is_tree() {
+            copy_tree(src, dst, entry.oid.to_owned())?;
+        } else if !entry.mode.is_commit() && !dst.has_object(entry.oid) {
+            copy_object(src, dst, entry.oid.to_owned())?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the history of `tip` that `dst` lacks, i.e. a fetch or a push.
+fn copy_history(src: &Repository, dst: &Repository, tip: ObjectId) -> Result<(), StemError> {
+    let mut pending = vec![tip];
+    while let Some(id) = pending.pop() {
+        if dst.has_object(id) {
+            continue;
+        }
+        let commit = src.find_commit(id).map_err(git_error)?;
+        copy_tree(src, dst, commit.tree_id().map_err(git_error)?.detach())?;
+        pending.extend(commit.parent_ids().map(|p| p.detach()));
+        copy_object(src, dst, id)?;
+    }
+    Ok(())
+}
+
+fn is_ancestor(repo: &Repository, ancestor: ObjectId, of: ObjectId) -> bool {
+    repo.merge_base(ancestor, of).is_ok_and(|base| base.detach() == ancestor)
+}
+
+/// Mirrors the committed snapshot into the working tree.
+fn checkout(dir: &Path, previous: &SnapshotFiles, files: &SnapshotFiles) -> Result<(), StemError> {
+    for (path, bytes) in files {
+        write_if_changed(&dir.join(path), bytes)?;
+    }
+    for path in previous.keys().filter(|p| !files.contains_key(*p)) {
+        let path = dir.join(path);
+        let _ = fs::remove_file(&path);
+        prune_empty_dirs(dir, path.parent());
+    }
+    Ok(())
+}
+
+// ===== Sync =====
+
+pub(crate) fn sync_with_git(db: &Database, git: &GitSync, config: &GitSyncConfig) -> Result<GitSyncReport, StemError> {
+    let _guard = git.lock.lock().map_err(|_| StemError::Git("Synchronisation interrompue".to_string()))?;
+    let repo = open_or_init(&git.dir, config)?;
+    let remote = open_remote(config)?;
+    let branch = config.branch_ref();
+    let mut report = GitSyncReport::default();
+
+    // Fetch
+    let head = find_tip(&repo, &branch)?;
+    let remote_tip = find_tip(&remote, &branch)?;
+    if let Some(tip) = remote_tip {
+        copy_history(&remote, &repo, tip)?;
+        let tracking = format!("refs/remotes/{}/{}", REMOTE_NAME, config.branch);
+        set_ref(&repo, &tracking, tip, PreviousValue::Any, config, "fetch")?;
+    }
+
+    // Commit what changed in the app since the last sync
+    let head_files = head.map(|id| read_files(&repo, id)).transpose()?.unwrap_or_default();
+    let current = VaultState::load(db)?;
+    let local_files = current.to_files();
+    let mut tip = head;
+    if local_files != head_files {
+        let message = describe_changes(&VaultState::from_files(&head_files)?, &current);
+        tip = Some(write_commit(&repo, &local_files, head.as_slice(), &message, config)?);
+        report.committed = true;
+    }
+
+    let mut conflicts = Vec::new();
+    let target = match (tip, remote_tip) {
+        (None, None) => return Ok(report),
+        (Some(local), None) => local,
+        (None, Some(remote)) => remote,
+        (Some(local), Some(remote)) if local == remote || is_ancestor(&repo, remote, local) => local,
+        (Some(local), Some(remote)) if is_ancestor(&repo, local, remote) => remote,
+        (Some(local), Some(remote_id)) => {
+            let base = match repo.merge_base(local, remote_id) {
+                Ok(base) => read_files(&repo, base.detach())?,
+                Err(_) => SnapshotFiles::new(),
+            };
+            let merged = merge_states(
+                &VaultState::from_files(&base)?,
+                &VaultState::from_files(&read_files(&repo, local)?)?,
+                &VaultState::from_files(&read_files(&repo, remote_id)?)?,
+            );
+            let message = match merged.conflicts.len() {
+                0 => format!("Fusionne {}/{}", REMOTE_NAME, config.branch),
+                n => format!("Fusionne {}/{} ({} conflits)", REMOTE_NAME, config.branch, n),
+            };
+            report.merged = true;
+            report.conflicts = merged.conflicts.iter().map(|c| c.title.clone()).collect();
+            conflicts = merged.conflicts;
+            write_commit(&repo, &merged.state.to_files(), &[local, remote_id], &message, config)?
+        }
+    };
+
+    let target_files = read_files(&repo, target)?;
+    if target_files != local_files {
+        report.applied = apply_state(db, &current, &VaultState::from_files(&target_files)?)?;
+    }
+    record_conflicts(db, &conflicts)?;
+    if head != Some(target) {
+        set_ref(&repo, &branch, target, expect(head), config, "sync")?;
+    }
+    checkout(&git.dir, &head_files, &target_files)?;
+
+    // Push
+    if remote_tip != Some(target) {
+        copy_history(&repo, &remote, target)?;
+        set_ref(&remote, &branch, target, expect(remote_tip), config, "push").map_err(|_| {
+            StemError::Git("Le dépôt distant a changé pendant la synchronisation, relancez-la".to_string())
+        })?;
+        let tracking = format!("refs/remotes/{}/{}", REMOTE_NAME, config.branch);
+        set_ref(&repo, &tracking, target, PreviousValue::Any, config, "push")?;
+        report.pushed = true;
+    }
+    report.head = Some(target.to_string());
+    Ok(report)
+}
+
+// ===== Scheduler =====
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledGitSync {
+    pub report: Option<GitSyncReport>,
+    pub error: Option<String>,
+}
+
+/// Background loop started at launch: pulls right away, then commits, pulls
+/// and pushes every five minutes while a remote is configured. Emits
+/// `refresh-notes` when remote changes were written to the database.
+pub fn run_git_scheduler(app: &AppHandle) {
+    loop {
+        let db = app.state::<Database>().inner().clone();
+        let git = app.state::<GitSync>().inner().clone();
+        if let Ok(Some(config)) = load_config(&db) {
+            let event = match sync_with_git(&db, &git, &config) {
+                Ok(report) => {
+                    if !report.applied.is_empty() {
+                        let _ = app.emit("refresh-notes", ());
+                    }
+                    ScheduledGitSync { report: Some(report), error: None }
+                }
+                Err(e) => ScheduledGitSync { report: None, error: Some(e.to_string()) },
+            };
+            let _ = app.emit(GIT_SYNC_EVENT, event);
+        }
+        std::thread::sleep(SYNC_INTERVAL);
+    }
+}
+
+// ===== Tauri Commands =====
+
+#[tauri::command]
+pub async fn get_git_sync_config(db: State<'_, Database>) -> Result<Option<GitSyncConfig>, StemError> {
+    db.inner().clone().spawn(move |db| load_config(&db)).await
+}
+
+/// Sets the remote to sync with. The remote must be an existing repository.
+#[tauri::command]
+pub async fn set_git_sync_config(db: State<'_, Database>, config: GitSyncConfig) -> Result<(), StemError> {
+    config.validate()?;
+    open_remote(&config)?;
+    db.inner().clone().spawn(move |db| {
+        let json = serde_json::to_string(&config).map_err(|e| StemError::Validation(e.to_string()))?;
+        set_setting(&*db.try_connection()?, SETTINGS_KEY, &json)
+    }).await
+}
+
+/// Commits local changes, pulls, merges and pushes. Emits `refresh-notes`
+/// when remote changes were written to the database.
+#[tauri::command]
+pub async fn git_sync(app: AppHandle, db: State<'_, Database>, git: State<'_, GitSync>) -> Result<GitSyncReport, StemError> {
+    let git = git.inner().clone();
+    let report = db.inner().clone().spawn(move |db| {
+        let config = load_config(&db)?
+            .ok_or_else(|| StemError::Validation("Aucun dépôt Git configuré".to_string()))?;
+        sync_with_git(&db, &git, &config)
+    }).await?;
+    if !report.applied.is_empty() {
+        let _ = app.emit("refresh-notes", ());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support::{self, content};
+    use uuid::Uuid;
+
+    fn device(root: &Path, name: &str) -> (Database, GitSync) {
+        (test_support::device(), GitSync::new(root.join(name)))
+    }
+
+    fn insert_note(db: &Database, id: &str, title: &str, content: &str) {
+        db.connection().execute(
+            "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES (?1, ?2, ?3, 1700000000, 1700000000)",
+            [id, title, content],
+        ).unwrap();
+    }
+
+    #[test]
+    fn test_two_devices_converge_through_bare_remote() {
+        let root = std::env::temp_dir().join(format!("stem-git-{}", Uuid::new_v4()));
+        fs::create_dir_all(root.join("remote.git")).unwrap();
+        gix::init_bare(root.join("remote.git")).unwrap();
+        let config = GitSyncConfig {
+            remote: format!("file://{}", root.join("remote.git").display()),
+            branch: "main".into(),
+            author_name: None,
+            author_email: None,
+        };
+        let (db_a, git_a) = device(&root, "a");
+        let (db_b, git_b) = device(&root, "b");
+
+        insert_note(&db_a, "n1", "Courses", "Pain\n");
+        insert_note(&db_a, "n2", "Idées", "Une idée\n");
+        let report = sync_with_git(&db_a, &git_a, &config).unwrap();
+        assert!(report.committed && report.pushed);
+        assert!(root.join("a/Courses.md").exists());
+
+        let report = sync_with_git(&db_b, &git_b, &config).unwrap();
+        assert_eq!(report.applied.notes_added, 2);
+        assert_eq!(content(&db_b, "n1").as_deref(), Some("Pain\n"));
+
+        // Concurrent edits to different notes, plus a deletion
+        db_a.connection().execute("UPDATE notes SET content = 'Pain\nLait\n', updated_at = 1700000100 WHERE id = 'n1'", []).unwrap();
+        db_b.connection().execute("DELETE FROM notes WHERE id = 'n2'", []).unwrap();
+        insert_note(&db_b, "n3", "Nouvelle", "Depuis B\n");
+        sync_with_git(&db_a, &git_a, &config).unwrap();
+        let report = sync_with_git(&db_b, &git_b, &config).unwrap();
+        assert!(report.merged && report.conflicts.is_empty());
+        assert_eq!(content(&db_b, "n1").as_deref(), Some("Pain\nLait\n"));
+
+        let report = sync_with_git(&db_a, &git_a, &config).unwrap();
+        assert!(!report.committed && !report.pushed);
+        assert_eq!(content(&db_a, "n2"), None);
+        assert_eq!(content(&db_a, "n3").as_deref(), Some("Depuis B\n"));
+        assert!(!root.join("a/Idées.md").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
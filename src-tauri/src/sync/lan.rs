@@ -0,0 +1,767 @@
+//! LAN sync between two Stem instances, without any cloud service. One of
+//! them runs a small TCP server; the other pairs with it once using the code
+//! it shows, then syncs whenever asked.
+//!
+//! Connections are encrypted and authenticated with Noise
+//! (`NNpsk0`): while pairing, the pre-shared key comes from the pairing code;
+//! afterwards from a random secret both devices stored when pairing.
+//!
+//! Every note and folder write is numbered in `change_log`, so a sync only
+//! exchanges what changed since the sequences recorded at the end of the last
+//! one. The connecting device pulls first, merges with the version both
+//! devices had at the last sync (`lan_base`) and records conflicts like the
+//! other backends, then pushes its own changes, which the server applies as is.
+//! Deletions travel as changes without text.
+
+use super::conflicts::record_conflicts;
+use super::merge::merge_states;
+use super::{apply_state, device_id, max_seq, parse_sync_note, ApplyReport, VaultState};
+use crate::commands::{row_to_folder, row_to_note, Folder, FOLDER_COLUMNS, NOTE_COLUMNS};
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snow::{Builder, HandshakeState, TransportState};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+
+pub const DEFAULT_PORT: u16 = 47_650;
+const PROTOCOL_VERSION: u32 = 1;
+const NOISE_PARAMS: &str = "Noise_NNpsk0_25519_ChaChaPoly_SHA256";
+const MAX_FRAME: usize = 65_535;
+const TAG_LEN: usize = 16;
+/// Largest message accepted from a peer.
+const MAX_MESSAGE: usize = 256 * 1024 * 1024;
+const PAIRING_TTL_SECS: i64 = 10 * 60;
+/// 32 symbols without look-alikes (no I, O, 0, 1): 16 of them make 80 bits.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 16;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn lan_error(message: impl Into<String>) -> StemError {
+    StemError::Sync(message.into())
+}
+
+fn noise_error(e: snow::Error) -> StemError {
+    lan_error(format!("Connexion refusée : code d'appairage incorrect ou appareil inconnu ({})", e))
+}
+
+fn unexpected() -> StemError {
+    lan_error("Réponse inattendue de l'autre appareil")
+}
+
+fn device_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "Stem".to_string())
+}
+
+/// Pre-shared key of a pairing code, ignoring case, spaces and dashes.
+fn code_psk(code: &str) -> [u8; 32] {
+    let normalized: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect();
+    Sha256::digest(format!("stem-lan-pairing:{}", normalized).as_bytes()).into()
+}
+
+fn generate_code() -> String {
+    let mut bytes = [0u8; CODE_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    let symbols: Vec<char> = bytes.iter().map(|b| CODE_ALPHABET[*b as usize % CODE_ALPHABET.len()] as char).collect();
+    symbols.chunks(4).map(|group| group.iter().collect::<String>()).collect::<Vec<_>>().join("-")
+}
+
+// ===== Wire format =====
+
+/// First frame of a connection, in clear: it tells the server which key to
+/// expect, and is bound to the handshake as its prologue.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    version: u32,
+    device_id: String,
+    pairing: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Pair { name: String, secret: String, port: Option<u16> },
+    Paired { device_id: String, name: String },
+    Pull { since: i64 },
+    /// Changes up to `seq`, the sender's latest sequence.
+    Changes { changes: Vec<Change>, seq: i64 },
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Note,
+    Folder,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Note => "note",
+            Kind::Folder => "folder",
+        }
+    }
+}
+
+/// Current version of a changed item: the sync text of a note, the JSON of a
+/// folder, or `None` once deleted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Change {
+    kind: Kind,
+    id: String,
+    text: Option<String>,
+}
+
+fn write_frame(stream: &mut TcpStream, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&(bytes.len() as u16).to_be_bytes())?;
+    stream.write_all(bytes)
+}
+
+fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut frame = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Encrypted, message-oriented connection. A message is its length then its
+/// JSON, split over as many Noise frames as needed.
+struct Channel {
+    stream: TcpStream,
+    noise: TransportState,
+}
+
+impl Channel {
+    fn handshake_state(psk: &[u8; 32], prologue: &[u8], initiator: bool) -> Result<HandshakeState, StemError> {
+        let params = NOISE_PARAMS.parse().map_err(noise_error)?;
+        let builder = Builder::new(params).psk(0, psk).prologue(prologue);
+        if initiator { builder.build_initiator() } else { builder.build_responder() }.map_err(noise_error)
+    }
+
+    fn connect(mut stream: TcpStream, psk: &[u8; 32], hello: &Hello) -> Result<Self, StemError> {
+        let hello = serde_json::to_vec(hello).map_err(|e| StemError::Validation(e.to_string()))?;
+        write_frame(&mut stream, &hello)?;
+        let mut handshake = Self::handshake_state(psk, &hello, true)?;
+        let mut buf = vec![0u8; MAX_FRAME];
+        let len = handshake.write_message(&[], &mut buf).map_err(noise_error)?;
+        write_frame(&mut stream, &buf[..len])?;
+        let reply = read_frame(&mut stream).map_err(|_| noise_error(snow::Error::Decrypt))?;
+        handshake.read_message(&reply, &mut buf).map_err(noise_error)?;
+        Ok(Self { stream, noise: handshake.into_transport_mode().map_err(noise_error)? })
+    }
+
+    fn accept(mut stream: TcpStream, psk: &[u8; 32], hello: &[u8]) -> Result<Self, StemError> {
+        let mut handshake = Self::handshake_state(psk, hello, false)?;
+        let mut buf = vec![0u8; MAX_FRAME];
+        handshake.read_message(&read_frame(&mut stream)?, &mut buf).map_err(noise_error)?;
+        let len = handshake.write_message(&[], &mut buf).map_err(noise_error)?;
+        write_frame(&mut stream, &buf[..len])?;
+        Ok(Self { stream, noise: handshake.into_transport_mode().map_err(noise_error)? })
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), StemError> {
+        let json = serde_json::to_vec(message).map_err(|e| StemError::Validation(e.to_string()))?;
+        let mut plain = (json.len() as u32).to_be_bytes().to_vec();
+        plain.extend_from_slice(&json);
+        let mut buf = vec![0u8; MAX_FRAME];
+        for chunk in plain.chunks(MAX_FRAME - TAG_LEN) {
+            let len = self.noise.write_message(chunk, &mut buf).map_err(noise_error)?;
+            write_frame(&mut self.stream, &buf[..len])?;
+        }
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> Result<Vec<u8>, StemError> {
+        let frame = read_frame(&mut self.stream)?;
+        let mut buf = vec![0u8; frame.len()];
+        let len = self.noise.read_message(&frame, &mut buf).map_err(|_| lan_error("Message altéré ou illisible"))?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn recv<T: DeserializeOwned>(&mut self) -> Result<T, StemError> {
+        let mut plain = self.recv_frame()?;
+        if plain.len() < 4 {
+            return Err(unexpected());
+        }
+        let len = u32::from_be_bytes([plain[0], plain[1], plain[2], plain[3]]) as usize;
+        if len > MAX_MESSAGE {
+            return Err(lan_error("Message trop volumineux"));
+        }
+        plain.drain(..4);
+        while plain.len() < len {
+            plain.extend(self.recv_frame()?);
+        }
+        serde_json::from_slice(&plain[..len]).map_err(|_| unexpected())
+    }
+}
+
+// ===== Changes =====
+
+fn load_folders(conn: &Connection) -> Result<BTreeMap<String, Folder>, StemError> {
+    let mut stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders"))?;
+    let folders = stmt.query_map([], row_to_folder)?.collect::<Result<Vec<_>, _>>()?;
+    Ok(folders.into_iter().map(|f| (f.id.clone(), f)).collect())
+}
+
+/// Every folder, and the notes among `changes`: the part of the vault a sync touches.
+fn load_state<'a>(conn: &Connection, changes: impl Iterator<Item = &'a Change>) -> Result<VaultState, StemError> {
+    let mut state = VaultState { notes: BTreeMap::new(), folders: load_folders(conn)? };
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"))?;
+    for change in changes.filter(|c| c.kind == Kind::Note) {
+        if let Some(note) = stmt.query_row([&change.id], row_to_note).optional()? {
+            state.notes.insert(note.id.clone(), note);
+        }
+    }
+    Ok(state)
+}
+
+fn text_of(state: &VaultState, kind: Kind, id: &str) -> Option<String> {
+    match kind {
+        Kind::Note => state.note_text(id),
+        Kind::Folder => state.folder_text(id),
+    }
+}
+
+/// Replaces the items of `state` named by `changes` with the versions they carry.
+fn overlay<'a>(state: &mut VaultState, changes: impl Iterator<Item = (Kind, &'a str, Option<&'a str>)>) -> Result<(), StemError> {
+    let invalid = |id: &str| lan_error(format!("Élément {} illisible", id));
+    for (kind, id, text) in changes {
+        match kind {
+            Kind::Note => {
+                state.notes.remove(id);
+                if let Some(text) = text {
+                    let note = parse_sync_note(text).filter(|n| n.id == id).ok_or_else(|| invalid(id))?;
+                    state.notes.insert(id.to_string(), note);
+                }
+            }
+            Kind::Folder => {
+                state.folders.remove(id);
+                if let Some(text) = text {
+                    let folder: Folder = serde_json::from_str(text).map_err(|_| invalid(id))?;
+                    state.folders.insert(id.to_string(), folder);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Local changes after `since`, and the sequence they go up to.
+fn changes_since(db: &Database, since: i64) -> Result<(Vec<Change>, i64), StemError> {
+    let conn = db.try_connection()?;
+    let seq = max_seq(&conn)?;
+    let mut stmt = conn.prepare("SELECT kind, item_id FROM change_log WHERE seq > ?1 AND seq <= ?2 ORDER BY seq")?;
+    let mut changes = stmt
+        .query_map([since, seq], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .filter_map(|row| row.ok())
+        .map(|(kind, id)| Change { kind: if kind == "folder" { Kind::Folder } else { Kind::Note }, id, text: None })
+        .collect::<Vec<_>>();
+    let state = load_state(&conn, changes.iter())?;
+    for change in &mut changes {
+        change.text = text_of(&state, change.kind, &change.id);
+    }
+    Ok((changes, seq))
+}
+
+fn load_bases(conn: &Connection, peer_id: &str, changes: &[Change]) -> Result<HashMap<(Kind, String), String>, StemError> {
+    let mut stmt = conn.prepare("SELECT text FROM lan_base WHERE peer_id = ?1 AND kind = ?2 AND item_id = ?3")?;
+    let mut bases = HashMap::new();
+    for change in changes {
+        if let Some(text) = stmt.query_row(params![peer_id, change.kind.as_str(), change.id], |row| row.get(0)).optional()? {
+            bases.insert((change.kind, change.id.clone()), text);
+        }
+    }
+    Ok(bases)
+}
+
+/// Records what both devices hold once the sync is over; later changes win.
+fn save_bases<'a>(conn: &Connection, peer_id: &str, changes: impl Iterator<Item = &'a Change>) -> Result<(), StemError> {
+    for change in changes {
+        match &change.text {
+            Some(text) => conn.execute(
+                "INSERT INTO lan_base (peer_id, kind, item_id, text) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(peer_id, kind, item_id) DO UPDATE SET text = excluded.text",
+                params![peer_id, change.kind.as_str(), change.id, text],
+            )?,
+            None => conn.execute(
+                "DELETE FROM lan_base WHERE peer_id = ?1 AND kind = ?2 AND item_id = ?3",
+                params![peer_id, change.kind.as_str(), change.id],
+            )?,
+        };
+    }
+    Ok(())
+}
+
+/// Merges the changes pulled from a peer into the database.
+fn merge_incoming(db: &Database, peer_id: &str, incoming: &[Change]) -> Result<(ApplyReport, Vec<String>), StemError> {
+    let (local, bases) = {
+        let conn = db.try_connection()?;
+        (load_state(&conn, incoming.iter())?, load_bases(&conn, peer_id, incoming)?)
+    };
+    let mut base = VaultState { notes: BTreeMap::new(), folders: local.folders.clone() };
+    overlay(
+        &mut base,
+        incoming.iter().map(|c| (c.kind, c.id.as_str(), bases.get(&(c.kind, c.id.clone())).map(String::as_str))),
+    )?;
+    let mut remote = VaultState { notes: BTreeMap::new(), folders: local.folders.clone() };
+    overlay(&mut remote, incoming.iter().map(|c| (c.kind, c.id.as_str(), c.text.as_deref())))?;
+
+    let merged = merge_states(&base, &local, &remote);
+    let applied = apply_state(db, &local, &merged.state)?;
+    record_conflicts(db, &merged.conflicts)?;
+    Ok((applied, merged.conflicts.into_iter().map(|c| c.title).collect()))
+}
+
+/// Applies the changes a peer pushed after merging them with ours.
+fn apply_pushed(db: &Database, pushed: &[Change]) -> Result<ApplyReport, StemError> {
+    let current = load_state(&*db.try_connection()?, pushed.iter())?;
+    let mut target = current.clone();
+    overlay(&mut target, pushed.iter().map(|c| (c.kind, c.id.as_str(), c.text.as_deref())))?;
+    apply_state(db, &current, &target)
+}
+
+// ===== Peers =====
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanPeer {
+    pub device_id: String,
+    pub name: String,
+    /// `host:port` of its server, when known.
+    pub address: Option<String>,
+    pub paired_at: i64,
+    pub last_sync_at: Option<i64>,
+}
+
+struct PeerState {
+    secret: Vec<u8>,
+    address: Option<String>,
+    local_seq: i64,
+    remote_seq: i64,
+}
+
+fn load_peer(conn: &Connection, device_id: &str) -> Result<Option<PeerState>, StemError> {
+    Ok(conn
+        .query_row(
+            "SELECT secret, address, local_seq, remote_seq FROM lan_peers WHERE device_id = ?1",
+            [device_id],
+            |row| Ok(PeerState { secret: row.get(0)?, address: row.get(1)?, local_seq: row.get(2)?, remote_seq: row.get(3)? }),
+        )
+        .optional()?)
+}
+
+fn peer_psk(peer: &PeerState) -> Result<[u8; 32], StemError> {
+    peer.secret.as_slice().try_into().map_err(|_| lan_error("Clé d'appairage invalide"))
+}
+
+/// Pairing again replaces the secret and starts over from the first change.
+fn save_peer(conn: &Connection, device_id: &str, name: &str, address: Option<&str>, secret: &[u8]) -> Result<(), StemError> {
+    conn.execute("DELETE FROM lan_peers WHERE device_id = ?1", [device_id])?;
+    conn.execute(
+        "INSERT INTO lan_peers (device_id, name, address, secret, paired_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![device_id, name, address, secret, current_timestamp()],
+    )?;
+    Ok(())
+}
+
+fn record_sync(conn: &Connection, device_id: &str, local_seq: i64, remote_seq: i64) -> Result<(), StemError> {
+    conn.execute(
+        "UPDATE lan_peers SET local_seq = ?2, remote_seq = ?3, last_sync_at = ?4 WHERE device_id = ?1",
+        params![device_id, local_seq, remote_seq, current_timestamp()],
+    )?;
+    Ok(())
+}
+
+fn list_peers(conn: &Connection) -> Result<Vec<LanPeer>, StemError> {
+    let mut stmt = conn.prepare("SELECT device_id, name, address, paired_at, last_sync_at FROM lan_peers ORDER BY name")?;
+    let peers = stmt
+        .query_map([], |row| {
+            Ok(LanPeer {
+                device_id: row.get(0)?,
+                name: row.get(1)?,
+                address: row.get(2)?,
+                paired_at: row.get(3)?,
+                last_sync_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(peers)
+}
+
+fn with_default_port(address: &str) -> String {
+    if address.parse::<SocketAddr>().is_ok() || address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    }
+}
+
+fn open_stream(address: &str) -> Result<TcpStream, StemError> {
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| lan_error(format!("Adresse introuvable : {}", address)))?;
+    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(10))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok(stream)
+}
+
+// ===== Client =====
+
+/// Pairs with the server at `address` using the code it shows.
+fn pair(db: &Database, address: &str, code: &str, own_port: Option<u16>) -> Result<LanPeer, StemError> {
+    let address = with_default_port(address.trim());
+    let own_id = device_id(&*db.try_connection()?)?;
+    let hello = Hello { version: PROTOCOL_VERSION, device_id: own_id, pairing: true };
+    let mut channel = Channel::connect(open_stream(&address)?, &code_psk(code), &hello)?;
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    channel.send(&Message::Pair { name: device_name(), secret: STANDARD.encode(secret), port: own_port })?;
+    let Message::Paired { device_id, name } = channel.recv()? else { return Err(unexpected()) };
+    let conn = db.try_connection()?;
+    save_peer(&conn, &device_id, &name, Some(&address), &secret)?;
+    list_peers(&conn)?.into_iter().find(|p| p.device_id == device_id).ok_or_else(unexpected)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LanSyncReport {
+    pub received: usize,
+    pub sent: usize,
+    /// Titles of the notes left with conflicts (see `list_sync_conflicts`).
+    pub conflicts: Vec<String>,
+    /// Changes from the other device written to the database.
+    pub applied: ApplyReport,
+}
+
+/// Pulls, merges and pushes with a paired device.
+fn sync_with_peer(db: &Database, peer_id: &str) -> Result<LanSyncReport, StemError> {
+    let (peer, own_id) = {
+        let conn = db.try_connection()?;
+        let peer = load_peer(&conn, peer_id)?.ok_or_else(|| StemError::NotFound(format!("Appareil {}", peer_id)))?;
+        (peer, device_id(&conn)?)
+    };
+    let address = peer.address.as_deref().ok_or_else(|| lan_error("Adresse de l'appareil inconnue : appairez-le depuis cet appareil"))?;
+    let hello = Hello { version: PROTOCOL_VERSION, device_id: own_id, pairing: false };
+    let mut channel = Channel::connect(open_stream(address)?, &peer_psk(&peer)?, &hello)?;
+
+    channel.send(&Message::Pull { since: peer.remote_seq })?;
+    let Message::Changes { changes: pulled, seq: remote_seq } = channel.recv()? else { return Err(unexpected()) };
+    let (applied, conflicts) = merge_incoming(db, peer_id, &pulled)?;
+
+    let (mut pushed, local_seq) = changes_since(db, peer.local_seq)?;
+    // What was just pulled and applied unchanged needs no echo
+    pushed.retain(|change| !pulled.contains(change));
+    channel.send(&Message::Changes { changes: pushed.clone(), seq: local_seq })?;
+    let Message::Done = channel.recv()? else { return Err(unexpected()) };
+
+    let conn = db.try_connection()?;
+    save_bases(&conn, peer_id, pulled.iter().chain(pushed.iter()))?;
+    record_sync(&conn, peer_id, local_seq, remote_seq)?;
+    Ok(LanSyncReport { received: pulled.len(), sent: pushed.len(), conflicts, applied })
+}
+
+// ===== Server =====
+
+struct Pairing {
+    psk: [u8; 32],
+    expires_at: i64,
+}
+
+/// Handles one connection; `on_change` runs when the database was written.
+fn handle_connection(db: &Database, pairing: &Mutex<Option<Pairing>>, mut stream: TcpStream, on_change: &dyn Fn()) -> Result<(), StemError> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let hello_bytes = read_frame(&mut stream)?;
+    let hello: Hello = serde_json::from_slice(&hello_bytes).map_err(|_| unexpected())?;
+    if hello.version != PROTOCOL_VERSION {
+        return Err(lan_error("Version de protocole incompatible"));
+    }
+
+    if hello.pairing {
+        let psk = {
+            let pairing = pairing.lock().map_err(|_| unexpected())?;
+            pairing.as_ref().filter(|p| p.expires_at > current_timestamp()).map(|p| p.psk)
+        };
+        let psk = psk.ok_or_else(|| lan_error("Aucun appairage en cours"))?;
+        let peer_ip = stream.peer_addr()?.ip();
+        let mut channel = Channel::accept(stream, &psk, &hello_bytes)?;
+        let Message::Pair { name, secret, port } = channel.recv()? else { return Err(unexpected()) };
+        let secret = STANDARD.decode(&secret).ok().filter(|s| s.len() == 32).ok_or_else(unexpected)?;
+        let address = port.map(|port| SocketAddr::new(peer_ip, port).to_string());
+        let own_id = {
+            let conn = db.try_connection()?;
+            save_peer(&conn, &hello.device_id, &name, address.as_deref(), &secret)?;
+            device_id(&conn)?
+        };
+        // A code pairs a single device
+        *pairing.lock().map_err(|_| unexpected())? = None;
+        return channel.send(&Message::Paired { device_id: own_id, name: device_name() });
+    }
+
+    let peer = load_peer(&*db.try_connection()?, &hello.device_id)?.ok_or_else(|| lan_error("Appareil inconnu"))?;
+    let mut channel = Channel::accept(stream, &peer_psk(&peer)?, &hello_bytes)?;
+    let Message::Pull { since } = channel.recv()? else { return Err(unexpected()) };
+    let (changes, seq) = changes_since(db, since)?;
+    channel.send(&Message::Changes { changes: changes.clone(), seq })?;
+    let Message::Changes { changes: pushed, seq: peer_seq } = channel.recv()? else { return Err(unexpected()) };
+    let applied = apply_pushed(db, &pushed)?;
+    {
+        let conn = db.try_connection()?;
+        save_bases(&conn, &hello.device_id, changes.iter().chain(pushed.iter()))?;
+        record_sync(&conn, &hello.device_id, seq, peer_seq)?;
+    }
+    channel.send(&Message::Done)?;
+    if !applied.is_empty() {
+        on_change();
+    }
+    Ok(())
+}
+
+/// Accepts connections one at a time until `stop` is set.
+fn serve(listener: TcpListener, db: Database, pairing: Arc<Mutex<Option<Pairing>>>, stop: Arc<AtomicBool>, on_change: impl Fn()) {
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // A failed connection only concerns the device that made it
+                let _ = handle_connection(&db, &pairing, stream, &on_change);
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+struct ServerHandle {
+    port: u16,
+    stop: Arc<AtomicBool>,
+}
+
+/// The running server and pending pairing code, managed as Tauri state.
+#[derive(Clone, Default)]
+pub struct LanSync {
+    server: Arc<Mutex<Option<ServerHandle>>>,
+    pairing: Arc<Mutex<Option<Pairing>>>,
+}
+
+impl LanSync {
+    fn start(&self, db: Database, port: u16, on_change: impl Fn() + Send + 'static) -> Result<u16, StemError> {
+        let mut server = self.server.lock().map_err(|_| unexpected())?;
+        if let Some(running) = server.as_ref() {
+            return Ok(running.port);
+        }
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (pairing, flag) = (self.pairing.clone(), stop.clone());
+        std::thread::spawn(move || serve(listener, db, pairing, flag, on_change));
+        *server = Some(ServerHandle { port, stop });
+        Ok(port)
+    }
+
+    fn stop(&self) -> Result<(), StemError> {
+        if let Some(server) = self.server.lock().map_err(|_| unexpected())?.take() {
+            server.stop.store(true, Ordering::SeqCst);
+        }
+        *self.pairing.lock().map_err(|_| unexpected())? = None;
+        Ok(())
+    }
+
+    fn port(&self) -> Option<u16> {
+        self.server.lock().ok()?.as_ref().map(|s| s.port)
+    }
+
+    fn new_pairing_code(&self) -> Result<PairingCode, StemError> {
+        if self.port().is_none() {
+            return Err(StemError::Validation("Démarrez d'abord le serveur de synchronisation".to_string()));
+        }
+        let code = generate_code();
+        let expires_at = current_timestamp() + PAIRING_TTL_SECS;
+        *self.pairing.lock().map_err(|_| unexpected())? = Some(Pairing { psk: code_psk(&code), expires_at });
+        Ok(PairingCode { code, expires_at })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PairingCode {
+    pub code: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LanServerInfo {
+    pub port: u16,
+    pub device_id: String,
+    pub name: String,
+}
+
+// ===== Tauri Commands =====
+
+/// Starts accepting pairings and syncs on `port` (47650 by default).
+/// Emits `refresh-notes` when a peer's changes were written.
+#[tauri::command]
+pub async fn start_lan_server(app: AppHandle, db: State<'_, Database>, lan: State<'_, LanSync>, port: Option<u16>) -> Result<LanServerInfo, StemError> {
+    let lan = lan.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let device_id = device_id(&*db.try_connection()?)?;
+        let port = lan.start(db, port.unwrap_or(DEFAULT_PORT), move || {
+            let _ = app.emit("refresh-notes", ());
+        })?;
+        Ok(LanServerInfo { port, device_id, name: device_name() })
+    }).await
+}
+
+#[tauri::command]
+pub async fn stop_lan_server(lan: State<'_, LanSync>) -> Result<(), StemError> {
+    lan.stop()
+}
+
+/// A single-use code, valid for ten minutes, for another device to pair with this one.
+#[tauri::command]
+pub async fn create_lan_pairing_code(lan: State<'_, LanSync>) -> Result<PairingCode, StemError> {
+    lan.new_pairing_code()
+}
+
+/// Pairs with the device at `address` (`host` or `host:port`) showing `code`.
+#[tauri::command]
+pub async fn pair_lan_device(db: State<'_, Database>, lan: State<'_, LanSync>, address: String, code: String) -> Result<LanPeer, StemError> {
+    let own_port = lan.port();
+    db.inner().clone().spawn(move |db| pair(&db, &address, &code, own_port)).await
+}
+
+#[tauri::command]
+pub async fn list_lan_peers(db: State<'_, Database>) -> Result<Vec<LanPeer>, StemError> {
+    db.inner().clone().spawn(move |db| list_peers(&*db.try_connection()?)).await
+}
+
+#[tauri::command]
+pub async fn remove_lan_peer(db: State<'_, Database>, device_id: String) -> Result<(), StemError> {
+    db.inner().clone().spawn(move |db| {
+        db.try_connection()?.execute("DELETE FROM lan_peers WHERE device_id = ?1", [&device_id])?;
+        Ok(())
+    }).await
+}
+
+/// Syncs with a paired device. Emits `refresh-notes` when its changes were written.
+#[tauri::command]
+pub async fn lan_sync(app: AppHandle, db: State<'_, Database>, device_id: String) -> Result<LanSyncReport, StemError> {
+    let report = db.inner().clone().spawn(move |db| sync_with_peer(&db, &device_id)).await?;
+    if !report.applied.is_empty() {
+        let _ = app.emit("refresh-notes", ());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support::{content, device};
+
+    fn insert(db: &Database, id: &str, content: &str, folder: Option<&str>) {
+        db.connection()
+            .execute(
+                "INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES (?1, ?1, ?2, 1, 1, ?3)",
+                params![id, content, folder],
+            )
+            .unwrap();
+    }
+
+    fn edit(db: &Database, id: &str, content: &str, updated_at: i64) {
+        db.connection()
+            .execute("UPDATE notes SET content = ?2, updated_at = ?3 WHERE id = ?1", params![id, content, updated_at])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pair_and_sync_two_instances() {
+        let (desktop, laptop) = (device(), device());
+        let server = LanSync::default();
+        let port = server.start(desktop.clone(), 0, || {}).unwrap();
+        let address = format!("127.0.0.1:{}", port);
+
+        // Pairing needs the code shown by the server
+        let code = server.new_pairing_code().unwrap().code;
+        assert!(pair(&laptop, &address, "AAAA-BBBB-CCCC-DDDD", None).is_err());
+        let peer = pair(&laptop, &address, &code.to_lowercase(), None).unwrap();
+        assert_eq!(peer.device_id, device_id(&desktop.connection()).unwrap());
+        assert!(pair(&laptop, &address, &code, None).is_err(), "codes are single-use");
+        assert_eq!(list_peers(&desktop.connection()).unwrap().len(), 1);
+        let desktop_id = peer.device_id;
+
+        desktop.connection().execute("INSERT INTO folders (id, name, position, created_at) VALUES ('f1', 'Travail', 0, 1)", []).unwrap();
+        insert(&desktop, "shared", "un\ndeux\ntrois", Some("f1"));
+        insert(&desktop, "doomed", "à supprimer", None);
+        insert(&laptop, "mine", "du portable", None);
+
+        let report = sync_with_peer(&laptop, &desktop_id).unwrap();
+        assert_eq!((report.received, report.sent), (3, 1));
+        assert_eq!(content(&laptop, "shared").as_deref(), Some("un\ndeux\ntrois"));
+        assert_eq!(content(&desktop, "mine").as_deref(), Some("du portable"));
+        let folder: Option<String> = laptop.connection().query_row("SELECT folder_id FROM notes WHERE id = 'shared'", [], |r| r.get(0)).unwrap();
+        assert_eq!(folder.as_deref(), Some("f1"));
+
+        // Nothing changed: nothing is exchanged but the echo of the last push
+        let report = sync_with_peer(&laptop, &desktop_id).unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.sent, 0);
+
+        // Concurrent edits merge, deletions travel
+        edit(&desktop, "shared", "UN\ndeux\ntrois", 5);
+        edit(&laptop, "shared", "un\ndeux\nTROIS", 6);
+        laptop.connection().execute("DELETE FROM notes WHERE id = 'doomed'", []).unwrap();
+        let report = sync_with_peer(&laptop, &desktop_id).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(content(&laptop, "shared").as_deref(), Some("UN\ndeux\nTROIS"));
+        assert_eq!(content(&desktop, "shared").as_deref(), Some("UN\ndeux\nTROIS"));
+        assert_eq!(content(&desktop, "doomed"), None);
+
+        // Overlapping edits are kept as a conflict on both devices
+        edit(&desktop, "shared", "Un\ndeux\nTROIS", 7);
+        edit(&laptop, "shared", "uN\ndeux\nTROIS", 8);
+        let report = sync_with_peer(&laptop, &desktop_id).unwrap();
+        assert_eq!(report.conflicts, vec!["shared".to_string()]);
+        assert_eq!(content(&desktop, "shared"), content(&laptop, "shared"));
+        assert!(content(&desktop, "shared").unwrap().contains("<<<<<<< Cet appareil"));
+
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn test_channel_splits_large_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let psk = code_psk("code");
+        let text = "é".repeat(100_000);
+        let expected = text.clone();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let hello = read_frame(&mut stream).unwrap();
+            let mut channel = Channel::accept(stream, &psk, &hello).unwrap();
+            let Message::Changes { changes, .. } = channel.recv().unwrap() else { panic!() };
+            changes[0].text.clone().unwrap()
+        });
+        let hello = Hello { version: PROTOCOL_VERSION, device_id: "a".to_string(), pairing: true };
+        let mut channel = Channel::connect(TcpStream::connect(address).unwrap(), &psk, &hello).unwrap();
+        let change = Change { kind: Kind::Note, id: "n".to_string(), text: Some(text) };
+        channel.send(&Message::Changes { changes: vec![change], seq: 1 }).unwrap();
+        assert_eq!(server.join().unwrap(), expected);
+        assert_eq!(generate_code().len(), 19);
+    }
+}
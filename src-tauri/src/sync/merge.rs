@@ -0,0 +1,225 @@
+//! Three-way merge of vault snapshots, using the last synced snapshot as the
+//! common base. Items changed on one side only take that side's version.
+//! Notes changed on both sides are merged field by field, and their Markdown
+//! line by line; overlapping edits are kept with conflict markers (or, for
+//! encrypted notes, in a conflict copy) and reported so they can be resolved
+//! later (see conflicts.rs).
+
+use super::VaultState;
+use crate::commands::Note;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+const LOCAL_LABEL: &str = "Cet appareil";
+const REMOTE_LABEL: &str = "Autre appareil";
+
+/// A note whose content could not be merged automatically.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteConflict {
+    pub note_id: String,
+    pub title: String,
+    pub base: Option<String>,
+    pub local: String,
+    pub remote: String,
+    /// Set when the remote version was saved as a separate note instead of
+    /// being inlined with markers.
+    pub copy_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeOutcome {
+    pub state: VaultState,
+    pub conflicts: Vec<NoteConflict>,
+}
+
+/// Line-based merge of Markdown. On overlapping edits, returns the text with
+/// both versions between `<<<<<<<` / `=======` / `>>>>>>>` markers.
+pub fn merge_text(base: &str, local: &str, remote: &str) -> Result<String, String> {
+    let mut options = diffy::MergeOptions::new();
+    options.set_conflict_style(diffy::ConflictStyle::Merge);
+    options.merge(base, local, remote).map_err(|marked| {
+        marked
+            .split_inclusive('\n')
+            .map(|line| match line.trim_end() {
+                "<<<<<<< ours" => format!("<<<<<<< {}\n", LOCAL_LABEL),
+                ">>>>>>> theirs" => format!(">>>>>>> {}\n", REMOTE_LABEL),
+                _ => line.to_string(),
+            })
+            .collect()
+    })
+}
+
+/// The version of a value after a three-way merge, or `None` when both sides
+/// changed it differently.
+fn pick<T: PartialEq + Clone>(base: Option<&T>, local: &T, remote: &T) -> Option<T> {
+    if local == remote || base == Some(remote) {
+        Some(local.clone())
+    } else if base == Some(local) {
+        Some(remote.clone())
+    } else {
+        None
+    }
+}
+
+fn pick_or_newer<T: PartialEq + Clone>(base: Option<&T>, local: &T, remote: &T, remote_newer: bool) -> T {
+    pick(base, local, remote).unwrap_or_else(|| if remote_newer { remote.clone() } else { local.clone() })
+}
+
+fn merge_note(base: Option<&Note>, local: &Note, remote: &Note) -> (Note, Option<Note>, Option<NoteConflict>) {
+    // Metadata edited on both sides: the most recent edit wins
+    let remote_newer = remote.updated_at > local.updated_at;
+    let title = pick_or_newer(base.map(|b| &b.title), &local.title, &remote.title, remote_newer);
+    let mut merged = Note {
+        id: local.id.clone(),
+        title: title.clone(),
+        content: local.content.clone(),
+        created_at: local.created_at.min(remote.created_at),
+        updated_at: local.updated_at.max(remote.updated_at),
+        is_pinned: pick_or_newer(base.map(|b| &b.is_pinned), &local.is_pinned, &remote.is_pinned, remote_newer),
+        folder_id: pick_or_newer(base.map(|b| &b.folder_id), &local.folder_id, &remote.folder_id, remote_newer),
+        is_archived: pick_or_newer(base.map(|b| &b.is_archived), &local.is_archived, &remote.is_archived, remote_newer),
+        is_encrypted: local.is_encrypted,
+    };
+
+    let body = |n: &Note| (n.content.clone().unwrap_or_default(), n.is_encrypted);
+    if let Some((content, is_encrypted)) = pick(base.map(body).as_ref(), &body(local), &body(remote)) {
+        merged.content = Some(content);
+        merged.is_encrypted = is_encrypted;
+        return (merged, None, None);
+    }
+
+    let base_text = base.and_then(|b| b.content.clone());
+    let local_text = local.content.clone().unwrap_or_default();
+    let remote_text = remote.content.clone().unwrap_or_default();
+    let mut conflict = NoteConflict {
+        note_id: local.id.clone(),
+        title,
+        base: base_text.clone(),
+        local: local_text.clone(),
+        remote: remote_text.clone(),
+        copy_id: None,
+    };
+
+    if local.is_encrypted || remote.is_encrypted || base.is_some_and(|b| b.is_encrypted) {
+        // Ciphertext cannot be merged: keep the local version and copy the remote one
+        let copy = Note {
+            id: Uuid::new_v4().to_string(),
+            title: format!("{} (conflit)", remote.title),
+            ..remote.clone()
+        };
+        conflict.copy_id = Some(copy.id.clone());
+        return (merged, Some(copy), Some(conflict));
+    }
+
+    match merge_text(base_text.as_deref().unwrap_or(""), &local_text, &remote_text) {
+        Ok(text) => {
+            merged.content = Some(text);
+            (merged, None, None)
+        }
+        Err(marked) => {
+            merged.content = Some(marked);
+            (merged, None, Some(conflict))
+        }
+    }
+}
+
+/// Three-way merge keyed by id. Edits win over deletions, and references to
+/// a folder that the other side deleted are cleared.
+pub fn merge_states(base: &VaultState, local: &VaultState, remote: &VaultState) -> MergeOutcome {
+    let mut outcome = MergeOutcome::default();
+
+    let folder_ids: BTreeSet<&String> = local.folders.keys().chain(remote.folders.keys()).collect();
+    for id in folder_ids {
+        let (b, l, r) = (base.folder_text(id), local.folder_text(id), remote.folder_text(id));
+        let picked = match pick(Some(&b), &l, &r) {
+            Some(text) if text == l => local.folders.get(id),
+            Some(_) => remote.folders.get(id),
+            None => local.folders.get(id).or(remote.folders.get(id)),
+        };
+        if let Some(folder) = picked {
+            outcome.state.folders.insert(id.clone(), folder.clone());
+        }
+    }
+
+    let note_ids: BTreeSet<&String> = local.notes.keys().chain(remote.notes.keys()).collect();
+    for id in note_ids {
+        let (b, l, r) = (base.note_text(id), local.note_text(id), remote.note_text(id));
+        let merged = match pick(Some(&b), &l, &r) {
+            Some(text) if text == l => local.notes.get(id).cloned(),
+            Some(_) => remote.notes.get(id).cloned(),
+            None => match (local.notes.get(id), remote.notes.get(id)) {
+                (Some(l), Some(r)) => {
+                    let (note, copy, conflict) = merge_note(base.notes.get(id), l, r);
+                    if let Some(copy) = copy {
+                        outcome.state.notes.insert(copy.id.clone(), copy);
+                    }
+                    outcome.conflicts.extend(conflict);
+                    Some(note)
+                }
+                (l, r) => l.or(r).cloned(),
+            },
+        };
+        if let Some(note) = merged {
+            outcome.state.notes.insert(id.clone(), note);
+        }
+    }
+
+    let folder_ids: BTreeSet<String> = outcome.state.folders.keys().cloned().collect();
+    for folder in outcome.state.folders.values_mut() {
+        if folder.parent_id.as_ref().is_some_and(|p| !folder_ids.contains(p)) {
+            folder.parent_id = None;
+        }
+    }
+    for note in outcome.state.notes.values_mut() {
+        if note.folder_id.as_ref().is_some_and(|f| !folder_ids.contains(f)) {
+            note.folder_id = None;
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support::{note, state};
+
+    #[test]
+    fn test_merge_takes_each_side_changes() {
+        let base = state(&[note("a", "A", "a\n", 1), note("b", "B", "b\n", 1), note("c", "C", "un\ndeux\ntrois\n", 1)]);
+        let local = state(&[note("a", "A", "a local\n", 2), note("b", "B", "b\n", 1), note("c", "C", "UN\ndeux\ntrois\n", 5)]);
+        let remote = state(&[note("a", "A", "a\n", 1), note("c", "C bis", "un\ndeux\nTROIS\n", 3), note("d", "D", "d\n", 4)]);
+
+        let merged = merge_states(&base, &local, &remote);
+        let get = |id: &str| merged.state.notes.get(id);
+        assert_eq!(get("a").unwrap().content.as_deref(), Some("a local\n"));
+        assert!(get("b").is_none(), "deleted remotely, untouched locally");
+        assert_eq!(get("c").unwrap().content.as_deref(), Some("UN\ndeux\nTROIS\n"));
+        assert_eq!(get("c").unwrap().title, "C bis");
+        assert_eq!(get("c").unwrap().updated_at, 5);
+        assert_eq!(get("d").unwrap().content.as_deref(), Some("d\n"));
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_edits_keep_both_versions() {
+        let base = state(&[note("a", "A", "ligne\n", 1)]);
+        let local = state(&[note("a", "A", "ligne locale\n", 2)]);
+        let remote = state(&[note("a", "A", "ligne distante\n", 3)]);
+        let merged = merge_states(&base, &local, &remote);
+        assert_eq!(
+            merged.state.notes["a"].content.as_deref(),
+            Some("<<<<<<< Cet appareil\nligne locale\n=======\nligne distante\n>>>>>>> Autre appareil\n")
+        );
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].remote, "ligne distante\n");
+
+        // Encrypted content goes to a conflict copy instead
+        let mut local = local.clone();
+        local.notes.get_mut("a").unwrap().is_encrypted = true;
+        let merged = merge_states(&base, &local, &remote);
+        let copy_id = merged.conflicts[0].copy_id.clone().unwrap();
+        assert_eq!(merged.state.notes["a"].content.as_deref(), Some("ligne locale\n"));
+        assert_eq!(merged.state.notes[&copy_id].content.as_deref(), Some("ligne distante\n"));
+        assert_eq!(merged.state.notes[&copy_id].title, "A (conflit)");
+    }
+}
@@ -0,0 +1,581 @@
+//! Two-way mirror of the vault into a directory of `.md` files, for editing
+//! notes in another editor. Files are laid out like `export_markdown`; the
+//! directory tree is the folder tree, so renaming or moving a file on disk
+//! renames the note or moves it to another folder.
+//!
+//! Every pass is a three-way merge (see `merge_states`) between the database,
+//! the directory and the state both last agreed on. That state is rebuilt
+//! from `.stem-mirror.json`, which records the folders and a hash of every
+//! note as last synced, so a pass also catches up on changes made while the
+//! app was closed. A pass that finds nothing to do writes nothing: the files
+//! and rows it wrote itself match the manifest and never bounce back.
+//!
+//! Encrypted notes are never written in plaintext and are left alone.
+
+use super::conflicts::record_conflicts;
+use super::merge::merge_states;
+use super::{apply_state, content_hash, get_setting, max_seq, set_setting, ApplyReport, VaultState};
+use crate::commands::{Folder, Note};
+use crate::db::{current_timestamp, Database};
+use crate::embeddings::embed_note;
+use crate::error::StemError;
+use crate::vault::{
+    note_body, parse_front_matter, parse_timestamp, plan_layout, prune_empty_dirs, render_note, sanitize_file_name, to_portable, write_if_changed,
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+use uuid::Uuid;
+
+const SETTINGS_KEY: &str = "mirror";
+pub const MANIFEST_FILE: &str = ".stem-mirror.json";
+/// Editors often save in several steps (temp file, rename, chmod).
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// How often the database is checked for edits made in the app.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Absolute path of the mirrored directory.
+    pub dir: String,
+    /// Model used to refresh the embeddings of notes edited on disk; none
+    /// leaves them to the app.
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub ollama_url: Option<String>,
+}
+
+impl MirrorConfig {
+    fn root(&self) -> Result<PathBuf, StemError> {
+        let root = PathBuf::from(&self.dir);
+        if !root.is_absolute() {
+            return Err(StemError::Validation("Le dossier miroir doit être un chemin absolu".to_string()));
+        }
+        Ok(root)
+    }
+}
+
+fn load_config(db: &Database) -> Result<Option<MirrorConfig>, StemError> {
+    let conn = db.try_connection()?;
+    get_setting(&conn, SETTINGS_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| StemError::Validation(e.to_string())))
+        .transpose()
+}
+
+// ===== Manifest =====
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SyncedFile {
+    path: String,
+    /// Hash of the note's canonical text (`VaultState::note_text`).
+    hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MirrorManifest {
+    version: u32,
+    folders: Vec<Folder>,
+    /// Note id -> file.
+    notes: BTreeMap<String, SyncedFile>,
+}
+
+impl MirrorManifest {
+    fn read(root: &Path) -> Self {
+        fs::read(root.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Directory of every folder, as written by the previous pass.
+    fn folder_dirs(&self) -> HashMap<String, String> {
+        plan_layout(Vec::new(), &self.folders)
+            .folder_dirs
+            .into_iter()
+            .map(|(id, dir)| (to_portable(&dir), id))
+            .collect()
+    }
+}
+
+// ===== Reading the directory =====
+
+/// Non-hidden directories and `.md` files, `/`-separated, parents first.
+fn scan(root: &Path, rel: &str, dirs: &mut Vec<String>, files: &mut Vec<String>) -> Result<(), StemError> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(rel))?.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else { continue };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = if rel.is_empty() { name.clone() } else { format!("{}/{}", rel, name) };
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => {
+                dirs.push(path.clone());
+                scan(root, &path, dirs, files)?;
+            }
+            Ok(t) if t.is_file() && name.to_lowercase().ends_with(".md") => files.push(path),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+fn file_stem(name: &str) -> &str {
+    &name[..name.len() - 3]
+}
+
+/// The directory as notes and folders, plus the files it was read from.
+struct DiskView {
+    state: VaultState,
+    /// Note id -> file, for every file that was read as a note.
+    paths: BTreeMap<String, String>,
+    /// Last modification of each note's file.
+    modified: HashMap<String, i64>,
+    dirs: Vec<String>,
+}
+
+fn read_disk(root: &Path, manifest: &MirrorManifest, skip_ids: &HashSet<String>) -> Result<DiskView, StemError> {
+    let (mut dirs, mut files) = (Vec::new(), Vec::new());
+    scan(root, "", &mut dirs, &mut files)?;
+    let mut view = DiskView { state: VaultState::default(), paths: BTreeMap::new(), modified: HashMap::new(), dirs: Vec::new() };
+    let now = current_timestamp();
+
+    // Notes first: where their files used to be tells renamed folders apart from new ones
+    let mut note_dirs: Vec<(String, String)> = Vec::new();
+    for path in files {
+        let full = root.join(&path);
+        let Ok(text) = fs::read_to_string(&full) else { continue };
+        let modified = full
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(now, |d| d.as_secs() as i64);
+        let (fields, body) = parse_front_matter(&text);
+        let text_field = |key: &str| fields.get(key).and_then(|v| v.as_text()).filter(|v| !v.is_empty());
+        let bool_field = |key: &str| fields.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let id = match text_field("id") {
+            Some(id) if !skip_ids.contains(id) && !view.paths.contains_key(id) => id.to_string(),
+            // A new file, or a copy of another note's file
+            _ => Uuid::new_v4().to_string(),
+        };
+        let (dir, name) = split_path(&path);
+        let renamed = manifest.notes.get(&id).is_some_and(|prev| split_path(&prev.path).1 != name);
+        let title = match text_field("title") {
+            Some(title) if !renamed => title.to_string(),
+            _ => file_stem(name).to_string(),
+        };
+        let created_at = text_field("created").and_then(parse_timestamp).unwrap_or(modified);
+        view.state.notes.insert(id.clone(), Note {
+            id: id.clone(),
+            title,
+            content: Some(note_body(body).to_string()),
+            created_at,
+            updated_at: text_field("updated").and_then(parse_timestamp).unwrap_or(modified),
+            is_pinned: bool_field("pinned"),
+            folder_id: None,
+            is_archived: bool_field("archived"),
+            is_encrypted: false,
+        });
+        note_dirs.push((id.clone(), dir.to_string()));
+        view.modified.insert(id.clone(), modified);
+        view.paths.insert(id, path);
+    }
+
+    // Directories still where the previous pass put them keep their folder;
+    // the others are renamed or moved folders when notes followed them, new
+    // folders otherwise.
+    let known = manifest.folder_dirs();
+    let previous: HashMap<&str, &Folder> = manifest.folders.iter().map(|f| (f.id.as_str(), f)).collect();
+    let mut vanished: HashSet<&str> = known
+        .iter()
+        .filter(|(dir, _)| !dirs.contains(dir))
+        .map(|(_, id)| id.as_str())
+        .collect();
+    let mut dir_ids: HashMap<String, String> = HashMap::new();
+    for dir in &dirs {
+        let (parent, name) = split_path(dir);
+        let parent_id = dir_ids.get(parent).cloned();
+        let folder = match known.get(dir) {
+            Some(id) => previous[id.as_str()].clone(),
+            None => {
+                let followed = note_dirs
+                    .iter()
+                    .filter(|(_, d)| d == dir)
+                    .filter_map(|(id, _)| manifest.notes.get(id))
+                    .filter_map(|prev| known.get(split_path(&prev.path).0))
+                    .find(|id| vanished.contains(id.as_str()));
+                match followed.and_then(|id| vanished.take(id.as_str())) {
+                    Some(id) => {
+                        let old = previous[id];
+                        let renamed = sanitize_file_name(&old.name) != name;
+                        Folder {
+                            name: if renamed { name.to_string() } else { old.name.clone() },
+                            parent_id: parent_id.clone(),
+                            ..old.clone()
+                        }
+                    }
+                    None => Folder {
+                        id: Uuid::new_v4().to_string(),
+                        name: name.to_string(),
+                        parent_id: parent_id.clone(),
+                        position: view.state.folders.values().filter(|f| f.parent_id == parent_id).count() as i32,
+                        created_at: now,
+                        is_archived: false,
+                    },
+                }
+            }
+        };
+        dir_ids.insert(dir.clone(), folder.id.clone());
+        view.state.folders.insert(folder.id.clone(), folder);
+    }
+    for (id, dir) in note_dirs {
+        if let Some(note) = view.state.notes.get_mut(&id) {
+            note.folder_id = dir_ids.get(&dir).cloned();
+        }
+    }
+    view.dirs = dirs;
+    Ok(view)
+}
+
+// ===== Reconciling =====
+
+#[derive(Debug, Default, Serialize)]
+pub struct MirrorReport {
+    /// Changes from the directory written to the database.
+    pub applied: ApplyReport,
+    /// Notes added or changed in the database by this pass.
+    pub changed_notes: Vec<String>,
+    pub files_written: usize,
+    pub files_removed: usize,
+    /// Titles of the notes edited on both sides (see `list_sync_conflicts`).
+    pub conflicts: Vec<String>,
+}
+
+fn plain_notes(state: &mut VaultState) -> HashSet<String> {
+    let encrypted: HashSet<String> = state.notes.values().filter(|n| n.is_encrypted).map(|n| n.id.clone()).collect();
+    state.notes.retain(|id, _| !encrypted.contains(id));
+    encrypted
+}
+
+/// One pass: merges the database and the directory, then brings both to the result.
+pub(crate) fn reconcile(db: &Database, root: &Path) -> Result<MirrorReport, StemError> {
+    fs::create_dir_all(root)?;
+    let manifest = MirrorManifest::read(root);
+    let mut current = VaultState::load(db)?;
+    let encrypted = plain_notes(&mut current);
+    let mut disk = read_disk(root, &manifest, &encrypted)?;
+
+    // The last agreed state: whichever side still matches the recorded hash
+    let mut base = VaultState { notes: BTreeMap::new(), folders: manifest.folders.iter().map(|f| (f.id.clone(), f.clone())).collect() };
+    for (id, synced) in &manifest.notes {
+        let matches = |state: &VaultState| state.note_text(id).is_some_and(|text| content_hash(text.as_bytes()) == synced.hash);
+        if let Some(note) = [&disk.state, &current].into_iter().find(|s| matches(s)).and_then(|s| s.notes.get(id)) {
+            base.notes.insert(id.clone(), note.clone());
+        }
+    }
+    // Files edited outside the app carry their old `updated:` line
+    let edited: Vec<String> = disk.state.notes.keys().filter(|id| base.note_text(id) != disk.state.note_text(id)).cloned().collect();
+    for id in edited {
+        if let Some(note) = disk.state.notes.get_mut(&id) {
+            note.updated_at = note.updated_at.max(disk.modified[&id]);
+        }
+    }
+
+    let merged = merge_states(&base, &current, &disk.state);
+    let mut report = MirrorReport {
+        applied: apply_state(db, &current, &merged.state)?,
+        changed_notes: merged.state.notes.keys().filter(|id| current.note_text(id) != merged.state.note_text(id)).cloned().collect(),
+        conflicts: merged.conflicts.iter().map(|c| c.title.clone()).collect(),
+        ..Default::default()
+    };
+    record_conflicts(db, &merged.conflicts)?;
+
+    let folders: Vec<Folder> = merged.state.folders.values().cloned().collect();
+    let layout = plan_layout(merged.state.notes.values().cloned().collect(), &folders);
+    let mut next = MirrorManifest { version: 1, folders, notes: BTreeMap::new() };
+    for dir in layout.folder_dirs.values() {
+        fs::create_dir_all(root.join(dir))?;
+    }
+    for (note, rel) in &layout.notes {
+        if write_if_changed(&root.join(rel), render_note(note).as_bytes())? {
+            report.files_written += 1;
+        }
+        let text = merged.state.note_text(&note.id).unwrap_or_default();
+        next.notes.insert(note.id.clone(), SyncedFile { path: to_portable(rel), hash: content_hash(text.as_bytes()) });
+    }
+
+    let kept: HashSet<&String> = next.notes.values().map(|f| &f.path).collect();
+    for path in disk.paths.values().filter(|p| !kept.contains(p)) {
+        let path = root.join(path);
+        if fs::remove_file(&path).is_ok() {
+            report.files_removed += 1;
+        }
+        prune_empty_dirs(root, path.parent());
+    }
+    let dirs: HashSet<String> = layout.folder_dirs.values().map(|d| to_portable(d)).collect();
+    for dir in disk.dirs.iter().rev().filter(|d| !dirs.contains(*d)) {
+        prune_empty_dirs(root, Some(&root.join(dir)));
+    }
+
+    let json = serde_json::to_vec_pretty(&next).map_err(|e| StemError::Validation(e.to_string()))?;
+    write_if_changed(&root.join(MANIFEST_FILE), &json)?;
+    Ok(report)
+}
+
+// ===== Watching =====
+
+enum Signal {
+    Changed,
+    Stop,
+}
+
+struct Worker {
+    signals: Sender<Signal>,
+    thread: JoinHandle<()>,
+    _watcher: RecommendedWatcher,
+}
+
+/// The running mirror, managed as Tauri state.
+#[derive(Default, Clone)]
+pub struct FsMirror {
+    worker: Arc<Mutex<Option<Worker>>>,
+}
+
+impl FsMirror {
+    fn stop(&self) {
+        let worker = self.worker.lock().ok().and_then(|mut w| w.take());
+        if let Some(worker) = worker {
+            let _ = worker.signals.send(Signal::Stop);
+            let _ = worker.thread.join();
+        }
+    }
+}
+
+/// Only files a pass would read matter; the manifest and editors' swap and
+/// backup files (hidden, `~`-suffixed) do not.
+fn is_relevant(root: &Path, path: &Path) -> bool {
+    let Ok(rel) = path.strip_prefix(root) else { return false };
+    let hidden = rel.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+    !hidden && !rel.to_string_lossy().ends_with('~')
+}
+
+fn last_change(db: &Database) -> Option<i64> {
+    db.try_connection().ok().and_then(|conn| max_seq(&conn).ok())
+}
+
+fn run_pass(app: &AppHandle, db: &Database, config: &MirrorConfig) -> Result<MirrorReport, StemError> {
+    let report = reconcile(db, &config.root()?)?;
+    if !report.applied.is_empty() {
+        let _ = app.emit("refresh-notes", ());
+    }
+    if !report.changed_notes.is_empty() {
+        let _ = app.emit("mirror-changed", &report.changed_notes);
+    }
+    if let Some(model) = &config.embedding_model {
+        let client = app.state::<reqwest::Client>().inner().clone();
+        let notes = VaultState::load(db)?.notes;
+        for id in &report.changed_notes {
+            let Some(note) = notes.get(id) else { continue };
+            let (client, db, id, text) = (client.clone(), db.clone(), id.clone(), note.content.clone().unwrap_or_default());
+            let (model, url) = (Some(model.clone()), config.ollama_url.clone());
+            tauri::async_runtime::spawn(async move {
+                let _ = embed_note(&client, &db, id, text, model, url).await;
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Runs a first pass, then keeps the directory and the database in step
+/// until stopped: file events are debounced, and `change_log` is polled for
+/// note and folder writes from the app (read again after each pass, so that
+/// the pass's own writes are left out).
+fn start(app: &AppHandle, db: &Database, mirror: &FsMirror, config: MirrorConfig) -> Result<MirrorReport, StemError> {
+    let root = config.root()?;
+    mirror.stop();
+    let report = run_pass(app, db, &config)?;
+
+    let (signals, receiver) = mpsc::channel();
+    let events = signals.clone();
+    let watch_root = root.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if event.paths.iter().any(|p| is_relevant(&watch_root, p)) {
+                let _ = events.send(Signal::Changed);
+            }
+        }
+    })
+    .map_err(|e| StemError::Validation(format!("Surveillance du dossier impossible : {}", e)))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| StemError::Validation(format!("Surveillance du dossier impossible : {}", e)))?;
+
+    let (app, db) = (app.clone(), db.clone());
+    let thread = std::thread::spawn(move || {
+        let mut seen = last_change(&db).unwrap_or(0);
+        loop {
+            let due = match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(Signal::Changed) => {
+                    // Wait for the editor to finish saving
+                    loop {
+                        match receiver.recv_timeout(DEBOUNCE) {
+                            Ok(Signal::Changed) => continue,
+                            Ok(Signal::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                            Err(RecvTimeoutError::Timeout) => break,
+                        }
+                    }
+                    true
+                }
+                Ok(Signal::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => last_change(&db).is_some_and(|seq| seq != seen),
+            };
+            if due {
+                let _ = run_pass(&app, &db, &config);
+                seen = last_change(&db).unwrap_or(seen);
+                // Drop the events of the files this pass wrote
+                while let Ok(signal) = receiver.try_recv() {
+                    if let Signal::Stop = signal {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    if let Ok(mut worker) = mirror.worker.lock() {
+        *worker = Some(Worker { signals, thread, _watcher: watcher });
+    }
+    Ok(report)
+}
+
+/// Restarts the mirror saved by `start_mirror`, at app launch.
+pub fn resume_mirror(app: &AppHandle) -> Result<(), StemError> {
+    let db = app.state::<Database>().inner().clone();
+    if let Some(config) = load_config(&db)? {
+        start(app, &db, app.state::<FsMirror>().inner(), config)?;
+    }
+    Ok(())
+}
+
+// ===== Tauri Commands =====
+
+/// The mirrored directory, if the mirror is on.
+#[tauri::command]
+pub async fn get_mirror_config(db: State<'_, Database>) -> Result<Option<MirrorConfig>, StemError> {
+    db.inner().clone().spawn(move |db| load_config(&db)).await
+}
+
+/// Mirrors the vault into `config.dir` and keeps watching it, also after a
+/// restart of the app. Returns the result of the first pass.
+#[tauri::command]
+pub async fn start_mirror(
+    app: AppHandle,
+    db: State<'_, Database>,
+    mirror: State<'_, FsMirror>,
+    config: MirrorConfig,
+) -> Result<MirrorReport, StemError> {
+    let mirror = mirror.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        let report = start(&app, &db, &mirror, config.clone())?;
+        let json = serde_json::to_string(&config).map_err(|e| StemError::Validation(e.to_string()))?;
+        let conn = db.try_connection()?;
+        set_setting(&conn, SETTINGS_KEY, &json)?;
+        Ok(report)
+    }).await
+}
+
+/// Stops watching. The files stay where they are.
+#[tauri::command]
+pub async fn stop_mirror(db: State<'_, Database>, mirror: State<'_, FsMirror>) -> Result<(), StemError> {
+    let mirror = mirror.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        mirror.stop();
+        let conn = db.try_connection()?;
+        conn.execute("DELETE FROM sync_settings WHERE key = ?1", [SETTINGS_KEY])?;
+        Ok(())
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(root: &Path, rel: &str) -> String {
+        fs::read_to_string(root.join(rel)).unwrap()
+    }
+
+    fn note(db: &Database, title: &str) -> Note {
+        VaultState::load(db).unwrap().notes.into_values().find(|n| n.title == title).unwrap()
+    }
+
+    #[test]
+    fn test_mirror_both_ways() {
+        let db = Database::in_memory().unwrap();
+        db.init().unwrap();
+        db.connection().execute_batch(
+            "INSERT INTO folders (id, name, position, created_at) VALUES ('f', 'Projets', 0, 1);
+             INSERT INTO notes (id, title, content, created_at, updated_at, folder_id) VALUES ('a', 'Plan', 'Étapes', 1, 1, 'f');
+             INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('b', 'Idée', 'Brouillon', 1, 1);
+             INSERT INTO notes (id, title, content, created_at, updated_at, is_encrypted) VALUES ('c', 'Secret', 'xyz', 1, 1, 1);",
+        ).unwrap();
+        let root = std::env::temp_dir().join(format!("stem-mirror-{}", Uuid::new_v4()));
+
+        let report = reconcile(&db, &root).unwrap();
+        assert_eq!(report.files_written, 2);
+        assert!(report.applied.is_empty());
+        assert!(read(&root, "Projets/Plan.md").ends_with("Étapes\n"));
+        assert!(!root.join("Secret.md").exists());
+
+        // Edited, renamed, moved and created outside the app
+        fs::write(root.join("Projets/Plan.md"), read(&root, "Projets/Plan.md").replace("Étapes", "Étapes revues")).unwrap();
+        fs::rename(root.join("Idée.md"), root.join("Idée neuve.md")).unwrap();
+        fs::rename(root.join("Projets"), root.join("Travaux")).unwrap();
+        fs::write(root.join("Courses.md"), "Pain\n").unwrap();
+
+        let report = reconcile(&db, &root).unwrap();
+        assert_eq!(report.changed_notes.len(), 3);
+        let plan = note(&db, "Plan");
+        assert_eq!(plan.content.as_deref(), Some("Étapes revues"));
+        assert_eq!(plan.folder_id.as_deref(), Some("f"));
+        assert!(plan.updated_at > 1);
+        let folder: String = db.connection().query_row("SELECT name FROM folders WHERE id = 'f'", [], |r| r.get(0)).unwrap();
+        assert_eq!(folder, "Travaux");
+        assert_eq!(note(&db, "Idée neuve").id, "b");
+        assert_eq!(note(&db, "Courses").content.as_deref(), Some("Pain"));
+        assert!(read(&root, "Courses.md").starts_with("---\nid: "));
+        let encrypted: i64 = db.connection().query_row("SELECT COUNT(*) FROM notes WHERE is_encrypted = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(encrypted, 1);
+
+        // Nothing bounces back
+        let report = reconcile(&db, &root).unwrap();
+        assert!(report.applied.is_empty() && report.changed_notes.is_empty());
+        assert_eq!(report.files_written + report.files_removed, 0);
+
+        // Edits in the app reach the files, moves included
+        db.connection().execute("UPDATE notes SET folder_id = NULL, content = 'Étapes finales', updated_at = 5 WHERE id = 'a'", []).unwrap();
+        let report = reconcile(&db, &root).unwrap();
+        assert!(report.applied.is_empty());
+        assert!(read(&root, "Plan.md").ends_with("Étapes finales\n"));
+        assert!(!root.join("Travaux/Plan.md").exists());
+
+        fs::remove_file(root.join("Courses.md")).unwrap();
+        reconcile(&db, &root).unwrap();
+        assert!(VaultState::load(&db).unwrap().notes.values().all(|n| n.title != "Courses"));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,312 @@
+//! Synchronisation of the vault between devices. Every backend exchanges the
+//! same plain-files snapshot: one `.md` file per note laid out like
+//! `export_markdown`, with the ids needed to map it back in the front-matter,
+//! and `.stem/folders.json` for the folder tree. Merging and applying a
+//! snapshot work on notes and folders by id, so renames and moves never look
+//! like a deletion plus a creation.
+
+pub mod conflicts;
+pub mod crdt;
+pub mod git;
+pub mod lan;
+pub mod merge;
+pub mod mirror;
+pub mod provider;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod webdav;
+
+use crate::commands::{Folder, Note};
+use crate::db::Database;
+use crate::error::StemError;
+use crate::vault::{load_vault, note_body, parse_front_matter, parse_timestamp, plan_layout, render_note, to_portable};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+pub const FOLDERS_FILE: &str = ".stem/folders.json";
+
+/// Snapshot of the vault as files: portable path -> contents.
+pub type SnapshotFiles = BTreeMap<String, Vec<u8>>;
+
+/// Hex SHA-256, to tell file versions apart.
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ===== Settings =====
+
+pub(crate) fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, StemError> {
+    Ok(conn
+        .query_row("SELECT value FROM sync_settings WHERE key = ?1", [key], |row| row.get(0))
+        .optional()?)
+}
+
+pub(crate) fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), StemError> {
+    conn.execute(
+        "INSERT INTO sync_settings (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [key, value],
+    )?;
+    Ok(())
+}
+
+/// Number of the last note or folder write in `change_log`.
+pub(crate) fn max_seq(conn: &Connection) -> Result<i64, StemError> {
+    Ok(conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM change_log", [], |row| row.get(0))?)
+}
+
+/// Random id of this installation, created on first use.
+pub(crate) fn device_id(conn: &Connection) -> Result<String, StemError> {
+    if let Some(id) = get_setting(conn, "device-id")? {
+        return Ok(id);
+    }
+    let id = Uuid::new_v4().to_string();
+    set_setting(conn, "device-id", &id)?;
+    Ok(id)
+}
+
+// ===== Snapshot =====
+
+/// `render_note` plus the fields that only matter for syncing.
+fn render_sync_note(note: &Note) -> String {
+    let mut extra = String::new();
+    if let Some(folder_id) = &note.folder_id {
+        extra.push_str(&format!("folder: {}\n", folder_id));
+    }
+    if note.is_encrypted {
+        extra.push_str("encrypted: true\n");
+    }
+    let mut out = render_note(note);
+    // Insert before the closing `---` of the front-matter
+    if let Some(end) = out.find("\n---\n") {
+        out.insert_str(end + 1, &extra);
+    }
+    out
+}
+
+fn parse_sync_note(text: &str) -> Option<Note> {
+    let (fields, body) = parse_front_matter(text);
+    let text_field = |key: &str| fields.get(key).and_then(|v| v.as_text());
+    let bool_field = |key: &str| fields.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    let id = text_field("id").filter(|id| !id.is_empty())?.to_string();
+    let created_at = text_field("created").and_then(parse_timestamp).unwrap_or(0);
+    Some(Note {
+        id,
+        title: text_field("title").unwrap_or("Sans titre").to_string(),
+        content: Some(note_body(body).to_string()),
+        created_at,
+        updated_at: text_field("updated").and_then(parse_timestamp).unwrap_or(created_at),
+        is_pinned: bool_field("pinned"),
+        folder_id: text_field("folder").filter(|f| !f.is_empty()).map(str::to_string),
+        is_archived: bool_field("archived"),
+        is_encrypted: bool_field("encrypted"),
+    })
+}
+
+fn folder_json(folder: &Folder) -> String {
+    serde_json::to_string(folder).unwrap_or_default()
+}
+
+/// Notes and folders by id, the unit that backends merge and apply.
+#[derive(Debug, Clone, Default)]
+pub struct VaultState {
+    pub notes: BTreeMap<String, Note>,
+    pub folders: BTreeMap<String, Folder>,
+}
+
+impl VaultState {
+    pub fn load(db: &Database) -> Result<Self, StemError> {
+        let (notes, folders) = load_vault(db)?;
+        Ok(Self {
+            notes: notes.into_iter().map(|n| (n.id.clone(), n)).collect(),
+            folders: folders.into_iter().map(|f| (f.id.clone(), f)).collect(),
+        })
+    }
+
+    /// Canonical text of a note; two notes are the same version when it matches.
+    pub fn note_text(&self, id: &str) -> Option<String> {
+        self.notes.get(id).map(render_sync_note)
+    }
+
+    pub fn folder_text(&self, id: &str) -> Option<String> {
+        self.folders.get(id).map(folder_json)
+    }
+
+    pub fn to_files(&self) -> SnapshotFiles {
+        let folders: Vec<Folder> = self.folders.values().cloned().collect();
+        let layout = plan_layout(self.notes.values().cloned().collect(), &folders);
+        let mut files: SnapshotFiles = layout
+            .notes
+            .iter()
+            .map(|(note, path)| (to_portable(path), render_sync_note(note).into_bytes()))
+            .collect();
+        if !folders.is_empty() {
+            let json = serde_json::to_vec_pretty(&folders).unwrap_or_default();
+            files.insert(FOLDERS_FILE.to_string(), json);
+        }
+        files
+    }
+
+    /// Reads a snapshot back. Files that are not Stem notes are ignored.
+    pub fn from_files(files: &SnapshotFiles) -> Result<Self, StemError> {
+        let mut state = Self::default();
+        if let Some(json) = files.get(FOLDERS_FILE) {
+            let folders: Vec<Folder> = serde_json::from_slice(json)
+                .map_err(|e| StemError::Validation(format!("{} invalide : {}", FOLDERS_FILE, e)))?;
+            state.folders = folders.into_iter().map(|f| (f.id.clone(), f)).collect();
+        }
+        for (path, bytes) in files {
+            if !path.ends_with(".md") {
+                continue;
+            }
+            if let Some(note) = std::str::from_utf8(bytes).ok().and_then(parse_sync_note) {
+                state.notes.insert(note.id.clone(), note);
+            }
+        }
+        Ok(state)
+    }
+}
+
+// ===== Applying =====
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ApplyReport {
+    pub notes_added: usize,
+    pub notes_updated: usize,
+    pub notes_deleted: usize,
+    pub folders_changed: usize,
+}
+
+impl ApplyReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Brings the database from `current` (what was read before syncing) to
+/// `target`. A note edited in the app since `current` was read is left
+/// alone; the next sync picks the edit up.
+pub fn apply_state(db: &Database, current: &VaultState, target: &VaultState) -> Result<ApplyReport, StemError> {
+    let mut conn = db.try_connection()?;
+    let tx = conn.transaction()?;
+    let mut report = ApplyReport::default();
+
+    for (id, folder) in &target.folders {
+        if current.folder_text(id) == target.folder_text(id) {
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO folders (id, name, parent_id, position, created_at, is_archived) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET name = excluded.name, parent_id = excluded.parent_id,
+                 position = excluded.position, is_archived = excluded.is_archived",
+            params![id, folder.name, folder.parent_id, folder.position, folder.created_at, folder.is_archived],
+        )?;
+        report.folders_changed += 1;
+    }
+
+    for (id, note) in &target.notes {
+        match current.notes.get(id) {
+            Some(_) if current.note_text(id) == target.note_text(id) => {}
+            Some(existing) => {
+                let changed = tx.execute(
+                    "UPDATE notes SET title = ?2, content = ?3, created_at = ?4, updated_at = ?5, is_pinned = ?6,
+                         folder_id = ?7, is_archived = ?8, is_encrypted = ?9
+                     WHERE id = ?1 AND updated_at = ?10",
+                    params![
+                        id, note.title, note.content, note.created_at, note.updated_at, note.is_pinned,
+                        note.folder_id, note.is_archived, note.is_encrypted, existing.updated_at,
+                    ],
+                )?;
+                report.notes_updated += changed;
+            }
+            None => {
+                let inserted = tx.execute(
+                    "INSERT OR IGNORE INTO notes (id, title, content, created_at, updated_at, is_pinned, folder_id, is_archived, is_encrypted)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        id, note.title, note.content, note.created_at, note.updated_at, note.is_pinned,
+                        note.folder_id, note.is_archived, note.is_encrypted,
+                    ],
+                )?;
+                report.notes_added += inserted;
+            }
+        }
+    }
+
+    for (id, note) in &current.notes {
+        if !target.notes.contains_key(id) {
+            report.notes_deleted += tx.execute("DELETE FROM notes WHERE id = ?1 AND updated_at = ?2", params![id, note.updated_at])?;
+        }
+    }
+    for id in current.folders.keys() {
+        if !target.folders.contains_key(id) {
+            tx.execute("UPDATE notes SET folder_id = NULL WHERE folder_id = ?1", [id])?;
+            report.folders_changed += tx.execute("DELETE FROM folders WHERE id = ?1", [id])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+// ===== Change descriptions =====
+
+/// Commit-message style summary of what changed between two snapshots.
+pub fn describe_changes(before: &VaultState, after: &VaultState) -> String {
+    let title = |state: &VaultState, id: &str| state.notes.get(id).map(|n| n.title.clone()).unwrap_or_default();
+    let mut lines = Vec::new();
+    for id in after.notes.keys() {
+        match before.note_text(id) {
+            None => lines.push(("Ajoute", title(after, id))),
+            Some(text) if Some(&text) != after.note_text(id).as_ref() => lines.push(("Modifie", title(after, id))),
+            Some(_) => {}
+        }
+    }
+    for id in before.notes.keys().filter(|id| !after.notes.contains_key(*id)) {
+        lines.push(("Supprime", title(before, id)));
+    }
+
+    match lines.as_slice() {
+        [] => "Met à jour les dossiers".to_string(),
+        [(verb, title)] => format!("{} « {} »", verb, title),
+        _ => {
+            let mut message = format!("Synchronise {} notes\n\n", lines.len());
+            for (verb, title) in &lines {
+                message.push_str(&format!("- {} « {} »\n", verb, title));
+            }
+            message
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{note, state};
+    use super::*;
+
+    #[test]
+    fn test_files_roundtrip() {
+        let mut original = state(&[
+            note("a", "Réunion: notes", "Corps\n", 1_700_000_100),
+            note("b", "Sans fin de ligne", "un", 1_700_000_100),
+            note("c", "Vide", "", 1_700_000_100),
+        ]);
+        original.folders.insert("f".into(), Folder {
+            id: "f".into(), name: "Projets".into(), parent_id: None, position: 0, created_at: 1, is_archived: false,
+        });
+        original.notes.get_mut("a").unwrap().folder_id = Some("f".into());
+        original.notes.get_mut("a").unwrap().is_encrypted = true;
+
+        let files = original.to_files();
+        assert!(files.contains_key("Projets/Réunion- notes.md"));
+        let read = VaultState::from_files(&files).unwrap();
+        assert_eq!(read.note_text("a"), original.note_text("a"));
+        assert_eq!(read.folder_text("f"), original.folder_text("f"));
+        assert_eq!(read.to_files(), files);
+        for id in ["a", "b", "c"] {
+            assert_eq!(read.notes[id].content, original.notes[id].content);
+        }
+    }
+}
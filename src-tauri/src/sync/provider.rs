@@ -0,0 +1,273 @@
+//! Sync against remote file stores (WebDAV, ...). A provider only reads,
+//! writes and deletes files with ETag preconditions; this module does the
+//! rest, the same way for every store.
+//!
+//! The remote holds `manifest.json`, the change manifest mapping every
+//! snapshot path to the SHA-256 of its contents, and `objects/<hash>`, the
+//! contents themselves. Objects never change once written, so an interrupted
+//! sync picks up where it left off; the manifest is replaced with `If-Match`
+//! on the ETag it was read with, so when two devices sync at once the second
+//! one merges again instead of overwriting the first.
+//!
+//! The manifest and objects of the last sync are cached locally: a sync only
+//! downloads the objects it lacks, and nothing at all when the manifest's
+//! ETag has not changed.
+
+use super::conflicts::record_conflicts;
+use super::merge::merge_states;
+use super::{apply_state, content_hash, ApplyReport, SnapshotFiles, VaultState};
+use crate::db::Database;
+use crate::error::StemError;
+use crate::vault::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const OBJECTS_DIR: &str = "objects";
+/// Syncs lost to another device before giving up.
+const MAX_ATTEMPTS: usize = 3;
+
+fn object_path(hash: &str) -> String {
+    format!("{}/{}", OBJECTS_DIR, hash)
+}
+
+/// Hashes from the remote become local file names: anything but a SHA-256 in
+/// lowercase hex is refused.
+fn is_object_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+// ===== Providers =====
+
+pub enum Fetched {
+    /// The file still has the ETag given to `get`.
+    NotModified,
+    Missing,
+    Found { bytes: Vec<u8>, etag: Option<String> },
+}
+
+pub enum Precondition {
+    None,
+    /// Only create the file.
+    Absent,
+    /// Only replace the version with this ETag.
+    Matches(String),
+}
+
+pub enum PutOutcome {
+    Written { etag: Option<String> },
+    PreconditionFailed,
+}
+
+/// A remote store of files addressed by `/`-separated paths.
+pub trait SyncProvider {
+    /// Creates what the store needs before the first sync (directories...).
+    fn prepare(&self) -> Result<(), StemError> {
+        Ok(())
+    }
+    fn get(&self, path: &str, if_none_match: Option<&str>) -> Result<Fetched, StemError>;
+    fn put(&self, path: &str, bytes: &[u8], precondition: Precondition) -> Result<PutOutcome, StemError>;
+    /// Deleting a missing file is not an error.
+    fn delete(&self, path: &str) -> Result<(), StemError>;
+}
+
+// ===== Manifests =====
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeManifest {
+    pub version: u32,
+    /// Incremented by every sync that changes the remote.
+    pub generation: u64,
+    /// Snapshot path -> SHA-256 of the contents.
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheState {
+    etag: Option<String>,
+    manifest: ChangeManifest,
+}
+
+/// Local copy of the remote as of the last sync, plus the journal of the
+/// objects uploaded by a sync that has not finished yet.
+struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    fn open(dir: &Path) -> Result<Self, StemError> {
+        fs::create_dir_all(dir.join(OBJECTS_DIR))?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    fn state(&self) -> CacheState {
+        fs::read(self.dir.join("state.json"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn object(&self, hash: &str) -> Option<Vec<u8>> {
+        fs::read(self.dir.join(object_path(hash))).ok()
+    }
+
+    fn store_object(&self, hash: &str, bytes: &[u8]) -> Result<(), StemError> {
+        Ok(write_atomic(&self.dir.join(object_path(hash)), bytes)?)
+    }
+
+    fn files(&self, manifest: &ChangeManifest) -> Result<SnapshotFiles, StemError> {
+        manifest
+            .files
+            .iter()
+            .map(|(path, hash)| {
+                let bytes = self.object(hash).ok_or_else(|| StemError::NotFound(format!("Objet {} en cache", hash)))?;
+                Ok((path.clone(), bytes))
+            })
+            .collect()
+    }
+
+    fn uploaded(&self) -> HashSet<String> {
+        fs::read_to_string(self.dir.join("uploads.log"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn mark_uploaded(&self, hash: &str) -> Result<(), StemError> {
+        let mut log = OpenOptions::new().create(true).append(true).open(self.dir.join("uploads.log"))?;
+        writeln!(log, "{}", hash)?;
+        Ok(())
+    }
+
+    /// Records a finished sync and drops the objects it no longer needs.
+    fn finish(&self, state: &CacheState) -> Result<(), StemError> {
+        let json = serde_json::to_vec_pretty(state).map_err(|e| StemError::Validation(e.to_string()))?;
+        write_atomic(&self.dir.join("state.json"), &json)?;
+        let _ = fs::remove_file(self.dir.join("uploads.log"));
+        let keep: HashSet<&String> = state.manifest.files.values().collect();
+        for entry in fs::read_dir(self.dir.join(OBJECTS_DIR))?.filter_map(|e| e.ok()) {
+            if !entry.file_name().to_str().is_some_and(|name| keep.contains(&name.to_string())) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===== Sync =====
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProviderSyncReport {
+    /// Generation of the remote manifest after syncing.
+    pub generation: u64,
+    pub downloaded: usize,
+    pub uploaded: usize,
+    /// Titles of the notes left with conflicts (see `list_sync_conflicts`).
+    pub conflicts: Vec<String>,
+    /// Remote changes written to the database.
+    pub applied: ApplyReport,
+}
+
+/// Downloads, merges and uploads, retrying when another device syncs at the same time.
+pub(crate) fn sync_with_provider(db: &Database, provider: &dyn SyncProvider, cache_dir: &Path) -> Result<ProviderSyncReport, StemError> {
+    let cache = Cache::open(cache_dir)?;
+    provider.prepare()?;
+    for _ in 0..MAX_ATTEMPTS {
+        if let Some(report) = try_sync(db, provider, &cache)? {
+            return Ok(report);
+        }
+    }
+    Err(StemError::Sync("Le serveur a changé pendant chaque tentative, relancez la synchronisation".to_string()))
+}
+
+/// One attempt; `None` when the manifest changed on the server in the meantime.
+fn try_sync(db: &Database, provider: &dyn SyncProvider, cache: &Cache) -> Result<Option<ProviderSyncReport>, StemError> {
+    let mut report = ProviderSyncReport::default();
+    let cached = cache.state();
+    let (remote, mut etag, exists) = match provider.get(MANIFEST_FILE, cached.etag.as_deref())? {
+        Fetched::NotModified => (cached.manifest.clone(), cached.etag.clone(), true),
+        Fetched::Missing => (ChangeManifest::default(), None, false),
+        Fetched::Found { bytes, etag } => {
+            let manifest: ChangeManifest = serde_json::from_slice(&bytes)
+                .map_err(|e| StemError::Sync(format!("{} invalide : {}", MANIFEST_FILE, e)))?;
+            if let Some(hash) = manifest.files.values().find(|hash| !is_object_hash(hash)) {
+                return Err(StemError::Sync(format!("{} invalide : objet {}", MANIFEST_FILE, hash)));
+            }
+            (manifest, etag, true)
+        }
+    };
+
+    for hash in remote.files.values().collect::<HashSet<_>>() {
+        if cache.object(hash).is_some() {
+            continue;
+        }
+        let bytes = match provider.get(&object_path(hash), None)? {
+            Fetched::Found { bytes, .. } => bytes,
+            _ => return Err(StemError::NotFound(format!("Objet distant {}", hash))),
+        };
+        if content_hash(&bytes) != *hash {
+            return Err(StemError::Sync(format!("Objet distant {} corrompu", hash)));
+        }
+        cache.store_object(hash, &bytes)?;
+        report.downloaded += 1;
+    }
+
+    let current = VaultState::load(db)?;
+    let (target, conflicts) = if remote == cached.manifest {
+        (current.clone(), Vec::new())
+    } else {
+        let base = VaultState::from_files(&cache.files(&cached.manifest)?)?;
+        let merged = merge_states(&base, &current, &VaultState::from_files(&cache.files(&remote)?)?);
+        (merged.state, merged.conflicts)
+    };
+
+    let files = target.to_files();
+    let mut next = ChangeManifest {
+        version: 1,
+        generation: remote.generation,
+        files: files.iter().map(|(path, bytes)| (path.clone(), content_hash(bytes))).collect(),
+    };
+    if next.files != remote.files {
+        next.generation += 1;
+        let on_remote: HashSet<&String> = remote.files.values().collect();
+        let uploaded = cache.uploaded();
+        for (path, bytes) in &files {
+            let hash = &next.files[path];
+            if on_remote.contains(hash) || uploaded.contains(hash) {
+                continue;
+            }
+            // Already there means uploaded by an interrupted sync or another device
+            provider.put(&object_path(hash), bytes, Precondition::Absent)?;
+            cache.store_object(hash, bytes)?;
+            cache.mark_uploaded(hash)?;
+            report.uploaded += 1;
+        }
+
+        let json = serde_json::to_vec_pretty(&next).map_err(|e| StemError::Validation(e.to_string()))?;
+        let precondition = match (&etag, exists) {
+            (Some(etag), _) => Precondition::Matches(etag.clone()),
+            (None, false) => Precondition::Absent,
+            (None, true) => Precondition::None,
+        };
+        match provider.put(MANIFEST_FILE, &json, precondition)? {
+            PutOutcome::PreconditionFailed => return Ok(None),
+            PutOutcome::Written { etag: written } => etag = written,
+        }
+
+        let kept: HashSet<&String> = next.files.values().collect();
+        for hash in on_remote.into_iter().filter(|h| !kept.contains(h)) {
+            provider.delete(&object_path(hash))?;
+        }
+    }
+
+    report.applied = apply_state(db, &current, &target)?;
+    record_conflicts(db, &conflicts)?;
+    report.conflicts = conflicts.into_iter().map(|c| c.title).collect();
+    report.generation = next.generation;
+    cache.finish(&CacheState { etag, manifest: next })?;
+    Ok(Some(report))
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the sync test modules.
+
+use super::VaultState;
+use crate::commands::Note;
+use crate::db::Database;
+
+/// A fresh in-memory database standing for one device.
+pub fn device() -> Database {
+    let db = Database::in_memory().unwrap();
+    db.init().unwrap();
+    db
+}
+
+pub fn content(db: &Database, id: &str) -> Option<String> {
+    db.connection().query_row("SELECT content FROM notes WHERE id = ?1", [id], |r| r.get(0)).ok()
+}
+
+pub fn note(id: &str, title: &str, content: &str, updated_at: i64) -> Note {
+    Note {
+        id: id.to_string(),
+        title: title.to_string(),
+        content: Some(content.to_string()),
+        created_at: 1_700_000_000,
+        updated_at,
+        is_pinned: false,
+        folder_id: None,
+        is_archived: false,
+        is_encrypted: false,
+    }
+}
+
+pub fn state(notes: &[Note]) -> VaultState {
+    VaultState { notes: notes.iter().map(|n| (n.id.clone(), n.clone())).collect(), folders: Default::default() }
+}
@@ -0,0 +1,424 @@
+//! WebDAV sync backend (Nextcloud, ownCloud, Apache mod_dav...), on top of
+//! the provider sync in provider.rs. The configured collection holds the
+//! change manifest and the objects; basic auth only. The password is kept in
+//! an owner-only file next to the local cache, never in the database.
+
+use super::provider::{sync_with_provider, Fetched, Precondition, ProviderSyncReport, PutOutcome, SyncProvider, OBJECTS_DIR};
+use super::{get_setting, set_setting};
+use crate::backup::write_private;
+use crate::db::Database;
+use crate::error::StemError;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+
+const SETTINGS_KEY: &str = "webdav";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebDavConfig {
+    /// Collection holding the vault, e.g.
+    /// `https://cloud.example.com/remote.php/dav/files/alice/Stem/`.
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    /// Never sent back to the app nor stored in the database; leaving it
+    /// empty keeps the saved one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_saved: bool,
+}
+
+impl WebDavConfig {
+    fn validate(&self) -> Result<(), StemError> {
+        if !self.url.starts_with("https://") && !self.url.starts_with("http://") {
+            return Err(StemError::Validation("L'URL WebDAV doit commencer par https:// ou http://".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn save_config(db: &Database, config: &WebDavConfig) -> Result<(), StemError> {
+    let saved = WebDavConfig { password: None, password_saved: config.password.is_some(), ..config.clone() };
+    let json = serde_json::to_string(&saved).map_err(|e| StemError::Validation(e.to_string()))?;
+    set_setting(&*db.try_connection()?, SETTINGS_KEY, &json)
+}
+
+/// The saved settings with their password. A password left in the database
+/// by an earlier version is moved to the credentials file.
+fn load_config(db: &Database, webdav: &WebDavSync) -> Result<Option<WebDavConfig>, StemError> {
+    let saved: Option<WebDavConfig> = get_setting(&*db.try_connection()?, SETTINGS_KEY)?
+        .map(|json| serde_json::from_str(&json).map_err(|e| StemError::Validation(e.to_string())))
+        .transpose()?;
+    let Some(mut config) = saved else { return Ok(None) };
+    match &config.password {
+        Some(password) => {
+            webdav.save_password(password)?;
+            save_config(db, &config)?;
+        }
+        None => config.password = webdav.load_password()?,
+    }
+    Ok(Some(config))
+}
+
+/// Local cache of the remote and the password file, managed as Tauri state;
+/// the lock keeps syncs sequential.
+#[derive(Debug, Clone)]
+pub struct WebDavSync {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl WebDavSync {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Arc::new(Mutex::new(())) }
+    }
+
+    fn password_path(&self) -> PathBuf {
+        self.dir.join("password")
+    }
+
+    fn load_password(&self) -> Result<Option<String>, StemError> {
+        match fs::read_to_string(self.password_path()) {
+            Ok(password) => Ok(Some(password)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_password(&self, password: &str) -> Result<(), StemError> {
+        fs::create_dir_all(&self.dir)?;
+        write_private(&self.password_path(), password.as_bytes())
+    }
+}
+
+// ===== Provider =====
+
+pub struct WebDavProvider {
+    client: Client,
+    /// Collection URL, ending with `/`.
+    base: String,
+    username: String,
+    password: Option<String>,
+}
+
+impl WebDavProvider {
+    pub fn new(config: &WebDavConfig) -> Result<Self, StemError> {
+        config.validate()?;
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(15))
+            .timeout(Duration::from_secs(120))
+            .build()?;
+        let base = format!("{}/", config.url.trim_end_matches('/'));
+        Ok(Self { client, base, username: config.username.clone(), password: config.password.clone() })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.base, path));
+        if self.username.is_empty() {
+            request
+        } else {
+            request.basic_auth(&self.username, self.password.as_ref())
+        }
+    }
+
+    fn send(&self, request: RequestBuilder, what: &str) -> Result<Response, StemError> {
+        let response = request.send()?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(StemError::Sync("Identifiants WebDAV refusés".to_string()))
+            }
+            status if status.is_server_error() => Err(StemError::Sync(format!("{} : erreur du serveur ({})", what, status))),
+            _ => Ok(response),
+        }
+    }
+
+    fn unexpected(what: &str, status: StatusCode) -> StemError {
+        StemError::Sync(format!("{} : réponse inattendue ({})", what, status))
+    }
+}
+
+fn etag(response: &Response) -> Option<String> {
+    response.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+impl SyncProvider for WebDavProvider {
+    fn prepare(&self) -> Result<(), StemError> {
+        for path in ["", OBJECTS_DIR] {
+            let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+            let response = self.send(self.request(mkcol, path), "MKCOL")?;
+            // 405: the collection already exists
+            if !response.status().is_success() && response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(Self::unexpected("Création du dossier WebDAV", response.status()));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, path: &str, if_none_match: Option<&str>) -> Result<Fetched, StemError> {
+        let mut request = self.request(Method::GET, path);
+        if let Some(etag) = if_none_match {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = self.send(request, path)?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => Ok(Fetched::NotModified),
+            StatusCode::NOT_FOUND => Ok(Fetched::Missing),
+            status if status.is_success() => {
+                let etag = etag(&response);
+                Ok(Fetched::Found { bytes: response.bytes()?.to_vec(), etag })
+            }
+            status => Err(Self::unexpected(path, status)),
+        }
+    }
+
+    fn put(&self, path: &str, bytes: &[u8], precondition: Precondition) -> Result<PutOutcome, StemError> {
+        let mut request = self.request(Method::PUT, path).body(bytes.to_vec());
+        request = match precondition {
+            Precondition::None => request,
+            Precondition::Absent => request.header(IF_NONE_MATCH, "*"),
+            Precondition::Matches(etag) => request.header(IF_MATCH, etag),
+        };
+        let response = self.send(request, path)?;
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED => Ok(PutOutcome::PreconditionFailed),
+            status if status.is_success() => Ok(PutOutcome::Written { etag: etag(&response) }),
+            status => Err(Self::unexpected(path, status)),
+        }
+    }
+
+    fn delete(&self, path: &str) -> Result<(), StemError> {
+        let response = self.send(self.request(Method::DELETE, path), path)?;
+        match response.status() {
+            status if status.is_success() || status == StatusCode::NOT_FOUND => Ok(()),
+            status => Err(Self::unexpected(path, status)),
+        }
+    }
+}
+
+// ===== Tauri Commands =====
+
+/// The WebDAV settings, without the password.
+#[tauri::command]
+pub async fn get_webdav_config(db: State<'_, Database>, webdav: State<'_, WebDavSync>) -> Result<Option<WebDavConfig>, StemError> {
+    let webdav = webdav.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        Ok(load_config(&db, &webdav)?.map(|config| WebDavConfig { password: None, ..config }))
+    }).await
+}
+
+/// Saves the WebDAV settings after checking that the server accepts them.
+#[tauri::command]
+pub async fn set_webdav_config(
+    db: State<'_, Database>,
+    webdav: State<'_, WebDavSync>,
+    mut config: WebDavConfig,
+) -> Result<(), StemError> {
+    config.validate()?;
+    let webdav = webdav.inner().clone();
+    db.inner().clone().spawn(move |db| {
+        if config.password.as_deref().is_none_or(str::is_empty) {
+            config.password = load_config(&db, &webdav)?.and_then(|saved| saved.password);
+        }
+        WebDavProvider::new(&config)?.prepare()?;
+        if let Some(password) = &config.password {
+            webdav.save_password(password)?;
+        }
+        save_config(&db, &config)
+    }).await
+}
+
+/// Syncs with the WebDAV server. Emits `refresh-notes` when remote changes
+/// were written to the database.
+#[tauri::command]
+pub async fn webdav_sync(app: AppHandle, db: State<'_, Database>, webdav: State<'_, WebDavSync>) -> Result<ProviderSyncReport, StemError> {
+    let webdav = webdav.inner().clone();
+    let report = db.inner().clone().spawn(move |db| {
+        let config = load_config(&db, &webdav)?
+            .ok_or_else(|| StemError::Validation("Aucun serveur WebDAV configuré".to_string()))?;
+        let _guard = webdav.lock.lock().map_err(|_| StemError::Sync("Synchronisation interrompue".to_string()))?;
+        sync_with_provider(&db, &WebDavProvider::new(&config)?, &webdav.dir)
+    }).await?;
+    if !report.applied.is_empty() {
+        let _ = app.emit("refresh-notes", ());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::test_support::{self, content};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tiny_http::{Header, Response as HttpResponse, Server};
+    use uuid::Uuid;
+
+    /// Path -> contents and ETag.
+    type DavFiles = Arc<Mutex<HashMap<String, (Vec<u8>, String)>>>;
+
+    /// Minimal WebDAV server: MKCOL, GET, PUT and DELETE with ETags,
+    /// conditional requests and basic auth. `fail_puts_after` makes it drop
+    /// uploads after that many, to simulate an interrupted sync.
+    struct DavServer {
+        url: String,
+        files: DavFiles,
+        puts: Arc<AtomicUsize>,
+        fail_puts_after: Arc<AtomicUsize>,
+    }
+
+    fn status(code: u16) -> HttpResponse<std::io::Cursor<Vec<u8>>> {
+        HttpResponse::from_data(Vec::new()).with_status_code(code)
+    }
+
+    fn start_server() -> DavServer {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dav/Stem", server.server_addr().to_ip().unwrap());
+        let files: DavFiles = Arc::default();
+        let puts = Arc::new(AtomicUsize::new(0));
+        let fail_puts_after = Arc::new(AtomicUsize::new(usize::MAX));
+        let (store, put_count, fail_after) = (files.clone(), puts.clone(), fail_puts_after.clone());
+        std::thread::spawn(move || {
+            let mut version = 0;
+            // "alice:secret"
+            let auth = "Basic YWxpY2U6c2VjcmV0";
+            for mut request in server.incoming_requests() {
+                let header = |name: &'static str| {
+                    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str().to_string())
+                };
+                if header("Authorization").as_deref() != Some(auth) {
+                    let _ = request.respond(status(401));
+                    continue;
+                }
+                let (if_match, if_none_match) = (header("If-Match"), header("If-None-Match"));
+                let path = request.url().trim_start_matches("/dav/Stem").trim_matches('/').to_string();
+                let mut files = store.lock().unwrap();
+                let current = files.get(&path).map(|(_, etag)| etag.clone());
+                let response = match request.method().as_str() {
+                    "MKCOL" => status(if path.is_empty() && current.is_none() { 201 } else { 405 }),
+                    "GET" => match files.get(&path) {
+                        Some((_, etag)) if if_none_match.as_ref() == Some(etag) => status(304),
+                        Some((bytes, etag)) => HttpResponse::from_data(bytes.clone())
+                            .with_header(Header::from_bytes("ETag", etag.as_str()).unwrap())
+                            .with_status_code(200),
+                        None => status(404),
+                    },
+                    "PUT" => {
+                        let conflict = (if_none_match.as_deref() == Some("*") && current.is_some())
+                            || if_match.is_some_and(|m| current.as_ref() != Some(&m));
+                        if conflict {
+                            status(412)
+                        } else if put_count.fetch_add(1, Ordering::SeqCst) >= fail_after.load(Ordering::SeqCst) {
+                            status(503)
+                        } else {
+                            let mut body = Vec::new();
+                            std::io::Read::read_to_end(request.as_reader(), &mut body).unwrap();
+                            version += 1;
+                            let etag = format!("\"v{}\"", version);
+                            files.insert(path, (body, etag.clone()));
+                            status(201).with_header(Header::from_bytes("ETag", etag.as_str()).unwrap())
+                        }
+                    }
+                    "DELETE" => status(if files.remove(&path).is_some() { 204 } else { 404 }),
+                    _ => status(405),
+                };
+                drop(files);
+                let _ = request.respond(response);
+            }
+        });
+        DavServer { url, files, puts, fail_puts_after }
+    }
+
+    fn device(root: &std::path::Path, name: &str) -> (Database, PathBuf) {
+        (test_support::device(), root.join(name))
+    }
+
+    #[test]
+    fn test_webdav_sync_between_devices() {
+        let server = start_server();
+        let root = std::env::temp_dir().join(format!("stem-webdav-{}", Uuid::new_v4()));
+        let config = WebDavConfig { url: server.url.clone(), username: "alice".into(), password: Some("secret".into()), password_saved: false };
+        let provider = WebDavProvider::new(&config).unwrap();
+        let (laptop, laptop_cache) = device(&root, "laptop");
+        let (desktop, desktop_cache) = device(&root, "desktop");
+
+        let wrong = WebDavProvider::new(&WebDavConfig { password: Some("nope".into()), ..config.clone() }).unwrap();
+        assert!(matches!(sync_with_provider(&laptop, &wrong, &laptop_cache), Err(StemError::Sync(_))));
+
+        laptop.connection().execute_batch(
+            "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('a', 'Alpha', 'un', 1700000000, 1700000000);
+             INSERT INTO notes (id, title, content, created_at, updated_at) VALUES ('b', 'Beta', 'deux', 1700000000, 1700000000);",
+        ).unwrap();
+
+        // The upload is cut off after the first object, then resumed
+        server.fail_puts_after.store(1, Ordering::SeqCst);
+        assert!(sync_with_provider(&laptop, &provider, &laptop_cache).is_err());
+        assert!(!server.files.lock().unwrap().contains_key("manifest.json"));
+        server.fail_puts_after.store(usize::MAX, Ordering::SeqCst);
+        server.puts.store(0, Ordering::SeqCst);
+        let report = sync_with_provider(&laptop, &provider, &laptop_cache).unwrap();
+        assert_eq!((report.uploaded, report.generation), (1, 1));
+        assert_eq!(server.puts.load(Ordering::SeqCst), 2, "one object and the manifest");
+
+        let report = sync_with_provider(&desktop, &provider, &desktop_cache).unwrap();
+        assert_eq!((report.downloaded, report.applied.notes_added), (2, 2));
+        assert_eq!(content(&desktop, "a").as_deref(), Some("un"));
+
+        // Nothing changed: the manifest is not downloaded again
+        let report = sync_with_provider(&desktop, &provider, &desktop_cache).unwrap();
+        assert_eq!((report.downloaded, report.uploaded, report.generation), (0, 0, 1));
+
+        // Edits on both sides are merged
+        desktop.connection().execute("UPDATE notes SET content = 'un bis', updated_at = 1700000100 WHERE id = 'a'", []).unwrap();
+        laptop.connection().execute("DELETE FROM notes WHERE id = 'b'", []).unwrap();
+        sync_with_provider(&desktop, &provider, &desktop_cache).unwrap();
+        let report = sync_with_provider(&laptop, &provider, &laptop_cache).unwrap();
+        assert_eq!(report.generation, 3);
+        assert_eq!(content(&laptop, "a").as_deref(), Some("un bis"));
+        sync_with_provider(&desktop, &provider, &desktop_cache).unwrap();
+        assert_eq!(content(&desktop, "b"), None);
+
+        // Objects of deleted versions are removed from the server
+        let objects = server.files.lock().unwrap().keys().filter(|k| k.starts_with("objects/")).count();
+        assert_eq!(objects, 1);
+
+        // Manifest hashes never reach the file system unchecked
+        {
+            let mut files = server.files.lock().unwrap();
+            let manifest = String::from_utf8(files["manifest.json"].0.clone()).unwrap();
+            let hash = manifest.split('"').find(|part| part.len() == 64).unwrap().to_string();
+            files.insert("manifest.json".into(), (manifest.replace(&hash, "../../x.md").into_bytes(), "\"tampered\"".into()));
+        }
+        assert!(matches!(sync_with_provider(&laptop, &provider, &laptop_cache), Err(StemError::Sync(m)) if m.contains("../../x.md")));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_password_stays_out_of_the_database() {
+        let (db, dir) = device(&std::env::temp_dir(), &format!("stem-webdav-{}", Uuid::new_v4()));
+        let webdav = WebDavSync::new(dir.clone());
+        let setting = || get_setting(&db.connection(), SETTINGS_KEY).unwrap().unwrap();
+
+        // Saved in the database by an earlier version
+        set_setting(&db.connection(), SETTINGS_KEY, r#"{"url": "https://dav.example.com/", "username": "alice", "password": "secret"}"#).unwrap();
+        let config = load_config(&db, &webdav).unwrap().unwrap();
+        assert_eq!(config.password.as_deref(), Some("secret"));
+        assert!(!setting().contains("secret"));
+        assert!(setting().contains(r#""password_saved":true"#));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(webdav.password_path()).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let config = load_config(&db, &webdav).unwrap().unwrap();
+        assert_eq!((config.password.as_deref(), config.password_saved), (Some("secret"), true));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
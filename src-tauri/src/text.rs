@@ -0,0 +1,149 @@
+//! Plain-text helpers shared by the commands that summarise note content.
+//! Mirrors `extractPlainText` in `src/lib/utils/text.ts` closely enough that
+//! word counts computed here match the ones shown in the status bar.
+
+/// Strips Markdown syntax (fences, headings, list markers, emphasis, links)
+/// and returns the remaining text, one output line per non-empty input line.
+pub fn plain_text(content: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for raw in content.lines() {
+        let line = raw.trim();
+        if line.starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence || is_horizontal_rule(line) {
+            continue;
+        }
+
+        let stripped = strip_inline(strip_block_prefix(line));
+        let stripped = stripped.trim();
+        if !stripped.is_empty() {
+            lines.push(stripped.to_string());
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Counts whitespace-separated words of the plain text.
+pub fn word_count(content: &str) -> usize {
+    plain_text(content).split_whitespace().count()
+}
+
+/// Returns the first `max_chars` characters of the plain text on a single
+/// line, cut on a word boundary and suffixed with `…` when truncated.
+pub fn excerpt(content: &str, max_chars: usize) -> String {
+    let flat = plain_text(content).split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= max_chars {
+        return flat;
+    }
+
+    let cut: String = flat.chars().take(max_chars).collect();
+    let cut = match cut.rfind(' ') {
+        Some(idx) if idx > 0 => &cut[..idx],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+fn is_horizontal_rule(line: &str) -> bool {
+    (line.len() >= 3 && line.chars().all(|c| c == '-')) || line == "***" || line == "___"
+}
+
+fn strip_block_prefix(mut line: &str) -> &str {
+    while let Some(rest) = line.strip_prefix('>') {
+        line = rest.trim_start();
+    }
+
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+        return line[hashes..].trim_start();
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            line = rest;
+            break;
+        }
+    }
+
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && line[digits..].starts_with(". ") {
+        line = &line[digits + 2..];
+    }
+
+    for task in ["[ ] ", "[x] ", "[X] "] {
+        if let Some(rest) = line.strip_prefix(task) {
+            return rest;
+        }
+    }
+    line
+}
+
+/// Removes emphasis markers and keeps only the label of links and images.
+fn strip_inline(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' | '`' => i += 1,
+            '~' if chars.get(i + 1) == Some(&'~') => i += 2,
+            '_' if chars.get(i + 1) == Some(&'_') => i += 2,
+            '!' if chars.get(i + 1) == Some(&'[') => i += 1,
+            '[' => match link_label_end(&chars, i) {
+                Some((label_end, link_end)) => {
+                    out.extend(&chars[i + 1..label_end]);
+                    i = link_end;
+                }
+                None => {
+                    out.push('[');
+                    i += 1;
+                }
+            },
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// For `[label](target)` starting at `start`, returns the index of `]` and
+/// the index just past `)`.
+fn link_label_end(chars: &[char], start: usize) -> Option<(usize, usize)> {
+    let label_end = start + chars[start..].iter().position(|c| *c == ']')?;
+    if chars.get(label_end + 1) != Some(&'(') {
+        return None;
+    }
+    let target_end = label_end + 1 + chars[label_end + 1..].iter().position(|c| *c == ')')?;
+    Some((label_end, target_end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_strips_markdown() {
+        let md = "# Title\n\n- **bold** item\n- [ ] task\n> quote with [link](https://x.y)\n```rust\nfn main() {}\n```\n---";
+        assert_eq!(plain_text(md), "Title\nbold item\ntask\nquote with link");
+    }
+
+    #[test]
+    fn test_word_count_ignores_syntax() {
+        assert_eq!(word_count("## Hello *big* world\n\n1. one"), 4);
+        assert_eq!(word_count(""), 0);
+    }
+
+    #[test]
+    fn test_excerpt_truncates_on_word_boundary() {
+        assert_eq!(excerpt("Hello world", 50), "Hello world");
+        assert_eq!(excerpt("alpha beta gamma", 12), "alpha beta…");
+    }
+}
@@ -0,0 +1,208 @@
+use crate::db::{current_timestamp, Database};
+use crate::error::StemError;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::State;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+/// Views older than this are pruned; they no longer weigh on the score anyway.
+const RETENTION_DAYS: i64 = 90;
+/// A view loses half of its weight every `HALF_LIFE_DAYS`.
+const HALF_LIFE_DAYS: f64 = 14.0;
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NoteUsage {
+    pub note_id: String,
+    pub title: String,
+    pub folder_id: Option<String>,
+    pub last_opened_at: i64,
+    pub open_count: u32,
+    pub score: f64,
+}
+
+// ===== Helpers =====
+
+fn record_view(conn: &Connection, note_id: &str, now: i64) -> Result<(), StemError> {
+    let exists: bool = conn.query_row("SELECT COUNT(*) > 0 FROM notes WHERE id = ?1", [note_id], |row| row.get(0))?;
+    if !exists {
+        return Err(StemError::NotFound(format!("Note {}", note_id)));
+    }
+
+    conn.execute(
+        "INSERT INTO note_views (note_id, day, opens, last_opened_at) VALUES (?1, ?2, 1, ?3)
+         ON CONFLICT(note_id, day) DO UPDATE SET opens = opens + 1, last_opened_at = excluded.last_opened_at",
+        rusqlite::params![note_id, now / SECONDS_PER_DAY, now],
+    )?;
+    conn.execute(
+        "DELETE FROM note_views WHERE day < ?1",
+        [now / SECONDS_PER_DAY - RETENTION_DAYS],
+    )?;
+    Ok(())
+}
+
+/// Aggregates the view log per note. Each day's opens are weighted by
+/// `0.5 ^ (age / HALF_LIFE_DAYS)` so a note opened often last week outranks
+/// one opened as often two months ago. Archived notes are left out.
+fn load_usage(conn: &Connection, now: i64) -> Result<Vec<NoteUsage>, StemError> {
+    let today = now / SECONDS_PER_DAY;
+    let mut stmt = conn.prepare(
+        "SELECT v.note_id, n.title, n.folder_id, v.day, v.opens, v.last_opened_at
+         FROM note_views v JOIN notes n ON n.id = v.note_id
+         WHERE n.is_archived = 0",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, u32>(4)?,
+            row.get::<_, i64>(5)?,
+        ))
+    })?;
+
+    let mut by_note: HashMap<String, NoteUsage> = HashMap::new();
+    for row in rows {
+        let (note_id, title, folder_id, day, opens, last_opened_at) = row?;
+        let age = (today - day).max(0) as f64;
+        let weight = 0.5f64.powf(age / HALF_LIFE_DAYS);
+
+        let entry = by_note.entry(note_id.clone()).or_insert(NoteUsage {
+            note_id,
+            title,
+            folder_id,
+            last_opened_at,
+            open_count: 0,
+            score: 0.0,
+        });
+        entry.open_count += opens;
+        entry.score += opens as f64 * weight;
+        entry.last_opened_at = entry.last_opened_at.max(last_opened_at);
+    }
+    Ok(by_note.into_values().collect())
+}
+
+fn recent_notes(conn: &Connection, now: i64, limit: usize) -> Result<Vec<NoteUsage>, StemError> {
+    let mut usage = load_usage(conn, now)?;
+    usage.sort_by(|a, b| b.last_opened_at.cmp(&a.last_opened_at).then_with(|| a.note_id.cmp(&b.note_id)));
+    usage.truncate(limit);
+    Ok(usage)
+}
+
+fn frequent_notes(conn: &Connection, now: i64, limit: usize) -> Result<Vec<NoteUsage>, StemError> {
+    let mut usage = load_usage(conn, now)?;
+    usage.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| b.last_opened_at.cmp(&a.last_opened_at))
+    });
+    usage.truncate(limit);
+    Ok(usage)
+}
+
+fn clamp_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+// ===== Tauri Commands =====
+
+/// Records that a note was opened in the editor.
+#[tauri::command]
+pub async fn note_opened(db: State<'_, Database>, note_id: String) -> Result<(), StemError> {
+    db.inner().clone().spawn(move |db| {
+        let conn = db.try_connection()?;
+        record_view(&conn, &note_id, current_timestamp())
+    }).await
+}
+
+/// Most recently opened notes, newest first.
+#[tauri::command]
+pub async fn get_recent_notes(db: State<'_, Database>, limit: Option<usize>) -> Result<Vec<NoteUsage>, StemError> {
+    db.inner().clone().spawn(move |db| {
+        let conn = db.try_connection()?;
+        recent_notes(&conn, current_timestamp(), clamp_limit(limit))
+    }).await
+}
+
+/// Notes ranked by frecency (frequency weighted by recency).
+#[tauri::command]
+pub async fn get_frequent_notes(db: State<'_, Database>, limit: Option<usize>) -> Result<Vec<NoteUsage>, StemError> {
+    db.inner().clone().spawn(move |db| {
+        let conn = db.try_connection()?;
+        frequent_notes(&conn, current_timestamp(), clamp_limit(limit))
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SECONDS_PER_DAY;
+
+    fn setup_db() -> Database {
+        let db = Database::in_memory().expect("Failed to create in-memory DB");
+        db.init().expect("Failed to init DB");
+        for id in ["a", "b", "c"] {
+            db.connection().execute(
+                "INSERT INTO notes (id, title, content, created_at, updated_at) VALUES (?1, ?1, NULL, 0, 0)",
+                [id],
+            ).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_views_are_aggregated_per_day() {
+        let db = setup_db();
+        let conn = db.connection();
+        let now = 100 * DAY;
+        record_view(&conn, "a", now).unwrap();
+        record_view(&conn, "a", now + 10).unwrap();
+
+        let (rows, opens): (i64, i64) = conn
+            .query_row("SELECT COUNT(*), SUM(opens) FROM note_views", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!((rows, opens), (1, 2));
+        assert!(record_view(&conn, "missing", now).is_err());
+    }
+
+    #[test]
+    fn test_recent_and_frequent_rankings() {
+        let db = setup_db();
+        let conn = db.connection();
+        let now = 100 * DAY;
+        // "a": opened a lot, but a month ago. "b": a couple of times this week. "c": once, just now.
+        for _ in 0..6 {
+            record_view(&conn, "a", now - 30 * DAY).unwrap();
+        }
+        record_view(&conn, "b", now - 2 * DAY).unwrap();
+        record_view(&conn, "b", now - DAY).unwrap();
+        record_view(&conn, "c", now).unwrap();
+
+        let recent: Vec<_> = recent_notes(&conn, now, 10).unwrap().into_iter().map(|u| u.note_id).collect();
+        assert_eq!(recent, vec!["c", "b", "a"]);
+
+        let frequent = frequent_notes(&conn, now, 10).unwrap();
+        let ids: Vec<_> = frequent.iter().map(|u| u.note_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a", "c"]);
+        assert_eq!(frequent[1].open_count, 6);
+    }
+
+    #[test]
+    fn test_old_views_are_pruned() {
+        let db = setup_db();
+        let conn = db.connection();
+        record_view(&conn, "a", 0).unwrap();
+        record_view(&conn, "b", (RETENTION_DAYS + 1) * DAY).unwrap();
+
+        let ids: Vec<String> = conn
+            .prepare("SELECT note_id FROM note_views").unwrap()
+            .query_map([], |r| r.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec!["b"]);
+    }
+}
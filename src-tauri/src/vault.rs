@@ -0,0 +1,594 @@
+//! Plain-files representation of the vault: one `.md` file per note, laid out
+//! in directories mirroring the `folders` tree, each with a YAML front-matter.
+
+use crate::commands::{resolve_scope, row_to_folder, row_to_note, ExportScope, Folder, Note, FOLDER_COLUMNS, NOTE_COLUMNS};
+use crate::db::Database;
+use crate::error::StemError;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tauri::State;
+
+/// Written next to the exported files to know what a previous run produced.
+pub const MANIFEST_FILE: &str = ".stem-export.json";
+
+const MAX_NAME_BYTES: usize = 120;
+const FALLBACK_NAME: &str = "Sans titre";
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// ===== File names =====
+
+/// Turns a note title or folder name into a name that is valid on Windows,
+/// macOS and Linux: path separators and reserved characters become `-`,
+/// trailing dots/spaces are trimmed and device names like `CON` get a suffix.
+pub fn sanitize_file_name(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+
+    let mut cleaned = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.len() > MAX_NAME_BYTES {
+        let mut cut = MAX_NAME_BYTES;
+        while !cleaned.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        cleaned.truncate(cut);
+    }
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).trim_start_matches('.').to_string();
+
+    if cleaned.is_empty() {
+        return FALLBACK_NAME.to_string();
+    }
+    let stem = cleaned.split('.').next().unwrap_or("");
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return format!("{}_", cleaned);
+    }
+    cleaned
+}
+
+/// Hands out names unique within one directory, compared case-insensitively
+/// because macOS and Windows file systems are.
+#[derive(Default)]
+struct NameAllocator {
+    taken: HashMap<PathBuf, HashSet<String>>,
+}
+
+impl NameAllocator {
+    fn allocate(&mut self, dir: &Path, base: &str, extension: &str) -> String {
+        let taken = self.taken.entry(dir.to_path_buf()).or_default();
+        let mut n = 1;
+        loop {
+            let candidate = if n == 1 {
+                format!("{}{}", base, extension)
+            } else {
+                format!("{} ({}){}", base, n, extension)
+            };
+            if taken.insert(candidate.to_lowercase()) {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+}
+
+// ===== Layout =====
+
+/// Where every folder and note goes, relative to the vault root.
+pub struct VaultLayout {
+    /// Folder id -> directory.
+    pub folder_dirs: BTreeMap<String, PathBuf>,
+    /// Notes with their `.md` path.
+    pub notes: Vec<(Note, PathBuf)>,
+}
+
+/// Computes a deterministic layout: folders and notes are visited in creation
+/// order so that collision suffixes stay stable between runs.
+pub fn plan_layout(mut notes: Vec<Note>, folders: &[Folder]) -> VaultLayout {
+    let by_id: HashMap<&str, &Folder> = folders.iter().map(|f| (f.id.as_str(), f)).collect();
+    let mut ordered: Vec<&Folder> = folders.iter().collect();
+    ordered.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+
+    let mut names = NameAllocator::default();
+    let mut folder_dirs: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    // Parents must be placed before children: resolve each chain root-first.
+    for folder in &ordered {
+        let mut chain = vec![*folder];
+        let mut seen: HashSet<&str> = HashSet::from([folder.id.as_str()]);
+        while let Some(parent) = chain.last().and_then(|f| f.parent_id.as_deref()).and_then(|p| by_id.get(p)) {
+            if folder_dirs.contains_key(&parent.id) || !seen.insert(parent.id.as_str()) {
+                break;
+            }
+            chain.push(parent);
+        }
+        for f in chain.into_iter().rev() {
+            if folder_dirs.contains_key(&f.id) {
+                continue;
+            }
+            let parent_dir = f
+                .parent_id
+                .as_ref()
+                .and_then(|p| folder_dirs.get(p))
+                .cloned()
+                .unwrap_or_default();
+            let name = names.allocate(&parent_dir, &sanitize_file_name(&f.name), "");
+            folder_dirs.insert(f.id.clone(), parent_dir.join(name));
+        }
+    }
+
+    notes.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+    let notes = notes
+        .into_iter()
+        .map(|note| {
+            let dir = note
+                .folder_id
+                .as_ref()
+                .and_then(|f| folder_dirs.get(f))
+                .cloned()
+                .unwrap_or_default();
+            let file = names.allocate(&dir, &sanitize_file_name(&note.title), ".md");
+            let path = dir.join(file);
+            (note, path)
+        })
+        .collect();
+
+    VaultLayout { folder_dirs, notes }
+}
+
+// ===== Front-matter =====
+
+pub fn format_timestamp(ts: i64) -> String {
+    DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// YAML front-matter followed by the note's Markdown.
+pub fn render_note(note: &Note) -> String {
+    let mut out = String::from("---\n");
+    out.push_str(&format!("id: {}\n", note.id));
+    // JSON string literals are valid YAML double-quoted scalars
+    out.push_str(&format!("title: {}\n", serde_json::to_string(&note.title).unwrap_or_default()));
+    out.push_str(&format!("created: {}\n", format_timestamp(note.created_at)));
+    out.push_str(&format!("updated: {}\n", format_timestamp(note.updated_at)));
+    out.push_str(&format!("pinned: {}\n", note.is_pinned));
+    if note.is_archived {
+        out.push_str("archived: true\n");
+    }
+    out.push_str("---\n\n");
+    out.push_str(note.content.as_deref().unwrap_or(""));
+    // Always added, so that `note_body` can strip it and contents round-trip as is
+    out.push('\n');
+    out
+}
+
+/// Content of a note from the body of its file: the line break `render_note`
+/// ends every file with is removed.
+pub fn note_body(body: &str) -> &str {
+    body.strip_suffix('\n').unwrap_or(body)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrontValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl FrontValue {
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            FrontValue::Text(s) => Some(s),
+            FrontValue::List(_) => None,
+        }
+    }
+
+    /// A single scalar is treated as a one-item list (`tags: work`).
+    pub fn to_list(&self) -> Vec<String> {
+        match self {
+            FrontValue::Text(s) if s.is_empty() => vec![],
+            FrontValue::Text(s) => vec![s.clone()],
+            FrontValue::List(items) => items.clone(),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.as_text()?.to_ascii_lowercase().as_str() {
+            "true" | "yes" => Some(true),
+            "false" | "no" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+fn unquote(raw: &str) -> String {
+    let raw = raw.trim();
+    if raw.starts_with('"') {
+        if let Ok(s) = serde_json::from_str::<String>(raw) {
+            return s;
+        }
+        return raw.trim_matches('"').to_string();
+    }
+    if let Some(inner) = raw.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')) {
+        return inner.replace("''", "'");
+    }
+    raw.to_string()
+}
+
+/// Reads the YAML front-matter written by `render_note` and by tools such as
+/// Obsidian: `key: value` scalars and lists (`[a, b]` or `- item` lines).
+/// Anything fancier is kept as raw text. Returns the fields and the body.
+pub fn parse_front_matter(text: &str) -> (BTreeMap<String, FrontValue>, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut fields = BTreeMap::new();
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (fields, text);
+    };
+
+    let mut offset = text.len() - rest.len();
+    let mut body_start = None;
+    let mut current_key: Option<String> = None;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            body_start = Some(offset);
+            break;
+        }
+        if let Some(item) = trimmed.trim_start().strip_prefix("- ").filter(|_| current_key.is_some()) {
+            if let Some(FrontValue::List(items)) = current_key.as_ref().and_then(|k| fields.get_mut(k)) {
+                items.push(unquote(item));
+            }
+            continue;
+        }
+        if trimmed.starts_with([' ', '\t', '#']) || trimmed.is_empty() {
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else { continue };
+        let key = key.trim().to_string();
+        let value = value.trim();
+        let parsed = if value.is_empty() {
+            FrontValue::List(vec![])
+        } else if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            FrontValue::List(inner.split(',').map(unquote).filter(|s| !s.is_empty()).collect())
+        } else {
+            FrontValue::Text(unquote(value))
+        };
+        fields.insert(key.clone(), parsed);
+        current_key = Some(key);
+    }
+
+    match body_start {
+        Some(start) => {
+            let body = &text[start..];
+            let body = body.strip_prefix("\r\n").or_else(|| body.strip_prefix('\n')).unwrap_or(body);
+            (fields, body)
+        }
+        None => (BTreeMap::new(), text),
+    }
+}
+
+/// Parses the date formats found in front-matter: RFC 3339, `YYYY-MM-DD`,
+/// `YYYY-MM-DD HH:MM[:SS]` (read as UTC) or a Unix timestamp.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.timestamp());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(dt.and_utc().timestamp());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc().timestamp());
+    }
+    value.parse::<i64>().ok()
+}
+
+// ===== Writing =====
+
+/// Writes to a temporary sibling then renames, so a crash never leaves a half-written file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+/// Writes `bytes` only when the file is missing or differs. Returns whether it wrote.
+pub fn write_if_changed(path: &Path, bytes: &[u8]) -> std::io::Result<bool> {
+    if fs::read(path).map(|existing| existing == bytes).unwrap_or(false) {
+        return Ok(false);
+    }
+    write_atomic(path, bytes)?;
+    Ok(true)
+}
+
+/// Relative path with `/` separators, as stored in manifests.
+pub fn to_portable(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ExportManifest {
+    version: u32,
+    /// Note id -> file path relative to the export root.
+    files: BTreeMap<String, String>,
+    dirs: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct MarkdownExportReport {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    /// Encrypted notes are never written in plaintext.
+    pub skipped_encrypted: usize,
+}
+
+pub(crate) fn load_vault(db: &Database) -> Result<(Vec<Note>, Vec<Folder>), StemError> {
+    let conn = db.try_connection()?;
+    let mut stmt = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes"))?;
+    let notes = stmt.query_map([], row_to_note)?.collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn.prepare(&format!("SELECT {FOLDER_COLUMNS} FROM folders"))?;
+    let folders = stmt.query_map([], row_to_folder)?.collect::<Result<Vec<_>, _>>()?;
+    Ok((notes, folders))
+}
+
+/// `root` joined with a relative path read back from a manifest, or `None` if
+/// it could lead outside `root`: absolute paths, `..`, or a symlinked parent.
+pub(crate) fn path_under(root: &Path, rel: &str) -> Option<PathBuf> {
+    let rel = Path::new(rel);
+    if rel.as_os_str().is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let path = root.join(rel);
+    let real_root = root.canonicalize().ok()?;
+    let real_parent = path.parent()?.canonicalize().ok()?;
+    real_parent.starts_with(real_root).then_some(path)
+}
+
+/// Whether `path` is a note file written by `render_note` for note `id`.
+fn is_exported_note(path: &Path, id: &str) -> bool {
+    let Ok(text) = fs::read_to_string(path) else { return false };
+    let (fields, _) = parse_front_matter(&text);
+    fields.get("id").and_then(FrontValue::as_text) == Some(id)
+}
+
+/// Removes now-empty directories from `dir` up to (excluding) `root`.
+pub(crate) fn prune_empty_dirs(root: &Path, mut dir: Option<&Path>) {
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+pub(crate) fn export_markdown_to(notes: Vec<Note>, folders: &[Folder], root: &Path) -> Result<MarkdownExportReport, StemError> {
+    fs::create_dir_all(root)?;
+    let manifest_path = root.join(MANIFEST_FILE);
+    let previous: ExportManifest = fs::read(&manifest_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    let mut report = MarkdownExportReport::default();
+    let (plain, encrypted): (Vec<Note>, Vec<Note>) = notes.into_iter().partition(|n| !n.is_encrypted);
+    report.skipped_encrypted = encrypted.len();
+
+    let layout = plan_layout(plain, folders);
+    let mut manifest = ExportManifest { version: 1, ..Default::default() };
+
+    for dir in layout.folder_dirs.values() {
+        fs::create_dir_all(root.join(dir))?;
+        manifest.dirs.insert(to_portable(dir));
+    }
+    for (note, rel) in &layout.notes {
+        if write_if_changed(&root.join(rel), render_note(note).as_bytes())? {
+            report.written += 1;
+        } else {
+            report.unchanged += 1;
+        }
+        manifest.files.insert(note.id.clone(), to_portable(rel));
+    }
+
+    // Files from the previous run that are no longer produced (deleted, renamed
+    // or moved notes). The manifest is only trusted for files that still carry
+    // the note's id, so an edited one cannot delete anything else.
+    let current: HashSet<&String> = manifest.files.values().collect();
+    for (id, old) in previous.files.iter().filter(|(_, p)| !current.contains(p)) {
+        let Some(path) = path_under(root, old) else { continue };
+        if is_exported_note(&path, id) && fs::remove_file(&path).is_ok() {
+            report.removed += 1;
+        }
+        prune_empty_dirs(root, path.parent());
+    }
+    for old in previous.dirs.difference(&manifest.dirs) {
+        if let Some(dir) = path_under(root, old) {
+            prune_empty_dirs(root, Some(&dir));
+        }
+    }
+
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| StemError::Validation(e.to_string()))?;
+    write_if_changed(&manifest_path, &json)?;
+    Ok(report)
+}
+
+// ===== Tauri Commands =====
+
+/// Exports every note as a `.md` file into `target_dir`, mirroring the folder
+/// tree, or only the part selected by `scope`. Re-running into the same
+/// directory only rewrites changed files.
+#[tauri::command]
+pub async fn export_markdown(
+    db: State<'_, Database>,
+    target_dir: String,
+    scope: Option<ExportScope>,
+) -> Result<MarkdownExportReport, StemError> {
+    let root = PathBuf::from(target_dir);
+    if !root.is_absolute() {
+        return Err(StemError::Validation("Le dossier d'export doit être un chemin absolu".to_string()));
+    }
+    db.inner().clone().spawn(move |db| {
+        let (mut notes, mut folders) = load_vault(&db)?;
+        if let Some(scope) = &scope {
+            resolve_scope(&*db.try_connection()?, scope)?.retain(&mut notes, &mut folders);
+        }
+        export_markdown_to(notes, &folders, &root)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, folder_id: Option<&str>, created_at: i64) -> Note {
+        Note {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: Some(format!("Body of {}", id)),
+            created_at,
+            updated_at: created_at,
+            is_pinned: false,
+            folder_id: folder_id.map(str::to_string),
+            is_archived: false,
+            is_encrypted: false,
+        }
+    }
+
+    fn folder(id: &str, name: &str, parent_id: Option<&str>) -> Folder {
+        Folder {
+            id: id.to_string(),
+            name: name.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            position: 0,
+            created_at: 0,
+            is_archived: false,
+        }
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("a/b\\c:d*e?"), "a-b-c-d-e-");
+        assert_eq!(sanitize_file_name("  spaced   out.  "), "spaced out");
+        assert_eq!(sanitize_file_name("CON"), "CON_");
+        assert_eq!(sanitize_file_name("..."), FALLBACK_NAME);
+        assert_eq!(sanitize_file_name(&"é".repeat(100)).len(), MAX_NAME_BYTES);
+    }
+
+    #[test]
+    fn test_layout_mirrors_tree_and_resolves_collisions() {
+        // Child listed before its parent on purpose
+        let folders = vec![folder("f2", "Sub", Some("f1")), folder("f1", "Projects", None)];
+        let notes = vec![
+            note("a", "Plan", Some("f2"), 1),
+            note("b", "plan", Some("f2"), 2),
+            note("c", "Inbox", None, 3),
+        ];
+        let layout = plan_layout(notes, &folders);
+        let paths: Vec<String> = layout.notes.iter().map(|(_, p)| to_portable(p)).collect();
+        assert_eq!(paths, vec!["Projects/Sub/Plan.md", "Projects/Sub/plan (2).md", "Inbox.md"]);
+    }
+
+    #[test]
+    fn test_render_note_front_matter() {
+        let mut n = note("id-1", "Quote \"me\"", None, 0);
+        n.is_pinned = true;
+        let rendered = render_note(&n);
+        assert!(rendered.starts_with("---\nid: id-1\ntitle: \"Quote \\\"me\\\"\"\ncreated: 1970-01-01T00:00:00Z\n"));
+        assert!(rendered.contains("pinned: true\n---\n\nBody of id-1\n"));
+    }
+
+    #[test]
+    fn test_parse_front_matter() {
+        let rendered = render_note(&note("id-1", "Quote \"me\"", None, 86_400));
+        let (fields, body) = parse_front_matter(&rendered);
+        assert_eq!(fields["title"].as_text(), Some("Quote \"me\""));
+        assert_eq!(fields["pinned"].as_bool(), Some(false));
+        assert_eq!(parse_timestamp(fields["created"].as_text().unwrap()), Some(86_400));
+        assert_eq!(body, "Body of id-1\n");
+        assert_eq!(note_body(body), "Body of id-1");
+
+        let (fields, body) = parse_front_matter("---\ntags: [a, 'b c']\naliases:\n  - Alt\ndate: 2024-01-02\n---\nText");
+        assert_eq!(fields["tags"].to_list(), vec!["a", "b c"]);
+        assert_eq!(fields["aliases"].to_list(), vec!["Alt"]);
+        assert_eq!(parse_timestamp(fields["date"].as_text().unwrap()), Some(1_704_153_600));
+        assert_eq!(body, "Text");
+
+        let (fields, body) = parse_front_matter("---\nnot closed");
+        assert!(fields.is_empty());
+        assert_eq!(body, "---\nnot closed");
+    }
+
+    #[test]
+    fn test_reexport_is_incremental() {
+        let root = std::env::temp_dir().join(format!("stem-md-export-{}", uuid::Uuid::new_v4()));
+        let folders = vec![folder("f1", "Work", None)];
+        let mut secret = note("s", "Secret", None, 0);
+        secret.is_encrypted = true;
+        let notes = vec![note("a", "One", Some("f1"), 1), note("b", "Two", None, 2), secret];
+
+        let report = export_markdown_to(notes, &folders, &root).unwrap();
+        assert_eq!(report, MarkdownExportReport { written: 2, unchanged: 0, removed: 0, skipped_encrypted: 1 });
+        assert!(root.join("Work/One.md").exists());
+        assert!(!root.join("Secret.md").exists());
+
+        // Rename "Two", leave "One" untouched
+        let notes = vec![note("a", "One", Some("f1"), 1), note("b", "Deux", None, 2)];
+        let report = export_markdown_to(notes, &folders, &root).unwrap();
+        assert_eq!(report, MarkdownExportReport { written: 1, unchanged: 1, removed: 1, skipped_encrypted: 0 });
+        assert!(!root.join("Two.md").exists());
+        assert!(root.join("Deux.md").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_manifest_cannot_delete_other_files() {
+        let base = std::env::temp_dir().join(format!("stem-md-export-{}", uuid::Uuid::new_v4()));
+        let root = base.join("export");
+        fs::create_dir_all(&root).unwrap();
+        let outside = base.join("precious.txt");
+        fs::write(&outside, "keep me").unwrap();
+        fs::write(root.join("Mine.md"), "not an export").unwrap();
+        let exported = root.join("Old.md");
+        fs::write(&exported, render_note(&note("old", "Old", None, 0))).unwrap();
+
+        let manifest = serde_json::json!({
+            "version": 1,
+            "files": {
+                "x": "../precious.txt",
+                "y": outside.to_string_lossy(),
+                "z": "Mine.md",
+                "old": "Old.md",
+            },
+            "dirs": ["..", "../.."],
+        });
+        fs::write(root.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+
+        let report = export_markdown_to(vec![note("a", "One", None, 1)], &[], &root).unwrap();
+        assert_eq!(report.removed, 1);
+        assert!(!exported.exists());
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "keep me");
+        assert!(root.join("Mine.md").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
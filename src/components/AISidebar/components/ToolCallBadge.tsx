@@ -3,6 +3,9 @@ import { Brain, Eye, Plus, Pencil, Minus, Search } from "lucide-react";
 const TOOL_META: Record<string, { label: string; icon: React.ReactNode }> = {
   list_notes: { label: "Lecture des notes", icon: <Eye size={10} /> },
   read_note: { label: "Lecture d'une note", icon: <Eye size={10} /> },
+  get_note_outline: { label: "Lecture du plan d'une note", icon: <Eye size={10} /> },
+  get_note_section: { label: "Lecture d'une section", icon: <Eye size={10} /> },
+  replace_note_section: { label: "Mise à jour d'une section", icon: <Pencil size={10} /> },
   create_note: { label: "Création d'une note", icon: <Plus size={10} /> },
   update_note: { label: "Mise à jour d'une note", icon: <Pencil size={10} /> },
   delete_note: { label: "Suppression d'une note", icon: <Minus size={10} /> },
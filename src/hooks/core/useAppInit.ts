@@ -2,18 +2,21 @@ import { useState, useEffect, useCallback } from "react";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import { useNotesStore } from "@/store/useNotesStore";
 import { useFoldersStore } from "@/store/useFoldersStore";
+import { NoteRepository } from "@/services/db";
 
 /**
  * Handles one-time app initialization:
  * - Fetches notes and folders on mount
  * - Detects quick-capture window
  * - Listens for "refresh-notes" Tauri event
+ * - Opens the note of a clicked `stem-note://` link ("open-note" event)
  * - Provides quick-capture save handler
  */
 export function useAppInit() {
   const fetchNotes = useNotesStore((s) => s.fetchNotes);
   const createNote = useNotesStore((s) => s.createNote);
   const updateNote = useNotesStore((s) => s.updateNote);
+  const selectNote = useNotesStore((s) => s.selectNote);
   const fetchFolders = useFoldersStore((s) => s.fetchFolders);
 
   const [isQuickCapture, setIsQuickCapture] = useState(false);
@@ -50,6 +53,25 @@ export function useAppInit() {
     };
   }, [fetchNotes]);
 
+  // Internal links are routed by the stem-note:// protocol handler
+  useEffect(() => {
+    const appWindow = getCurrentWindow();
+    let unlistenFn: (() => void) | null = null;
+
+    appWindow
+      .listen<string>("open-note", async (event) => {
+        const note = await NoteRepository.getById(event.payload);
+        if (note) selectNote(note);
+      })
+      .then((fn) => {
+        unlistenFn = fn;
+      });
+
+    return () => {
+      if (unlistenFn) unlistenFn();
+    };
+  }, [selectNote]);
+
   // Quick capture save handler
   const handleQuickCaptureSave = useCallback(
     async (content: string) => {
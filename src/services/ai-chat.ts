@@ -53,6 +53,51 @@ const OLLAMA_TOOLS = [
       },
     },
   },
+  {
+    type: "function",
+    function: {
+      name: "get_note_outline",
+      description: "Liste les titres (#, ##...) d'une note avec leur niveau et leur ancre, sans son contenu",
+      parameters: {
+        type: "object",
+        required: ["note_id"],
+        properties: {
+          note_id: { type: "string", description: "L'ID de la note" },
+        },
+      },
+    },
+  },
+  {
+    type: "function",
+    function: {
+      name: "get_note_section",
+      description: "Lit une seule section d'une note : son titre et tout ce qui suit jusqu'au prochain titre de même niveau",
+      parameters: {
+        type: "object",
+        required: ["note_id", "heading"],
+        properties: {
+          note_id: { type: "string", description: "L'ID de la note" },
+          heading: { type: "string", description: "L'ancre ou le texte du titre de la section" },
+        },
+      },
+    },
+  },
+  {
+    type: "function",
+    function: {
+      name: "replace_note_section",
+      description: "Remplace une section d'une note, ligne de titre comprise, sans toucher au reste de la note",
+      parameters: {
+        type: "object",
+        required: ["note_id", "heading", "content"],
+        properties: {
+          note_id: { type: "string", description: "L'ID de la note à modifier" },
+          heading: { type: "string", description: "L'ancre ou le texte du titre de la section à remplacer" },
+          content: { type: "string", description: "La nouvelle section en Markdown, ligne de titre comprise" },
+        },
+      },
+    },
+  },
   {
     type: "function",
     function: {
@@ -132,6 +177,7 @@ Réponds TOUJOURS en français, sauf demande explicite contraire.
 ## Règles d'utilisation des outils
 - Quand l'utilisateur parle de "cette note" ou "la note", utilise l'ID de la note actuellement ouverte fourni dans le contexte.
 - Pour modifier une note, utilise TOUJOURS update_note avec le note_id. Le contenu doit être le Markdown complet (pas un diff).
+- Pour une longue note, commence par get_note_outline, puis lis ou modifie une seule partie avec get_note_section et replace_note_section.
 - Si tu n'as pas assez d'infos pour agir (ex: pas de note ouverte), demande une clarification.
 - Commence par list_notes ou read_note si tu as besoin d'infos avant d'agir.
 
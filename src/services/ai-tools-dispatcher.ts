@@ -1,6 +1,6 @@
 import { safeInvoke } from "@/lib/tauri";
 import { NoteRepository } from "@/services/db";
-import { NoteArraySchema } from "@/types/schemas";
+import { NoteArraySchema, NoteSchema, NoteSectionSchema, OutlineHeadingArraySchema } from "@/types/schemas";
 import { extractPlainText } from "@/lib/utils/text";
 import type { Note } from "@/types";
 import type { AIToolCall, AIToolResult } from "@/lib/ai-tools";
@@ -56,6 +56,38 @@ async function executeToolCall(
       return `Titre: ${note.title}\n\nContenu:\n${plainText || "(vide)"}`;
     }
 
+    case "get_note_outline": {
+      const { note_id } = call.arguments as { note_id: string };
+      if (!note_id) throw new Error("note_id est requis");
+      const headings = await safeInvoke("get_note_outline", OutlineHeadingArraySchema, { noteId: note_id });
+      if (headings.length === 0) return "Cette note n'a aucun titre.";
+      return headings
+        .map((h) => `${"  ".repeat(h.level - 1)}- ${h.text}${h.slug ? ` (#${h.slug})` : ""}`)
+        .join("\n");
+    }
+
+    case "get_note_section": {
+      const { note_id, heading } = call.arguments as { note_id: string; heading: string };
+      if (!note_id) throw new Error("note_id est requis");
+      if (!heading) throw new Error("heading est requis");
+      const section = await safeInvoke("get_note_section", NoteSectionSchema, { noteId: note_id, heading });
+      return section.content || "(vide)";
+    }
+
+    case "replace_note_section": {
+      const { note_id, heading, content } = call.arguments as {
+        note_id: string;
+        heading: string;
+        content: string;
+      };
+      if (!note_id) throw new Error("note_id est requis");
+      if (!heading) throw new Error("heading est requis");
+      if (content === undefined) throw new Error("content est requis");
+      const updated = await safeInvoke("replace_note_section", NoteSchema, { noteId: note_id, heading, content });
+      callbacks?.onNoteUpdated(updated);
+      return `Section « ${heading} » de la note "${updated.title}" remplacée avec succès.`;
+    }
+
     case "create_note": {
       const { title, content } = call.arguments as { title: string; content?: string };
       if (!title) throw new Error("title est requis");
@@ -40,6 +40,24 @@ export const SemanticResultSchema = z.object({
 
 export const SemanticResultArraySchema = z.array(SemanticResultSchema);
 
+// ===== Note outline schemas =====
+
+export const OutlineHeadingSchema = z.object({
+  level: z.number(),
+  text: z.string(),
+  slug: z.string(),
+  start: z.number(),
+  end: z.number(),
+  section_end: z.number(),
+});
+
+export const OutlineHeadingArraySchema = z.array(OutlineHeadingSchema);
+
+export const NoteSectionSchema = z.object({
+  heading: OutlineHeadingSchema,
+  content: z.string(),
+});
+
 // ===== Input validation schemas =====
 
 export const OllamaUrlSchema = z